@@ -0,0 +1,513 @@
+// src/analysis/mod.rs
+
+//! Module implementing image statistics that predict how compressible an image is.
+//!
+//! [`analyze`] goes beyond the order-0 byte entropy of
+//! [`calculate_entropy`](crate::compression::utils::calculate_entropy) and
+//! reports, per channel, the histogram, order-0 entropy, order-1 and order-2
+//! conditional entropy (given the previous one or two samples of the channel
+//! in scan order) and a noise estimate. For the image as a whole it reports
+//! the residual entropy left after each prediction filter and the number of
+//! unique colors.
+//!
+//! All results are plain data deriving `Serialize`, so they can be stored or
+//! passed to other services as JSON. [`build_report`] combines them with
+//! size estimates per algorithm and a recommended configuration from
+//! [`AutoSelector`].
+//!
+//! # Examples
+//!
+//! ```rust
+//! use image::{DynamicImage, GrayImage, Luma};
+//! use image_compression::analysis::analyze;
+//!
+//! let image = DynamicImage::ImageLuma8(GrayImage::from_fn(64, 64, |x, y| Luma([(x + y) as u8])));
+//! let analysis = analyze(&image);
+//! assert_eq!(analysis.channels.len(), 1);
+//! assert!(analysis.best_filter().entropy < analysis.byte_entropy);
+//! ```
+
+use crate::compression::auto::AutoSelector;
+use crate::compression::png::{filter_row, PngFilter};
+use crate::compression::utils::calculate_entropy;
+use crate::compression::CompressionError;
+use image::DynamicImage;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+/// Statistics of one image channel.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChannelStatistics {
+    /// Channel name such as `"R"`, `"L"` or `"A"`.
+    pub name: String,
+    /// Order-0 entropy in bits per sample.
+    pub entropy: f64,
+    /// Entropy given the previous sample of the channel, in bits per sample.
+    pub order1_entropy: f64,
+    /// Entropy given the two previous samples of the channel, in bits per sample.
+    ///
+    /// With few samples per context this underestimates the true entropy.
+    pub order2_entropy: f64,
+    pub mean: f64,
+    pub standard_deviation: f64,
+    /// Estimated standard deviation of additive Gaussian noise (Immerkær's method).
+    pub noise_sigma: f64,
+    /// Number of samples with each value 0-255.
+    pub histogram: Vec<u64>,
+}
+
+/// Entropy of the residuals left by a prediction filter.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FilterResidual {
+    /// Filter name: `none`, `sub`, `up`, `average`, `paeth`, `gradient` or `med`.
+    pub filter: String,
+    /// Order-0 entropy of the residual bytes over all channels, in bits per sample.
+    pub entropy: f64,
+}
+
+/// Statistics of a whole image.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ImageAnalysis {
+    pub width: u32,
+    pub height: u32,
+    /// Color type of the source image, e.g. `"Rgb8"`.
+    pub color_type: String,
+    /// Order-0 entropy of all interleaved samples, as `calculate_entropy` computes it.
+    pub byte_entropy: f64,
+    pub unique_colors: usize,
+    pub channels: Vec<ChannelStatistics>,
+    /// Residual entropy for each prediction filter, `none` first.
+    pub residuals: Vec<FilterResidual>,
+}
+
+impl ImageAnalysis {
+    /// Returns the prediction filter leaving the lowest residual entropy.
+    pub fn best_filter(&self) -> &FilterResidual {
+        self.residuals
+            .iter()
+            .min_by(|a, b| a.entropy.total_cmp(&b.entropy))
+            .expect("residuals are always computed")
+    }
+
+    /// Estimates the size in bytes of an ideal order-0 coder after the best prediction filter.
+    pub fn estimated_size(&self) -> usize {
+        let samples = self.width as usize * self.height as usize * self.channels.len();
+        (samples as f64 * self.best_filter().entropy / 8.0).ceil() as usize
+    }
+}
+
+/// Estimated output of one algorithm configuration.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AlgorithmEstimate {
+    pub algorithm: String,
+    pub level: Option<u32>,
+    pub preprocessing: String,
+    pub estimated_size: usize,
+    /// Raw size divided by the estimated size.
+    pub ratio: f64,
+}
+
+/// The configuration [`AutoSelector`] would choose, with its reasoning.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Recommendation {
+    pub algorithm: String,
+    pub level: Option<u32>,
+    pub preprocessing: String,
+    pub reasoning: Vec<String>,
+}
+
+/// Statistics, size estimates and a recommendation for one image.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AnalysisReport {
+    pub analysis: ImageAnalysis,
+    /// Size of the 8-bit interleaved samples in bytes.
+    pub raw_size: usize,
+    /// Size an ideal order-0 coder would reach after the best prediction filter.
+    pub entropy_bound: usize,
+    pub estimates: Vec<AlgorithmEstimate>,
+    pub recommendation: Recommendation,
+}
+
+/// Analyzes an image and trial-compresses it to estimate each algorithm's output size.
+///
+/// Every candidate algorithm is tried on a sample of the image, including those the
+/// recommendation skipped because of its time budget or entropy shortcut.
+///
+/// # Arguments
+///
+/// * `image` - The image to analyze.
+/// * `selector` - The selector making the recommendation; its sample size bounds the trials.
+///
+/// # Returns
+///
+/// A `Result` containing the `AnalysisReport` or a `CompressionError` if no algorithm succeeded.
+pub fn build_report(image: &DynamicImage, selector: &AutoSelector) -> Result<AnalysisReport, CompressionError> {
+    let (data, names) = samples(image);
+    let mut analysis = analyze_pixels(&data, image.width(), image.height(), names)?;
+    analysis.color_type = format!("{:?}", image.color());
+    let geometry = Some((image.width(), image.height(), names.len() as u8));
+    let decision = selector.select(&data, geometry)?;
+
+    let ratio = |size: usize| data.len() as f64 / size.max(1) as f64;
+    let estimates = selector
+        .trial_all(&data, geometry)?
+        .iter()
+        .map(|trial| AlgorithmEstimate {
+            algorithm: trial.algorithm.to_string(),
+            level: trial.level,
+            preprocessing: trial.preprocessing.to_string(),
+            estimated_size: trial.estimated_size,
+            ratio: ratio(trial.estimated_size),
+        })
+        .collect();
+    Ok(AnalysisReport {
+        entropy_bound: analysis.estimated_size(),
+        raw_size: data.len(),
+        analysis,
+        estimates,
+        recommendation: Recommendation {
+            algorithm: decision.algorithm.to_string(),
+            level: decision.level,
+            preprocessing: decision.preprocessing.to_string(),
+            reasoning: decision.reasoning,
+        },
+    })
+}
+
+impl fmt::Display for AnalysisReport {
+    /// Renders the report as aligned plain-text tables.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let a = &self.analysis;
+        writeln!(f, "Dimensions:     {}x{}", a.width, a.height)?;
+        writeln!(f, "Color type:     {}", a.color_type)?;
+        writeln!(f, "Unique colors:  {}", a.unique_colors)?;
+        writeln!(f, "Byte entropy:   {:.3} bits/byte", a.byte_entropy)?;
+        writeln!(f, "Raw size:       {} bytes", self.raw_size)?;
+        writeln!(f, "Entropy bound:  {} bytes", self.entropy_bound)?;
+        writeln!(f)?;
+        writeln!(f, "{:<8} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8}", "Channel", "Entropy", "Order-1", "Order-2", "Mean", "Std dev", "Noise")?;
+        for c in &a.channels {
+            writeln!(
+                f,
+                "{:<8} {:>8.3} {:>8.3} {:>8.3} {:>8.2} {:>8.2} {:>8.2}",
+                c.name, c.entropy, c.order1_entropy, c.order2_entropy, c.mean, c.standard_deviation, c.noise_sigma
+            )?;
+        }
+        writeln!(f)?;
+        writeln!(f, "{:<10} {:>16}", "Filter", "Residual entropy")?;
+        for r in &a.residuals {
+            writeln!(f, "{:<10} {:>16.3}", r.filter, r.entropy)?;
+        }
+        writeln!(f)?;
+        writeln!(f, "{:<36} {:>14} {:>8}", "Algorithm", "Estimated size", "Ratio")?;
+        for e in &self.estimates {
+            writeln!(f, "{:<36} {:>14} {:>7.2}:1", describe(&e.algorithm, e.level, &e.preprocessing), e.estimated_size, e.ratio)?;
+        }
+        writeln!(f)?;
+        let r = &self.recommendation;
+        writeln!(f, "Recommendation: {}", describe(&r.algorithm, r.level, &r.preprocessing))?;
+        write!(f, "Reasoning:      {}", r.reasoning.join("; "))
+    }
+}
+
+fn describe(algorithm: &str, level: Option<u32>, preprocessing: &str) -> String {
+    match level {
+        Some(level) => format!("{} level {} ({})", algorithm, level, preprocessing),
+        None => format!("{} ({})", algorithm, preprocessing),
+    }
+}
+
+/// Returns the image as interleaved 8-bit samples with a name per channel.
+pub(crate) fn samples(image: &DynamicImage) -> (Vec<u8>, &'static [&'static str]) {
+    let color = image.color();
+    match (color.has_color(), color.has_alpha()) {
+        (false, false) => (image.to_luma8().into_raw(), &["L"]),
+        (false, true) => (image.to_luma_alpha8().into_raw(), &["L", "A"]),
+        (true, false) => (image.to_rgb8().into_raw(), &["R", "G", "B"]),
+        (true, true) => (image.to_rgba8().into_raw(), &["R", "G", "B", "A"]),
+    }
+}
+
+/// Analyzes an image; samples deeper than 8 bits are reduced to 8 bits first.
+///
+/// # Arguments
+///
+/// * `image` - The image to analyze.
+///
+/// # Returns
+///
+/// The `ImageAnalysis` of the image.
+pub fn analyze(image: &DynamicImage) -> ImageAnalysis {
+    let (data, names) = samples(image);
+    // The samples of a decoded image always match its geometry
+    let mut analysis = analyze_pixels(&data, image.width(), image.height(), names).expect("samples match the image");
+    analysis.color_type = format!("{:?}", image.color());
+    analysis
+}
+
+/// Analyzes interleaved 8-bit samples with one entry of `names` per channel.
+///
+/// # Returns
+///
+/// A `Result` containing the `ImageAnalysis`, or `CompressionError::Compression` if the length of
+/// `data` does not match `width` x `height` x `names.len()`.
+pub fn analyze_pixels(data: &[u8], width: u32, height: u32, names: &[&str]) -> Result<ImageAnalysis, CompressionError> {
+    let channel_count = names.len().max(1);
+    let (w, h) = (width as usize, height as usize);
+    if w.checked_mul(h).and_then(|pixels| pixels.checked_mul(channel_count)) != Some(data.len()) {
+        return Err(CompressionError::Compression(format!(
+            "expected {}x{}x{} samples, got {} bytes",
+            width,
+            height,
+            channel_count,
+            data.len()
+        )));
+    }
+
+    let channels = names
+        .iter()
+        .enumerate()
+        .map(|(c, name)| {
+            let plane: Vec<u8> = data.iter().skip(c).step_by(channel_count).copied().collect();
+            channel_statistics(name, &plane, w, h)
+        })
+        .collect();
+
+    let residuals = Filter::ALL
+        .iter()
+        .map(|&filter| FilterResidual {
+            filter: filter.name(),
+            entropy: calculate_entropy(&residual(data, w, h, channel_count, filter)),
+        })
+        .collect();
+
+    let unique_colors = data
+        .chunks_exact(channel_count)
+        .map(|pixel| pixel.iter().fold(0u32, |key, &v| (key << 8) | v as u32))
+        .collect::<HashSet<_>>()
+        .len();
+
+    Ok(ImageAnalysis {
+        width,
+        height,
+        color_type: format!("{} x 8-bit", channel_count),
+        byte_entropy: calculate_entropy(data),
+        unique_colors,
+        channels,
+        residuals,
+    })
+}
+
+fn channel_statistics(name: &str, plane: &[u8], width: usize, height: usize) -> ChannelStatistics {
+    let mut histogram = vec![0u64; 256];
+    for &v in plane {
+        histogram[v as usize] += 1;
+    }
+    let count = plane.len().max(1) as f64;
+    let mean = plane.iter().map(|&v| v as f64).sum::<f64>() / count;
+    let variance = plane.iter().map(|&v| (v as f64 - mean).powi(2)).sum::<f64>() / count;
+
+    ChannelStatistics {
+        name: name.to_string(),
+        entropy: calculate_entropy(plane),
+        order1_entropy: conditional_entropy(plane, 1),
+        order2_entropy: conditional_entropy(plane, 2),
+        mean,
+        standard_deviation: variance.sqrt(),
+        noise_sigma: noise_sigma(plane, width, height),
+        histogram,
+    }
+}
+
+/// Computes H(X | previous `order` samples) as H(context, X) - H(context).
+fn conditional_entropy(plane: &[u8], order: usize) -> f64 {
+    if plane.len() <= order {
+        return 0.0;
+    }
+    let mut joint: HashMap<u32, u64> = HashMap::new();
+    let mut contexts: HashMap<u32, u64> = HashMap::new();
+    for window in plane.windows(order + 1) {
+        let context = window[..order].iter().fold(0u32, |key, &v| (key << 8) | v as u32);
+        *contexts.entry(context).or_default() += 1;
+        *joint.entry((context << 8) | window[order] as u32).or_default() += 1;
+    }
+    let total = (plane.len() - order) as f64;
+    let entropy = |counts: &HashMap<u32, u64>| {
+        counts
+            .values()
+            .map(|&n| {
+                let p = n as f64 / total;
+                -p * p.log2()
+            })
+            .sum::<f64>()
+    };
+    (entropy(&joint) - entropy(&contexts)).max(0.0)
+}
+
+/// Estimates the noise standard deviation with Immerkær's Laplacian-difference operator.
+fn noise_sigma(plane: &[u8], width: usize, height: usize) -> f64 {
+    if width < 3 || height < 3 {
+        return 0.0;
+    }
+    let at = |x: usize, y: usize| plane[y * width + x] as i32;
+    let mut sum = 0u64;
+    for y in 1..height - 1 {
+        for x in 1..width - 1 {
+            let response = at(x - 1, y - 1) - 2 * at(x, y - 1) + at(x + 1, y - 1) - 2 * at(x - 1, y) + 4 * at(x, y)
+                - 2 * at(x + 1, y)
+                + at(x - 1, y + 1)
+                - 2 * at(x, y + 1)
+                + at(x + 1, y + 1);
+            sum += response.unsigned_abs() as u64;
+        }
+    }
+    (std::f64::consts::FRAC_PI_2).sqrt() * sum as f64 / (6.0 * (width - 2) as f64 * (height - 2) as f64)
+}
+
+/// Prediction filters evaluated by the analysis: the PNG filters plus two that PNG lacks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Filter {
+    Png(PngFilter),
+    Gradient,
+    Med,
+}
+
+impl Filter {
+    const ALL: [Filter; 7] = [
+        Filter::Png(PngFilter::None),
+        Filter::Png(PngFilter::Sub),
+        Filter::Png(PngFilter::Up),
+        Filter::Png(PngFilter::Average),
+        Filter::Png(PngFilter::Paeth),
+        Filter::Gradient,
+        Filter::Med,
+    ];
+
+    fn name(self) -> String {
+        match self {
+            Filter::Png(filter) => filter.to_string().to_lowercase(),
+            Filter::Gradient => "gradient".to_string(),
+            Filter::Med => "med".to_string(),
+        }
+    }
+}
+
+/// Median edge detector (LOCO-I) prediction from the left (`a`), upper (`b`) and upper-left (`c`) neighbours.
+fn med(a: u8, b: u8, c: u8) -> u8 {
+    if c >= a.max(b) {
+        a.min(b)
+    } else if c <= a.min(b) {
+        a.max(b)
+    } else {
+        (a as i16 + b as i16 - c as i16) as u8
+    }
+}
+
+/// Returns prediction residuals modulo 256, with zero outside the image.
+fn residual(data: &[u8], width: usize, height: usize, channels: usize, filter: Filter) -> Vec<u8> {
+    let stride = width * channels;
+    let mut out = Vec::with_capacity(data.len());
+    let mut filtered = Vec::with_capacity(stride + 1);
+    for y in 0..height {
+        let row = &data[y * stride..(y + 1) * stride];
+        let prev = if y > 0 { &data[(y - 1) * stride..y * stride] } else { &[] };
+        let predict: fn(u8, u8, u8) -> u8 = match filter {
+            Filter::Png(png) => {
+                // filter_row writes the filter type byte first, which is not a residual.
+                filtered.clear();
+                filter_row(png.code(), row, prev, channels, &mut filtered);
+                out.extend_from_slice(&filtered[1..]);
+                continue;
+            }
+            Filter::Gradient => |a, b, c| (a as i16 + b as i16 - c as i16).clamp(0, 255) as u8,
+            Filter::Med => med,
+        };
+        for i in 0..stride {
+            let a = if i >= channels { row[i - channels] } else { 0 };
+            let b = prev.get(i).copied().unwrap_or(0);
+            let c = if i >= channels { prev.get(i - channels).copied().unwrap_or(0) } else { 0 };
+            out.push(row[i].wrapping_sub(predict(a, b, c)));
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{GrayImage, Luma, Rgb, RgbImage, RgbaImage};
+
+    fn noisy(width: u32, height: u32, amplitude: u32) -> GrayImage {
+        GrayImage::from_fn(width, height, |x, y| {
+            let hash = (x.wrapping_mul(374_761_393) ^ y.wrapping_mul(668_265_263)).wrapping_mul(2_654_435_761);
+            Luma([(100 + (hash >> 24) % (2 * amplitude + 1)) as u8])
+        })
+    }
+
+    #[test]
+    fn test_flat_image() {
+        let analysis = analyze(&DynamicImage::ImageRgba8(RgbaImage::from_pixel(20, 10, image::Rgba([1, 2, 3, 4]))));
+        assert_eq!(analysis.channels.len(), 4);
+        assert_eq!(analysis.unique_colors, 1);
+        assert_eq!(analysis.byte_entropy, 2.0);
+        for channel in &analysis.channels {
+            assert_eq!((channel.entropy, channel.order1_entropy, channel.noise_sigma), (0.0, 0.0, 0.0));
+            assert_eq!(channel.histogram.iter().sum::<u64>(), 200);
+        }
+    }
+
+    #[test]
+    fn test_gradient_prefers_prediction() {
+        let image = DynamicImage::ImageRgb8(RgbImage::from_fn(64, 64, |x, y| Rgb([(x * 3 + y) as u8, (x + y * 2) as u8, 9])));
+        let analysis = analyze(&image);
+        assert_eq!(analysis.color_type, "Rgb8");
+        assert_eq!(analysis.residuals.len(), 7);
+        assert_eq!(analysis.residuals[0].entropy, analysis.byte_entropy);
+        assert!(analysis.best_filter().entropy < 1.0, "{:?}", analysis.best_filter());
+        let red = &analysis.channels[0];
+        assert!(red.order1_entropy < 0.1 && red.entropy > 7.0);
+        assert!(red.order2_entropy <= red.order1_entropy + 0.001);
+        assert!(analysis.estimated_size() < 64 * 64 * 3 / 8);
+    }
+
+    #[test]
+    fn test_report_serializes() {
+        let image = DynamicImage::ImageLuma8(noisy(40, 30, 4));
+        let report = build_report(&image, &AutoSelector::new()).unwrap();
+        assert!(!report.estimates.is_empty());
+        assert!(report.estimates.iter().any(|e| e.algorithm == report.recommendation.algorithm));
+        assert!(report.to_string().contains("Recommendation:"));
+
+        let json = serde_json::to_string(&report).unwrap();
+        let parsed: AnalysisReport = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.raw_size, 1200);
+        assert_eq!(parsed.analysis.channels[0].histogram.len(), 256);
+    }
+
+    #[test]
+    fn test_report_estimates_every_algorithm() {
+        let noise = GrayImage::from_fn(64, 48, |x, y| Luma([(x.wrapping_mul(2_654_435_761) ^ y.wrapping_mul(40_503)).to_le_bytes()[1]]));
+        let selector = AutoSelector::new().with_time_budget(std::time::Duration::ZERO);
+        let report = build_report(&DynamicImage::ImageLuma8(noise), &selector).unwrap();
+        for algorithm in ["deflate", "lzw", "wavelet", "spiht"] {
+            assert!(report.estimates.iter().any(|e| e.algorithm == algorithm), "{}", report);
+        }
+    }
+
+    #[test]
+    fn test_noise_estimate_tracks_amplitude() {
+        let quiet = analyze(&DynamicImage::ImageLuma8(noisy(80, 60, 2)));
+        let loud = analyze(&DynamicImage::ImageLuma8(noisy(80, 60, 30)));
+        assert!(quiet.channels[0].noise_sigma < loud.channels[0].noise_sigma);
+        assert!(loud.channels[0].noise_sigma > 10.0);
+        assert!(loud.unique_colors > quiet.unique_colors);
+    }
+
+    #[test]
+    fn test_mismatched_buffer_is_an_error() {
+        assert!(analyze_pixels(&[0; 11], 4, 3, &["L"]).is_err());
+        assert!(analyze_pixels(&[0; 24], 4, 3, &["L", "A"]).is_ok());
+        assert!(analyze_pixels(&[], u32::MAX, u32::MAX, &["R", "G", "B"]).is_err());
+    }
+}
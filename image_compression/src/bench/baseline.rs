@@ -0,0 +1,324 @@
+// src/bench/baseline.rs
+
+//! Module implementing named benchmark baselines and regression detection.
+//!
+//! A [`Baseline`] is a saved [`BenchReport`]. [`compare`] matches a later
+//! run against it by file and algorithm. Any growth in compressed size is a
+//! ratio regression. A drop in encode or decode throughput counts only if
+//! it exceeds a relative tolerance and is significant under a one-sided
+//! Welch's t-test at the 5% level, so noisy timings do not fail a build.
+
+use super::{BenchReport, Stats};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A benchmark report saved under a name for later comparison.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Baseline {
+    pub name: String,
+    /// Seconds since the Unix epoch when the baseline was created.
+    pub created: u64,
+    pub report: BenchReport,
+}
+
+impl Baseline {
+    /// Creates a new `Baseline` from a report, timestamped now.
+    pub fn new(name: &str, report: BenchReport) -> Self {
+        Baseline {
+            name: name.to_string(),
+            created: SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs()),
+            report,
+        }
+    }
+
+    /// Returns the file a baseline called `name` is stored in under `dir`.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result` containing the path, or an `InvalidInput` error if `name` is empty or contains
+    /// a path separator or `..`, so a baseline can never be read or written outside `dir`.
+    pub fn path(dir: &Path, name: &str) -> io::Result<PathBuf> {
+        if name.is_empty() || name.contains(['/', '\\']) || name.contains("..") {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("invalid baseline name {:?}", name)));
+        }
+        Ok(dir.join(format!("{}.json", name)))
+    }
+
+    /// Writes the baseline as JSON to [`Baseline::path`], creating `dir` if needed.
+    pub fn save(&self, dir: &Path) -> io::Result<PathBuf> {
+        let path = Self::path(dir, &self.name)?;
+        fs::create_dir_all(dir)?;
+        fs::write(&path, serde_json::to_string_pretty(self)?)?;
+        Ok(path)
+    }
+
+    /// Reads the baseline called `name` from `dir`.
+    pub fn load(dir: &Path, name: &str) -> io::Result<Self> {
+        let data = fs::read(Self::path(dir, name)?)?;
+        Ok(serde_json::from_slice(&data)?)
+    }
+}
+
+/// The measurement that got worse.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RegressionKind {
+    /// The compressed output got bigger.
+    Ratio,
+    EncodeSpeed,
+    DecodeSpeed,
+}
+
+impl fmt::Display for RegressionKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RegressionKind::Ratio => write!(f, "compressed size"),
+            RegressionKind::EncodeSpeed => write!(f, "encode MB/s"),
+            RegressionKind::DecodeSpeed => write!(f, "decode MB/s"),
+        }
+    }
+}
+
+/// A measurement of one file and algorithm that is worse than in the baseline.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Regression {
+    pub file: String,
+    pub algorithm: String,
+    pub kind: RegressionKind,
+    pub baseline: f64,
+    pub current: f64,
+    /// Relative change from the baseline; positive means bigger output or faster.
+    pub change: f64,
+    /// Welch's t statistic for speed regressions.
+    pub t_statistic: Option<f64>,
+}
+
+impl fmt::Display for Regression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {}: {} {:.2} -> {:.2} ({:+.1}%)",
+            self.file,
+            self.algorithm,
+            self.kind,
+            self.baseline,
+            self.current,
+            self.change * 100.0
+        )?;
+        if let Some(t) = self.t_statistic {
+            write!(f, ", t = {:.2}", t)?;
+        }
+        Ok(())
+    }
+}
+
+/// The outcome of comparing a run with a baseline.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Comparison {
+    pub baseline: String,
+    /// Number of file and algorithm pairs present in both runs.
+    pub compared: usize,
+    /// Pairs whose compressed size shrank.
+    pub improved: usize,
+    pub regressions: Vec<Regression>,
+    /// Baseline pairs absent from the current run, as `file algorithm`.
+    pub missing: Vec<String>,
+}
+
+impl Comparison {
+    /// Returns whether any regression was found.
+    pub fn has_regressions(&self) -> bool {
+        !self.regressions.is_empty()
+    }
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Compared {} result(s) with baseline \"{}\": {} regression(s), {} smaller",
+            self.compared,
+            self.baseline,
+            self.regressions.len(),
+            self.improved
+        )?;
+        for regression in &self.regressions {
+            write!(f, "\n  REGRESSION {}", regression)?;
+        }
+        for missing in &self.missing {
+            write!(f, "\n  missing {}", missing)?;
+        }
+        Ok(())
+    }
+}
+
+/// Compares `current` with `baseline`.
+///
+/// # Arguments
+///
+/// * `baseline` - The saved baseline.
+/// * `current` - The new run.
+/// * `speed_tolerance` - Relative throughput drop (e.g. `0.05`) below which slowdowns are ignored.
+///
+/// # Returns
+///
+/// The `Comparison` listing regressions and baseline results missing from `current`.
+pub fn compare(baseline: &Baseline, current: &BenchReport, speed_tolerance: f64) -> Comparison {
+    let mut comparison = Comparison {
+        baseline: baseline.name.clone(),
+        compared: 0,
+        improved: 0,
+        regressions: Vec::new(),
+        missing: Vec::new(),
+    };
+    for old in &baseline.report.results {
+        let Some(new) = current.results.iter().find(|r| r.file == old.file && r.algorithm == old.algorithm) else {
+            comparison.missing.push(format!("{} {}", old.file, old.algorithm));
+            continue;
+        };
+        comparison.compared += 1;
+        let regression = |kind, baseline: f64, current: f64, t_statistic| Regression {
+            file: old.file.clone(),
+            algorithm: old.algorithm.clone(),
+            kind,
+            baseline,
+            current,
+            change: current / baseline - 1.0,
+            t_statistic,
+        };
+        if new.compressed_size > old.compressed_size {
+            comparison.regressions.push(regression(
+                RegressionKind::Ratio,
+                old.compressed_size as f64,
+                new.compressed_size as f64,
+                None,
+            ));
+        } else if new.compressed_size < old.compressed_size {
+            comparison.improved += 1;
+        }
+        for (kind, old_speed, new_speed) in [
+            (RegressionKind::EncodeSpeed, &old.encode_mbps, &new.encode_mbps),
+            (RegressionKind::DecodeSpeed, &old.decode_mbps, &new.decode_mbps),
+        ] {
+            if let Some(t) = significant_slowdown(old_speed, new_speed, speed_tolerance) {
+                comparison.regressions.push(regression(kind, old_speed.mean, new_speed.mean, Some(t)));
+            }
+        }
+    }
+    comparison
+}
+
+/// Returns Welch's t statistic if `new` is slower than `old` by more than `tolerance` and significantly so.
+fn significant_slowdown(old: &Stats, new: &Stats, tolerance: f64) -> Option<f64> {
+    if old.samples < 2 || new.samples < 2 || new.mean >= old.mean * (1.0 - tolerance) {
+        return None;
+    }
+    let (old_var, new_var) = (old.std_dev.powi(2) / old.samples as f64, new.std_dev.powi(2) / new.samples as f64);
+    let standard_error = (old_var + new_var).sqrt();
+    if standard_error == 0.0 {
+        return Some(f64::INFINITY);
+    }
+    let t = (old.mean - new.mean) / standard_error;
+    let df = (old_var + new_var).powi(2)
+        / (old_var.powi(2) / (old.samples - 1) as f64 + new_var.powi(2) / (new.samples - 1) as f64);
+    (t > t_critical(df)).then_some(t)
+}
+
+/// One-sided 95% critical values of Student's t distribution for 1 to 30 degrees of freedom.
+const T_CRITICAL: [f64; 30] = [
+    6.314, 2.920, 2.353, 2.132, 2.015, 1.943, 1.895, 1.860, 1.833, 1.812, 1.796, 1.782, 1.771, 1.761, 1.753, 1.746,
+    1.740, 1.734, 1.729, 1.725, 1.721, 1.717, 1.714, 1.711, 1.708, 1.706, 1.703, 1.701, 1.699, 1.697,
+];
+
+/// Returns the one-sided 95% critical value for `df` degrees of freedom, rounding `df` down.
+fn t_critical(df: f64) -> f64 {
+    let z = 1.645;
+    match df.floor() as usize {
+        0 => T_CRITICAL[0],
+        n if n <= T_CRITICAL.len() => T_CRITICAL[n - 1],
+        // First Cornish-Fisher correction to the normal quantile.
+        _ => z + (z * z * z + z) / (4.0 * df),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bench::BenchResult;
+
+    fn result(algorithm: &str, size: usize, speeds: &[f64]) -> BenchResult {
+        let stats = Stats::from_samples(speeds);
+        BenchResult {
+            file: "a.png".to_string(),
+            algorithm: algorithm.to_string(),
+            width: 10,
+            height: 10,
+            input_size: 300,
+            compressed_size: size,
+            ratio: 300.0 / size as f64,
+            bits_per_pixel: size as f64 * 0.08,
+            encode_mbps: stats,
+            decode_mbps: stats,
+        }
+    }
+
+    #[test]
+    fn test_detects_regressions() {
+        let baseline = Baseline::new(
+            "main",
+            BenchReport::new(1, 5, vec![
+                result("deflate:6", 100, &[50.0, 51.0, 49.0, 50.5, 49.5]),
+                result("lzw:4096", 200, &[20.0, 21.0, 19.0, 20.5, 19.5]),
+                result("spiht", 80, &[10.0, 10.0, 10.0, 10.0, 10.0]),
+            ]),
+        );
+        let current = BenchReport::new(1, 5, vec![
+            // Noisy and only slightly slower: not a regression.
+            result("deflate:6", 90, &[55.0, 40.0, 52.0, 45.0, 50.0]),
+            // Bigger and clearly slower.
+            result("lzw:4096", 210, &[15.0, 15.5, 14.5, 15.2, 14.8]),
+        ]);
+        let comparison = compare(&baseline, &current, 0.05);
+        assert_eq!(comparison.compared, 2);
+        assert_eq!(comparison.improved, 1);
+        assert_eq!(comparison.missing, vec!["a.png spiht".to_string()]);
+        let kinds: Vec<_> = comparison.regressions.iter().map(|r| (r.algorithm.as_str(), r.kind)).collect();
+        assert_eq!(kinds, vec![
+            ("lzw:4096", RegressionKind::Ratio),
+            ("lzw:4096", RegressionKind::EncodeSpeed),
+            ("lzw:4096", RegressionKind::DecodeSpeed),
+        ]);
+        assert!((comparison.regressions[0].change - 0.05).abs() < 1e-9);
+        assert!(comparison.to_string().contains("REGRESSION a.png lzw:4096: compressed size"));
+        assert!(!compare(&baseline, &baseline.report, 0.05).has_regressions());
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let baseline = Baseline::new("nightly", BenchReport::new(0, 1, vec![result("deflate:9", 64, &[3.0])]));
+        let path = baseline.save(&dir.path().join("baselines")).unwrap();
+        assert!(path.ends_with("nightly.json"));
+        let loaded = Baseline::load(&dir.path().join("baselines"), "nightly").unwrap();
+        assert_eq!(loaded.name, "nightly");
+        assert_eq!(loaded.report.results[0].compressed_size, 64);
+        assert!(Baseline::load(dir.path(), "missing").is_err());
+
+        for name in ["", "../nightly", "a/b", "a\\b", ".."] {
+            let error = Baseline::load(&dir.path().join("baselines"), name).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+            assert!(Baseline::new(name, BenchReport::new(0, 1, Vec::new())).save(dir.path()).is_err());
+        }
+        assert!(Baseline::path(dir.path(), "v1.2").is_ok());
+    }
+
+    #[test]
+    fn test_t_critical() {
+        assert_eq!(t_critical(0.4), 6.314);
+        assert_eq!(t_critical(4.7), 2.132);
+        assert!((t_critical(120.0) - 1.658).abs() < 0.002);
+    }
+}
@@ -0,0 +1,611 @@
+// src/bench/mod.rs
+
+//! Module implementing repeatable benchmarks of the compressors over an image corpus.
+//!
+//! A [`Benchmark`] decodes every image once, then runs each configured
+//! [`BenchAlgorithm`] on its pixels with a number of unmeasured warmup runs
+//! followed by timed repetitions. Every run is checked to round-trip
+//! exactly. Results carry compression ratio, bits per pixel and encode and
+//! decode throughput with their spread, and can be rendered as a table, as
+//! CSV or serialized as JSON. Runs can be saved and compared with the
+//! [`baseline`] submodule and rendered as HTML with the [`report`] submodule.
+
+pub mod baseline;
+pub mod report;
+
+use crate::analysis::samples;
+use crate::compression::auto::AutoCompressor;
+use crate::compression::ccitt::{BilevelImage, CcittCompressor, CcittMode};
+use crate::compression::deflate::DeflateCompressor;
+use crate::compression::jpeg_recompress::JpegRecompressor;
+use crate::compression::lzw::LzwCompressor;
+use crate::compression::png_recompress::PngRecompressor;
+use crate::compression::spiht::SpihtCompressor;
+use crate::compression::wavelet::WaveletCompressor;
+use crate::compression::{CompressionError, Compressor};
+use image::{DynamicImage, ImageFormat};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+/// A compressor together with the bytes it is benchmarked on.
+type Prepared = (Box<dyn Compressor>, Vec<u8>);
+
+/// A compressor configuration to benchmark, written as `name` or `name:parameter`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BenchAlgorithm {
+    /// Deflate at the given level (0-9).
+    Deflate(u32),
+    /// LZW with the given maximum table size.
+    Lzw(usize),
+    Wavelet,
+    Spiht,
+    /// CCITT Group 4; only runs on images that are purely black and white.
+    Ccitt,
+    /// Automatic selection with the default time budget.
+    Auto,
+    /// Operates on the original file bytes; only runs on JPEG files.
+    JpegRecompress,
+    /// Operates on the original file bytes; only runs on PNG files.
+    PngRecompress,
+}
+
+impl BenchAlgorithm {
+    /// Parses a specification such as `deflate:6`, `lzw:4096` or `spiht`.
+    ///
+    /// # Arguments
+    ///
+    /// * `spec` - The algorithm name, optionally followed by `:` and its level or table size.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the `BenchAlgorithm` or a `CompressionError` if the specification is invalid.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use image_compression::bench::BenchAlgorithm;
+    ///
+    /// assert_eq!(BenchAlgorithm::parse("deflate:6").unwrap(), BenchAlgorithm::Deflate(6));
+    /// assert_eq!(BenchAlgorithm::parse("lzw").unwrap(), BenchAlgorithm::Lzw(4096));
+    /// ```
+    pub fn parse(spec: &str) -> Result<Self, CompressionError> {
+        let (name, parameter) = match spec.split_once(':') {
+            Some((name, parameter)) => (name, Some(parameter)),
+            None => (spec, None),
+        };
+        let number = |default: usize| -> Result<usize, CompressionError> {
+            parameter.map_or(Ok(default), |p| {
+                p.parse().map_err(|_| CompressionError::InvalidLevel(format!("invalid parameter in {}", spec)))
+            })
+        };
+        let algorithm = match name.to_lowercase().as_str() {
+            "deflate" => {
+                let level = number(6)?;
+                if level > 9 {
+                    return Err(CompressionError::InvalidLevel(format!("deflate level {}", level)));
+                }
+                BenchAlgorithm::Deflate(level as u32)
+            }
+            "lzw" => {
+                let table_size = number(4096)?;
+                if !(257..=1 << 16).contains(&table_size) {
+                    return Err(CompressionError::InvalidLevel(format!("LZW table size {}", table_size)));
+                }
+                BenchAlgorithm::Lzw(table_size)
+            }
+            "wavelet" => BenchAlgorithm::Wavelet,
+            "spiht" => BenchAlgorithm::Spiht,
+            "ccitt" => BenchAlgorithm::Ccitt,
+            "auto" => BenchAlgorithm::Auto,
+            "jpeg-recompress" => BenchAlgorithm::JpegRecompress,
+            "png-recompress" => BenchAlgorithm::PngRecompress,
+            other => return Err(CompressionError::UnknownAlgorithm(other.to_string())),
+        };
+        if parameter.is_some() && !matches!(algorithm, BenchAlgorithm::Deflate(_) | BenchAlgorithm::Lzw(_)) {
+            return Err(CompressionError::InvalidLevel(format!("{} takes no parameter", name)));
+        }
+        Ok(algorithm)
+    }
+
+    /// The configurations benchmarked when none are given.
+    ///
+    /// Automatic selection is left out: what it picks depends on its time budget and so on the
+    /// machine's speed, which makes its results incomparable between runs.
+    pub fn defaults() -> Vec<Self> {
+        vec![
+            BenchAlgorithm::Deflate(1),
+            BenchAlgorithm::Deflate(6),
+            BenchAlgorithm::Deflate(9),
+            BenchAlgorithm::Lzw(4096),
+            BenchAlgorithm::Lzw(65536),
+            BenchAlgorithm::Wavelet,
+            BenchAlgorithm::Spiht,
+            BenchAlgorithm::Ccitt,
+        ]
+    }
+
+    /// Returns the compressor and its input for an image, or `None` if the algorithm does not apply to it.
+    fn prepare(&self, image: &DynamicImage, file: &[u8]) -> Result<Option<Prepared>, CompressionError> {
+        let (width, height) = (image.width(), image.height());
+        let pixels = || {
+            let (data, names) = samples(image);
+            (data, names.len() as u8)
+        };
+        let prepared: Prepared = match *self {
+            BenchAlgorithm::Deflate(level) => (Box::new(DeflateCompressor::with_level_number(level)?), pixels().0),
+            BenchAlgorithm::Lzw(table_size) => (Box::new(LzwCompressor::new(table_size)), pixels().0),
+            BenchAlgorithm::Wavelet => {
+                let (data, channels) = pixels();
+                (Box::new(WaveletCompressor::new(width, height, channels)), data)
+            }
+            BenchAlgorithm::Spiht => {
+                let (data, channels) = pixels();
+                (Box::new(SpihtCompressor::new(width, height, channels)), data)
+            }
+            BenchAlgorithm::Ccitt => match BilevelImage::from_exact(image) {
+                Some(bilevel) => (
+                    Box::new(CcittCompressor::new(CcittMode::Group4, width, height)),
+                    bilevel.packed().to_vec(),
+                ),
+                None => return Ok(None),
+            },
+            BenchAlgorithm::Auto => {
+                let (data, channels) = pixels();
+                (Box::new(AutoCompressor::new().with_geometry(width, height, channels)), data)
+            }
+            BenchAlgorithm::JpegRecompress => match image::guess_format(file) {
+                Ok(ImageFormat::Jpeg) => (Box::new(JpegRecompressor::new()), file.to_vec()),
+                _ => return Ok(None),
+            },
+            BenchAlgorithm::PngRecompress => match image::guess_format(file) {
+                Ok(ImageFormat::Png) => (Box::new(PngRecompressor::new()), file.to_vec()),
+                _ => return Ok(None),
+            },
+        };
+        Ok(Some(prepared))
+    }
+}
+
+impl fmt::Display for BenchAlgorithm {
+    /// Formats the algorithm in the form accepted by [`BenchAlgorithm::parse`].
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BenchAlgorithm::Deflate(level) => write!(f, "deflate:{}", level),
+            BenchAlgorithm::Lzw(table_size) => write!(f, "lzw:{}", table_size),
+            BenchAlgorithm::Wavelet => write!(f, "wavelet"),
+            BenchAlgorithm::Spiht => write!(f, "spiht"),
+            BenchAlgorithm::Ccitt => write!(f, "ccitt"),
+            BenchAlgorithm::Auto => write!(f, "auto"),
+            BenchAlgorithm::JpegRecompress => write!(f, "jpeg-recompress"),
+            BenchAlgorithm::PngRecompress => write!(f, "png-recompress"),
+        }
+    }
+}
+
+/// Summary statistics of repeated measurements.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Stats {
+    pub mean: f64,
+    /// Sample standard deviation; zero for a single measurement.
+    pub std_dev: f64,
+    pub min: f64,
+    pub max: f64,
+    pub samples: usize,
+}
+
+impl Stats {
+    /// Computes statistics over `values`, which must not be empty.
+    pub fn from_samples(values: &[f64]) -> Self {
+        let n = values.len() as f64;
+        let mean = values.iter().sum::<f64>() / n;
+        let variance = if values.len() > 1 {
+            values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (n - 1.0)
+        } else {
+            0.0
+        };
+        Stats {
+            mean,
+            std_dev: variance.sqrt(),
+            min: values.iter().copied().fold(f64::INFINITY, f64::min),
+            max: values.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+            samples: values.len(),
+        }
+    }
+}
+
+/// Measurements of one algorithm on one image.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BenchResult {
+    pub file: String,
+    pub algorithm: String,
+    pub width: u32,
+    pub height: u32,
+    /// Bytes given to the compressor: pixels, packed bits or the original file.
+    pub input_size: usize,
+    pub compressed_size: usize,
+    /// Input size divided by compressed size.
+    pub ratio: f64,
+    pub bits_per_pixel: f64,
+    /// Encode throughput in MB/s of input.
+    pub encode_mbps: Stats,
+    /// Decode throughput in MB/s of restored output.
+    pub decode_mbps: Stats,
+}
+
+/// Totals of one algorithm over every image it ran on.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AlgorithmTotal {
+    pub algorithm: String,
+    pub files: usize,
+    pub input_size: usize,
+    pub compressed_size: usize,
+    pub ratio: f64,
+    pub bits_per_pixel: f64,
+    /// Total input over the summed mean encode times.
+    pub encode_mbps: f64,
+    /// Total output over the summed mean decode times.
+    pub decode_mbps: f64,
+}
+
+/// The results of a benchmark run.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BenchReport {
+    pub warmup: usize,
+    pub repetitions: usize,
+    pub results: Vec<BenchResult>,
+    pub totals: Vec<AlgorithmTotal>,
+}
+
+impl BenchReport {
+    /// Builds a report, computing per-algorithm totals in the order algorithms first appear.
+    pub fn new(warmup: usize, repetitions: usize, results: Vec<BenchResult>) -> Self {
+        let mut totals: Vec<AlgorithmTotal> = Vec::new();
+        let mut seconds: Vec<(f64, f64)> = Vec::new();
+        let mut pixels: Vec<u64> = Vec::new();
+        for result in &results {
+            let index = match totals.iter().position(|t| t.algorithm == result.algorithm) {
+                Some(index) => index,
+                None => {
+                    totals.push(AlgorithmTotal {
+                        algorithm: result.algorithm.clone(),
+                        files: 0,
+                        input_size: 0,
+                        compressed_size: 0,
+                        ratio: 0.0,
+                        bits_per_pixel: 0.0,
+                        encode_mbps: 0.0,
+                        decode_mbps: 0.0,
+                    });
+                    seconds.push((0.0, 0.0));
+                    pixels.push(0);
+                    totals.len() - 1
+                }
+            };
+            let total = &mut totals[index];
+            total.files += 1;
+            total.input_size += result.input_size;
+            total.compressed_size += result.compressed_size;
+            let megabytes = result.input_size as f64 / 1e6;
+            seconds[index].0 += megabytes / result.encode_mbps.mean;
+            seconds[index].1 += megabytes / result.decode_mbps.mean;
+            pixels[index] += result.width as u64 * result.height as u64;
+        }
+        for ((total, (encode, decode)), pixels) in totals.iter_mut().zip(seconds).zip(pixels) {
+            let megabytes = total.input_size as f64 / 1e6;
+            total.ratio = total.input_size as f64 / total.compressed_size.max(1) as f64;
+            total.bits_per_pixel = total.compressed_size as f64 * 8.0 / pixels.max(1) as f64;
+            total.encode_mbps = megabytes / encode;
+            total.decode_mbps = megabytes / decode;
+        }
+        BenchReport {
+            warmup,
+            repetitions,
+            results,
+            totals,
+        }
+    }
+
+    /// Renders the per-image results as CSV with a header row.
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from(
+            "file,algorithm,width,height,input_size,compressed_size,ratio,bits_per_pixel,\
+             encode_mbps_mean,encode_mbps_std_dev,decode_mbps_mean,decode_mbps_std_dev\n",
+        );
+        for r in &self.results {
+            csv.push_str(&format!(
+                "{},{},{},{},{},{},{:.4},{:.4},{:.3},{:.3},{:.3},{:.3}\n",
+                csv_field(&r.file),
+                r.algorithm,
+                r.width,
+                r.height,
+                r.input_size,
+                r.compressed_size,
+                r.ratio,
+                r.bits_per_pixel,
+                r.encode_mbps.mean,
+                r.encode_mbps.std_dev,
+                r.decode_mbps.mean,
+                r.decode_mbps.std_dev
+            ));
+        }
+        csv
+    }
+}
+
+impl fmt::Display for BenchReport {
+    /// Renders per-image results followed by per-algorithm totals as aligned tables.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} warmup run(s), {} repetition(s)", self.warmup, self.repetitions)?;
+        writeln!(f)?;
+        writeln!(
+            f,
+            "{:<32} {:<16} {:>12} {:>8} {:>8} {:>20} {:>20}",
+            "File", "Algorithm", "Compressed", "Ratio", "Bpp", "Encode MB/s", "Decode MB/s"
+        )?;
+        for r in &self.results {
+            writeln!(
+                f,
+                "{:<32} {:<16} {:>12} {:>8.3} {:>8.3} {:>20} {:>20}",
+                r.file,
+                r.algorithm,
+                r.compressed_size,
+                r.ratio,
+                r.bits_per_pixel,
+                format!("{:.2} ± {:.2}", r.encode_mbps.mean, r.encode_mbps.std_dev),
+                format!("{:.2} ± {:.2}", r.decode_mbps.mean, r.decode_mbps.std_dev)
+            )?;
+        }
+        writeln!(f)?;
+        write!(
+            f,
+            "{:<16} {:>6} {:>14} {:>8} {:>8} {:>12} {:>12}",
+            "Algorithm", "Files", "Compressed", "Ratio", "Bpp", "Encode MB/s", "Decode MB/s"
+        )?;
+        for t in &self.totals {
+            write!(
+                f,
+                "\n{:<16} {:>6} {:>14} {:>8.3} {:>8.3} {:>12.2} {:>12.2}",
+                t.algorithm, t.files, t.compressed_size, t.ratio, t.bits_per_pixel, t.encode_mbps, t.decode_mbps
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Quotes a CSV field if it contains a separator, quote or line break.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Runs compressors over images with warmup and repetitions.
+#[derive(Debug, Clone)]
+pub struct Benchmark {
+    algorithms: Vec<BenchAlgorithm>,
+    warmup: usize,
+    repetitions: usize,
+}
+
+impl Default for Benchmark {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Benchmark {
+    /// Creates a new `Benchmark` of [`BenchAlgorithm::defaults`] with one warmup run and five repetitions.
+    pub fn new() -> Self {
+        Benchmark {
+            algorithms: BenchAlgorithm::defaults(),
+            warmup: 1,
+            repetitions: 5,
+        }
+    }
+
+    /// Returns the benchmark running the given algorithms instead of the defaults.
+    pub fn with_algorithms(mut self, algorithms: Vec<BenchAlgorithm>) -> Result<Self, CompressionError> {
+        if algorithms.is_empty() {
+            return Err(CompressionError::InvalidLevel("no algorithms to benchmark".to_string()));
+        }
+        self.algorithms = algorithms;
+        Ok(self)
+    }
+
+    /// Returns the benchmark with `warmup` unmeasured runs before the timed ones.
+    pub fn with_warmup(mut self, warmup: usize) -> Self {
+        self.warmup = warmup;
+        self
+    }
+
+    /// Returns the benchmark with `repetitions` timed runs, which must be at least one.
+    pub fn with_repetitions(mut self, repetitions: usize) -> Result<Self, CompressionError> {
+        if repetitions == 0 {
+            return Err(CompressionError::InvalidLevel("at least one repetition is required".to_string()));
+        }
+        self.repetitions = repetitions;
+        Ok(self)
+    }
+
+    /// Returns the configured algorithms.
+    pub fn get_algorithms(&self) -> &[BenchAlgorithm] {
+        &self.algorithms
+    }
+
+    /// Benchmarks every image file, skipping files that cannot be decoded.
+    ///
+    /// # Arguments
+    ///
+    /// * `files` - Paths of the images, typically from [`collect_images`].
+    /// * `root` - Directory that file names in the report are made relative to.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the `BenchReport` or a `CompressionError` if a compressor fails or does not round-trip.
+    pub fn run(&self, files: &[PathBuf], root: &Path) -> Result<BenchReport, CompressionError> {
+        let mut results = Vec::new();
+        for path in files {
+            let name = path.strip_prefix(root).unwrap_or(path).display().to_string();
+            let file = match fs::read(path) {
+                Ok(file) => file,
+                Err(e) => {
+                    log::warn!("skipping {}: {}", name, e);
+                    continue;
+                }
+            };
+            let image = match image::load_from_memory(&file) {
+                Ok(image) => image,
+                Err(e) => {
+                    log::warn!("skipping {}: {}", name, e);
+                    continue;
+                }
+            };
+            results.extend(self.run_image(&name, &file, &image)?);
+        }
+        Ok(BenchReport::new(self.warmup, self.repetitions, results))
+    }
+
+    /// Benchmarks every applicable algorithm on one decoded image.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name recorded in the results.
+    /// * `file` - The encoded file, used by the recompressors.
+    /// * `image` - The decoded image.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing one `BenchResult` per applicable algorithm or a `CompressionError`.
+    pub fn run_image(&self, name: &str, file: &[u8], image: &DynamicImage) -> Result<Vec<BenchResult>, CompressionError> {
+        let mut results = Vec::new();
+        for algorithm in &self.algorithms {
+            let Some((compressor, input)) = algorithm.prepare(image, file)? else {
+                log::debug!("{} does not apply to {}", algorithm, name);
+                continue;
+            };
+            for _ in 0..self.warmup {
+                compressor.decompress(&compressor.compress(&input)?)?;
+            }
+            let megabytes = input.len() as f64 / 1e6;
+            let mut encode = Vec::with_capacity(self.repetitions);
+            let mut decode = Vec::with_capacity(self.repetitions);
+            let mut compressed = Vec::new();
+            for _ in 0..self.repetitions {
+                let start = Instant::now();
+                compressed = compressor.compress(&input)?;
+                encode.push(megabytes / start.elapsed().as_secs_f64().max(1e-9));
+                let start = Instant::now();
+                let restored = compressor.decompress(&compressed)?;
+                decode.push(megabytes / start.elapsed().as_secs_f64().max(1e-9));
+                if restored != input {
+                    return Err(CompressionError::Decompression(format!("{} did not round-trip {}", algorithm, name)));
+                }
+            }
+            let pixels = image.width() as f64 * image.height() as f64;
+            log::debug!("{} on {}: {} -> {} bytes", algorithm, name, input.len(), compressed.len());
+            results.push(BenchResult {
+                file: name.to_string(),
+                algorithm: algorithm.to_string(),
+                width: image.width(),
+                height: image.height(),
+                input_size: input.len(),
+                compressed_size: compressed.len(),
+                ratio: input.len() as f64 / compressed.len().max(1) as f64,
+                bits_per_pixel: compressed.len() as f64 * 8.0 / pixels.max(1.0),
+                encode_mbps: Stats::from_samples(&encode),
+                decode_mbps: Stats::from_samples(&decode),
+            });
+        }
+        Ok(results)
+    }
+}
+
+/// Recursively lists files under `dir` whose extension is a known image format, sorted by path.
+pub fn collect_images(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                pending.push(path);
+            } else if ImageFormat::from_path(&path).is_ok() {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{GrayImage, Luma, RgbImage};
+
+    #[test]
+    fn test_parse_round_trips() {
+        for algorithm in BenchAlgorithm::defaults() {
+            assert_eq!(BenchAlgorithm::parse(&algorithm.to_string()).unwrap(), algorithm);
+        }
+        assert!(!BenchAlgorithm::defaults().contains(&BenchAlgorithm::Auto));
+        assert_eq!(BenchAlgorithm::parse("auto").unwrap(), BenchAlgorithm::Auto);
+        assert!(BenchAlgorithm::parse("deflate:10").is_err());
+        assert!(BenchAlgorithm::parse("lzw:12").is_err());
+        assert!(BenchAlgorithm::parse("spiht:3").is_err());
+        assert!(BenchAlgorithm::parse("brotli").is_err());
+    }
+
+    #[test]
+    fn test_stats() {
+        let stats = Stats::from_samples(&[2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0]);
+        assert_eq!(stats.mean, 5.0);
+        assert!((stats.std_dev - 2.138).abs() < 1e-3);
+        assert_eq!((stats.min, stats.max, stats.samples), (2.0, 9.0, 8));
+        assert_eq!(Stats::from_samples(&[3.0]).std_dev, 0.0);
+    }
+
+    #[test]
+    fn test_run_over_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let photo = RgbImage::from_fn(24, 16, |x, y| image::Rgb([(x * 10) as u8, (y * 15) as u8, 90]));
+        photo.save(dir.path().join("photo.png")).unwrap();
+        fs::create_dir(dir.path().join("scans")).unwrap();
+        let page = GrayImage::from_fn(32, 8, |x, _| Luma([if x % 5 == 0 { 0 } else { 255 }]));
+        page.save(dir.path().join("scans").join("page.png")).unwrap();
+        fs::write(dir.path().join("notes.txt"), "not an image").unwrap();
+
+        let files = collect_images(dir.path()).unwrap();
+        assert_eq!(files.len(), 2);
+        let benchmark = Benchmark::new()
+            .with_algorithms(vec![BenchAlgorithm::Deflate(6), BenchAlgorithm::Ccitt, BenchAlgorithm::PngRecompress])
+            .unwrap()
+            .with_warmup(0)
+            .with_repetitions(2)
+            .unwrap();
+        let report = benchmark.run(&files, dir.path()).unwrap();
+
+        // CCITT only applies to the black-and-white page.
+        assert_eq!(report.results.len(), 5);
+        let ccitt: Vec<_> = report.results.iter().filter(|r| r.algorithm == "ccitt").collect();
+        assert_eq!(ccitt.len(), 1);
+        assert!(ccitt[0].file.ends_with("page.png"));
+        assert_eq!(ccitt[0].input_size, 32);
+
+        let deflate = report.totals.iter().find(|t| t.algorithm == "deflate:6").unwrap();
+        assert_eq!(deflate.files, 2);
+        assert_eq!(deflate.input_size, 24 * 16 * 3 + 32 * 8);
+        assert!(report.results.iter().all(|r| r.encode_mbps.samples == 2 && r.ratio > 0.0));
+
+        assert_eq!(report.to_csv().lines().count(), 6);
+        let json = serde_json::to_string(&report).unwrap();
+        assert_eq!(serde_json::from_str::<BenchReport>(&json).unwrap().results.len(), 5);
+        assert!(report.to_string().contains("deflate:6"));
+    }
+}
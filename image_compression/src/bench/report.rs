@@ -0,0 +1,698 @@
+// src/bench/report.rs
+
+//! Module implementing a self-contained HTML report of benchmark results.
+//!
+//! [`HtmlReport`] renders a [`BenchReport`] as a single HTML file with no
+//! external resources: tables sort when a header is clicked, ratio/speed
+//! scatter plots and rate-distortion curves are inline SVG, and image
+//! thumbnails are embedded as base64 PNG data URIs. Rate-distortion curves
+//! come from [`rate_distortion`] for JPEG, [`spiht_rate_distortion`] for
+//! truncated SPIHT streams and [`wavelet_preview_distortion`] for the
+//! reduced-resolution previews of the wavelet codec.
+
+use super::BenchReport;
+use crate::compression::jpeg::{ChromaSubsampling, JpegEncoder};
+use crate::compression::spiht::SpihtCompressor;
+use crate::compression::wavelet::{parse_header, WaveletCompressor};
+use crate::compression::{CompressionError, Compressor};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageFormat};
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+use std::io::Cursor;
+
+/// Longest edge of embedded thumbnails, in pixels.
+pub const THUMBNAIL_SIZE: u32 = 96;
+
+/// Series colors, cycled when there are more series than colors.
+const PALETTE: [&str; 8] = ["#1f77b4", "#d62728", "#2ca02c", "#ff7f0e", "#9467bd", "#8c564b", "#e377c2", "#17becf"];
+
+/// Stream prefixes, in percent, at which SPIHT curves are sampled by default.
+pub const SPIHT_PERCENTAGES: [u8; 7] = [1, 2, 5, 10, 25, 50, 100];
+
+/// One encoding of an image at a given setting.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RdPoint {
+    /// The setting that produced the point, such as `quality 75` or `25% of stream`.
+    pub setting: String,
+    pub bits_per_pixel: f64,
+    /// Peak signal-to-noise ratio in dB, capped at 100 for identical images.
+    pub psnr: f64,
+}
+
+/// Rate-distortion measurements of one lossy mode on one image.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RdCurve {
+    pub file: String,
+    pub mode: String,
+    pub points: Vec<RdPoint>,
+}
+
+/// Encodes an image as JPEG at each quality and measures size and PSNR.
+///
+/// Alpha is dropped and grayscale images ignore `subsampling`.
+///
+/// # Arguments
+///
+/// * `file` - The name recorded in the curve.
+/// * `image` - The image to encode.
+/// * `subsampling` - Chroma subsampling used for color images.
+/// * `qualities` - JPEG qualities from 1 to 100.
+///
+/// # Returns
+///
+/// A `Result` containing the `RdCurve` or a `CompressionError` if encoding or decoding fails.
+pub fn rate_distortion(
+    file: &str,
+    image: &DynamicImage,
+    subsampling: ChromaSubsampling,
+    qualities: &[u8],
+) -> Result<RdCurve, CompressionError> {
+    let (data, channels) = samples(image);
+    let color = channels == 3;
+    let pixels = image.width() as f64 * image.height() as f64;
+    let mut points = Vec::with_capacity(qualities.len());
+    for &quality in qualities {
+        let encoder = JpegEncoder::new(quality)?.with_subsampling(subsampling);
+        let jpeg = encoder.encode(&data, image.width(), image.height(), channels)?;
+        let decoded = image::load_from_memory_with_format(&jpeg, ImageFormat::Jpeg)
+            .map_err(|e| CompressionError::Decompression(e.to_string()))?;
+        points.push(RdPoint {
+            setting: format!("quality {}", quality),
+            bits_per_pixel: jpeg.len() as f64 * 8.0 / pixels,
+            psnr: psnr(&data, &samples(&decoded).0),
+        });
+    }
+    let mode = match (color, subsampling) {
+        (false, _) => "jpeg gray",
+        (true, ChromaSubsampling::Yuv444) => "jpeg 4:4:4",
+        (true, ChromaSubsampling::Yuv422) => "jpeg 4:2:2",
+        (true, ChromaSubsampling::Yuv420) => "jpeg 4:2:0",
+    };
+    Ok(RdCurve {
+        file: file.to_string(),
+        mode: mode.to_string(),
+        points,
+    })
+}
+
+/// Encodes an image with SPIHT once and measures size and PSNR of growing stream prefixes.
+///
+/// Each prefix is what [`SpihtCompressor::truncate`] would keep, so the curve shows what
+/// truncating a stored file to that size costs without encoding the image again.
+///
+/// # Arguments
+///
+/// * `file` - The name recorded in the curve.
+/// * `image` - The image to encode; alpha is dropped.
+/// * `percentages` - Prefix lengths from 1 to 100 percent of the stream; those shorter than
+///   the header are skipped.
+///
+/// # Returns
+///
+/// A `Result` containing the `RdCurve` or a `CompressionError` if encoding or decoding fails.
+pub fn spiht_rate_distortion(file: &str, image: &DynamicImage, percentages: &[u8]) -> Result<RdCurve, CompressionError> {
+    let (data, channels) = samples(image);
+    let pixels = image.width() as f64 * image.height() as f64;
+    let stream = SpihtCompressor::new(image.width(), image.height(), channels).compress(&data)?;
+    let mut points = Vec::with_capacity(percentages.len());
+    for &percent in percentages {
+        // Prefixes too short to hold the header have no point.
+        let Ok(prefix) = SpihtCompressor::truncate(&stream, (stream.len() * percent.min(100) as usize).div_ceil(100)) else {
+            continue;
+        };
+        let restored = SpihtCompressor::decode_prefix(&prefix, prefix.len())?;
+        points.push(RdPoint {
+            setting: format!("{}% of stream", percent),
+            bits_per_pixel: prefix.len() as f64 * 8.0 / pixels,
+            psnr: psnr(&data, &restored),
+        });
+    }
+    Ok(RdCurve {
+        file: file.to_string(),
+        mode: if channels == 3 { "spiht" } else { "spiht gray" }.to_string(),
+        points,
+    })
+}
+
+/// Measures the reduced-resolution previews of the lossless wavelet codec, coarsest first.
+///
+/// A preview reads only the stream's coarse segments. It is scaled back up to the image size
+/// with bilinear filtering before its PSNR is measured, and its rate counts the bytes read.
+///
+/// # Returns
+///
+/// A `Result` containing the `RdCurve` or a `CompressionError` if encoding or decoding fails.
+pub fn wavelet_preview_distortion(file: &str, image: &DynamicImage) -> Result<RdCurve, CompressionError> {
+    let (data, channels) = samples(image);
+    let (width, height) = (image.width(), image.height());
+    let pixels = width as f64 * height as f64;
+    let stream = WaveletCompressor::new(width, height, channels).compress(&data)?;
+    let levels = parse_header(&stream)?.levels as u8;
+    let mut points = Vec::with_capacity(levels as usize + 1);
+    for discard in (0..=levels).rev() {
+        let preview = WaveletCompressor::decode_preview(&stream, discard)?;
+        let upscaled = if channels == 3 {
+            image::RgbImage::from_raw(preview.width, preview.height, preview.data).map(DynamicImage::ImageRgb8)
+        } else {
+            image::GrayImage::from_raw(preview.width, preview.height, preview.data).map(DynamicImage::ImageLuma8)
+        }
+        .ok_or_else(|| CompressionError::Decompression("Preview does not match its size".to_string()))?
+        .resize_exact(width, height, FilterType::Triangle);
+        points.push(RdPoint {
+            setting: if discard == 0 { "full image".to_string() } else { format!("1/{} scale", 1u32 << discard) },
+            bits_per_pixel: preview.bytes_read as f64 * 8.0 / pixels,
+            psnr: psnr(&data, &samples(&upscaled).0),
+        });
+    }
+    Ok(RdCurve {
+        file: file.to_string(),
+        mode: if channels == 3 { "wavelet preview" } else { "wavelet preview gray" }.to_string(),
+        points,
+    })
+}
+
+/// Returns the 8-bit RGB samples of a color image or the gray samples of any other, and the channel count.
+fn samples(image: &DynamicImage) -> (Vec<u8>, u8) {
+    if image.color().has_color() {
+        (image.to_rgb8().into_raw(), 3)
+    } else {
+        (image.to_luma8().into_raw(), 1)
+    }
+}
+
+/// Returns the PSNR in dB between two equally long sample buffers, capped at 100.
+fn psnr(original: &[u8], restored: &[u8]) -> f64 {
+    let squared: f64 = original.iter().zip(restored).map(|(&a, &b)| (a as f64 - b as f64).powi(2)).sum();
+    let mse = squared / original.len().max(1) as f64;
+    if mse == 0.0 {
+        100.0
+    } else {
+        (10.0 * (255.0 * 255.0 / mse).log10()).min(100.0)
+    }
+}
+
+/// An image shown in the report's gallery.
+#[derive(Debug, Clone)]
+struct ImageEntry {
+    name: String,
+    width: u32,
+    height: u32,
+    /// `data:` URI of the PNG thumbnail.
+    thumbnail: String,
+}
+
+/// Builds a single-file HTML report from benchmark results.
+#[derive(Debug, Clone)]
+pub struct HtmlReport {
+    title: String,
+    report: BenchReport,
+    images: Vec<ImageEntry>,
+    curves: Vec<RdCurve>,
+}
+
+impl HtmlReport {
+    /// Creates a new `HtmlReport` of the given results, with no images or curves.
+    pub fn new(report: BenchReport) -> Self {
+        HtmlReport {
+            title: "Compression benchmark".to_string(),
+            report,
+            images: Vec::new(),
+            curves: Vec::new(),
+        }
+    }
+
+    /// Returns the report with a different page title.
+    pub fn with_title(mut self, title: &str) -> Self {
+        self.title = title.to_string();
+        self
+    }
+
+    /// Adds a gallery entry with an embedded thumbnail for the image named `name` in the results.
+    pub fn add_image(&mut self, name: &str, image: &DynamicImage) -> Result<(), CompressionError> {
+        let thumbnail = image.resize(THUMBNAIL_SIZE, THUMBNAIL_SIZE, FilterType::Triangle);
+        let mut png = Vec::new();
+        thumbnail
+            .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+            .map_err(|e| CompressionError::Compression(e.to_string()))?;
+        self.images.push(ImageEntry {
+            name: name.to_string(),
+            width: image.width(),
+            height: image.height(),
+            thumbnail: format!("data:image/png;base64,{}", STANDARD.encode(&png)),
+        });
+        Ok(())
+    }
+
+    /// Adds a rate-distortion curve, drawn next to the image with the same name.
+    pub fn add_curve(&mut self, curve: RdCurve) {
+        self.curves.push(curve);
+    }
+
+    /// Renders the complete HTML document.
+    pub fn render(&self) -> String {
+        let mut html = String::new();
+        let _ = write!(
+            html,
+            "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n<style>{}</style>\n</head>\n<body>\n",
+            escape(&self.title),
+            STYLE
+        );
+        let _ = writeln!(html, "<h1>{}</h1>", escape(&self.title));
+        let _ = writeln!(
+            html,
+            "<p>{} image(s), {} algorithm(s), {} warmup run(s) and {} timed repetition(s) each. Click a column header to sort.</p>",
+            self.file_names().len(),
+            self.report.totals.len(),
+            self.report.warmup,
+            self.report.repetitions
+        );
+        self.render_totals(&mut html);
+        self.render_scatter(&mut html);
+        self.render_results(&mut html);
+        self.render_gallery(&mut html);
+        let _ = write!(html, "<script>{}</script>\n</body>\n</html>\n", SCRIPT);
+        html
+    }
+
+    fn file_names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = Vec::new();
+        for result in &self.report.results {
+            if !names.contains(&result.file.as_str()) {
+                names.push(&result.file);
+            }
+        }
+        names
+    }
+
+    fn render_totals(&self, html: &mut String) {
+        html.push_str("<h2>Summary by algorithm</h2>\n<table class=\"sortable\">\n<thead><tr>");
+        for header in ["Algorithm", "Files", "Input bytes", "Compressed bytes", "Ratio", "Bits/pixel", "Encode MB/s", "Decode MB/s"] {
+            let _ = write!(html, "<th>{}</th>", header);
+        }
+        html.push_str("</tr></thead>\n<tbody>\n");
+        for t in &self.report.totals {
+            let _ = writeln!(
+                html,
+                "<tr><td>{}</td>{}{}{}{}{}{}{}</tr>",
+                escape(&t.algorithm),
+                number(t.files as f64, 0),
+                number(t.input_size as f64, 0),
+                number(t.compressed_size as f64, 0),
+                number(t.ratio, 3),
+                number(t.bits_per_pixel, 3),
+                number(t.encode_mbps, 2),
+                number(t.decode_mbps, 2)
+            );
+        }
+        html.push_str("</tbody>\n</table>\n");
+    }
+
+    fn render_scatter(&self, html: &mut String) {
+        html.push_str("<h2>Ratio versus speed</h2>\n<div class=\"charts\">\n");
+        for (decode, label) in [(false, "Encode MB/s (log scale)"), (true, "Decode MB/s (log scale)")] {
+            let series: Vec<Series> = self
+                .report
+                .totals
+                .iter()
+                .map(|t| Series {
+                    name: t.algorithm.clone(),
+                    points: self
+                        .report
+                        .results
+                        .iter()
+                        .filter(|r| r.algorithm == t.algorithm)
+                        .map(|r| {
+                            let speed = if decode { r.decode_mbps.mean } else { r.encode_mbps.mean };
+                            Point {
+                                x: speed,
+                                y: r.ratio,
+                                tooltip: format!("{} {}: ratio {:.3}, {:.2} MB/s", r.file, r.algorithm, r.ratio, speed),
+                            }
+                        })
+                        .collect(),
+                })
+                .collect();
+            html.push_str(&chart(&series, label, "Compression ratio", true, false));
+        }
+        html.push_str("</div>\n");
+    }
+
+    fn render_results(&self, html: &mut String) {
+        html.push_str("<h2>Results per image</h2>\n<table class=\"sortable\">\n<thead><tr>");
+        for header in ["File", "Algorithm", "Size", "Compressed bytes", "Ratio", "Bits/pixel", "Encode MB/s", "±", "Decode MB/s", "±"] {
+            let _ = write!(html, "<th>{}</th>", header);
+        }
+        html.push_str("</tr></thead>\n<tbody>\n");
+        for r in &self.report.results {
+            let _ = writeln!(
+                html,
+                "<tr><td>{}</td><td>{}</td><td data-value=\"{}\">{}x{}</td>{}{}{}{}{}{}{}</tr>",
+                escape(&r.file),
+                escape(&r.algorithm),
+                r.width as u64 * r.height as u64,
+                r.width,
+                r.height,
+                number(r.compressed_size as f64, 0),
+                number(r.ratio, 3),
+                number(r.bits_per_pixel, 3),
+                number(r.encode_mbps.mean, 2),
+                number(r.encode_mbps.std_dev, 2),
+                number(r.decode_mbps.mean, 2),
+                number(r.decode_mbps.std_dev, 2)
+            );
+        }
+        html.push_str("</tbody>\n</table>\n");
+    }
+
+    fn render_gallery(&self, html: &mut String) {
+        if self.images.is_empty() && self.curves.is_empty() {
+            return;
+        }
+        html.push_str("<h2>Images</h2>\n");
+        let mut names: Vec<&str> = self.images.iter().map(|i| i.name.as_str()).collect();
+        for curve in &self.curves {
+            if !names.contains(&curve.file.as_str()) {
+                names.push(&curve.file);
+            }
+        }
+        for name in names {
+            html.push_str("<div class=\"image\">\n");
+            if let Some(entry) = self.images.iter().find(|i| i.name == name) {
+                let _ = writeln!(
+                    html,
+                    "<figure><img src=\"{}\" alt=\"{}\"><figcaption>{}<br>{}x{}</figcaption></figure>",
+                    entry.thumbnail,
+                    escape(name),
+                    escape(name),
+                    entry.width,
+                    entry.height
+                );
+            } else {
+                let _ = writeln!(html, "<figure><figcaption>{}</figcaption></figure>", escape(name));
+            }
+            let best = self
+                .report
+                .results
+                .iter()
+                .filter(|r| r.file == name)
+                .min_by_key(|r| r.compressed_size);
+            if let Some(best) = best {
+                let _ = writeln!(
+                    html,
+                    "<p>Smallest lossless: <b>{}</b>, {} bytes ({:.3} bits/pixel)</p>",
+                    escape(&best.algorithm),
+                    best.compressed_size,
+                    best.bits_per_pixel
+                );
+            }
+            let series: Vec<Series> = self
+                .curves
+                .iter()
+                .filter(|c| c.file == name)
+                .map(|c| Series {
+                    name: c.mode.clone(),
+                    points: c
+                        .points
+                        .iter()
+                        .map(|p| Point {
+                            x: p.bits_per_pixel,
+                            y: p.psnr,
+                            tooltip: format!("{} {}: {:.3} bits/pixel, {:.2} dB", c.mode, p.setting, p.bits_per_pixel, p.psnr),
+                        })
+                        .collect(),
+                })
+                .collect();
+            if !series.is_empty() {
+                html.push_str(&chart(&series, "Bits per pixel", "PSNR (dB)", false, true));
+            }
+            html.push_str("</div>\n");
+        }
+    }
+}
+
+/// A numeric table cell sorted by its exact value.
+fn number(value: f64, decimals: usize) -> String {
+    format!("<td data-value=\"{}\">{:.*}</td>", value, decimals, value)
+}
+
+/// Escapes text for use in HTML content and attribute values.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+struct Point {
+    x: f64,
+    y: f64,
+    tooltip: String,
+}
+
+struct Series {
+    name: String,
+    points: Vec<Point>,
+}
+
+/// Maps data values onto a chart axis.
+struct Axis {
+    min: f64,
+    max: f64,
+    log: bool,
+}
+
+impl Axis {
+    /// Spans `values` with 5% padding; log axes ignore non-positive values.
+    fn new(values: impl Iterator<Item = f64>, log: bool) -> Self {
+        let values: Vec<f64> = values
+            .filter(|v| v.is_finite() && (!log || *v > 0.0))
+            .map(|v| if log { v.log10() } else { v })
+            .collect();
+        let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+        let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        let (min, max) = match (min.is_finite(), max > min) {
+            (false, _) => (0.0, 1.0),
+            (true, false) => (min - 0.5, max + 0.5),
+            (true, true) => (min - (max - min) * 0.05, max + (max - min) * 0.05),
+        };
+        // Padding must not push an axis of non-negative values below zero.
+        let min = if !log && values.iter().all(|&v| v >= 0.0) { min.max(0.0) } else { min };
+        Axis { min, max, log }
+    }
+
+    /// Returns the position of `value` from 0 (start) to 1 (end).
+    fn position(&self, value: f64) -> f64 {
+        let value = if self.log { value.max(1e-12).log10() } else { value };
+        (value - self.min) / (self.max - self.min)
+    }
+
+    /// Returns five evenly spaced tick values.
+    fn ticks(&self) -> Vec<f64> {
+        (0..5)
+            .map(|i| {
+                let t = self.min + (self.max - self.min) * i as f64 / 4.0;
+                if self.log {
+                    10f64.powf(t)
+                } else {
+                    t
+                }
+            })
+            .collect()
+    }
+}
+
+fn format_tick(value: f64) -> String {
+    match value.abs() {
+        v if v >= 100.0 => format!("{:.0}", value),
+        v if v >= 10.0 => format!("{:.1}", value),
+        _ => format!("{:.2}", value),
+    }
+}
+
+/// Renders series as an SVG scatter plot, optionally joining each series' points with lines.
+fn chart(series: &[Series], x_label: &str, y_label: &str, log_x: bool, lines: bool) -> String {
+    const WIDTH: f64 = 520.0;
+    const HEIGHT: f64 = 340.0;
+    const LEFT: f64 = 60.0;
+    const RIGHT: f64 = 150.0;
+    const TOP: f64 = 15.0;
+    const BOTTOM: f64 = 45.0;
+    let (plot_w, plot_h) = (WIDTH - LEFT - RIGHT, HEIGHT - TOP - BOTTOM);
+    let x_axis = Axis::new(series.iter().flat_map(|s| s.points.iter().map(|p| p.x)), log_x);
+    let y_axis = Axis::new(series.iter().flat_map(|s| s.points.iter().map(|p| p.y)), false);
+    let px = |x: f64| LEFT + x_axis.position(x) * plot_w;
+    let py = |y: f64| TOP + (1.0 - y_axis.position(y)) * plot_h;
+
+    let mut svg = String::new();
+    let _ = writeln!(svg, "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" viewBox=\"0 0 {} {}\">", WIDTH, HEIGHT, WIDTH, HEIGHT);
+    let _ = writeln!(svg, "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" class=\"plot\"/>", LEFT, TOP, plot_w, plot_h);
+    for tick in x_axis.ticks() {
+        let x = px(tick);
+        let _ = writeln!(
+            svg,
+            "<line x1=\"{x:.1}\" y1=\"{}\" x2=\"{x:.1}\" y2=\"{}\" class=\"grid\"/><text x=\"{x:.1}\" y=\"{}\" text-anchor=\"middle\">{}</text>",
+            TOP,
+            TOP + plot_h,
+            TOP + plot_h + 15.0,
+            format_tick(tick)
+        );
+    }
+    for tick in y_axis.ticks() {
+        let y = py(tick);
+        let _ = writeln!(
+            svg,
+            "<line x1=\"{}\" y1=\"{y:.1}\" x2=\"{}\" y2=\"{y:.1}\" class=\"grid\"/><text x=\"{}\" y=\"{:.1}\" text-anchor=\"end\">{}</text>",
+            LEFT,
+            LEFT + plot_w,
+            LEFT - 5.0,
+            y + 4.0,
+            format_tick(tick)
+        );
+    }
+    let _ = writeln!(svg, "<text x=\"{}\" y=\"{}\" text-anchor=\"middle\">{}</text>", LEFT + plot_w / 2.0, HEIGHT - 8.0, escape(x_label));
+    let _ = writeln!(
+        svg,
+        "<text x=\"14\" y=\"{0}\" text-anchor=\"middle\" transform=\"rotate(-90 14 {0})\">{1}</text>",
+        TOP + plot_h / 2.0,
+        escape(y_label)
+    );
+    for (i, s) in series.iter().enumerate() {
+        let color = PALETTE[i % PALETTE.len()];
+        if lines && s.points.len() > 1 {
+            let mut points: Vec<&Point> = s.points.iter().collect();
+            points.sort_by(|a, b| a.x.total_cmp(&b.x));
+            let path: Vec<String> = points.iter().map(|p| format!("{:.1},{:.1}", px(p.x), py(p.y))).collect();
+            let _ = writeln!(svg, "<polyline points=\"{}\" fill=\"none\" stroke=\"{}\" stroke-width=\"1.5\"/>", path.join(" "), color);
+        }
+        for p in &s.points {
+            let _ = writeln!(
+                svg,
+                "<circle cx=\"{:.1}\" cy=\"{:.1}\" r=\"4\" fill=\"{}\"><title>{}</title></circle>",
+                px(p.x),
+                py(p.y),
+                color,
+                escape(&p.tooltip)
+            );
+        }
+        let legend_y = TOP + 10.0 + i as f64 * 16.0;
+        let _ = writeln!(
+            svg,
+            "<circle cx=\"{}\" cy=\"{}\" r=\"4\" fill=\"{}\"/><text x=\"{}\" y=\"{}\">{}</text>",
+            LEFT + plot_w + 12.0,
+            legend_y,
+            color,
+            LEFT + plot_w + 20.0,
+            legend_y + 4.0,
+            escape(&s.name)
+        );
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+const STYLE: &str = "
+body { font-family: sans-serif; margin: 2em; color: #222; }
+table { border-collapse: collapse; margin-bottom: 2em; }
+th, td { border: 1px solid #ccc; padding: 4px 8px; }
+td[data-value] { text-align: right; font-variant-numeric: tabular-nums; }
+th { background: #f0f0f0; cursor: pointer; user-select: none; }
+th[data-order=asc]::after { content: ' \\25B2'; }
+th[data-order=desc]::after { content: ' \\25BC'; }
+.charts, .image { display: flex; flex-wrap: wrap; gap: 2em; align-items: flex-start; margin-bottom: 2em; }
+figure { margin: 0; text-align: center; }
+svg text { font-size: 11px; }
+svg .plot { fill: none; stroke: #888; }
+svg .grid { stroke: #e4e4e4; }
+";
+
+const SCRIPT: &str = "
+document.querySelectorAll('table.sortable th').forEach(function (th) {
+  th.addEventListener('click', function () {
+    var table = th.closest('table'), body = table.tBodies[0], column = th.cellIndex;
+    var ascending = th.dataset.order !== 'asc';
+    table.querySelectorAll('th').forEach(function (other) { delete other.dataset.order; });
+    th.dataset.order = ascending ? 'asc' : 'desc';
+    var key = function (row) {
+      var cell = row.cells[column];
+      return cell.dataset.value !== undefined ? parseFloat(cell.dataset.value) : cell.textContent;
+    };
+    Array.from(body.rows).sort(function (a, b) {
+      var x = key(a), y = key(b);
+      var order = typeof x === 'number' ? x - y : x.localeCompare(y);
+      return ascending ? order : -order;
+    }).forEach(function (row) { body.appendChild(row); });
+  });
+});
+";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bench::{BenchAlgorithm, Benchmark};
+    use image::RgbImage;
+
+    #[test]
+    fn test_rate_distortion_is_monotonic() {
+        let image = DynamicImage::ImageRgb8(RgbImage::from_fn(48, 32, |x, y| {
+            image::Rgb([(x * 5) as u8, (y * 7) as u8, ((x * y) % 256) as u8])
+        }));
+        let curve = rate_distortion("a.png", &image, ChromaSubsampling::Yuv444, &[20, 60, 95]).unwrap();
+        assert_eq!(curve.mode, "jpeg 4:4:4");
+        assert!(curve.points.windows(2).all(|w| w[1].bits_per_pixel > w[0].bits_per_pixel && w[1].psnr > w[0].psnr));
+        assert_eq!(psnr(&[1, 2, 3], &[1, 2, 3]), 100.0);
+    }
+
+    #[test]
+    fn test_spiht_and_wavelet_curves_end_lossless() {
+        let image = DynamicImage::ImageRgb8(RgbImage::from_fn(48, 32, |x, y| {
+            image::Rgb([(x * 5) as u8, (y * 7) as u8, ((x * y) % 256) as u8])
+        }));
+        let spiht = spiht_rate_distortion("a.png", &image, &SPIHT_PERCENTAGES).unwrap();
+        let wavelet = wavelet_preview_distortion("a.png", &image).unwrap();
+        assert_eq!((spiht.mode.as_str(), wavelet.mode.as_str()), ("spiht", "wavelet preview"));
+        for curve in [&spiht, &wavelet] {
+            assert!(curve.points.len() > 2, "{:?}", curve);
+            assert!(curve.points.windows(2).all(|w| w[1].bits_per_pixel > w[0].bits_per_pixel && w[1].psnr >= w[0].psnr));
+            assert_eq!(curve.points.last().unwrap().psnr, 100.0);
+        }
+        assert_eq!(spiht.points.last().unwrap().setting, "100% of stream");
+        assert_eq!(wavelet.points.last().unwrap().setting, "full image");
+    }
+
+    #[test]
+    fn test_render_is_self_contained() {
+        let image = DynamicImage::ImageRgb8(RgbImage::from_fn(200, 100, |x, _| image::Rgb([x as u8, 0, 255])));
+        let benchmark = Benchmark::new()
+            .with_algorithms(vec![BenchAlgorithm::Deflate(1), BenchAlgorithm::Deflate(9)])
+            .unwrap()
+            .with_warmup(0)
+            .with_repetitions(1)
+            .unwrap();
+        let results = benchmark.run_image("x<1>.png", &[], &image).unwrap();
+        let mut report = HtmlReport::new(BenchReport::new(0, 1, results)).with_title("Nightly & weekly");
+        report.add_image("x<1>.png", &image).unwrap();
+        report.add_curve(rate_distortion("x<1>.png", &image, ChromaSubsampling::Yuv420, &[30, 80]).unwrap());
+        report.add_curve(spiht_rate_distortion("x<1>.png", &image, &[50, 100]).unwrap());
+        let html = report.render();
+
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("<title>Nightly &amp; weekly</title>"));
+        assert!(html.contains("x&lt;1&gt;.png") && !html.contains("x<1>.png"));
+        assert!(html.contains("data:image/png;base64,"));
+        assert!(html.contains("jpeg 4:2:0") && html.contains("spiht 100% of stream"));
+        assert_eq!(html.matches("<svg").count(), 3);
+        assert_eq!(html.matches("class=\"sortable\"").count(), 2);
+        assert!(!html.contains("src=\"http") && !html.contains("href="));
+    }
+}
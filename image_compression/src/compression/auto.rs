@@ -0,0 +1,601 @@
+// src/compression/auto.rs
+
+//! Module implementing automatic algorithm, level and preprocessing selection.
+//!
+//! [`AutoSelector`] decides in three stages, each cheaper than the next:
+//!
+//! 1. A sample of evenly spaced slices (whole rows for images) stands in for
+//!    large inputs, and sizes measured on it are scaled up to estimates.
+//! 2. The byte entropy from [`calculate_entropy`] of the input, and of the
+//!    sample after horizontal differencing, detects data that is already
+//!    close to random, which is stored with the fastest Deflate level without
+//!    further trials.
+//! 3. Candidate algorithms, levels and preprocessing steps are
+//!    trial-compressed on the sample, cheapest first, until the time budget
+//!    runs out. The smallest estimate wins; near-ties go to the faster one.
+//!
+//! The decision and its reasoning are logged at `info` level and returned as
+//! an [`AutoDecision`]. [`AutoCompressor`] records the choice in a small
+//! header so decompression needs no configuration.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use image_compression::compression::Compressor;
+//! use image_compression::compression::auto::AutoCompressor;
+//!
+//! let pixels: Vec<u8> = (0..64 * 64).map(|i| (i % 64 + i / 64) as u8).collect();
+//! let compressor = AutoCompressor::new().with_geometry(64, 64, 1);
+//! let (compressed, decision) = compressor.compress_with_decision(&pixels).unwrap();
+//! assert!(!decision.reasoning.is_empty());
+//! assert_eq!(compressor.decompress(&compressed).unwrap(), pixels);
+//! ```
+
+use super::utils::{calculate_entropy, checked_image_len};
+use super::{CompressionAlgorithmType, CompressionError, Compressor};
+use log::{debug, info};
+use std::fmt;
+use std::time::{Duration, Instant};
+
+const MAGIC: &[u8; 4] = b"AUT\x01";
+const HEADER_LEN: usize = 15;
+/// Default limit on the time spent in trial compression.
+pub const DEFAULT_TIME_BUDGET: Duration = Duration::from_millis(200);
+const DEFAULT_SAMPLE_SIZE: usize = 256 * 1024;
+const SAMPLE_SLICES: usize = 8;
+/// Entropy in bits per byte above which data is treated as incompressible.
+const RANDOM_ENTROPY: f64 = 7.95;
+/// Relative size difference under which the faster candidate is preferred.
+const NEAR_TIE: f64 = 0.005;
+
+/// Sampled bytes and the geometry of the sampled rows.
+type Sample = (Vec<u8>, Option<(u32, u32, u8)>);
+
+/// A reversible transform applied before compression.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Preprocessing {
+    None,
+    /// Each byte is replaced by its difference to the same channel of the previous pixel in its row.
+    HorizontalDelta,
+}
+
+impl Preprocessing {
+    fn id(self) -> u8 {
+        match self {
+            Preprocessing::None => 0,
+            Preprocessing::HorizontalDelta => 1,
+        }
+    }
+
+    fn from_id(id: u8) -> Result<Self, CompressionError> {
+        match id {
+            0 => Ok(Preprocessing::None),
+            1 => Ok(Preprocessing::HorizontalDelta),
+            other => Err(CompressionError::Decompression(format!("unknown preprocessing {}", other))),
+        }
+    }
+
+    /// Applies the transform in place to rows of `row_len` bytes with `channels` interleaved channels.
+    fn apply(self, data: &mut [u8], row_len: usize, channels: usize) {
+        if self == Preprocessing::HorizontalDelta {
+            for row in data.chunks_mut(row_len.max(1)) {
+                for i in (channels..row.len()).rev() {
+                    row[i] = row[i].wrapping_sub(row[i - channels]);
+                }
+            }
+        }
+    }
+
+    fn revert(self, data: &mut [u8], row_len: usize, channels: usize) {
+        if self == Preprocessing::HorizontalDelta {
+            for row in data.chunks_mut(row_len.max(1)) {
+                for i in channels..row.len() {
+                    row[i] = row[i].wrapping_add(row[i - channels]);
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for Preprocessing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Preprocessing::None => write!(f, "no preprocessing"),
+            Preprocessing::HorizontalDelta => write!(f, "horizontal delta"),
+        }
+    }
+}
+
+/// One candidate measured during selection.
+#[derive(Debug, Clone)]
+pub struct Trial {
+    pub algorithm: &'static str,
+    pub level: Option<u32>,
+    pub preprocessing: Preprocessing,
+    /// Compressed size extrapolated from the sample to the whole input.
+    pub estimated_size: usize,
+    /// Time spent compressing the sample.
+    pub elapsed: Duration,
+}
+
+impl fmt::Display for Trial {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.algorithm)?;
+        if let Some(level) = self.level {
+            write!(f, " level {}", level)?;
+        }
+        write!(
+            f,
+            " with {}: ~{} bytes in {:.1} ms",
+            self.preprocessing,
+            self.estimated_size,
+            self.elapsed.as_secs_f64() * 1000.0
+        )
+    }
+}
+
+/// The outcome of automatic selection.
+#[derive(Debug, Clone)]
+pub struct AutoDecision {
+    pub algorithm: &'static str,
+    pub level: Option<u32>,
+    pub preprocessing: Preprocessing,
+    /// Byte entropy of the whole input in bits per byte.
+    pub entropy: f64,
+    /// Estimated compressed size of the whole input.
+    pub estimated_size: usize,
+    /// Every candidate tried, in trial order.
+    pub trials: Vec<Trial>,
+    /// Human-readable explanation of each step of the decision.
+    pub reasoning: Vec<String>,
+}
+
+impl fmt::Display for AutoDecision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.algorithm)?;
+        if let Some(level) = self.level {
+            write!(f, " level {}", level)?;
+        }
+        write!(f, " with {} ({})", self.preprocessing, self.reasoning.join("; "))
+    }
+}
+
+/// Chooses a compression algorithm, level and preprocessing for a given input.
+#[derive(Debug, Clone)]
+pub struct AutoSelector {
+    time_budget: Duration,
+    sample_size: usize,
+}
+
+impl Default for AutoSelector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AutoSelector {
+    /// Creates a new `AutoSelector` with a 200 ms time budget and 256 KiB samples.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use image_compression::compression::auto::AutoSelector;
+    ///
+    /// let selector = AutoSelector::new();
+    /// assert_eq!(selector.get_time_budget().as_millis(), 200);
+    /// ```
+    pub fn new() -> Self {
+        AutoSelector {
+            time_budget: DEFAULT_TIME_BUDGET,
+            sample_size: DEFAULT_SAMPLE_SIZE,
+        }
+    }
+
+    /// Returns the selector with a different limit on time spent in trial compression.
+    ///
+    /// At least one candidate is always tried, so the budget may be exceeded by one trial.
+    pub fn with_time_budget(mut self, time_budget: Duration) -> Self {
+        self.time_budget = time_budget;
+        self
+    }
+
+    /// Returns the selector sampling up to `sample_size` bytes of large inputs.
+    pub fn with_sample_size(mut self, sample_size: usize) -> Result<Self, CompressionError> {
+        if sample_size == 0 {
+            return Err(CompressionError::InvalidLevel("sample size must be positive".to_string()));
+        }
+        self.sample_size = sample_size;
+        Ok(self)
+    }
+
+    /// Retrieves the trial compression time budget.
+    pub fn get_time_budget(&self) -> Duration {
+        self.time_budget
+    }
+
+    /// Selects how to compress `data`.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The bytes to compress.
+    /// * `geometry` - `(width, height, channels)` if `data` holds interleaved 8-bit pixels,
+    ///   which enables the image codecs and row-aware sampling and preprocessing.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the `AutoDecision` or a `CompressionError` if `data` does not match
+    /// `geometry` or no candidate succeeded.
+    pub fn select(&self, data: &[u8], geometry: Option<(u32, u32, u8)>) -> Result<AutoDecision, CompressionError> {
+        let start = Instant::now();
+        let (sample, sample_geometry) = self.sample(data, geometry)?;
+        let entropy = calculate_entropy(data);
+        // Smooth gradients have a flat byte histogram but predictable neighbours.
+        let (row_len, channels) = layout(sample.len(), sample_geometry);
+        let mut delta = sample.clone();
+        Preprocessing::HorizontalDelta.apply(&mut delta, row_len, channels);
+        let delta_entropy = calculate_entropy(&delta);
+        let mut reasoning = vec![format!(
+            "entropy {:.2} bits/byte, {:.2} after horizontal delta",
+            entropy, delta_entropy
+        )];
+
+        if entropy.min(delta_entropy) >= RANDOM_ENTROPY || data.is_empty() {
+            reasoning.push("data looks incompressible, skipping trials".to_string());
+            let decision = AutoDecision {
+                algorithm: "deflate",
+                level: Some(1),
+                preprocessing: Preprocessing::None,
+                entropy,
+                estimated_size: data.len(),
+                trials: Vec::new(),
+                reasoning,
+            };
+            info!("auto selection: {}", decision);
+            return Ok(decision);
+        }
+
+        if sample.len() < data.len() {
+            reasoning.push(format!("estimated from a {} byte sample", sample.len()));
+        }
+
+        let mut trials = Vec::new();
+        for candidate in candidates(geometry.is_some()) {
+            if !trials.is_empty() && start.elapsed() >= self.time_budget {
+                reasoning.push(format!("time budget exhausted after {} trials", trials.len()));
+                break;
+            }
+            trials.extend(trial(&sample, sample_geometry, data.len(), candidate)?);
+        }
+
+        let smallest = trials
+            .iter()
+            .map(|t| t.estimated_size)
+            .min()
+            .ok_or_else(|| CompressionError::Compression("no candidate algorithm succeeded".to_string()))?;
+        let limit = (smallest as f64 * (1.0 + NEAR_TIE)).floor() as usize;
+        let chosen = trials
+            .iter()
+            .filter(|t| t.estimated_size <= limit)
+            .min_by_key(|t| t.elapsed)
+            .unwrap()
+            .clone();
+        reasoning.push(format!("tried {} candidates", trials.len()));
+        if chosen.estimated_size > smallest {
+            reasoning.push(format!(
+                "chose a faster candidate within {:.1}% of the smallest estimate",
+                NEAR_TIE * 100.0
+            ));
+        }
+        reasoning.push(format!(
+            "best estimate ~{} bytes ({:.2}:1)",
+            chosen.estimated_size,
+            data.len() as f64 / chosen.estimated_size.max(1) as f64
+        ));
+
+        let decision = AutoDecision {
+            algorithm: chosen.algorithm,
+            level: chosen.level,
+            preprocessing: chosen.preprocessing,
+            entropy,
+            estimated_size: chosen.estimated_size,
+            trials,
+            reasoning,
+        };
+        info!("auto selection: {}", decision);
+        Ok(decision)
+    }
+
+    /// Trial-compresses a sample of `data` with every candidate, ignoring the time budget.
+    ///
+    /// Unlike [`AutoSelector::select`] this never skips trials, so every algorithm gets an estimate
+    /// even for data that looks incompressible.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the trials in candidate order, omitting candidates that failed, or a
+    /// `CompressionError` if `data` does not match `geometry` or an algorithm could not be created.
+    pub fn trial_all(&self, data: &[u8], geometry: Option<(u32, u32, u8)>) -> Result<Vec<Trial>, CompressionError> {
+        let (sample, sample_geometry) = self.sample(data, geometry)?;
+        let mut trials = Vec::new();
+        for candidate in candidates(geometry.is_some()) {
+            trials.extend(trial(&sample, sample_geometry, data.len(), candidate)?);
+        }
+        Ok(trials)
+    }
+
+    /// Takes evenly spaced slices of `data`, whole rows for images, totalling about `sample_size` bytes.
+    ///
+    /// Fails if `data` is not exactly the size of a `geometry` image.
+    fn sample(&self, data: &[u8], geometry: Option<(u32, u32, u8)>) -> Result<Sample, CompressionError> {
+        if let Some((width, height, channels)) = geometry {
+            let expected = (width as usize)
+                .checked_mul(height as usize)
+                .and_then(|pixels| pixels.checked_mul(channels as usize));
+            if expected != Some(data.len()) {
+                return Err(CompressionError::Compression(format!(
+                    "expected a {}x{}x{} image, got {} bytes",
+                    width,
+                    height,
+                    channels,
+                    data.len()
+                )));
+            }
+        }
+        if data.len() <= self.sample_size {
+            return Ok((data.to_vec(), geometry));
+        }
+        let (unit, units) = match geometry {
+            Some((width, height, channels)) => (width as usize * channels as usize, height as usize),
+            None => (1, data.len()),
+        };
+        let per_slice = (self.sample_size / SAMPLE_SLICES / unit).max(1);
+        let mut sample = Vec::with_capacity(self.sample_size + unit);
+        let mut rows = 0;
+        for slice in 0..SAMPLE_SLICES {
+            let first = slice * units / SAMPLE_SLICES;
+            let count = per_slice.min(units - first);
+            sample.extend_from_slice(&data[first * unit..(first + count) * unit]);
+            rows += count;
+        }
+        Ok((sample, geometry.map(|(width, _, channels)| (width, rows as u32, channels))))
+    }
+}
+
+/// Candidates as `(algorithm id, level, preprocessing)`, cheapest first.
+fn candidates(image: bool) -> Vec<(u8, Option<u32>, Preprocessing)> {
+    let mut list = vec![
+        (0, Some(1), Preprocessing::None),
+        (0, Some(6), Preprocessing::None),
+        (1, None, Preprocessing::None),
+        (0, Some(6), Preprocessing::HorizontalDelta),
+        (1, None, Preprocessing::HorizontalDelta),
+        (0, Some(9), Preprocessing::None),
+        (0, Some(9), Preprocessing::HorizontalDelta),
+    ];
+    if image {
+        list.push((2, None, Preprocessing::None));
+        list.push((3, None, Preprocessing::None));
+    }
+    list
+}
+
+/// Compresses `sample` with one candidate and scales its size up to an input of `total_len` bytes.
+///
+/// Returns `None` if the algorithm cannot compress the sample.
+fn trial(
+    sample: &[u8],
+    geometry: Option<(u32, u32, u8)>,
+    total_len: usize,
+    (id, level, preprocessing): (u8, Option<u32>, Preprocessing),
+) -> Result<Option<Trial>, CompressionError> {
+    let (rows, channels) = layout(sample.len(), geometry);
+    let mut input = sample.to_vec();
+    preprocessing.apply(&mut input, rows, channels);
+    let algorithm = build(id, level, geometry)?;
+    let trial_start = Instant::now();
+    let Ok(compressed) = algorithm.compress(&input) else {
+        return Ok(None);
+    };
+    let scale = if sample.is_empty() { 1.0 } else { total_len as f64 / sample.len() as f64 };
+    let trial = Trial {
+        algorithm: algorithm.name(),
+        level,
+        preprocessing,
+        estimated_size: (compressed.len() as f64 * scale).round() as usize,
+        elapsed: trial_start.elapsed(),
+    };
+    debug!("auto trial: {}", trial);
+    Ok(Some(trial))
+}
+
+fn build(id: u8, level: Option<u32>, geometry: Option<(u32, u32, u8)>) -> Result<CompressionAlgorithmType, CompressionError> {
+    match (id, level) {
+        (0, Some(level)) => CompressionAlgorithmType::create("deflate", Some(level)),
+        _ => {
+            let (width, height, channels) = geometry.unwrap_or((0, 0, 1));
+            CompressionAlgorithmType::for_block(id, width, height, channels)
+        }
+    }
+}
+
+/// Returns the row length and channel count used by preprocessing.
+fn layout(len: usize, geometry: Option<(u32, u32, u8)>) -> (usize, usize) {
+    match geometry {
+        Some((width, _, channels)) => (width as usize * channels as usize, channels as usize),
+        None => (len, 1),
+    }
+}
+
+/// A compressor that runs [`AutoSelector`] on each input and records the choice in its output.
+#[derive(Debug, Clone, Default)]
+pub struct AutoCompressor {
+    selector: AutoSelector,
+    geometry: Option<(u32, u32, u8)>,
+}
+
+impl AutoCompressor {
+    /// Creates a new `AutoCompressor` with a default [`AutoSelector`] for untyped bytes.
+    pub fn new() -> Self {
+        AutoCompressor {
+            selector: AutoSelector::new(),
+            geometry: None,
+        }
+    }
+
+    /// Creates a new `AutoCompressor` using the given selector.
+    pub fn with_selector(selector: AutoSelector) -> Self {
+        AutoCompressor { selector, geometry: None }
+    }
+
+    /// Returns the compressor treating inputs as interleaved 8-bit pixels of the given geometry.
+    pub fn with_geometry(mut self, width: u32, height: u32, channels: u8) -> Self {
+        self.geometry = Some((width, height, channels));
+        self
+    }
+
+    /// Compresses `data` and returns the decision that was made.
+    pub fn compress_with_decision(&self, data: &[u8]) -> Result<(Vec<u8>, AutoDecision), CompressionError> {
+        let decision = self.selector.select(data, self.geometry)?;
+        let id = CompressionAlgorithmType::id_of(decision.algorithm).unwrap();
+        let (row_len, channels) = layout(data.len(), self.geometry);
+        let mut input = data.to_vec();
+        decision.preprocessing.apply(&mut input, row_len, channels);
+        let payload = build(id, decision.level, self.geometry)?.compress(&input)?;
+
+        let (width, height, channels) = self.geometry.unwrap_or((0, 0, 0));
+        let mut output = Vec::with_capacity(HEADER_LEN + payload.len());
+        output.extend_from_slice(MAGIC);
+        // Decoding does not depend on the level, so only the algorithm is recorded.
+        output.push(id);
+        output.push(decision.preprocessing.id());
+        output.extend_from_slice(&width.to_be_bytes());
+        output.extend_from_slice(&height.to_be_bytes());
+        output.push(channels);
+        output.extend_from_slice(&payload);
+        Ok((output, decision))
+    }
+}
+
+impl Compressor for AutoCompressor {
+    fn compress(&self, data: &[u8]) -> Result<Vec<u8>, CompressionError> {
+        self.compress_with_decision(data).map(|(compressed, _)| compressed)
+    }
+
+    /// Decompresses data using the algorithm and geometry recorded in its header.
+    fn decompress(&self, data: &[u8]) -> Result<Vec<u8>, CompressionError> {
+        if data.len() < HEADER_LEN || &data[..4] != MAGIC {
+            return Err(CompressionError::Decompression("Invalid auto-compressed data".to_string()));
+        }
+        let preprocessing = Preprocessing::from_id(data[5])?;
+        let width = u32::from_be_bytes([data[6], data[7], data[8], data[9]]);
+        let height = u32::from_be_bytes([data[10], data[11], data[12], data[13]]);
+        let geometry = (data[14] != 0).then_some((width, height, data[14]));
+        // The geometry is untrusted, so bound the image before a codec allocates for it.
+        match geometry {
+            Some(_) => {
+                checked_image_len(width as usize, height as usize, data[14] as usize)?;
+            }
+            None if (width, height) != (0, 0) => {
+                return Err(CompressionError::Decompression("Invalid auto-compressed data".to_string()));
+            }
+            None => {}
+        }
+        let algorithm = CompressionAlgorithmType::for_block(data[4], width, height, data[14].max(1))?;
+        let mut output = algorithm.decompress(&data[HEADER_LEN..])?;
+        let (row_len, channels) = layout(output.len(), geometry);
+        preprocessing.revert(&mut output, row_len, channels);
+        Ok(output)
+    }
+}
+
+impl fmt::Display for AutoCompressor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "AutoCompressor (Time Budget: {} ms)", self.selector.time_budget.as_millis())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gradient(width: u32, height: u32, channels: u8) -> Vec<u8> {
+        (0..width * height * channels as u32)
+            .map(|i| {
+                let (x, y) = ((i / channels as u32) % width, i / channels as u32 / width);
+                (x * 2 + y + (i % channels as u32) * 40) as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_round_trip_images_and_bytes() {
+        let image = gradient(96, 80, 3);
+        let compressor = AutoCompressor::new().with_geometry(96, 80, 3);
+        let (compressed, decision) = compressor.compress_with_decision(&image).unwrap();
+        assert!(compressed.len() * 4 < image.len(), "{}", decision);
+        assert_eq!(compressor.decompress(&compressed).unwrap(), image);
+
+        let text = b"the quick brown fox jumps over the lazy dog ".repeat(50);
+        let compressor = AutoCompressor::new();
+        let (compressed, decision) = compressor.compress_with_decision(&text).unwrap();
+        assert!(decision.trials.iter().all(|t| !matches!(t.algorithm, "wavelet" | "spiht")));
+        assert_eq!(compressor.decompress(&compressed).unwrap(), text);
+    }
+
+    #[test]
+    fn test_random_data_skips_trials() {
+        let mut state = 0x2545_f491_u32;
+        let random: Vec<u8> = (0..1 << 16)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                (state >> 24) as u8
+            })
+            .collect();
+        let decision = AutoSelector::new().select(&random, None).unwrap();
+        assert!(decision.trials.is_empty());
+        assert_eq!((decision.algorithm, decision.level), ("deflate", Some(1)));
+    }
+
+    #[test]
+    fn test_sampling_and_time_budget() {
+        let image = gradient(512, 512, 1);
+        let selector = AutoSelector::new().with_sample_size(16 * 1024).unwrap().with_time_budget(Duration::ZERO);
+        let decision = selector.select(&image, Some((512, 512, 1))).unwrap();
+        assert_eq!(decision.trials.len(), 1);
+        assert!(decision.reasoning.iter().any(|r| r.contains("sample")));
+        assert!(decision.reasoning.iter().any(|r| r.contains("time budget")));
+
+        let mut delta = image.clone();
+        Preprocessing::HorizontalDelta.apply(&mut delta, 512, 1);
+        Preprocessing::HorizontalDelta.revert(&mut delta, 512, 1);
+        assert_eq!(delta, image);
+    }
+
+    #[test]
+    fn test_mismatched_geometry_is_an_error() {
+        let selector = AutoSelector::new().with_sample_size(1024).unwrap();
+        let image = gradient(64, 64, 1);
+        assert!(selector.select(&image, Some((0, 64, 1))).is_err());
+        assert!(selector.select(&image, Some((64, 64, 0))).is_err());
+        assert!(selector.select(&image[..4000], Some((64, 64, 1))).is_err());
+        assert!(selector.trial_all(&image, Some((64, 65, 1))).is_err());
+        assert!(selector.select(&image, Some((u32::MAX, u32::MAX, 255))).is_err());
+        assert!(AutoCompressor::new().with_geometry(64, 63, 1).compress(&image).is_err());
+        assert!(selector.select(&image, Some((64, 64, 1))).is_ok());
+    }
+
+    #[test]
+    fn test_hostile_headers_are_errors() {
+        let image = gradient(32, 32, 1);
+        let compressed = AutoCompressor::new().with_geometry(32, 32, 1).compress(&image).unwrap();
+        for (id, channels) in [(2, 1), (3, 3), (6, 1), (6, 0), (0, 0)] {
+            let mut hostile = compressed.clone();
+            hostile[4] = id;
+            hostile[6..14].fill(0xFF);
+            hostile[14] = channels;
+            assert!(AutoCompressor::new().decompress(&hostile).is_err(), "algorithm {}", id);
+        }
+    }
+}
@@ -0,0 +1,472 @@
+// src/compression/blocks.rs
+
+//! Module implementing per-block algorithm selection for mixed-content images.
+//!
+//! The image is split into square blocks and every block is trial-compressed
+//! with each pixel codec in [`CompressionAlgorithmType::PIXEL_IDS`]; the
+//! recompressors of JPEG and PNG files are not tried. The smallest output that
+//! decompresses back to the block wins. The chosen algorithm identifiers are
+//! stored as a block map in the header, so decompression dispatches each block
+//! to its own codec, and the map can be inspected with
+//! [`BlockCompressor::read_block_map`].
+//!
+//! Blocks of an 8-bit single-channel image that are purely black and white
+//! are also offered to the CCITT codec, packed to one bit per pixel.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use image_compression::compression::Compressor;
+//! use image_compression::compression::blocks::BlockCompressor;
+//!
+//! let pixels: Vec<u8> = (0..48 * 40).map(|i| if i % 48 < 24 { (i % 7) as u8 } else { 255 }).collect();
+//! let compressor = BlockCompressor::with_block_size(48, 40, 1, 16).unwrap();
+//! let (compressed, map) = compressor.compress_with_map(&pixels).unwrap();
+//! assert_eq!(map.blocks_x(), 3);
+//! assert_eq!(compressor.decompress(&compressed).unwrap(), pixels);
+//! ```
+
+use super::ccitt::BilevelImage;
+use super::utils::checked_image_len;
+use super::stream::StreamingCompressor;
+use super::{CompressionAlgorithmType, CompressionError, Compressor};
+use std::fmt;
+use std::io::Read;
+
+const MAGIC: &[u8; 4] = b"BLK\x01";
+const HEADER_LEN: usize = 15;
+const DEFAULT_BLOCK_SIZE: u16 = 64;
+
+/// A compressor choosing the best algorithm for each block of an image.
+#[derive(Debug, Clone)]
+pub struct BlockCompressor {
+    width: u32,
+    height: u32,
+    channels: u8,
+    block_size: u16,
+}
+
+/// The algorithm chosen for each block, in row-major block order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockMap {
+    blocks_x: u32,
+    blocks_y: u32,
+    block_size: u16,
+    algorithms: Vec<u8>,
+}
+
+impl BlockMap {
+    /// Retrieves the number of block columns.
+    pub fn blocks_x(&self) -> u32 {
+        self.blocks_x
+    }
+
+    /// Retrieves the number of block rows.
+    pub fn blocks_y(&self) -> u32 {
+        self.blocks_y
+    }
+
+    /// Retrieves the block edge length in pixels.
+    pub fn block_size(&self) -> u16 {
+        self.block_size
+    }
+
+    /// Returns the name of the algorithm chosen for block (`bx`, `by`).
+    pub fn algorithm(&self, bx: u32, by: u32) -> &'static str {
+        algorithm_name(self.algorithms[(by * self.blocks_x + bx) as usize])
+    }
+
+    /// Returns how many blocks use each algorithm, in identifier order, omitting unused ones.
+    pub fn counts(&self) -> Vec<(&'static str, usize)> {
+        CompressionAlgorithmType::PIXEL_IDS
+            .iter()
+            .map(|&id| (algorithm_name(id), self.algorithms.iter().filter(|&&a| a == id).count()))
+            .filter(|&(_, count)| count > 0)
+            .collect()
+    }
+}
+
+impl fmt::Display for BlockMap {
+    /// Draws the map with the first letter of each algorithm, followed by a legend.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for row in self.algorithms.chunks(self.blocks_x.max(1) as usize) {
+            let line: String = row.iter().map(|&id| symbol(algorithm_name(id))).collect();
+            writeln!(f, "{}", line)?;
+        }
+        let legend: Vec<String> = self
+            .counts()
+            .iter()
+            .map(|(name, count)| format!("{}={} ({})", symbol(name), name, count))
+            .collect();
+        write!(f, "{}", legend.join(", "))
+    }
+}
+
+fn algorithm_name(id: u8) -> &'static str {
+    CompressionAlgorithmType::name_of(id).unwrap_or("unknown")
+}
+
+fn symbol(name: &str) -> char {
+    name.chars().next().unwrap_or('?').to_ascii_uppercase()
+}
+
+impl BlockCompressor {
+    /// Creates a new `BlockCompressor` for images of the given geometry with 64-pixel blocks.
+    ///
+    /// # Arguments
+    ///
+    /// * `width` - Image width in pixels.
+    /// * `height` - Image height in pixels.
+    /// * `channels` - Number of interleaved 8-bit channels per pixel.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use image_compression::compression::blocks::BlockCompressor;
+    ///
+    /// let compressor = BlockCompressor::new(640, 480, 3);
+    /// assert_eq!(compressor.get_block_size(), 64);
+    /// ```
+    pub fn new(width: u32, height: u32, channels: u8) -> Self {
+        BlockCompressor {
+            width,
+            height,
+            channels,
+            block_size: DEFAULT_BLOCK_SIZE,
+        }
+    }
+
+    /// Creates a new `BlockCompressor` with a specified block size.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the `BlockCompressor` or a `CompressionError` if the block size is zero.
+    pub fn with_block_size(width: u32, height: u32, channels: u8, block_size: u16) -> Result<Self, CompressionError> {
+        if block_size == 0 {
+            return Err(CompressionError::InvalidLevel("block size must be positive".to_string()));
+        }
+        Ok(BlockCompressor {
+            width,
+            height,
+            channels,
+            block_size,
+        })
+    }
+
+    /// Retrieves the block edge length in pixels.
+    pub fn get_block_size(&self) -> u16 {
+        self.block_size
+    }
+
+    /// Compresses an image and returns the block map that was chosen.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - Interleaved 8-bit samples of the whole image.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the compressed data and its block map, or a `CompressionError`.
+    pub fn compress_with_map(&self, data: &[u8]) -> Result<(Vec<u8>, BlockMap), CompressionError> {
+        let expected = self.width as usize * self.height as usize * self.channels as usize;
+        if self.channels == 0 || data.len() != expected {
+            return Err(CompressionError::Compression(format!(
+                "expected {} bytes for a {}x{}x{} image, got {}",
+                expected,
+                self.width,
+                self.height,
+                self.channels,
+                data.len()
+            )));
+        }
+
+        let mut map = self.empty_map();
+        let mut payloads = Vec::with_capacity(map.algorithms.len());
+        for (bx, by, w, h) in self.blocks() {
+            let block = self.extract(data, bx, by, w, h);
+            let (id, payload) = self.best_encoding(&block, w, h)?;
+            map.algorithms.push(id);
+            payloads.push(payload);
+        }
+
+        let mut output = Vec::with_capacity(HEADER_LEN + map.algorithms.len() * 5);
+        output.extend_from_slice(MAGIC);
+        output.extend_from_slice(&self.width.to_be_bytes());
+        output.extend_from_slice(&self.height.to_be_bytes());
+        output.push(self.channels);
+        output.extend_from_slice(&self.block_size.to_be_bytes());
+        output.extend_from_slice(&map.algorithms);
+        for payload in &payloads {
+            let len = u32::try_from(payload.len())
+                .map_err(|_| CompressionError::Compression("Block exceeds 4 GiB".to_string()))?;
+            output.extend_from_slice(&len.to_be_bytes());
+            output.extend_from_slice(payload);
+        }
+        Ok((output, map))
+    }
+
+    /// Creates a `BlockCompressor` with the geometry recorded in compressed data.
+    pub fn from_header(data: &[u8]) -> Result<Self, CompressionError> {
+        Self::parse(data).map(|(compressor, _, _)| compressor)
+    }
+
+    /// Retrieves the image width, height and channel count.
+    pub fn get_dimensions(&self) -> (u32, u32, u8) {
+        (self.width, self.height, self.channels)
+    }
+
+    /// Reads the block map stored in compressed data without decompressing any block.
+    pub fn read_block_map(data: &[u8]) -> Result<BlockMap, CompressionError> {
+        Self::parse(data).map(|(_, map, _)| map)
+    }
+
+    /// Tries every pixel codec on a block and returns the smallest verified encoding.
+    fn best_encoding(&self, block: &[u8], width: u32, height: u32) -> Result<(u8, Vec<u8>), CompressionError> {
+        let mut best: Option<(u8, Vec<u8>)> = None;
+        for id in CompressionAlgorithmType::PIXEL_IDS {
+            let Some(input) = self.block_input(id, block, width, height) else {
+                continue;
+            };
+            let algorithm = CompressionAlgorithmType::for_block(id, width, height, self.channels)?;
+            let Ok(encoded) = algorithm.compress(&input) else {
+                continue;
+            };
+            if best.as_ref().is_some_and(|(_, b)| b.len() <= encoded.len()) {
+                continue;
+            }
+            if algorithm.decompress(&encoded).is_ok_and(|decoded| decoded == input) {
+                best = Some((id, encoded));
+            }
+        }
+        best.ok_or_else(|| CompressionError::Compression("no algorithm could encode the block".to_string()))
+    }
+
+    /// Returns the bytes handed to algorithm `id`, or `None` if it cannot represent the block.
+    fn block_input(&self, id: u8, block: &[u8], width: u32, height: u32) -> Option<Vec<u8>> {
+        if id != CompressionAlgorithmType::CCITT_ID {
+            return Some(block.to_vec());
+        }
+        if self.channels != 1 || !block.iter().all(|&v| v == 0 || v == 255) {
+            return None;
+        }
+        let mut bilevel = BilevelImage::new(width, height);
+        for (i, &v) in block.iter().enumerate() {
+            bilevel.set(i as u32 % width, i as u32 / width, v == 0);
+        }
+        Some(bilevel.packed().to_vec())
+    }
+
+    fn empty_map(&self) -> BlockMap {
+        let size = self.block_size as u32;
+        BlockMap {
+            blocks_x: self.width.div_ceil(size),
+            blocks_y: self.height.div_ceil(size),
+            block_size: self.block_size,
+            algorithms: Vec::new(),
+        }
+    }
+
+    /// Iterates over blocks as `(x, y, width, height)` in pixels, clipped at the image edge.
+    fn blocks(&self) -> impl Iterator<Item = (u32, u32, u32, u32)> + '_ {
+        let size = self.block_size as u32;
+        (0..self.height.div_ceil(size)).flat_map(move |by| {
+            (0..self.width.div_ceil(size)).map(move |bx| {
+                let (x, y) = (bx * size, by * size);
+                (x, y, size.min(self.width - x), size.min(self.height - y))
+            })
+        })
+    }
+
+    fn extract(&self, data: &[u8], x: u32, y: u32, width: u32, height: u32) -> Vec<u8> {
+        let channels = self.channels as usize;
+        let row_len = width as usize * channels;
+        let mut block = Vec::with_capacity(row_len * height as usize);
+        for row in y..y + height {
+            let start = (row as usize * self.width as usize + x as usize) * channels;
+            block.extend_from_slice(&data[start..start + row_len]);
+        }
+        block
+    }
+
+    fn parse(data: &[u8]) -> Result<(Self, BlockMap, usize), CompressionError> {
+        let invalid = || CompressionError::Decompression("Invalid block-compressed data".to_string());
+        if data.len() < HEADER_LEN || &data[..4] != MAGIC {
+            return Err(invalid());
+        }
+        let compressor = BlockCompressor::with_block_size(
+            u32::from_be_bytes([data[4], data[5], data[6], data[7]]),
+            u32::from_be_bytes([data[8], data[9], data[10], data[11]]),
+            data[12],
+            u16::from_be_bytes([data[13], data[14]]),
+        )
+        .map_err(|_| invalid())?;
+        let mut map = compressor.empty_map();
+        let count = map.blocks_x as usize * map.blocks_y as usize;
+        let algorithms = data.get(HEADER_LEN..HEADER_LEN + count).ok_or_else(invalid)?;
+        if compressor.channels == 0 || algorithms.iter().any(|id| !CompressionAlgorithmType::PIXEL_IDS.contains(id)) {
+            return Err(invalid());
+        }
+        map.algorithms = algorithms.to_vec();
+        Ok((compressor, map, HEADER_LEN + count))
+    }
+}
+
+impl Compressor for BlockCompressor {
+    fn compress(&self, data: &[u8]) -> Result<Vec<u8>, CompressionError> {
+        self.compress_with_map(data).map(|(compressed, _)| compressed)
+    }
+
+    /// Decompresses data using the geometry and block map in its header.
+    fn decompress(&self, data: &[u8]) -> Result<Vec<u8>, CompressionError> {
+        let (compressor, map, mut position) = Self::parse(data)?;
+        let invalid = || CompressionError::Decompression("Truncated block-compressed data".to_string());
+        let channels = compressor.channels as usize;
+        // Every block stores at least its payload length, so the map must be backed by data before allocating
+        if data.len() < position + 4 * map.algorithms.len() {
+            return Err(invalid());
+        }
+        let size = checked_image_len(compressor.width as usize, compressor.height as usize, channels)?;
+        let mut output = vec![0u8; size];
+
+        for ((x, y, w, h), &id) in compressor.blocks().zip(&map.algorithms) {
+            let len_bytes = data.get(position..position + 4).ok_or_else(invalid)?;
+            let len = u32::from_be_bytes([len_bytes[0], len_bytes[1], len_bytes[2], len_bytes[3]]) as usize;
+            let payload = data.get(position + 4..position + 4 + len).ok_or_else(invalid)?;
+            position += 4 + len;
+
+            let algorithm = CompressionAlgorithmType::for_block(id, w, h, compressor.channels)?;
+            let row_len = w as usize * channels;
+            let mut block = decode_block(&algorithm, payload, row_len * h as usize)?;
+            if id == CompressionAlgorithmType::CCITT_ID {
+                let bilevel = BilevelImage::from_packed(w, h, block)?;
+                block = bilevel.to_luma8().into_raw();
+            }
+            if block.len() != row_len * h as usize {
+                return Err(CompressionError::Decompression(format!("block at {},{} has the wrong size", x, y)));
+            }
+            for (row, pixels) in block.chunks(row_len).enumerate() {
+                let start = ((y as usize + row) * compressor.width as usize + x as usize) * channels;
+                output[start..start + row_len].copy_from_slice(pixels);
+            }
+        }
+        Ok(output)
+    }
+}
+
+/// Decodes one block's payload, stopping LZ codecs one byte past `expected` so an oversized block is
+/// rejected by its size check instead of being inflated in full.
+fn decode_block(algorithm: &CompressionAlgorithmType, payload: &[u8], expected: usize) -> Result<Vec<u8>, CompressionError> {
+    match algorithm {
+        CompressionAlgorithmType::Deflate(_) | CompressionAlgorithmType::Lzw(_) => {
+            let mut block = Vec::new();
+            algorithm
+                .decoder(Box::new(payload))?
+                .take(expected as u64 + 1)
+                .read_to_end(&mut block)
+                .map_err(|e| CompressionError::Decompression(e.to_string()))?;
+            Ok(block)
+        }
+        _ => algorithm.decompress(payload),
+    }
+}
+
+impl fmt::Display for BlockCompressor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "BlockCompressor ({}x{}x{}, Block Size: {})",
+            self.width, self.height, self.channels, self.block_size
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A screenshot-like image: flat UI on the left, a photo-like gradient with noise on the right.
+    fn mixed_content(width: u32, height: u32) -> Vec<u8> {
+        let mut pixels = Vec::new();
+        for y in 0..height {
+            for x in 0..width {
+                let value = if x < width / 2 {
+                    if (y / 8) % 2 == 0 && x % 12 < 9 { 0 } else { 255 }
+                } else {
+                    let noise = (x.wrapping_mul(2_654_435_761) ^ y.wrapping_mul(40_503)) % 5;
+                    ((x * 3 + y * 2) % 200 + noise) as u8
+                };
+                pixels.push(value);
+            }
+        }
+        pixels
+    }
+
+    #[test]
+    fn test_round_trip_and_mixed_choices() {
+        let pixels = mixed_content(130, 70);
+        let compressor = BlockCompressor::with_block_size(130, 70, 1, 32).unwrap();
+        let (compressed, map) = compressor.compress_with_map(&pixels).unwrap();
+        assert_eq!((map.blocks_x(), map.blocks_y()), (5, 3));
+        assert!(map.counts().len() >= 2, "{}", map);
+        assert_ne!(map.algorithm(0, 0), map.algorithm(4, 0), "{}", map);
+        assert_eq!(BlockCompressor::read_block_map(&compressed).unwrap(), map);
+        assert_eq!(compressor.decompress(&compressed).unwrap(), pixels);
+        assert!(compressed.len() * 2 < pixels.len());
+    }
+
+    #[test]
+    fn test_multi_channel_round_trip() {
+        let pixels: Vec<u8> = (0..37 * 23 * 3).map(|i| ((i * 7) % 256) as u8).collect();
+        let compressor = BlockCompressor::with_block_size(37, 23, 3, 16).unwrap();
+        let compressed = compressor.compress(&pixels).unwrap();
+        assert_eq!(compressor.decompress(&compressed).unwrap(), pixels);
+    }
+
+    #[test]
+    fn test_invalid_input() {
+        assert!(BlockCompressor::with_block_size(8, 8, 1, 0).is_err());
+        let compressor = BlockCompressor::new(8, 8, 1);
+        assert!(compressor.compress(&[0; 63]).is_err());
+        let compressed = compressor.compress(&[9; 64]).unwrap();
+        assert!(compressor.decompress(&compressed[..compressed.len() - 1]).is_err());
+        assert!(compressor.decompress(b"BLK").is_err());
+
+        // A huge image whose single block has no payload is rejected before allocating
+        let mut header = MAGIC.to_vec();
+        header.extend_from_slice(&u32::MAX.to_be_bytes());
+        header.extend_from_slice(&u32::MAX.to_be_bytes());
+        header.extend_from_slice(&[255, 0xFF, 0xFF, 0]);
+        assert!(compressor.decompress(&header).is_err());
+        header.extend_from_slice(&[0; 4]);
+        assert!(compressor.decompress(&header).is_err());
+
+        // File recompressors are not pixel codecs
+        let mut recompressed = compressed.clone();
+        recompressed[HEADER_LEN] = 5;
+        assert!(BlockCompressor::read_block_map(&recompressed).is_err());
+
+        // A Deflate block inflating far past its 64 pixels is rejected
+        let deflate_block = |pixels: &[u8]| {
+            let payload = CompressionAlgorithmType::for_block(0, 8, 8, 1).unwrap().compress(pixels).unwrap();
+            let mut data = compressed[..HEADER_LEN].to_vec();
+            data.push(0);
+            data.extend_from_slice(&u32::try_from(payload.len()).unwrap().to_be_bytes());
+            data.extend_from_slice(&payload);
+            data
+        };
+        assert!(compressor.decompress(&deflate_block(&vec![0; 1 << 20])).is_err());
+        assert!(compressor.decompress(&deflate_block(&[0; 65])).is_err());
+        assert_eq!(compressor.decompress(&deflate_block(&[0; 64])).unwrap(), vec![0; 64]);
+    }
+
+    #[test]
+    fn test_algorithm_names() {
+        assert_eq!(algorithm_name(6), "ccitt");
+        assert_eq!(algorithm_name(99), "unknown");
+        for id in CompressionAlgorithmType::IDS {
+            let algorithm = CompressionAlgorithmType::for_block(id, 1, 1, 1).unwrap();
+            assert_eq!(CompressionAlgorithmType::name_of(id), Some(algorithm.name()));
+            assert_eq!(CompressionAlgorithmType::id_of(algorithm.name()), Some(id));
+        }
+    }
+}
@@ -0,0 +1,759 @@
+// src/compression/ccitt.rs
+
+//! Module implementing CCITT T.4 (Group 3) and T.6 (Group 4) coding of bilevel images.
+//!
+//! Bilevel images are held as [`BilevelImage`], one bit per pixel packed
+//! MSB-first with `1` meaning black, which is also the layout TIFF uses with
+//! `WhiteIsZero` photometric interpretation. Grayscale images are converted
+//! with a fixed threshold or Otsu's method, see [`Binarization`].
+//!
+//! The codec produces bare fax streams as stored in TIFF files with
+//! compression 3 or 4:
+//!
+//! * Group 3 one-dimensional: every line is Modified Huffman coded and
+//!   preceded by an EOL code.
+//! * Group 3 two-dimensional: an EOL plus a tag bit precedes every line and
+//!   every fourth line is coded one-dimensionally, the others relative to the
+//!   line above (Modified READ).
+//! * Group 4: every line is coded relative to the line above without EOLs.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use image_compression::compression::ccitt::{BilevelImage, CcittCompressor, CcittMode};
+//!
+//! let mut page = BilevelImage::new(64, 16);
+//! for x in 10..50 {
+//!     page.set(x, 8, true);
+//! }
+//! let compressor = CcittCompressor::new(CcittMode::Group4, 64, 16);
+//! let encoded = compressor.encode(&page).unwrap();
+//! assert_eq!(compressor.decode(&encoded).unwrap(), page);
+//! ```
+
+use super::utils::checked_image_len;
+use super::{CompressionError, Compressor};
+use image::{DynamicImage, GrayImage, Luma};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::OnceLock;
+
+/// Lines between one-dimensionally coded lines in two-dimensional Group 3 streams.
+const GROUP3_K: usize = 4;
+const EOL: (u32, u8) = (1, 12);
+
+/// White run-length codes: terminating codes for 0-63 then make-up codes for 64-1728.
+const WHITE_CODES: [&str; 91] = [
+    "00110101", "000111", "0111", "1000", "1011", "1100", "1110", "1111", "10011", "10100", "00111", "01000",
+    "001000", "000011", "110100", "110101", "101010", "101011", "0100111", "0001100", "0001000", "0010111",
+    "0000011", "0000100", "0101000", "0101011", "0010011", "0100100", "0011000", "00000010", "00000011",
+    "00011010", "00011011", "00010010", "00010011", "00010100", "00010101", "00010110", "00010111", "00101000",
+    "00101001", "00101010", "00101011", "00101100", "00101101", "00000100", "00000101", "00001010", "00001011",
+    "01010010", "01010011", "01010100", "01010101", "00100100", "00100101", "01011000", "01011001", "01011010",
+    "01011011", "01001010", "01001011", "00110010", "00110011", "00110100",
+    // Make-up codes
+    "11011", "10010", "010111", "0110111", "00110110", "00110111", "01100100", "01100101", "01101000",
+    "01100111", "011001100", "011001101", "011010010", "011010011", "011010100", "011010101", "011010110",
+    "011010111", "011011000", "011011001", "011011010", "011011011", "010011000", "010011001", "010011010",
+    "011000", "010011011",
+];
+
+/// Black run-length codes, laid out like [`WHITE_CODES`].
+const BLACK_CODES: [&str; 91] = [
+    "0000110111", "010", "11", "10", "011", "0011", "0010", "00011", "000101", "000100", "0000100", "0000101",
+    "0000111", "00000100", "00000111", "000011000", "0000010111", "0000011000", "0000001000", "00001100111",
+    "00001101000", "00001101100", "00000110111", "00000101000", "00000010111", "00000011000", "000011001010",
+    "000011001011", "000011001100", "000011001101", "000001101000", "000001101001", "000001101010",
+    "000001101011", "000011010010", "000011010011", "000011010100", "000011010101", "000011010110",
+    "000011010111", "000001101100", "000001101101", "000011011010", "000011011011", "000001010100",
+    "000001010101", "000001010110", "000001010111", "000001100100", "000001100101", "000001010010",
+    "000001010011", "000000100100", "000000110111", "000000111000", "000000100111", "000000101000",
+    "000001011000", "000001011001", "000000101011", "000000101100", "000001011010", "000001100110",
+    "000001100111",
+    // Make-up codes
+    "0000001111", "000011001000", "000011001001", "000001011011", "000000110011", "000000110100",
+    "000000110101", "0000001101100", "0000001101101", "0000001001010", "0000001001011", "0000001001100",
+    "0000001001101", "0000001110010", "0000001110011", "0000001110100", "0000001110101", "0000001110110",
+    "0000001110111", "0000001010010", "0000001010011", "0000001010100", "0000001010101", "0000001011010",
+    "0000001011011", "0000001100100", "0000001100101",
+];
+
+/// Make-up codes for runs of 1792-2560 shared by both colors.
+const EXTENDED_MAKEUP_CODES: [&str; 13] = [
+    "00000001000", "00000001100", "00000001101", "000000010010", "000000010011", "000000010100",
+    "000000010101", "000000010110", "000000010111", "000000011100", "000000011101", "000000011110",
+    "000000011111",
+];
+
+/// How grayscale pixels are classified as black or white.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Binarization {
+    /// Pixels darker than the threshold are black.
+    Threshold(u8),
+    /// The threshold is chosen per image with Otsu's method.
+    Otsu,
+}
+
+impl Binarization {
+    /// Returns the threshold used for `image`; pixels below it become black.
+    pub fn threshold(self, image: &GrayImage) -> u8 {
+        match self {
+            Binarization::Threshold(threshold) => threshold,
+            Binarization::Otsu => otsu_threshold(image),
+        }
+    }
+}
+
+/// Computes the threshold maximizing the between-class variance of dark (`< t`) and light pixels.
+///
+/// Images with a single gray level yield 128.
+pub fn otsu_threshold(image: &GrayImage) -> u8 {
+    let mut histogram = [0u64; 256];
+    for pixel in image.pixels() {
+        histogram[pixel[0] as usize] += 1;
+    }
+    let total: u64 = histogram.iter().sum();
+    let sum: f64 = histogram.iter().enumerate().map(|(v, &n)| v as f64 * n as f64).sum();
+
+    let (mut best, mut best_variance) = (128u8, 0.0f64);
+    let (mut dark, mut dark_sum) = (0u64, 0.0f64);
+    for t in 1..256 {
+        dark += histogram[t - 1];
+        dark_sum += (t - 1) as f64 * histogram[t - 1] as f64;
+        let light = total - dark;
+        if dark == 0 || light == 0 {
+            continue;
+        }
+        let difference = dark_sum / dark as f64 - (sum - dark_sum) / light as f64;
+        let variance = dark as f64 * light as f64 * difference * difference;
+        if variance > best_variance {
+            best_variance = variance;
+            best = t as u8;
+        }
+    }
+    best
+}
+
+/// A 1-bit image packed MSB-first, one byte-aligned row after another; set bits are black.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BilevelImage {
+    width: u32,
+    height: u32,
+    data: Vec<u8>,
+}
+
+impl BilevelImage {
+    /// Creates an all-white image.
+    pub fn new(width: u32, height: u32) -> Self {
+        let row_bytes = (width as usize).div_ceil(8);
+        BilevelImage {
+            width,
+            height,
+            data: vec![0; row_bytes * height as usize],
+        }
+    }
+
+    /// Wraps packed rows of `ceil(width / 8)` bytes each.
+    pub fn from_packed(width: u32, height: u32, data: Vec<u8>) -> Result<Self, CompressionError> {
+        if data.len() != (width as usize).div_ceil(8) * height as usize {
+            return Err(CompressionError::Compression(format!(
+                "{} bytes do not hold a packed {}x{} bilevel image",
+                data.len(),
+                width,
+                height
+            )));
+        }
+        Ok(BilevelImage { width, height, data })
+    }
+
+    /// Binarizes an image after converting it to 8-bit grayscale.
+    ///
+    /// # Arguments
+    ///
+    /// * `image` - The image to convert.
+    /// * `binarization` - How the black/white threshold is chosen.
+    ///
+    /// # Returns
+    ///
+    /// The bilevel image with pixels below the threshold set to black.
+    pub fn from_image(image: &DynamicImage, binarization: Binarization) -> Self {
+        let gray = image.to_luma8();
+        let threshold = binarization.threshold(&gray);
+        let mut bilevel = BilevelImage::new(gray.width(), gray.height());
+        for (x, y, pixel) in gray.enumerate_pixels() {
+            if pixel[0] < threshold {
+                bilevel.set(x, y, true);
+            }
+        }
+        bilevel
+    }
+
+    /// Returns the image if every pixel is pure black or pure white, such as scanned documents.
+    pub fn from_exact(image: &DynamicImage) -> Option<Self> {
+        if image.color().has_color() || image.color().has_alpha() {
+            return None;
+        }
+        let gray = image.to_luma8();
+        gray.pixels()
+            .all(|p| p[0] == 0 || p[0] == 255)
+            .then(|| BilevelImage::from_image(image, Binarization::Threshold(128)))
+    }
+
+    /// Retrieves the width in pixels.
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Retrieves the height in pixels.
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Retrieves the number of bytes per packed row.
+    pub fn row_bytes(&self) -> usize {
+        (self.width as usize).div_ceil(8)
+    }
+
+    /// Retrieves the packed rows.
+    pub fn packed(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Returns whether the pixel at (`x`, `y`) is black.
+    pub fn get(&self, x: u32, y: u32) -> bool {
+        self.data[y as usize * self.row_bytes() + x as usize / 8] & (0x80 >> (x % 8)) != 0
+    }
+
+    /// Sets the pixel at (`x`, `y`) to black or white.
+    pub fn set(&mut self, x: u32, y: u32, black: bool) {
+        let index = y as usize * self.row_bytes() + x as usize / 8;
+        if black {
+            self.data[index] |= 0x80 >> (x % 8);
+        } else {
+            self.data[index] &= !(0x80 >> (x % 8));
+        }
+    }
+
+    /// Converts the image to 8-bit grayscale with black as 0 and white as 255.
+    pub fn to_luma8(&self) -> GrayImage {
+        GrayImage::from_fn(self.width, self.height, |x, y| Luma([if self.get(x, y) { 0 } else { 255 }]))
+    }
+
+    fn row(&self, y: u32) -> Vec<bool> {
+        (0..self.width).map(|x| self.get(x, y)).collect()
+    }
+
+    fn set_row(&mut self, y: u32, row: &[bool]) {
+        for (x, &black) in row.iter().enumerate() {
+            self.set(x as u32, y, black);
+        }
+    }
+}
+
+/// The CCITT coding scheme.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CcittMode {
+    /// T.4 Modified Huffman coding with EOLs.
+    Group3,
+    /// T.4 Modified READ coding with EOLs and a K factor of 4.
+    Group3TwoDimensional,
+    /// T.6 Modified Modified READ coding.
+    Group4,
+}
+
+impl CcittMode {
+    /// Returns the TIFF Compression tag value.
+    pub fn tiff_compression(self) -> u16 {
+        match self {
+            CcittMode::Group3 | CcittMode::Group3TwoDimensional => 3,
+            CcittMode::Group4 => 4,
+        }
+    }
+
+    /// Returns the value of the TIFF T4Options or T6Options tag.
+    pub fn tiff_options(self) -> u32 {
+        match self {
+            CcittMode::Group3TwoDimensional => 1,
+            _ => 0,
+        }
+    }
+}
+
+impl fmt::Display for CcittMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CcittMode::Group3 => write!(f, "Group 3"),
+            CcittMode::Group3TwoDimensional => write!(f, "Group 3 2D"),
+            CcittMode::Group4 => write!(f, "Group 4"),
+        }
+    }
+}
+
+/// A compressor for packed 1-bit images of a fixed size using CCITT fax coding.
+#[derive(Debug, Clone)]
+pub struct CcittCompressor {
+    mode: CcittMode,
+    width: u32,
+    height: u32,
+}
+
+impl CcittCompressor {
+    /// Creates a new `CcittCompressor` for `width` x `height` images.
+    ///
+    /// # Arguments
+    ///
+    /// * `mode` - The Group 3 or Group 4 coding scheme.
+    /// * `width`, `height` - The image dimensions, which the bare fax stream does not record.
+    ///
+    /// # Returns
+    ///
+    /// A new instance of `CcittCompressor`.
+    pub fn new(mode: CcittMode, width: u32, height: u32) -> Self {
+        CcittCompressor { mode, width, height }
+    }
+
+    /// Retrieves the coding scheme.
+    pub fn get_mode(&self) -> CcittMode {
+        self.mode
+    }
+
+    /// Encodes a bilevel image of the compressor's size.
+    pub fn encode(&self, image: &BilevelImage) -> Result<Vec<u8>, CompressionError> {
+        if (image.width, image.height) != (self.width, self.height) {
+            return Err(CompressionError::Compression(format!(
+                "expected a {}x{} image, got {}x{}",
+                self.width, self.height, image.width, image.height
+            )));
+        }
+        let mut writer = BitWriter::default();
+        let mut reference = vec![false; self.width as usize];
+        for y in 0..self.height {
+            let line = image.row(y);
+            match self.mode {
+                CcittMode::Group3 => {
+                    writer.write(EOL);
+                    encode_1d(&mut writer, &line);
+                }
+                CcittMode::Group3TwoDimensional => {
+                    writer.write(EOL);
+                    if (y as usize).is_multiple_of(GROUP3_K) {
+                        writer.write((1, 1));
+                        encode_1d(&mut writer, &line);
+                    } else {
+                        writer.write((0, 1));
+                        encode_2d(&mut writer, &line, &reference);
+                    }
+                }
+                CcittMode::Group4 => encode_2d(&mut writer, &line, &reference),
+            }
+            reference = line;
+        }
+        // Return to control (six EOLs) for Group 3, end of facsimile block (two EOLs) for Group 4.
+        match self.mode {
+            CcittMode::Group3 => (0..6).for_each(|_| writer.write(EOL)),
+            CcittMode::Group3TwoDimensional => (0..6).for_each(|_| {
+                writer.write(EOL);
+                writer.write((1, 1));
+            }),
+            CcittMode::Group4 => (0..2).for_each(|_| writer.write(EOL)),
+        }
+        Ok(writer.finish())
+    }
+
+    /// Decodes a fax stream into a bilevel image of the compressor's size.
+    ///
+    /// The size usually comes from an untrusted header, so images whose packed rows or single
+    /// line exceed [`MAX_DECODED_LEN`](super::utils::MAX_DECODED_LEN) bytes are rejected before
+    /// anything is allocated.
+    pub fn decode(&self, data: &[u8]) -> Result<BilevelImage, CompressionError> {
+        checked_image_len((self.width as usize).div_ceil(8), self.height as usize, 1)?;
+        checked_image_len(self.width as usize, 1, 1)?;
+        let mut reader = BitReader { data, position: 0 };
+        let mut image = BilevelImage::new(self.width, self.height);
+        let mut reference = vec![false; self.width as usize];
+        for y in 0..self.height {
+            let line = match self.mode {
+                CcittMode::Group3 => {
+                    reader.skip_eol()?;
+                    decode_1d(&mut reader, self.width as usize)?
+                }
+                CcittMode::Group3TwoDimensional => {
+                    reader.skip_eol()?;
+                    if reader.bit()? {
+                        decode_1d(&mut reader, self.width as usize)?
+                    } else {
+                        decode_2d(&mut reader, &reference)?
+                    }
+                }
+                CcittMode::Group4 => decode_2d(&mut reader, &reference)?,
+            };
+            image.set_row(y, &line);
+            reference = line;
+        }
+        Ok(image)
+    }
+}
+
+impl Compressor for CcittCompressor {
+    /// Compresses packed 1-bit rows, see [`BilevelImage::from_packed`].
+    fn compress(&self, data: &[u8]) -> Result<Vec<u8>, CompressionError> {
+        self.encode(&BilevelImage::from_packed(self.width, self.height, data.to_vec())?)
+    }
+
+    /// Decompresses a fax stream into packed 1-bit rows.
+    fn decompress(&self, data: &[u8]) -> Result<Vec<u8>, CompressionError> {
+        Ok(self.decode(data)?.data)
+    }
+}
+
+impl fmt::Display for CcittCompressor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "CcittCompressor ({}, {}x{})", self.mode, self.width, self.height)
+    }
+}
+
+/// Run-length code tables for one color.
+struct RunCodes {
+    /// Codes for runs 0-63 followed by make-up codes for 64, 128, ... 2560.
+    codes: Vec<(u32, u8)>,
+    /// Maps `(length, code)` to the run length it represents.
+    runs: HashMap<(u8, u32), u16>,
+}
+
+fn parse_code(bits: &str) -> (u32, u8) {
+    (u32::from_str_radix(bits, 2).unwrap(), bits.len() as u8)
+}
+
+fn run_codes(black: bool) -> &'static RunCodes {
+    static WHITE: OnceLock<RunCodes> = OnceLock::new();
+    static BLACK: OnceLock<RunCodes> = OnceLock::new();
+    let (cell, table) = if black { (&BLACK, &BLACK_CODES) } else { (&WHITE, &WHITE_CODES) };
+    cell.get_or_init(|| {
+        let codes: Vec<(u32, u8)> = table.iter().chain(EXTENDED_MAKEUP_CODES.iter()).map(|b| parse_code(b)).collect();
+        let runs = codes
+            .iter()
+            .enumerate()
+            .map(|(i, &(code, len))| ((len, code), if i < 64 { i as u16 } else { (i as u16 - 63) * 64 }))
+            .collect();
+        RunCodes { codes, runs }
+    })
+}
+
+fn write_run(writer: &mut BitWriter, mut run: usize, black: bool) {
+    let codes = &run_codes(black).codes;
+    while run >= 2560 + 64 {
+        writer.write(codes[63 + 2560 / 64]);
+        run -= 2560;
+    }
+    if run >= 64 {
+        writer.write(codes[63 + run / 64]);
+        run %= 64;
+    }
+    writer.write(codes[run]);
+}
+
+fn read_run(reader: &mut BitReader, black: bool) -> Result<usize, CompressionError> {
+    let runs = &run_codes(black).runs;
+    let mut total = 0;
+    loop {
+        let (mut code, mut len) = (0u32, 0u8);
+        let run = loop {
+            code = (code << 1) | reader.bit()? as u32;
+            len += 1;
+            if let Some(&run) = runs.get(&(len, code)) {
+                break run as usize;
+            }
+            if len >= 13 {
+                return Err(CompressionError::Decompression("invalid run-length code".to_string()));
+            }
+        };
+        total += run;
+        if run < 64 {
+            return Ok(total);
+        }
+    }
+}
+
+/// Returns the first changing element at or after `start` whose new color is `color`.
+///
+/// An imaginary white pixel precedes each line; `line.len()` is returned if there is none.
+fn next_change(line: &[bool], start: usize, color: bool) -> usize {
+    (start..line.len())
+        .find(|&i| line[i] == color && (if i == 0 { color } else { line[i - 1] != color }))
+        .unwrap_or(line.len())
+}
+
+fn encode_1d(writer: &mut BitWriter, line: &[bool]) {
+    let (mut position, mut color) = (0, false);
+    while position < line.len() {
+        let end = next_change(line, position, !color);
+        write_run(writer, end - position, color);
+        position = end;
+        color = !color;
+    }
+    if line.is_empty() {
+        write_run(writer, 0, false);
+    }
+}
+
+fn decode_1d(reader: &mut BitReader, width: usize) -> Result<Vec<bool>, CompressionError> {
+    let mut line = vec![false; width];
+    if width == 0 {
+        read_run(reader, false)?;
+        return Ok(line);
+    }
+    let (mut position, mut color) = (0, false);
+    while position < width {
+        let run = read_run(reader, color)?;
+        if position + run > width {
+            return Err(CompressionError::Decompression("run exceeds the line width".to_string()));
+        }
+        line[position..position + run].fill(color);
+        position += run;
+        color = !color;
+    }
+    Ok(line)
+}
+
+fn encode_2d(writer: &mut BitWriter, line: &[bool], reference: &[bool]) {
+    let width = line.len() as isize;
+    let (mut a0, mut color) = (-1isize, false);
+    while a0 < width {
+        let start = (a0 + 1) as usize;
+        let a1 = next_change(line, start, !color);
+        let b1 = next_change(reference, start, !color);
+        let b2 = next_change(reference, b1 + 1, color);
+        if b2 < a1 {
+            writer.write((1, 4));
+            a0 = b2 as isize;
+        } else if (a1 as isize - b1 as isize).abs() <= 3 {
+            writer.write(match a1 as isize - b1 as isize {
+                0 => (1, 1),
+                1 => (0b011, 3),
+                2 => (0b000011, 6),
+                3 => (0b0000011, 7),
+                -1 => (0b010, 3),
+                -2 => (0b000010, 6),
+                _ => (0b0000010, 7),
+            });
+            a0 = a1 as isize;
+            color = !color;
+        } else {
+            let a2 = next_change(line, a1 + 1, color);
+            writer.write((1, 3));
+            write_run(writer, a1 - a0.max(0) as usize, color);
+            write_run(writer, a2 - a1, !color);
+            a0 = a2 as isize;
+        }
+    }
+}
+
+fn decode_2d(reader: &mut BitReader, reference: &[bool]) -> Result<Vec<bool>, CompressionError> {
+    let invalid = |message: &str| CompressionError::Decompression(message.to_string());
+    let width = reference.len();
+    let mut line = vec![false; width];
+    let (mut a0, mut color) = (-1isize, false);
+    while a0 < width as isize {
+        let start = (a0 + 1) as usize;
+        let begin = a0.max(0) as usize;
+        let b1 = next_change(reference, start, !color);
+        let b2 = next_change(reference, b1 + 1, color);
+
+        let mut zeros = 0;
+        while !reader.bit()? {
+            zeros += 1;
+            if zeros > 6 {
+                return Err(invalid("unexpected EOL or extension code"));
+            }
+        }
+        let offset = match zeros {
+            0 => Some(0),
+            1 | 4 | 5 => {
+                let magnitude = [0, 1, 0, 0, 2, 3][zeros];
+                Some(if reader.bit()? { magnitude } else { -magnitude })
+            }
+            _ => None,
+        };
+        if let Some(offset) = offset {
+            let a1 = b1 as isize + offset;
+            if a1 < start as isize || a1 > width as isize {
+                return Err(invalid("vertical mode points outside the line"));
+            }
+            line[begin..a1 as usize].fill(color);
+            a0 = a1;
+            color = !color;
+        } else if zeros == 3 {
+            line[begin..b2].fill(color);
+            a0 = b2 as isize;
+        } else if zeros == 2 {
+            let a1 = begin + read_run(reader, color)?;
+            let a2 = a1 + read_run(reader, !color)?;
+            if a2 > width || a2 as isize <= a0 {
+                return Err(invalid("horizontal mode runs exceed the line width"));
+            }
+            line[begin..a1].fill(color);
+            line[a1..a2].fill(!color);
+            a0 = a2 as isize;
+        } else {
+            return Err(invalid("invalid mode code"));
+        }
+    }
+    Ok(line)
+}
+
+#[derive(Default)]
+struct BitWriter {
+    out: Vec<u8>,
+    buffer: u32,
+    bits: u8,
+}
+
+impl BitWriter {
+    fn write(&mut self, (code, len): (u32, u8)) {
+        for i in (0..len).rev() {
+            self.buffer = (self.buffer << 1) | ((code >> i) & 1);
+            self.bits += 1;
+            if self.bits == 8 {
+                self.out.push(self.buffer as u8);
+                self.buffer = 0;
+                self.bits = 0;
+            }
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.bits > 0 {
+            self.out.push((self.buffer << (8 - self.bits)) as u8);
+        }
+        self.out
+    }
+}
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl BitReader<'_> {
+    fn bit(&mut self) -> Result<bool, CompressionError> {
+        let byte = self
+            .data
+            .get(self.position / 8)
+            .ok_or_else(|| CompressionError::Decompression("unexpected end of fax data".to_string()))?;
+        let bit = byte & (0x80 >> (self.position % 8)) != 0;
+        self.position += 1;
+        Ok(bit)
+    }
+
+    /// Consumes an EOL code, including any fill bits before it.
+    fn skip_eol(&mut self) -> Result<(), CompressionError> {
+        let mut zeros = 0;
+        while !self.bit()? {
+            zeros += 1;
+        }
+        if zeros < 11 {
+            return Err(CompressionError::Decompression("missing EOL code".to_string()));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MODES: [CcittMode; 3] = [CcittMode::Group3, CcittMode::Group3TwoDimensional, CcittMode::Group4];
+
+    fn document(width: u32, height: u32) -> BilevelImage {
+        let mut page = BilevelImage::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                let text = (y / 6) % 3 == 1 && (x / 3 + y / 18) % 4 != 0 && x % 50 > 5;
+                let frame = x == 0 || y == 0 || x == width - 1;
+                let blob = (x as i32 - 40).pow(2) + (y as i32 - 30).pow(2) < 300;
+                page.set(x, y, text || frame || blob);
+            }
+        }
+        page
+    }
+
+    #[test]
+    fn test_code_tables_are_prefix_free() {
+        for black in [false, true] {
+            let mut codes: Vec<String> = run_codes(black)
+                .codes
+                .iter()
+                .map(|&(code, len)| format!("{:0width$b}", code, width = len as usize))
+                .collect();
+            codes.push("000000000001".to_string());
+            for (i, a) in codes.iter().enumerate() {
+                for (j, b) in codes.iter().enumerate() {
+                    assert!(i == j || !b.starts_with(a.as_str()), "{} is a prefix of {}", a, b);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_round_trip_all_modes() {
+        let long_runs = {
+            let mut page = BilevelImage::new(3000, 3);
+            (100..2900).for_each(|x| page.set(x, 1, true));
+            page
+        };
+        for page in [document(100, 70), document(13, 9), BilevelImage::new(1, 1), long_runs] {
+            for mode in MODES {
+                let compressor = CcittCompressor::new(mode, page.width(), page.height());
+                let encoded = compressor.compress(page.packed()).unwrap();
+                assert_eq!(compressor.decompress(&encoded).unwrap(), page.packed(), "{}", compressor);
+            }
+        }
+    }
+
+    #[test]
+    fn test_known_encodings() {
+        // A white line is V0 against the white reference line, followed by EOFB.
+        let compressor = CcittCompressor::new(CcittMode::Group4, 1728, 2);
+        let encoded = compressor.encode(&BilevelImage::new(1728, 2)).unwrap();
+        assert_eq!(encoded, [0b1100_0000, 0b0000_0100, 0b0000_0000, 0b0100_0000]);
+
+        // EOL, white run 1728 (make-up 010011011 + terminating 0 00110101), then RTC.
+        let compressor = CcittCompressor::new(CcittMode::Group3, 1728, 1);
+        let encoded = compressor.encode(&BilevelImage::new(1728, 1)).unwrap();
+        assert_eq!(&encoded[..4], [0x00, 0x14, 0xD9, 0xA8]);
+    }
+
+    #[test]
+    fn test_group4_compresses_documents() {
+        let page = document(400, 300);
+        let encoded = CcittCompressor::new(CcittMode::Group4, 400, 300).encode(&page).unwrap();
+        assert!(encoded.len() * 4 < page.packed().len(), "{} bytes", encoded.len());
+        assert!(CcittCompressor::new(CcittMode::Group4, 400, 300).decode(&encoded[..10]).is_err());
+    }
+
+    #[test]
+    fn test_hostile_sizes_are_errors() {
+        let stream = CcittCompressor::new(CcittMode::Group4, 8, 8).encode(&BilevelImage::new(8, 8)).unwrap();
+        for (width, height) in [(u32::MAX, u32::MAX), (u32::MAX, 0), (1 << 20, 1 << 20)] {
+            for mode in MODES {
+                assert!(CcittCompressor::new(mode, width, height).decompress(&stream).is_err());
+            }
+        }
+    }
+
+    #[test]
+    fn test_binarization() {
+        let gray = GrayImage::from_fn(20, 10, |x, _| Luma([if x < 8 { 30 + x as u8 } else { 200 + x as u8 }]));
+        let threshold = otsu_threshold(&gray);
+        assert!((38..=208).contains(&threshold), "{}", threshold);
+
+        let image = DynamicImage::ImageLuma8(gray);
+        let otsu = BilevelImage::from_image(&image, Binarization::Otsu);
+        assert!((0..10).all(|y| (0..20).all(|x| otsu.get(x, y) == (x < 8))));
+        let fixed = BilevelImage::from_image(&image, Binarization::Threshold(35));
+        assert!(fixed.get(4, 0) && !fixed.get(5, 0));
+
+        assert_eq!(BilevelImage::from_exact(&DynamicImage::ImageLuma8(otsu.to_luma8())), Some(otsu));
+        assert_eq!(BilevelImage::from_exact(&image), None);
+    }
+}
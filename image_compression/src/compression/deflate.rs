@@ -9,6 +9,7 @@
 //!
 //! ```rust
 //! use image_compression::compression::deflate::DeflateCompressor;
+//! use image_compression::compression::Compressor;
 //!
 //! let compressor = DeflateCompressor::new();
 //! let data = b"Example data to compress";
@@ -16,17 +17,38 @@
 //! let decompressed = compressor.decompress(&compressed).unwrap();
 //! assert_eq!(data.to_vec(), decompressed);
 //! ```
+//!
+//! With a preset [`Dictionary`] the output is a zlib stream (RFC 1950) whose
+//! header carries the dictionary ID, so it can also be inflated by zlib with
+//! `inflateSetDictionary`. Without one it is raw Deflate, as before.
 
+use super::dictionary::Dictionary;
+use super::stream::{Buffered, StreamEncoder, StreamingCompressor};
+use super::utils::adler32;
 use super::{Compressor, CompressionError};
 use flate2::{read::DeflateDecoder, write::DeflateEncoder, Compression as Flate2Compression};
 use std::fmt;
 use std::io::{Read, Write};
 
+/// zlib CMF byte: Deflate with a 32 KiB window.
+const ZLIB_CMF: u8 = 0x78;
+/// zlib FLG bit announcing a preset dictionary.
+const ZLIB_FDICT: u8 = 0x20;
+/// Length of a zlib header carrying a dictionary ID.
+const ZLIB_HEADER_LEN: usize = 6;
+
 /// Struct representing a Deflate compressor with configurable compression levels.
 #[derive(Debug, Clone)]
 pub struct DeflateCompressor {
     level: Flate2Compression,
     level_number: u32,
+    dictionary: Option<Dictionary>,
+}
+
+impl Default for DeflateCompressor {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl DeflateCompressor {
@@ -44,8 +66,9 @@ impl DeflateCompressor {
     pub fn new() -> Self {
         let default_level = Flate2Compression::fast();
         DeflateCompressor {
-            level: default_level.clone(),
+            level: default_level,
             level_number: default_level.level(),
+            dictionary: None,
         }
     }
 
@@ -65,8 +88,9 @@ impl DeflateCompressor {
     /// ```
     pub fn with_level(level: Flate2Compression) -> Self {
         DeflateCompressor {
-            level: level.clone(),
+            level,
             level_number: level.level(),
+            dictionary: None,
         }
     }
 
@@ -98,6 +122,7 @@ impl DeflateCompressor {
         Ok(DeflateCompressor {
             level: compression,
             level_number,
+            dictionary: None,
         })
     }
 
@@ -125,6 +150,7 @@ impl DeflateCompressor {
         Ok(DeflateCompressor {
             level: Flate2Compression::new(level),
             level_number: level,
+            dictionary: None,
         })
     }
 
@@ -136,6 +162,107 @@ impl DeflateCompressor {
     pub fn get_level(&self) -> u32 {
         self.level_number
     }
+
+    /// Sets a preset dictionary that both compression and decompression load first.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use image_compression::compression::deflate::DeflateCompressor;
+    /// use image_compression::compression::dictionary::Dictionary;
+    /// use image_compression::compression::Compressor;
+    ///
+    /// let dictionary = Dictionary::new(b"<svg xmlns=\"http://www.w3.org/2000/svg\">".to_vec()).unwrap();
+    /// let compressor = DeflateCompressor::new().with_dictionary(dictionary);
+    /// let data = b"<svg xmlns=\"http://www.w3.org/2000/svg\"></svg>";
+    /// let compressed = compressor.compress(data).unwrap();
+    /// assert_eq!(compressor.decompress(&compressed).unwrap(), data.to_vec());
+    /// ```
+    pub fn with_dictionary(mut self, dictionary: Dictionary) -> Self {
+        self.dictionary = Some(dictionary);
+        self
+    }
+
+    /// Retrieves the preset dictionary, if any.
+    pub fn get_dictionary(&self) -> Option<&Dictionary> {
+        self.dictionary.as_ref()
+    }
+
+    /// Compresses `data` into a zlib stream primed with `dictionary`.
+    ///
+    /// The encoder is fed the dictionary and sync-flushed, so the data that
+    /// follows may refer back into it; the flushed dictionary blocks are then
+    /// dropped, leaving only the blocks a decoder needs after loading it.
+    fn compress_with_dictionary(&self, dictionary: &Dictionary, data: &[u8]) -> Result<Vec<u8>, CompressionError> {
+        let error = |e: std::io::Error| CompressionError::Compression(e.to_string());
+        let mut encoder = DeflateEncoder::new(Vec::new(), self.level);
+        encoder.write_all(dictionary.as_bytes()).map_err(error)?;
+        encoder.flush().map_err(error)?;
+        let primed = encoder.get_ref().len();
+        encoder.write_all(data).map_err(error)?;
+        let deflated = encoder.finish().map_err(error)?;
+
+        let level_flag = match self.level_number {
+            0 | 1 => 0,
+            2..=5 => 1,
+            6 => 2,
+            _ => 3,
+        } << 6;
+        let mut flg = level_flag | ZLIB_FDICT;
+        flg += 31 - ((ZLIB_CMF as u16 * 256 + flg as u16) % 31) as u8;
+
+        let mut output = Vec::with_capacity(ZLIB_HEADER_LEN + deflated.len() - primed + 4);
+        output.extend_from_slice(&[ZLIB_CMF, flg]);
+        output.extend_from_slice(&dictionary.get_id().to_be_bytes());
+        output.extend_from_slice(&deflated[primed..]);
+        output.extend_from_slice(&adler32(data).to_be_bytes());
+        Ok(output)
+    }
+
+    /// Inflates a zlib stream primed with `dictionary`.
+    ///
+    /// The dictionary is replayed as stored blocks ahead of the compressed
+    /// blocks, which gives the decoder the same history the encoder had.
+    fn decompress_with_dictionary(&self, dictionary: &Dictionary, data: &[u8]) -> Result<Vec<u8>, CompressionError> {
+        let error = |message: String| CompressionError::Decompression(message);
+        if data.len() < ZLIB_HEADER_LEN + 4 {
+            return Err(error("zlib stream is truncated".to_string()));
+        }
+        let (cmf, flg) = (data[0], data[1]);
+        if cmf & 0x0F != 8 || !(cmf as u16 * 256 + flg as u16).is_multiple_of(31) {
+            return Err(error("Invalid zlib header".to_string()));
+        }
+        if flg & ZLIB_FDICT == 0 {
+            return Err(error("zlib stream was not compressed with a dictionary".to_string()));
+        }
+        let id = u32::from_be_bytes(data[2..6].try_into().unwrap());
+        if id != dictionary.get_id() {
+            return Err(error(format!(
+                "Data requires dictionary {:08x}, but dictionary {:08x} was given",
+                id,
+                dictionary.get_id()
+            )));
+        }
+        let (payload, trailer) = data[ZLIB_HEADER_LEN..].split_at(data.len() - ZLIB_HEADER_LEN - 4);
+
+        let mut primed = Vec::with_capacity(dictionary.as_bytes().len() + dictionary.as_bytes().len() / 65_535 * 5 + 5);
+        for block in dictionary.as_bytes().chunks(65_535) {
+            let len = block.len() as u16;
+            primed.push(0);
+            primed.extend_from_slice(&len.to_le_bytes());
+            primed.extend_from_slice(&(!len).to_le_bytes());
+            primed.extend_from_slice(block);
+        }
+        let mut decompressed = Vec::new();
+        DeflateDecoder::new(primed.as_slice().chain(payload))
+            .read_to_end(&mut decompressed)
+            .map_err(|e| error(e.to_string()))?;
+        let decompressed = decompressed.split_off(dictionary.as_bytes().len());
+        if adler32(&decompressed).to_be_bytes() != trailer {
+            return Err(error("zlib checksum mismatch".to_string()));
+        }
+        Ok(decompressed)
+    }
 }
 
 impl Compressor for DeflateCompressor {
@@ -156,12 +283,16 @@ impl Compressor for DeflateCompressor {
     ///
     /// ```rust
     /// use image_compression::compression::deflate::DeflateCompressor;
+    /// use image_compression::compression::Compressor;
     ///
     /// let compressor = DeflateCompressor::new();
     /// let data = b"Example data to compress";
     /// let compressed = compressor.compress(data).unwrap();
     /// ```
     fn compress(&self, data: &[u8]) -> Result<Vec<u8>, CompressionError> {
+        if let Some(dictionary) = &self.dictionary {
+            return self.compress_with_dictionary(dictionary, data);
+        }
         let mut encoder = DeflateEncoder::new(Vec::new(), self.level);
         encoder.write_all(data).map_err(|e| CompressionError::Compression(e.to_string()))?;
         encoder.finish().map_err(|e| CompressionError::Compression(e.to_string()))
@@ -184,6 +315,7 @@ impl Compressor for DeflateCompressor {
     ///
     /// ```rust
     /// use image_compression::compression::deflate::DeflateCompressor;
+    /// use image_compression::compression::Compressor;
     ///
     /// let compressor = DeflateCompressor::new();
     /// let data = b"Example data to compress";
@@ -192,6 +324,9 @@ impl Compressor for DeflateCompressor {
     /// assert_eq!(data.to_vec(), decompressed);
     /// ```
     fn decompress(&self, data: &[u8]) -> Result<Vec<u8>, CompressionError> {
+        if let Some(dictionary) = &self.dictionary {
+            return self.decompress_with_dictionary(dictionary, data);
+        }
         let mut decoder = DeflateDecoder::new(data);
         let mut decompressed = Vec::new();
         decoder.read_to_end(&mut decompressed)
@@ -200,6 +335,30 @@ impl Compressor for DeflateCompressor {
     }
 }
 
+/// Streams raw Deflate; with a dictionary, whole inputs are buffered.
+impl StreamingCompressor for DeflateCompressor {
+    fn encoder<'a>(&self, output: Box<dyn Write + 'a>) -> Result<Box<dyn StreamEncoder + 'a>, CompressionError> {
+        if self.dictionary.is_some() {
+            return Buffered(self.clone()).encoder(output);
+        }
+        Ok(Box::new(DeflateEncoder::new(output, self.level)))
+    }
+
+    fn decoder<'a>(&self, input: Box<dyn Read + 'a>) -> Result<Box<dyn Read + 'a>, CompressionError> {
+        if self.dictionary.is_some() {
+            return Buffered(self.clone()).decoder(input);
+        }
+        Ok(Box::new(DeflateDecoder::new(input)))
+    }
+}
+
+impl StreamEncoder for DeflateEncoder<Box<dyn Write + '_>> {
+    fn finish(self: Box<Self>) -> Result<(), CompressionError> {
+        let mut output = DeflateEncoder::finish(*self).map_err(|e| CompressionError::Compression(e.to_string()))?;
+        output.flush().map_err(|e| CompressionError::Compression(e.to_string()))
+    }
+}
+
 /// Implement `fmt::Display` for `DeflateCompressor` for better readability.
 impl fmt::Display for DeflateCompressor {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -207,14 +366,17 @@ impl fmt::Display for DeflateCompressor {
             f,
             "DeflateCompressor (Compression Level: {})",
             self.level_number
-        )
+        )?;
+        if let Some(dictionary) = &self.dictionary {
+            write!(f, " with dictionary {:08x}", dictionary.get_id())?;
+        }
+        Ok(())
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use flate2::Compression;
 
     #[test]
     fn test_deflate_compressor_default_level() {
@@ -273,4 +435,23 @@ mod tests {
             panic!("Expected InvalidLevel error");
         }
     }
+
+    #[test]
+    fn test_deflate_compressor_dictionary() {
+        let dictionary = Dictionary::new(b"0123456789abcdefghijklmnopqrstuvwxyz".repeat(3)).unwrap();
+        let compressor = DeflateCompressor::with_level_number(9).unwrap().with_dictionary(dictionary.clone());
+        let data = b"abcdefghijklmnopqrstuvwxyz0123456789";
+        let compressed = compressor.compress(data).unwrap();
+        assert!(compressed.len() < DeflateCompressor::with_level_number(9).unwrap().compress(data).unwrap().len());
+        assert_eq!(compressed[0], 0x78);
+        assert_eq!((compressed[0] as u16 * 256 + compressed[1] as u16) % 31, 0);
+        assert_eq!(&compressed[2..6], &dictionary.get_id().to_be_bytes());
+        assert_eq!(compressor.decompress(&compressed).unwrap(), data.to_vec());
+        assert_eq!(crate::compression::stream::decompress_to_vec(&compressor, &compressed).unwrap(), data.to_vec());
+
+        let other = DeflateCompressor::new().with_dictionary(Dictionary::new(b"other".to_vec()).unwrap());
+        let error = other.decompress(&compressed).unwrap_err().to_string();
+        assert!(error.contains(&format!("{:08x}", dictionary.get_id())), "{}", error);
+        assert!(compressor.decompress(&DeflateCompressor::new().compress(data).unwrap()).is_err());
+    }
 }
@@ -0,0 +1,211 @@
+// src/compression/dictionary.rs
+
+//! Module implementing shared preset dictionaries.
+//!
+//! Small inputs compress poorly because an LZ coder starts with nothing to
+//! refer back to. A preset [`Dictionary`] holds content typical of a
+//! corpus, which both sides load before the data, so even the first bytes
+//! can be coded as matches. [`train`] builds one from sample inputs.
+//!
+//! A dictionary is identified by the Adler-32 checksum of its bytes, as in
+//! zlib. Compressors store that ID in their output, and decompression fails
+//! with a clear error when the configured dictionary has a different ID.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use image_compression::compression::dictionary::train;
+//!
+//! let samples: Vec<Vec<u8>> = (0..20u8)
+//!     .map(|i| [b"<svg width=\"16\" height=\"16\">".as_slice(), &[i; 12], b"</svg>"].concat())
+//!     .collect();
+//! let dictionary = train(&samples, 256).unwrap();
+//! assert!(dictionary.as_bytes().windows(6).any(|w| w == b"</svg>"));
+//! ```
+
+use super::utils::adler32;
+use super::CompressionError;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Length of the substrings whose recurrence across samples scores candidate segments.
+const K: usize = 8;
+/// Length of the candidate segments the dictionary is assembled from.
+const SEGMENT: usize = 64;
+
+/// A preset dictionary shared by compressor and decompressor.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Dictionary {
+    data: Arc<[u8]>,
+    id: u32,
+}
+
+impl Dictionary {
+    /// Creates a new `Dictionary` from its content.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the dictionary or `CompressionError::InvalidLevel` if `data` is empty.
+    pub fn new(data: Vec<u8>) -> Result<Self, CompressionError> {
+        if data.is_empty() {
+            return Err(CompressionError::InvalidLevel("A dictionary cannot be empty".to_string()));
+        }
+        Ok(Dictionary {
+            id: adler32(&data),
+            data: data.into(),
+        })
+    }
+
+    /// Retrieves the dictionary ID, the Adler-32 checksum of its content.
+    pub fn get_id(&self) -> u32 {
+        self.id
+    }
+
+    /// Returns the dictionary content.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Reads a dictionary stored as raw bytes, as zlib tools expect.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        Self::new(fs::read(path)?).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))
+    }
+
+    /// Writes the dictionary content to `path`.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, &self.data)
+    }
+}
+
+impl fmt::Debug for Dictionary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Dictionary {{ id: {:08x}, len: {} }}", self.id, self.data.len())
+    }
+}
+
+impl fmt::Display for Dictionary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Dictionary (ID: {:08x}, Size: {} bytes)", self.id, self.data.len())
+    }
+}
+
+/// Trains a dictionary of at most `size` bytes on sample inputs.
+///
+/// Every sample is cut into overlapping 64-byte segments, and each segment
+/// is scored by how many samples share each of its 8-byte substrings.
+/// Segments are picked greedily, each scored only on substrings no earlier
+/// pick covers, so the dictionary holds many different common strings
+/// rather than one string many times. The best segment is placed last,
+/// where matches against it are cheapest to code.
+///
+/// # Arguments
+///
+/// * `samples` - Representative inputs, such as the raw pixels of a set of icons.
+/// * `size` - Maximum dictionary size; Deflate only uses the last 32 KiB.
+///
+/// # Returns
+///
+/// A `Result` containing the dictionary, or a `CompressionError` if `size` is zero or the
+/// samples share no content.
+pub fn train<S: AsRef<[u8]>>(samples: &[S], size: usize) -> Result<Dictionary, CompressionError> {
+    if size == 0 {
+        return Err(CompressionError::InvalidLevel("Dictionary size must be positive".to_string()));
+    }
+    let kmers = |data: &[u8]| -> HashSet<u64> {
+        data.windows(K).map(|w| u64::from_le_bytes(w.try_into().unwrap())).collect()
+    };
+
+    // Number of samples each substring occurs in.
+    let mut frequency: HashMap<u64, u32> = HashMap::new();
+    for sample in samples {
+        for kmer in kmers(sample.as_ref()) {
+            *frequency.entry(kmer).or_default() += 1;
+        }
+    }
+
+    let mut segments: Vec<&[u8]> = Vec::new();
+    for sample in samples {
+        let sample = sample.as_ref();
+        if sample.len() <= SEGMENT {
+            segments.push(sample);
+            continue;
+        }
+        let mut start = 0;
+        while start + SEGMENT < sample.len() {
+            segments.push(&sample[start..start + SEGMENT]);
+            start += SEGMENT / 2;
+        }
+        segments.push(&sample[sample.len() - SEGMENT..]);
+    }
+
+    let mut covered: HashSet<u64> = HashSet::new();
+    let score = |segment: &[u8], covered: &HashSet<u64>| -> u64 {
+        kmers(segment)
+            .into_iter()
+            .filter(|kmer| !covered.contains(kmer))
+            .map(|kmer| frequency[&kmer] as u64)
+            .filter(|&count| count > 1)
+            .sum()
+    };
+
+    // Lazy greedy selection: a segment's score only drops as coverage grows,
+    // so a popped segment whose rescored value still tops the heap is the best.
+    let mut heap: BinaryHeap<(u64, Reverse<usize>)> =
+        segments.iter().enumerate().map(|(i, segment)| (score(segment, &covered), Reverse(i))).collect();
+    let mut chosen: Vec<&[u8]> = Vec::new();
+    let mut total = 0;
+    while let Some((stale, Reverse(i))) = heap.pop() {
+        if stale == 0 || total >= size {
+            break;
+        }
+        let current = score(segments[i], &covered);
+        if heap.peek().is_some_and(|&(next, _)| current < next) {
+            heap.push((current, Reverse(i)));
+            continue;
+        }
+        if current == 0 {
+            break;
+        }
+        covered.extend(kmers(segments[i]));
+        chosen.push(segments[i]);
+        total += segments[i].len();
+    }
+    if chosen.is_empty() {
+        return Err(CompressionError::Compression("The samples share no content to train on".to_string()));
+    }
+
+    let mut data: Vec<u8> = chosen.iter().rev().flat_map(|segment| segment.iter().copied()).collect();
+    if data.len() > size {
+        data.drain(..data.len() - size);
+    }
+    Dictionary::new(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_train_prefers_shared_content() {
+        let header: Vec<u8> = (0..48u8).map(|i| i.wrapping_mul(37)).collect();
+        let samples: Vec<Vec<u8>> = (0..30u32)
+            .map(|i| {
+                let unique: Vec<u8> = (0..200u32).map(|j| ((i * 7919 + j * 104_729) % 251) as u8).collect();
+                [header.as_slice(), &unique].concat()
+            })
+            .collect();
+        let dictionary = train(&samples, 128).unwrap();
+        assert!(dictionary.as_bytes().len() <= 128);
+        assert!(dictionary.as_bytes().windows(header.len()).any(|w| w == header.as_slice()));
+        assert_eq!(dictionary.get_id(), adler32(dictionary.as_bytes()));
+
+        assert!(train(&samples, 0).is_err());
+        assert!(train(&[b"only one sample".to_vec()], 64).is_err());
+        assert!(Dictionary::new(Vec::new()).is_err());
+    }
+}
@@ -0,0 +1,403 @@
+// src/compression/entropy.rs
+
+//! Module implementing the adaptive binary range coder shared by the image codecs.
+//!
+//! The coder follows the classic LZMA design: every binary decision is coded
+//! against an 11-bit adaptive probability stored in a [`BitModel`]. Integers
+//! are binarised with an Elias-gamma style prefix whose bits are themselves
+//! context modelled, see [`UIntModel`] and [`IntModel`].
+//!
+//! Reading past the end of the input yields zero bytes instead of failing, so
+//! a decoder can be run over a truncated prefix of an embedded stream.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use image_compression::compression::entropy::{RangeDecoder, RangeEncoder, UIntModel};
+//!
+//! let mut model = UIntModel::default();
+//! let mut encoder = RangeEncoder::new();
+//! for value in [0, 3, 1000] {
+//!     model.encode(&mut encoder, value);
+//! }
+//! let bytes = encoder.finish();
+//!
+//! let mut model = UIntModel::default();
+//! let mut decoder = RangeDecoder::new(&bytes);
+//! assert_eq!(model.decode(&mut decoder), 0);
+//! assert_eq!(model.decode(&mut decoder), 3);
+//! assert_eq!(model.decode(&mut decoder), 1000);
+//! ```
+
+const PROB_BITS: u32 = 11;
+const PROB_ONE: u32 = 1 << PROB_BITS;
+const MOVE_BITS: u32 = 5;
+const TOP: u32 = 1 << 24;
+
+/// Number of mantissa bits below the leading one that get their own adaptive model.
+const MODELLED_MANTISSA_BITS: usize = 2;
+
+/// An adaptive probability for a single binary decision.
+#[derive(Debug, Clone, Copy)]
+pub struct BitModel(u16);
+
+impl Default for BitModel {
+    fn default() -> Self {
+        BitModel((PROB_ONE / 2) as u16)
+    }
+}
+
+impl BitModel {
+    fn update(&mut self, bit: bool) {
+        if bit {
+            self.0 -= self.0 >> MOVE_BITS;
+        } else {
+            self.0 += ((PROB_ONE - self.0 as u32) >> MOVE_BITS) as u16;
+        }
+    }
+}
+
+/// Encoder half of the range coder.
+#[derive(Debug)]
+pub struct RangeEncoder {
+    low: u64,
+    range: u32,
+    cache: u8,
+    cache_size: u64,
+    out: Vec<u8>,
+}
+
+impl Default for RangeEncoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RangeEncoder {
+    /// Creates an encoder writing into an empty buffer.
+    pub fn new() -> Self {
+        RangeEncoder {
+            low: 0,
+            range: u32::MAX,
+            cache: 0,
+            cache_size: 1,
+            out: Vec::new(),
+        }
+    }
+
+    /// Encodes one binary decision and adapts `model` towards it.
+    pub fn encode_bit(&mut self, model: &mut BitModel, bit: bool) {
+        let bound = (self.range >> PROB_BITS) * model.0 as u32;
+        if bit {
+            self.low += bound as u64;
+            self.range -= bound;
+        } else {
+            self.range = bound;
+        }
+        model.update(bit);
+        self.normalize();
+    }
+
+    /// Encodes the low `bits` bits of `value` with a fixed 1/2 probability, most significant first.
+    pub fn encode_direct(&mut self, value: u32, bits: u32) {
+        for i in (0..bits).rev() {
+            self.range >>= 1;
+            if (value >> i) & 1 == 1 {
+                self.low += self.range as u64;
+            }
+            self.normalize();
+        }
+    }
+
+    /// Flushes the pending state and returns the coded bytes.
+    pub fn finish(mut self) -> Vec<u8> {
+        for _ in 0..5 {
+            self.shift_low();
+        }
+        self.out
+    }
+
+    fn normalize(&mut self) {
+        while self.range < TOP {
+            self.range <<= 8;
+            self.shift_low();
+        }
+    }
+
+    fn shift_low(&mut self) {
+        if (self.low as u32) < 0xFF00_0000 || (self.low >> 32) != 0 {
+            let carry = (self.low >> 32) as u8;
+            let mut temp = self.cache;
+            loop {
+                self.out.push(temp.wrapping_add(carry));
+                temp = 0xFF;
+                self.cache_size -= 1;
+                if self.cache_size == 0 {
+                    break;
+                }
+            }
+            self.cache = (self.low >> 24) as u8;
+        }
+        self.cache_size += 1;
+        self.low = (self.low & 0x00FF_FFFF) << 8;
+    }
+}
+
+/// Decoder half of the range coder.
+#[derive(Debug)]
+pub struct RangeDecoder<'a> {
+    data: &'a [u8],
+    pos: usize,
+    range: u32,
+    code: u32,
+}
+
+impl<'a> RangeDecoder<'a> {
+    /// Creates a decoder over `data`, which may be a truncated stream.
+    pub fn new(data: &'a [u8]) -> Self {
+        let mut decoder = RangeDecoder {
+            data,
+            pos: 0,
+            range: u32::MAX,
+            code: 0,
+        };
+        for _ in 0..5 {
+            decoder.code = (decoder.code << 8) | decoder.next_byte() as u32;
+        }
+        decoder
+    }
+
+    /// Decodes one binary decision and adapts `model` towards it.
+    pub fn decode_bit(&mut self, model: &mut BitModel) -> bool {
+        let bound = (self.range >> PROB_BITS) * model.0 as u32;
+        let bit = if self.code < bound {
+            self.range = bound;
+            false
+        } else {
+            self.code -= bound;
+            self.range -= bound;
+            true
+        };
+        model.update(bit);
+        self.normalize();
+        bit
+    }
+
+    /// Decodes `bits` bits written by [`RangeEncoder::encode_direct`].
+    pub fn decode_direct(&mut self, bits: u32) -> u32 {
+        let mut value = 0;
+        for _ in 0..bits {
+            self.range >>= 1;
+            let bit = if self.code >= self.range {
+                self.code -= self.range;
+                1
+            } else {
+                0
+            };
+            value = (value << 1) | bit;
+            self.normalize();
+        }
+        value
+    }
+
+    /// Returns `true` once the decoder has consumed more bytes than the input holds.
+    pub fn is_exhausted(&self) -> bool {
+        self.pos > self.data.len()
+    }
+
+    fn normalize(&mut self) {
+        while self.range < TOP {
+            self.range <<= 8;
+            self.code = (self.code << 8) | self.next_byte() as u32;
+        }
+    }
+
+    fn next_byte(&mut self) -> u8 {
+        let byte = self.data.get(self.pos).copied().unwrap_or(0);
+        self.pos += 1;
+        byte
+    }
+}
+
+/// Adaptive model for unsigned 32-bit integers.
+///
+/// A value `v` is coded as the bit length of `v + 1` in unary followed by the
+/// remaining bits; the prefix and the top mantissa bits are context modelled.
+#[derive(Debug, Clone)]
+pub struct UIntModel {
+    prefix: [BitModel; 33],
+    mantissa: [[BitModel; 1 << MODELLED_MANTISSA_BITS]; 33],
+}
+
+impl Default for UIntModel {
+    fn default() -> Self {
+        UIntModel {
+            prefix: [BitModel::default(); 33],
+            mantissa: [[BitModel::default(); 1 << MODELLED_MANTISSA_BITS]; 33],
+        }
+    }
+}
+
+impl UIntModel {
+    /// Encodes `value` with this model.
+    pub fn encode(&mut self, encoder: &mut RangeEncoder, value: u32) {
+        let n = value as u64 + 1;
+        let k = 63 - n.leading_zeros() as usize;
+        for i in 0..k {
+            encoder.encode_bit(&mut self.prefix[i], true);
+        }
+        if k < 32 {
+            encoder.encode_bit(&mut self.prefix[k], false);
+        }
+        let modelled = k.min(MODELLED_MANTISSA_BITS);
+        let mut node = 1;
+        for i in 0..modelled {
+            let bit = (n >> (k - 1 - i)) & 1 == 1;
+            encoder.encode_bit(&mut self.mantissa[k][node - 1], bit);
+            node = (node << 1) | bit as usize;
+        }
+        let rest = (k - modelled) as u32;
+        encoder.encode_direct((n & ((1u64 << rest) - 1)) as u32, rest);
+    }
+
+    /// Decodes a value written by [`UIntModel::encode`].
+    pub fn decode(&mut self, decoder: &mut RangeDecoder<'_>) -> u32 {
+        let mut k = 0;
+        while k < 32 && decoder.decode_bit(&mut self.prefix[k]) {
+            k += 1;
+        }
+        let modelled = k.min(MODELLED_MANTISSA_BITS);
+        let mut node = 1usize;
+        for _ in 0..modelled {
+            let bit = decoder.decode_bit(&mut self.mantissa[k][node - 1]);
+            node = (node << 1) | bit as usize;
+        }
+        let rest = (k - modelled) as u32;
+        let n = ((node as u64) << rest) | decoder.decode_direct(rest) as u64;
+        (n - 1) as u32
+    }
+}
+
+/// Adaptive model for signed integers: a [`UIntModel`] magnitude plus a sign bit.
+#[derive(Debug, Clone, Default)]
+pub struct IntModel {
+    magnitude: UIntModel,
+    sign: BitModel,
+}
+
+impl IntModel {
+    /// Encodes `value` with this model.
+    pub fn encode(&mut self, encoder: &mut RangeEncoder, value: i32) {
+        self.magnitude.encode(encoder, value.unsigned_abs());
+        if value != 0 {
+            encoder.encode_bit(&mut self.sign, value < 0);
+        }
+    }
+
+    /// Decodes a value written by [`IntModel::encode`].
+    pub fn decode(&mut self, decoder: &mut RangeDecoder<'_>) -> i32 {
+        let magnitude = self.magnitude.decode(decoder);
+        if magnitude != 0 && decoder.decode_bit(&mut self.sign) {
+            (magnitude as i32).wrapping_neg()
+        } else {
+            magnitude as i32
+        }
+    }
+}
+
+/// Either side of the range coder, so one model traversal serves both directions.
+///
+/// When encoding, each `code_*` call writes the value it is given; when
+/// decoding, it overwrites the value with the decoded one.
+pub(crate) enum Coder<'a, 'b> {
+    Encode(&'a mut RangeEncoder),
+    Decode(&'a mut RangeDecoder<'b>),
+}
+
+impl Coder<'_, '_> {
+    pub(crate) fn code_bit(&mut self, model: &mut BitModel, value: &mut bool) {
+        match self {
+            Coder::Encode(encoder) => encoder.encode_bit(model, *value),
+            Coder::Decode(decoder) => *value = decoder.decode_bit(model),
+        }
+    }
+
+    /// Codes one bit of an embedded stream, returning `None` once a decoder runs out of real
+    /// input, so a truncated stream stops where it ends rather than decoding padding.
+    pub(crate) fn code_embedded_bit(&mut self, model: &mut BitModel, value: bool) -> Option<bool> {
+        match self {
+            Coder::Encode(encoder) => {
+                encoder.encode_bit(model, value);
+                Some(value)
+            }
+            Coder::Decode(decoder) if decoder.is_exhausted() => None,
+            Coder::Decode(decoder) => Some(decoder.decode_bit(model)),
+        }
+    }
+
+    /// Returns `true` once a decoder has consumed more bytes than its input holds.
+    pub(crate) fn is_exhausted(&self) -> bool {
+        matches!(self, Coder::Decode(decoder) if decoder.is_exhausted())
+    }
+
+    pub(crate) fn code_uint(&mut self, model: &mut UIntModel, value: &mut u32) {
+        match self {
+            Coder::Encode(encoder) => model.encode(encoder, *value),
+            Coder::Decode(decoder) => *value = model.decode(decoder),
+        }
+    }
+
+    pub(crate) fn code_int(&mut self, model: &mut IntModel, value: &mut i32) {
+        match self {
+            Coder::Encode(encoder) => model.encode(encoder, *value),
+            Coder::Decode(decoder) => *value = model.decode(decoder),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bits_round_trip() {
+        let bits: Vec<bool> = (0..5000).map(|i| i % 7 == 0 || i % 13 == 5).collect();
+        let mut model = BitModel::default();
+        let mut encoder = RangeEncoder::new();
+        for &bit in &bits {
+            encoder.encode_bit(&mut model, bit);
+        }
+        encoder.encode_direct(0xABCDE, 20);
+        let bytes = encoder.finish();
+        assert!(bytes.len() < bits.len() / 8);
+
+        let mut model = BitModel::default();
+        let mut decoder = RangeDecoder::new(&bytes);
+        for &bit in &bits {
+            assert_eq!(decoder.decode_bit(&mut model), bit);
+        }
+        assert_eq!(decoder.decode_direct(20), 0xABCDE);
+    }
+
+    #[test]
+    fn test_integers_round_trip() {
+        let values = [0i32, 1, -1, 2, 255, -256, 65_537, i32::MAX, i32::MIN + 1, i32::MIN];
+        let mut model = IntModel::default();
+        let mut unsigned = UIntModel::default();
+        let mut encoder = RangeEncoder::new();
+        for &v in &values {
+            model.encode(&mut encoder, v);
+            unsigned.encode(&mut encoder, v as u32);
+        }
+        let bytes = encoder.finish();
+
+        let mut model = IntModel::default();
+        let mut unsigned = UIntModel::default();
+        let mut decoder = RangeDecoder::new(&bytes);
+        for &v in &values {
+            assert_eq!(model.decode(&mut decoder), v);
+            assert_eq!(unsigned.decode(&mut decoder), v as u32);
+        }
+        assert!(!decoder.is_exhausted());
+    }
+}
@@ -0,0 +1,495 @@
+// src/compression/gif.rs
+
+//! Module implementing a GIF89a encoder on top of [`LzwCompressor`].
+//!
+//! Truecolor frames are reduced to at most 256 colors: frames that already
+//! use few enough colors keep them exactly, others are quantized with median
+//! cut. Pixels whose alpha is below one half become transparent. Every frame
+//! carries its own color table, delay and disposal method, and can optionally
+//! be interlaced.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use image_compression::compression::gif::{GifEncoder, GifFrame};
+//!
+//! let red = GifFrame::new(4, 4, [255, 0, 0, 255].repeat(16)).unwrap().with_delay(50);
+//! let blue = GifFrame::new(4, 4, [0, 0, 255, 255].repeat(16)).unwrap().with_delay(50);
+//! let gif = GifEncoder::new().encode(4, 4, &[red, blue]).unwrap();
+//! assert!(gif.starts_with(b"GIF89a"));
+//! ```
+
+use super::lzw::LzwCompressor;
+use super::CompressionError;
+use image::DynamicImage;
+use std::collections::HashMap;
+use std::fmt;
+
+/// What happens to a frame's area before the next frame is drawn.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DisposalMethod {
+    /// No disposal specified; decoders leave the frame in place.
+    Unspecified,
+    /// Leave the frame in place.
+    Keep,
+    /// Clear the frame's area to the background (transparent).
+    Background,
+    /// Restore what was there before the frame was drawn.
+    Previous,
+}
+
+impl DisposalMethod {
+    fn code(self) -> u8 {
+        match self {
+            DisposalMethod::Unspecified => 0,
+            DisposalMethod::Keep => 1,
+            DisposalMethod::Background => 2,
+            DisposalMethod::Previous => 3,
+        }
+    }
+}
+
+/// One frame of a GIF: RGBA pixels placed at an offset on the logical screen.
+#[derive(Debug, Clone)]
+pub struct GifFrame {
+    width: u16,
+    height: u16,
+    left: u16,
+    top: u16,
+    rgba: Vec<u8>,
+    delay: u16,
+    disposal: DisposalMethod,
+}
+
+impl GifFrame {
+    /// Creates a frame at the top left corner with no delay.
+    ///
+    /// # Arguments
+    ///
+    /// * `width`, `height` - Frame dimensions in pixels.
+    /// * `rgba` - `width * height` interleaved RGBA pixels.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the `GifFrame` or a `CompressionError` if the pixel count is wrong.
+    pub fn new(width: u16, height: u16, rgba: Vec<u8>) -> Result<Self, CompressionError> {
+        if width == 0 || height == 0 || rgba.len() != width as usize * height as usize * 4 {
+            return Err(CompressionError::Compression(format!(
+                "Expected {}x{} RGBA pixels, got {} bytes",
+                width,
+                height,
+                rgba.len()
+            )));
+        }
+        Ok(GifFrame {
+            width,
+            height,
+            left: 0,
+            top: 0,
+            rgba,
+            delay: 0,
+            disposal: DisposalMethod::Unspecified,
+        })
+    }
+
+    /// Creates a frame from any image, converting it to RGBA.
+    pub fn from_image(image: &DynamicImage) -> Result<Self, CompressionError> {
+        let (width, height) = (image.width(), image.height());
+        if width > u16::MAX as u32 || height > u16::MAX as u32 {
+            return Err(CompressionError::Compression(format!("{}x{} is too large for GIF", width, height)));
+        }
+        GifFrame::new(width as u16, height as u16, image.to_rgba8().into_raw())
+    }
+
+    /// Returns the frame placed at `(left, top)` on the logical screen.
+    pub fn with_offset(mut self, left: u16, top: u16) -> Self {
+        self.left = left;
+        self.top = top;
+        self
+    }
+
+    /// Returns the frame with a display time of `delay` hundredths of a second.
+    pub fn with_delay(mut self, delay: u16) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    /// Returns the frame with a different disposal method.
+    pub fn with_disposal(mut self, disposal: DisposalMethod) -> Self {
+        self.disposal = disposal;
+        self
+    }
+}
+
+/// A GIF encoder with configurable palette size, interlacing and looping.
+#[derive(Debug, Clone)]
+pub struct GifEncoder {
+    max_colors: usize,
+    interlaced: bool,
+    loop_count: Option<u16>,
+}
+
+impl Default for GifEncoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl GifEncoder {
+    /// Creates a new `GifEncoder` with 256 colors, no interlacing and endless looping.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use image_compression::compression::gif::GifEncoder;
+    ///
+    /// let encoder = GifEncoder::new();
+    /// assert_eq!(encoder.get_max_colors(), 256);
+    /// ```
+    pub fn new() -> Self {
+        GifEncoder {
+            max_colors: 256,
+            interlaced: false,
+            loop_count: Some(0),
+        }
+    }
+
+    /// Returns the encoder limited to `max_colors` palette entries per frame.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_colors` - Palette size from 2 to 256, including the transparent entry.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the `GifEncoder` or a `CompressionError` if the size is out of range.
+    pub fn with_max_colors(mut self, max_colors: usize) -> Result<Self, CompressionError> {
+        if !(2..=256).contains(&max_colors) {
+            return Err(CompressionError::InvalidLevel(format!("GIF palette size {}", max_colors)));
+        }
+        self.max_colors = max_colors;
+        Ok(self)
+    }
+
+    /// Returns the encoder writing interlaced frames.
+    pub fn with_interlacing(mut self, interlaced: bool) -> Self {
+        self.interlaced = interlaced;
+        self
+    }
+
+    /// Returns the encoder with a loop count for animations (`Some(0)` loops forever, `None` plays once).
+    pub fn with_loop_count(mut self, loop_count: Option<u16>) -> Self {
+        self.loop_count = loop_count;
+        self
+    }
+
+    /// Retrieves the maximum palette size.
+    pub fn get_max_colors(&self) -> usize {
+        self.max_colors
+    }
+
+    /// Retrieves whether frames are interlaced.
+    pub fn get_interlacing(&self) -> bool {
+        self.interlaced
+    }
+
+    /// Encodes a single image as a still GIF.
+    pub fn encode_image(&self, image: &DynamicImage) -> Result<Vec<u8>, CompressionError> {
+        let frame = GifFrame::from_image(image)?;
+        self.encode(frame.width, frame.height, &[frame])
+    }
+
+    /// Encodes frames on a logical screen of `width` x `height` pixels.
+    ///
+    /// # Arguments
+    ///
+    /// * `width`, `height` - Size of the logical screen.
+    /// * `frames` - Frames in display order; each must fit on the screen.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the GIF file or a `CompressionError`.
+    pub fn encode(&self, width: u16, height: u16, frames: &[GifFrame]) -> Result<Vec<u8>, CompressionError> {
+        if frames.is_empty() {
+            return Err(CompressionError::Compression("A GIF needs at least one frame".to_string()));
+        }
+        let mut gif = b"GIF89a".to_vec();
+        gif.extend_from_slice(&width.to_le_bytes());
+        gif.extend_from_slice(&height.to_le_bytes());
+        // No global color table; every frame brings its own.
+        gif.extend_from_slice(&[0, 0, 0]);
+
+        if let (Some(count), true) = (self.loop_count, frames.len() > 1) {
+            gif.extend_from_slice(&[0x21, 0xFF, 11]);
+            gif.extend_from_slice(b"NETSCAPE2.0");
+            gif.extend_from_slice(&[3, 1]);
+            gif.extend_from_slice(&count.to_le_bytes());
+            gif.push(0);
+        }
+
+        let lzw = LzwCompressor::new(4096);
+        for frame in frames {
+            if frame.left as u32 + frame.width as u32 > width as u32 || frame.top as u32 + frame.height as u32 > height as u32 {
+                return Err(CompressionError::Compression("Frame does not fit on the logical screen".to_string()));
+            }
+            let (palette, mut indices, transparent) = quantize(&frame.rgba, self.max_colors);
+            if self.interlaced {
+                indices = interlace(&indices, frame.width as usize, frame.height as usize);
+            }
+            let table_bits = (palette.len().max(2) as u32).next_power_of_two().trailing_zeros() as u8;
+
+            // Graphic control extension.
+            let flags = (frame.disposal.code() << 2) | transparent.is_some() as u8;
+            gif.extend_from_slice(&[0x21, 0xF9, 4, flags]);
+            gif.extend_from_slice(&frame.delay.to_le_bytes());
+            gif.extend_from_slice(&[transparent.unwrap_or(0), 0]);
+
+            // Image descriptor and local color table.
+            gif.push(0x2C);
+            for value in [frame.left, frame.top, frame.width, frame.height] {
+                gif.extend_from_slice(&value.to_le_bytes());
+            }
+            gif.push(0x80 | ((self.interlaced as u8) << 6) | (table_bits - 1));
+            for i in 0..1usize << table_bits {
+                gif.extend_from_slice(palette.get(i).unwrap_or(&[0, 0, 0]));
+            }
+
+            let min_code_size = table_bits.max(2);
+            let data = lzw.compress_variable_width(&indices, min_code_size)?;
+            gif.push(min_code_size);
+            for block in data.chunks(255) {
+                gif.push(block.len() as u8);
+                gif.extend_from_slice(block);
+            }
+            gif.push(0);
+        }
+        gif.push(0x3B);
+        Ok(gif)
+    }
+}
+
+/// Implement `fmt::Display` for `GifEncoder` for better readability.
+impl fmt::Display for GifEncoder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "GifEncoder (Max Colors: {}, Interlaced: {})",
+            self.max_colors, self.interlaced
+        )
+    }
+}
+
+/// Reorders rows into the four GIF interlacing passes.
+fn interlace(indices: &[u8], width: usize, height: usize) -> Vec<u8> {
+    let mut out = Vec::with_capacity(indices.len());
+    for (start, step) in [(0, 8), (4, 8), (2, 4), (1, 2)] {
+        for y in (start..height).step_by(step) {
+            out.extend_from_slice(&indices[y * width..(y + 1) * width]);
+        }
+    }
+    out
+}
+
+/// Maps RGBA pixels to a palette of at most `max_colors` entries.
+///
+/// Returns the palette, one index per pixel and the transparent index, if any
+/// pixel is transparent. Frames with few enough distinct colors are mapped
+/// exactly; others are quantized with median cut.
+fn quantize(rgba: &[u8], max_colors: usize) -> (Vec<[u8; 3]>, Vec<u8>, Option<u8>) {
+    let transparent = rgba.chunks_exact(4).any(|p| p[3] < 128);
+    let available = max_colors - transparent as usize;
+
+    let mut counts: HashMap<[u8; 3], u32> = HashMap::new();
+    for p in rgba.chunks_exact(4).filter(|p| p[3] >= 128) {
+        *counts.entry([p[0], p[1], p[2]]).or_default() += 1;
+    }
+    let mut colors: Vec<([u8; 3], u32)> = counts.into_iter().collect();
+    colors.sort_unstable();
+
+    let mut palette = if colors.len() <= available {
+        colors.iter().map(|&(color, _)| color).collect()
+    } else {
+        median_cut(colors, available)
+    };
+    let transparent_index = transparent.then(|| {
+        palette.push([0, 0, 0]);
+        (palette.len() - 1) as u8
+    });
+
+    let opaque_entries = palette.len() - transparent as usize;
+    let mut cache: HashMap<[u8; 3], u8> = HashMap::new();
+    let indices = rgba
+        .chunks_exact(4)
+        .map(|p| {
+            if p[3] < 128 {
+                return transparent_index.unwrap_or(0);
+            }
+            let color = [p[0], p[1], p[2]];
+            *cache.entry(color).or_insert_with(|| nearest(&palette[..opaque_entries], color))
+        })
+        .collect();
+    (palette, indices, transparent_index)
+}
+
+fn nearest(palette: &[[u8; 3]], color: [u8; 3]) -> u8 {
+    let distance = |c: &[u8; 3]| -> u32 {
+        (0..3).map(|i| (c[i] as i32 - color[i] as i32).pow(2) as u32).sum()
+    };
+    palette
+        .iter()
+        .enumerate()
+        .min_by_key(|(_, c)| distance(c))
+        .map_or(0, |(i, _)| i as u8)
+}
+
+/// Splits the color cube into `target` boxes of similar weight and averages each box.
+fn median_cut(colors: Vec<([u8; 3], u32)>, target: usize) -> Vec<[u8; 3]> {
+    let range = |colors: &[([u8; 3], u32)]| -> (usize, u8) {
+        (0..3)
+            .map(|c| {
+                let (lo, hi) = colors
+                    .iter()
+                    .fold((255u8, 0u8), |(lo, hi), (color, _)| (lo.min(color[c]), hi.max(color[c])));
+                (c, hi - lo)
+            })
+            .max_by_key(|&(_, r)| r)
+            .unwrap_or((0, 0))
+    };
+
+    let mut boxes = vec![colors];
+    while boxes.len() < target {
+        // Split the box with the largest weighted extent.
+        let Some((index, _)) = boxes
+            .iter()
+            .enumerate()
+            .filter(|(_, b)| b.len() > 1)
+            .map(|(i, b)| (i, range(b).1 as u64 * b.iter().map(|c| c.1 as u64).sum::<u64>()))
+            .max_by_key(|&(_, score)| score)
+        else {
+            break;
+        };
+        let mut colors = boxes.swap_remove(index);
+        let channel = range(&colors).0;
+        colors.sort_unstable_by_key(|(color, _)| color[channel]);
+        let total: u64 = colors.iter().map(|c| c.1 as u64).sum();
+        let mut running = 0;
+        let split = colors
+            .iter()
+            .position(|c| {
+                running += c.1 as u64;
+                running * 2 >= total
+            })
+            .map_or(1, |i| (i + 1).clamp(1, colors.len() - 1));
+        let upper = colors.split_off(split);
+        boxes.push(colors);
+        boxes.push(upper);
+    }
+
+    boxes
+        .iter()
+        .map(|b| {
+            let total: u64 = b.iter().map(|c| c.1 as u64).sum::<u64>().max(1);
+            let mut sum = [0u64; 3];
+            for (color, count) in b {
+                for c in 0..3 {
+                    sum[c] += color[c] as u64 * *count as u64;
+                }
+            }
+            sum.map(|s| ((s + total / 2) / total) as u8)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::codecs::gif::GifDecoder;
+    use image::AnimationDecoder;
+    use std::io::Cursor;
+
+    fn decode_frames(gif: &[u8]) -> Vec<image::Frame> {
+        GifDecoder::new(Cursor::new(gif)).unwrap().into_frames().collect_frames().unwrap()
+    }
+
+    fn pattern(width: u16, height: u16, colors: u32) -> Vec<u8> {
+        (0..width as u32 * height as u32)
+            .flat_map(|i| {
+                let c = (i * 7 + i / width as u32 * 3) % colors;
+                [(c * 37) as u8, (c * 91) as u8, (c * 53 + 11) as u8, 255]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_exact_palette_round_trip() {
+        for (colors, interlaced) in [(2, false), (13, true), (200, false), (256, true)] {
+            let rgba = pattern(37, 29, colors);
+            let frame = GifFrame::new(37, 29, rgba.clone()).unwrap();
+            let gif = GifEncoder::new().with_interlacing(interlaced).encode(37, 29, &[frame]).unwrap();
+            let frames = decode_frames(&gif);
+            assert_eq!(frames.len(), 1);
+            assert_eq!(frames[0].buffer().as_raw(), &rgba, "{} colors", colors);
+        }
+    }
+
+    #[test]
+    fn test_code_table_resets_on_large_frames() {
+        let rgba: Vec<u8> = (0..300u32 * 200)
+            .flat_map(|i| {
+                let c = i.wrapping_mul(2_654_435_761) >> 24;
+                [c as u8, (c * 3) as u8, (c * 7) as u8, 255]
+            })
+            .collect();
+        let gif = GifEncoder::new().encode(300, 200, &[GifFrame::new(300, 200, rgba.clone()).unwrap()]).unwrap();
+        assert_eq!(decode_frames(&gif)[0].buffer().as_raw(), &rgba);
+    }
+
+    #[test]
+    fn test_quantizes_truecolor() {
+        let rgba: Vec<u8> = (0..64u32 * 64)
+            .flat_map(|i| [(i % 64 * 4) as u8, (i / 64 * 4) as u8, 128, 255])
+            .collect();
+        let gif = GifEncoder::new().encode(64, 64, &[GifFrame::new(64, 64, rgba.clone()).unwrap()]).unwrap();
+        let decoded = decode_frames(&gif).remove(0).into_buffer().into_raw();
+        let error: f64 = decoded.iter().zip(&rgba).map(|(&a, &b)| (a as f64 - b as f64).abs()).sum::<f64>()
+            / rgba.len() as f64;
+        assert!(error < 4.0, "mean error {}", error);
+    }
+
+    #[test]
+    fn test_transparency_delays_and_disposal() {
+        let mut first = pattern(10, 10, 5);
+        for p in first.chunks_mut(4).step_by(3) {
+            p[3] = 0;
+        }
+        let frames = [
+            GifFrame::new(10, 10, first.clone()).unwrap().with_delay(20).with_disposal(DisposalMethod::Background),
+            GifFrame::new(4, 4, pattern(4, 4, 3)).unwrap().with_offset(3, 5).with_delay(70),
+        ];
+        let gif = GifEncoder::new().encode(10, 10, &frames).unwrap();
+        let decoded = decode_frames(&gif);
+        assert_eq!(decoded.len(), 2);
+        assert_eq!(decoded[0].delay().numer_denom_ms(), (200, 1));
+        assert_eq!(decoded[1].delay().numer_denom_ms(), (700, 1));
+        for (p, q) in decoded[0].buffer().as_raw().chunks(4).zip(first.chunks(4)) {
+            if q[3] == 0 {
+                assert_eq!(p[3], 0);
+            } else {
+                assert_eq!(p, q);
+            }
+        }
+        // The first frame was disposed to the background, so only the second frame's area is opaque.
+        let second = decoded[1].buffer();
+        assert_eq!(second.get_pixel(0, 0)[3], 0);
+        assert_eq!(second.get_pixel(3, 5)[3], 255);
+    }
+
+    #[test]
+    fn test_invalid_input() {
+        assert!(GifFrame::new(2, 2, vec![0; 15]).is_err());
+        assert!(GifEncoder::new().with_max_colors(1).is_err());
+        assert!(GifEncoder::new().encode(2, 2, &[]).is_err());
+        let frame = GifFrame::new(2, 2, vec![0; 16]).unwrap().with_offset(1, 0);
+        assert!(GifEncoder::new().encode(2, 2, &[frame]).is_err());
+    }
+}
@@ -0,0 +1,647 @@
+// src/compression/jpeg.rs
+
+//! Module implementing a baseline JPEG encoder.
+//!
+//! The encoder converts RGB input to YCbCr, optionally subsamples the chroma
+//! planes, applies a forward DCT and quantizes with the Annex K tables scaled
+//! by a 1-100 quality factor. Huffman tables are optimized per image with a
+//! counting pass before the entropy-coded scan is written, so the output is a
+//! standard baseline JFIF file readable by any JPEG decoder.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use image_compression::compression::jpeg::{ChromaSubsampling, JpegEncoder};
+//!
+//! let pixels = vec![200u8; 16 * 16 * 3];
+//! let encoder = JpegEncoder::new(85).unwrap().with_subsampling(ChromaSubsampling::Yuv420);
+//! let jpeg = encoder.encode(&pixels, 16, 16, 3).unwrap();
+//! assert_eq!(&jpeg[..2], &[0xFF, 0xD8]);
+//! ```
+
+use super::CompressionError;
+use std::fmt;
+
+/// Maps zigzag scan positions to natural (row-major) block positions.
+pub(crate) const ZIGZAG: [usize; 64] = [
+    0, 1, 8, 16, 9, 2, 3, 10, 17, 24, 32, 25, 18, 11, 4, 5, 12, 19, 26, 33, 40, 48, 41, 34, 27, 20, 13, 6, 7, 14, 21,
+    28, 35, 42, 49, 56, 57, 50, 43, 36, 29, 22, 15, 23, 30, 37, 44, 51, 58, 59, 52, 45, 38, 31, 39, 46, 53, 60, 61,
+    54, 47, 55, 62, 63,
+];
+
+/// Annex K luminance quantization table in natural order.
+const LUMA_QUANT: [u16; 64] = [
+    16, 11, 10, 16, 24, 40, 51, 61, 12, 12, 14, 19, 26, 58, 60, 55, 14, 13, 16, 24, 40, 57, 69, 56, 14, 17, 22, 29,
+    51, 87, 80, 62, 18, 22, 37, 56, 68, 109, 103, 77, 24, 35, 55, 64, 81, 104, 113, 92, 49, 64, 78, 87, 103, 121,
+    120, 101, 72, 92, 95, 98, 112, 100, 103, 99,
+];
+
+/// Annex K chrominance quantization table in natural order.
+const CHROMA_QUANT: [u16; 64] = [
+    17, 18, 24, 47, 99, 99, 99, 99, 18, 21, 26, 66, 99, 99, 99, 99, 24, 26, 56, 99, 99, 99, 99, 99, 47, 66, 99, 99,
+    99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99,
+    99, 99, 99, 99, 99, 99, 99, 99,
+];
+
+/// Chroma subsampling layouts supported by [`JpegEncoder`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChromaSubsampling {
+    /// Full-resolution chroma.
+    Yuv444,
+    /// Chroma halved horizontally.
+    Yuv422,
+    /// Chroma halved horizontally and vertically.
+    Yuv420,
+}
+
+impl ChromaSubsampling {
+    /// Parses a subsampling name such as `"420"`, `"4:2:2"` or `"444"`.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use image_compression::compression::jpeg::ChromaSubsampling;
+    ///
+    /// assert_eq!(ChromaSubsampling::from_name("4:2:0").unwrap(), ChromaSubsampling::Yuv420);
+    /// ```
+    pub fn from_name(name: &str) -> Result<Self, CompressionError> {
+        match name.replace(':', "").as_str() {
+            "444" => Ok(ChromaSubsampling::Yuv444),
+            "422" => Ok(ChromaSubsampling::Yuv422),
+            "420" => Ok(ChromaSubsampling::Yuv420),
+            _ => Err(CompressionError::InvalidLevel(format!("chroma subsampling {}", name))),
+        }
+    }
+
+    /// Luma sampling factors `(horizontal, vertical)` relative to chroma.
+    fn factors(self) -> (usize, usize) {
+        match self {
+            ChromaSubsampling::Yuv444 => (1, 1),
+            ChromaSubsampling::Yuv422 => (2, 1),
+            ChromaSubsampling::Yuv420 => (2, 2),
+        }
+    }
+}
+
+/// A baseline JPEG encoder with configurable quality and chroma subsampling.
+#[derive(Debug, Clone)]
+pub struct JpegEncoder {
+    quality: u8,
+    subsampling: ChromaSubsampling,
+}
+
+impl JpegEncoder {
+    /// Creates a new `JpegEncoder` with the given quality and 4:2:0 chroma subsampling.
+    ///
+    /// # Arguments
+    ///
+    /// * `quality` - Quality factor from 1 (smallest) to 100 (best), scaling the Annex K tables.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the `JpegEncoder` or a `CompressionError` if the quality is out of range.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use image_compression::compression::jpeg::JpegEncoder;
+    ///
+    /// let encoder = JpegEncoder::new(75).unwrap();
+    /// assert!(JpegEncoder::new(0).is_err());
+    /// ```
+    pub fn new(quality: u8) -> Result<Self, CompressionError> {
+        if !(1..=100).contains(&quality) {
+            return Err(CompressionError::InvalidLevel(format!("JPEG quality {}", quality)));
+        }
+        Ok(JpegEncoder {
+            quality,
+            subsampling: ChromaSubsampling::Yuv420,
+        })
+    }
+
+    /// Returns the encoder with a different chroma subsampling layout.
+    pub fn with_subsampling(mut self, subsampling: ChromaSubsampling) -> Self {
+        self.subsampling = subsampling;
+        self
+    }
+
+    /// Retrieves the quality factor.
+    pub fn get_quality(&self) -> u8 {
+        self.quality
+    }
+
+    /// Retrieves the chroma subsampling layout.
+    pub fn get_subsampling(&self) -> ChromaSubsampling {
+        self.subsampling
+    }
+
+    /// Encodes interleaved 8-bit samples as a baseline JFIF file.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - Row-major samples, `channels` per pixel.
+    /// * `width`, `height` - Image dimensions, at most 65535 each.
+    /// * `channels` - `1` for grayscale or `3` for RGB.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the JPEG file bytes or a `CompressionError` if the input is invalid.
+    pub fn encode(&self, data: &[u8], width: u32, height: u32, channels: u8) -> Result<Vec<u8>, CompressionError> {
+        let (w, h, nc) = (width as usize, height as usize, channels as usize);
+        if w == 0 || h == 0 || w > 0xFFFF || h > 0xFFFF {
+            return Err(CompressionError::Compression(format!("Unsupported JPEG dimensions {}x{}", w, h)));
+        }
+        if (nc != 1 && nc != 3) || data.len() != w * h * nc {
+            return Err(CompressionError::Compression(format!(
+                "Expected {}x{} samples with 1 or 3 channels, got {} bytes for {} channels",
+                w, h, data.len(), nc
+            )));
+        }
+
+        let (hmax, vmax) = if nc == 1 { (1, 1) } else { self.subsampling.factors() };
+        let (mcus_x, mcus_y) = (w.div_ceil(8 * hmax), h.div_ceil(8 * vmax));
+        let (pw, ph) = (mcus_x * 8 * hmax, mcus_y * 8 * vmax);
+        let planes = color_planes(data, w, h, nc, pw, ph);
+        let tables = [scale_table(&LUMA_QUANT, self.quality), scale_table(&CHROMA_QUANT, self.quality)];
+
+        let mut components: Vec<Component> = Vec::with_capacity(nc);
+        for (c, plane) in planes.into_iter().enumerate() {
+            let (hs, vs) = if c == 0 { (hmax, vmax) } else { (1, 1) };
+            let plane = if c == 0 { plane } else { downsample(&plane, pw, ph, hmax, vmax) };
+            let stride = pw / (hmax / hs);
+            let table = &tables[(c > 0) as usize];
+            let mut blocks = Vec::with_capacity(mcus_x * mcus_y * hs * vs);
+            for my in 0..mcus_y {
+                for mx in 0..mcus_x {
+                    for by in 0..vs {
+                        for bx in 0..hs {
+                            let (x0, y0) = ((mx * hs + bx) * 8, (my * vs + by) * 8);
+                            blocks.push(quantize(&fdct(&plane, stride, x0, y0), table));
+                        }
+                    }
+                }
+            }
+            components.push(Component {
+                id: c as u8 + 1,
+                h: hs as u8,
+                v: vs as u8,
+                table: (c > 0) as u8,
+                blocks,
+            });
+        }
+
+        let mut dc_freq = [[0u32; 257]; 2];
+        let mut ac_freq = [[0u32; 257]; 2];
+        scan(&components, |component, symbol, _, _| {
+            let t = component.table as usize;
+            match symbol {
+                Symbol::Dc(s) => dc_freq[t][s as usize] += 1,
+                Symbol::Ac(s) => ac_freq[t][s as usize] += 1,
+            }
+        });
+        let used_tables = if nc == 1 { 1 } else { 2 };
+        let dc_tables: Vec<HuffmanTable> = dc_freq[..used_tables].iter().map(HuffmanTable::optimal).collect();
+        let ac_tables: Vec<HuffmanTable> = ac_freq[..used_tables].iter().map(HuffmanTable::optimal).collect();
+
+        let mut out = Vec::new();
+        out.extend_from_slice(&[0xFF, 0xD8]);
+        write_segment(&mut out, 0xE0, &[b'J', b'F', b'I', b'F', 0, 1, 1, 0, 0, 1, 0, 1, 0, 0]);
+        let mut dqt = Vec::new();
+        for (id, table) in tables[..used_tables].iter().enumerate() {
+            dqt.push(id as u8);
+            dqt.extend(ZIGZAG.iter().map(|&n| table[n] as u8));
+        }
+        write_segment(&mut out, 0xDB, &dqt);
+        let mut sof = vec![8, (h >> 8) as u8, h as u8, (w >> 8) as u8, w as u8, nc as u8];
+        for component in &components {
+            sof.extend_from_slice(&[component.id, (component.h << 4) | component.v, component.table]);
+        }
+        write_segment(&mut out, 0xC0, &sof);
+        let mut dht = Vec::new();
+        for (class, tables) in [(0u8, &dc_tables), (1u8, &ac_tables)] {
+            for (id, table) in tables.iter().enumerate() {
+                dht.push((class << 4) | id as u8);
+                dht.extend_from_slice(&table.bits);
+                dht.extend_from_slice(&table.values);
+            }
+        }
+        write_segment(&mut out, 0xC4, &dht);
+        let mut sos = vec![nc as u8];
+        for component in &components {
+            sos.extend_from_slice(&[component.id, (component.table << 4) | component.table]);
+        }
+        sos.extend_from_slice(&[0, 63, 0]);
+        write_segment(&mut out, 0xDA, &sos);
+
+        let mut writer = BitWriter::new(out);
+        scan(&components, |component, symbol, extra, extra_bits| {
+            let t = component.table as usize;
+            let (code, len) = match symbol {
+                Symbol::Dc(s) => dc_tables[t].code(s),
+                Symbol::Ac(s) => ac_tables[t].code(s),
+            };
+            writer.write(code as u32, len);
+            writer.write(extra, extra_bits);
+        });
+        let mut out = writer.finish();
+        out.extend_from_slice(&[0xFF, 0xD9]);
+        Ok(out)
+    }
+}
+
+/// Implement `fmt::Display` for `JpegEncoder` for better readability.
+impl fmt::Display for JpegEncoder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "JpegEncoder (Quality: {}, Subsampling: {:?})", self.quality, self.subsampling)
+    }
+}
+
+struct Component {
+    id: u8,
+    h: u8,
+    v: u8,
+    table: u8,
+    blocks: Vec<[i16; 64]>,
+}
+
+enum Symbol {
+    Dc(u8),
+    Ac(u8),
+}
+
+/// Walks the interleaved scan in MCU order, reporting each Huffman symbol and its extra bits.
+fn scan<F: FnMut(&Component, Symbol, u32, u8)>(components: &[Component], mut emit: F) {
+    let per_mcu: Vec<usize> = components.iter().map(|c| c.h as usize * c.v as usize).collect();
+    let mcus = components[0].blocks.len() / per_mcu[0];
+    let mut predictors = vec![0i32; components.len()];
+    for mcu in 0..mcus {
+        for (c, component) in components.iter().enumerate() {
+            for block in &component.blocks[mcu * per_mcu[c]..(mcu + 1) * per_mcu[c]] {
+                let diff = block[0] as i32 - predictors[c];
+                predictors[c] = block[0] as i32;
+                let (size, bits) = magnitude(diff);
+                emit(component, Symbol::Dc(size), bits, size);
+
+                let mut run = 0;
+                for &coefficient in &block[1..] {
+                    if coefficient == 0 {
+                        run += 1;
+                        continue;
+                    }
+                    while run >= 16 {
+                        emit(component, Symbol::Ac(0xF0), 0, 0);
+                        run -= 16;
+                    }
+                    let (size, bits) = magnitude(coefficient as i32);
+                    emit(component, Symbol::Ac((run << 4) | size), bits, size);
+                    run = 0;
+                }
+                if run > 0 {
+                    emit(component, Symbol::Ac(0x00), 0, 0);
+                }
+            }
+        }
+    }
+}
+
+/// Returns the JPEG size category of `value` and its extra bits.
+pub(crate) fn magnitude(value: i32) -> (u8, u32) {
+    let size = 32 - value.unsigned_abs().leading_zeros();
+    let bits = if value < 0 { (value - 1) as u32 & ((1u32 << size) - 1) } else { value as u32 };
+    (size as u8, bits)
+}
+
+/// Splits interleaved samples into edge-padded planes (YCbCr for colour input).
+fn color_planes(data: &[u8], w: usize, h: usize, nc: usize, pw: usize, ph: usize) -> Vec<Vec<f32>> {
+    let mut planes = vec![vec![0f32; pw * ph]; nc];
+    for y in 0..ph {
+        for x in 0..pw {
+            let i = (y.min(h - 1) * w + x.min(w - 1)) * nc;
+            if nc == 1 {
+                planes[0][y * pw + x] = data[i] as f32;
+                continue;
+            }
+            let (r, g, b) = (data[i] as f32, data[i + 1] as f32, data[i + 2] as f32);
+            planes[0][y * pw + x] = 0.299 * r + 0.587 * g + 0.114 * b;
+            planes[1][y * pw + x] = -0.168_736 * r - 0.331_264 * g + 0.5 * b + 128.0;
+            planes[2][y * pw + x] = 0.5 * r - 0.418_688 * g - 0.081_312 * b + 128.0;
+        }
+    }
+    planes
+}
+
+/// Averages `hs` x `vs` boxes of a plane.
+fn downsample(plane: &[f32], pw: usize, ph: usize, hs: usize, vs: usize) -> Vec<f32> {
+    if hs == 1 && vs == 1 {
+        return plane.to_vec();
+    }
+    let (ow, oh) = (pw / hs, ph / vs);
+    let mut out = vec![0f32; ow * oh];
+    for y in 0..oh {
+        for x in 0..ow {
+            let mut sum = 0.0;
+            for dy in 0..vs {
+                for dx in 0..hs {
+                    sum += plane[(y * vs + dy) * pw + x * hs + dx];
+                }
+            }
+            out[y * ow + x] = sum / (hs * vs) as f32;
+        }
+    }
+    out
+}
+
+/// Scales an Annex K table with the IJG quality formula.
+pub(crate) fn scale_table(base: &[u16; 64], quality: u8) -> [u16; 64] {
+    let quality = quality.clamp(1, 100) as u32;
+    let scale = if quality < 50 { 5000 / quality } else { 200 - quality * 2 };
+    let mut table = [0u16; 64];
+    for (t, &b) in table.iter_mut().zip(base) {
+        *t = ((b as u32 * scale + 50) / 100).clamp(1, 255) as u16;
+    }
+    table
+}
+
+/// Forward 8x8 DCT of the block at `(x0, y0)`, level-shifted by 128.
+fn fdct(plane: &[f32], stride: usize, x0: usize, y0: usize) -> [f32; 64] {
+    let basis = dct_basis();
+    let mut rows = [0f32; 64];
+    for y in 0..8 {
+        for u in 0..8 {
+            rows[y * 8 + u] = (0..8).map(|x| basis[u][x] * (plane[(y0 + y) * stride + x0 + x] - 128.0)).sum();
+        }
+    }
+    let mut out = [0f32; 64];
+    for v in 0..8 {
+        for u in 0..8 {
+            out[v * 8 + u] = (0..8).map(|y| basis[v][y] * rows[y * 8 + u]).sum();
+        }
+    }
+    out
+}
+
+fn dct_basis() -> [[f32; 8]; 8] {
+    let mut basis = [[0f32; 8]; 8];
+    for (u, row) in basis.iter_mut().enumerate() {
+        let scale = if u == 0 { std::f32::consts::FRAC_1_SQRT_2 } else { 1.0 } / 2.0;
+        for (x, value) in row.iter_mut().enumerate() {
+            *value = scale * (((2 * x + 1) * u) as f32 * std::f32::consts::PI / 16.0).cos();
+        }
+    }
+    basis
+}
+
+/// Quantizes DCT coefficients and returns them in zigzag order.
+fn quantize(coefficients: &[f32; 64], table: &[u16; 64]) -> [i16; 64] {
+    let mut out = [0i16; 64];
+    for (k, &n) in ZIGZAG.iter().enumerate() {
+        out[k] = (coefficients[n] / table[n] as f32).round().clamp(-2047.0, 2047.0) as i16;
+    }
+    out
+}
+
+fn write_segment(out: &mut Vec<u8>, marker: u8, payload: &[u8]) {
+    let len = payload.len() + 2;
+    out.extend_from_slice(&[0xFF, marker, (len >> 8) as u8, len as u8]);
+    out.extend_from_slice(payload);
+}
+
+/// A canonical JPEG Huffman table as stored in a DHT segment.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct HuffmanTable {
+    /// Number of codes of each length 1-16.
+    pub(crate) bits: [u8; 16],
+    /// Symbols in order of increasing code length.
+    pub(crate) values: Vec<u8>,
+    codes: [(u16, u8); 256],
+}
+
+impl HuffmanTable {
+    /// Builds a table from DHT counts and symbols, assigning canonical codes (Annex C).
+    pub(crate) fn new(bits: [u8; 16], values: Vec<u8>) -> Self {
+        let mut codes = [(0u16, 0u8); 256];
+        let mut code = 0u32;
+        let mut k = 0;
+        for (i, &count) in bits.iter().enumerate() {
+            for _ in 0..count {
+                if let Some(&symbol) = values.get(k) {
+                    codes[symbol as usize] = (code as u16, i as u8 + 1);
+                }
+                code += 1;
+                k += 1;
+            }
+            code <<= 1;
+        }
+        HuffmanTable { bits, values, codes }
+    }
+
+    /// Builds length-limited optimal codes from symbol frequencies (Annex K.2).
+    ///
+    /// `freq` has one slot per symbol plus a reserved slot at index 256 so that
+    /// no symbol is assigned the all-ones code.
+    pub(crate) fn optimal(freq: &[u32; 257]) -> Self {
+        let mut freq = *freq;
+        freq[256] = 1;
+        let mut code_size = [0usize; 257];
+        let mut others = [usize::MAX; 257];
+        loop {
+            let mut c1 = usize::MAX;
+            let mut c2 = usize::MAX;
+            for i in 0..257 {
+                if freq[i] == 0 {
+                    continue;
+                }
+                if c1 == usize::MAX || freq[i] <= freq[c1] {
+                    c2 = c1;
+                    c1 = i;
+                } else if c2 == usize::MAX || freq[i] <= freq[c2] {
+                    c2 = i;
+                }
+            }
+            if c2 == usize::MAX {
+                break;
+            }
+            freq[c1] += freq[c2];
+            freq[c2] = 0;
+            code_size[c1] += 1;
+            while others[c1] != usize::MAX {
+                c1 = others[c1];
+                code_size[c1] += 1;
+            }
+            others[c1] = c2;
+            code_size[c2] += 1;
+            while others[c2] != usize::MAX {
+                c2 = others[c2];
+                code_size[c2] += 1;
+            }
+        }
+
+        let mut counts = [0u32; 33];
+        for &size in code_size.iter().filter(|&&s| s > 0) {
+            counts[size.min(32)] += 1;
+        }
+        for i in (17..=32).rev() {
+            while counts[i] > 0 {
+                let mut j = i - 2;
+                while counts[j] == 0 {
+                    j -= 1;
+                }
+                counts[i] -= 2;
+                counts[i - 1] += 1;
+                counts[j + 1] += 2;
+                counts[j] -= 1;
+            }
+        }
+        let mut last = 16;
+        while counts[last] == 0 {
+            last -= 1;
+        }
+        counts[last] -= 1;
+
+        let mut bits = [0u8; 16];
+        for (b, &count) in bits.iter_mut().zip(&counts[1..17]) {
+            *b = count as u8;
+        }
+        let mut values = Vec::new();
+        for size in 1..=32 {
+            for (symbol, &s) in code_size[..256].iter().enumerate() {
+                if s == size {
+                    values.push(symbol as u8);
+                }
+            }
+        }
+        HuffmanTable::new(bits, values)
+    }
+
+    /// Returns the `(code, length)` assigned to `symbol`.
+    pub(crate) fn code(&self, symbol: u8) -> (u16, u8) {
+        self.codes[symbol as usize]
+    }
+}
+
+/// MSB-first bit writer with JPEG 0xFF byte stuffing.
+pub(crate) struct BitWriter {
+    out: Vec<u8>,
+    acc: u32,
+    count: u8,
+}
+
+impl BitWriter {
+    pub(crate) fn new(out: Vec<u8>) -> Self {
+        BitWriter { out, acc: 0, count: 0 }
+    }
+
+    pub(crate) fn write(&mut self, value: u32, bits: u8) {
+        for i in (0..bits).rev() {
+            self.acc = (self.acc << 1) | ((value >> i) & 1);
+            self.count += 1;
+            if self.count == 8 {
+                self.push_byte();
+            }
+        }
+    }
+
+    /// Pads the final byte with one bits and returns the buffer.
+    pub(crate) fn finish(mut self) -> Vec<u8> {
+        self.finish_with_padding(true)
+    }
+
+    /// Pads the final byte with `pad` bits and returns the buffer.
+    pub(crate) fn finish_with_padding(&mut self, pad: bool) -> Vec<u8> {
+        while self.count != 0 {
+            self.write(pad as u32, 1);
+        }
+        std::mem::take(&mut self.out)
+    }
+
+    fn push_byte(&mut self) {
+        let byte = self.acc as u8;
+        self.out.push(byte);
+        if byte == 0xFF {
+            self.out.push(0x00);
+        }
+        self.acc = 0;
+        self.count = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::ImageFormat;
+
+    fn photo(width: usize, height: usize) -> Vec<u8> {
+        (0..width * height)
+            .flat_map(|p| {
+                let (x, y) = ((p % width) as f32, (p / width) as f32);
+                let r = 128.0 + 100.0 * (x / 11.0).sin();
+                let g = 128.0 + 90.0 * (y / 7.0).cos();
+                let b = (x + y) * 255.0 / (width + height) as f32;
+                [r as u8, g as u8, b as u8]
+            })
+            .collect()
+    }
+
+    fn decode_error(jpeg: &[u8], original: &[u8]) -> f64 {
+        let decoded = image::load_from_memory_with_format(jpeg, ImageFormat::Jpeg).unwrap().to_rgb8();
+        let decoded = decoded.as_raw();
+        assert_eq!(decoded.len(), original.len());
+        decoded.iter().zip(original).map(|(&a, &b)| a.abs_diff(b) as f64).sum::<f64>() / original.len() as f64
+    }
+
+    #[test]
+    fn test_jpeg_decodes_with_image_crate() {
+        let pixels = photo(37, 21);
+        for subsampling in [ChromaSubsampling::Yuv444, ChromaSubsampling::Yuv422, ChromaSubsampling::Yuv420] {
+            let encoder = JpegEncoder::new(90).unwrap().with_subsampling(subsampling);
+            let jpeg = encoder.encode(&pixels, 37, 21, 3).unwrap();
+            assert!(decode_error(&jpeg, &pixels) < 6.0, "{:?}", subsampling);
+        }
+    }
+
+    #[test]
+    fn test_jpeg_grayscale() {
+        let pixels: Vec<u8> = photo(20, 20).into_iter().step_by(3).collect();
+        let jpeg = JpegEncoder::new(80).unwrap().encode(&pixels, 20, 20, 1).unwrap();
+        let decoded = image::load_from_memory_with_format(&jpeg, ImageFormat::Jpeg).unwrap();
+        assert_eq!(decoded.color(), image::ColorType::L8);
+        assert_eq!((decoded.width(), decoded.height()), (20, 20));
+    }
+
+    #[test]
+    fn test_jpeg_quality_trades_size_for_error() {
+        let pixels = photo(64, 64);
+        let low = JpegEncoder::new(20).unwrap().encode(&pixels, 64, 64, 3).unwrap();
+        let high = JpegEncoder::new(95).unwrap().encode(&pixels, 64, 64, 3).unwrap();
+        assert!(low.len() < high.len());
+        assert!(decode_error(&high, &pixels) < decode_error(&low, &pixels));
+    }
+
+    #[test]
+    fn test_optimal_huffman_table_is_prefix_free() {
+        let mut freq = [0u32; 257];
+        for (i, f) in freq.iter_mut().enumerate().take(200) {
+            *f = (i as u32 * 7919) % 1000 + 1;
+        }
+        let table = HuffmanTable::optimal(&freq);
+        assert!(table.bits.iter().map(|&b| b as usize).sum::<usize>() == 200);
+        let codes: Vec<(u16, u8)> = (0..200).map(|s| table.code(s as u8)).collect();
+        for (i, &(a, la)) in codes.iter().enumerate() {
+            assert!((1..=16).contains(&la));
+            assert_ne!(a as u32, (1u32 << la) - 1, "all-ones code assigned");
+            for &(b, lb) in &codes[i + 1..] {
+                let l = la.min(lb);
+                assert_ne!(a >> (la - l), b >> (lb - l));
+            }
+        }
+    }
+
+    #[test]
+    fn test_jpeg_rejects_invalid_input() {
+        assert!(JpegEncoder::new(101).is_err());
+        assert!(ChromaSubsampling::from_name("411").is_err());
+        let encoder = JpegEncoder::new(50).unwrap();
+        assert!(encoder.encode(&[0; 12], 2, 2, 2).is_err());
+        assert!(encoder.encode(&[], 0, 0, 3).is_err());
+    }
+}
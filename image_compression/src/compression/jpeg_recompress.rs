@@ -0,0 +1,994 @@
+// src/compression/jpeg_recompress.rs
+
+//! Module implementing lossless recompression of existing JPEG files.
+//!
+//! Baseline Huffman-coded JPEGs are parsed down to their quantized DCT
+//! coefficients. Everything outside the entropy-coded scans (markers, tables,
+//! metadata and trailing bytes) is kept verbatim as a Deflate-compressed
+//! skeleton, while the coefficients are coded with the adaptive range coder
+//! from [`super::entropy`], using the neighbouring blocks as context.
+//!
+//! Decompression regenerates the Huffman-coded scans from the coefficients
+//! and restores the original file byte for byte. Before accepting a file the
+//! compressor checks that this reconstruction is exact; files it cannot
+//! reproduce (progressive or arithmetic-coded JPEGs, unusual padding, ...)
+//! are stored with plain Deflate instead, so the round trip is always exact.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use image_compression::compression::Compressor;
+//! use image_compression::compression::jpeg::JpegEncoder;
+//! use image_compression::compression::jpeg_recompress::JpegRecompressor;
+//!
+//! let pixels: Vec<u8> = (0..32 * 32 * 3).map(|i| (i * 7 % 256) as u8).collect();
+//! let jpeg = JpegEncoder::new(80).unwrap().encode(&pixels, 32, 32, 3).unwrap();
+//!
+//! let recompressor = JpegRecompressor::new();
+//! let packed = recompressor.compress(&jpeg).unwrap();
+//! assert_eq!(recompressor.decompress(&packed).unwrap(), jpeg);
+//! ```
+
+use super::deflate::DeflateCompressor;
+use super::entropy::{BitModel, Coder, IntModel, RangeDecoder, RangeEncoder, UIntModel};
+use super::jpeg::{magnitude, BitWriter, HuffmanTable};
+use super::utils::checked_image_len;
+use super::{CompressionError, Compressor};
+use std::fmt;
+
+const MAGIC: &[u8; 4] = b"JRC\x01";
+const MODE_STORED: u8 = 0;
+const MODE_MODELED: u8 = 1;
+
+const NEIGHBOR_CONTEXTS: usize = 6;
+const REMAINING_CONTEXTS: usize = 4;
+const COUNT_CONTEXTS: usize = 12;
+const MAGNITUDE_BANDS: usize = 8;
+
+/// A compressor that losslessly shrinks JPEG files and restores them bit-exactly.
+#[derive(Debug, Clone)]
+pub struct JpegRecompressor {
+    fallback: DeflateCompressor,
+}
+
+impl Default for JpegRecompressor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl JpegRecompressor {
+    /// Creates a new `JpegRecompressor`.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use image_compression::compression::jpeg_recompress::JpegRecompressor;
+    ///
+    /// let recompressor = JpegRecompressor::new();
+    /// ```
+    pub fn new() -> Self {
+        JpegRecompressor {
+            fallback: DeflateCompressor::with_level_number(9).expect("level 9 is valid"),
+        }
+    }
+
+    /// Returns `true` if `data` was packed with coefficient modelling rather than the Deflate fallback.
+    pub fn is_modeled(data: &[u8]) -> bool {
+        data.len() > MAGIC.len() && &data[..MAGIC.len()] == MAGIC && data[MAGIC.len()] == MODE_MODELED
+    }
+
+    fn compress_modeled(&self, data: &[u8]) -> Result<Vec<u8>, CompressionError> {
+        let mut coefficients: Option<Coefficients> = None;
+        let mut skeleton = Vec::with_capacity(4096);
+        let mut copied = 0;
+        walk(data, |pos, frame, tables, scan, restart| {
+            if coefficients.is_none() {
+                coefficients = Some(Coefficients::new(frame)?);
+            }
+            let coefficients = coefficients.as_mut().unwrap();
+            let len = decode_scan(&data[pos..], frame, tables, scan, restart, coefficients)?;
+            skeleton.extend_from_slice(&data[copied..pos]);
+            copied = pos + len;
+            Ok(len)
+        })?;
+        skeleton.extend_from_slice(&data[copied..]);
+        let coefficients =
+            coefficients.ok_or_else(|| CompressionError::Compression("JPEG has no scans".to_string()))?;
+
+        if restore(&skeleton, Some(coefficients.clone()), None)? != data {
+            return Err(CompressionError::Compression("JPEG cannot be reproduced exactly".to_string()));
+        }
+
+        let mut coefficients = coefficients;
+        let mut encoder = RangeEncoder::new();
+        code_coefficients(&mut Coder::Encode(&mut encoder), &mut coefficients)?;
+        let skeleton = self.fallback.compress(&skeleton)?;
+
+        let mut output = Vec::new();
+        output.extend_from_slice(MAGIC);
+        output.push(MODE_MODELED);
+        output.extend_from_slice(&(skeleton.len() as u32).to_be_bytes());
+        output.extend_from_slice(&skeleton);
+        output.extend_from_slice(&encoder.finish());
+        Ok(output)
+    }
+}
+
+impl Compressor for JpegRecompressor {
+    /// Packs a JPEG file, modelling its coefficients when it can be reproduced exactly.
+    fn compress(&self, data: &[u8]) -> Result<Vec<u8>, CompressionError> {
+        if let Ok(output) = self.compress_modeled(data) {
+            return Ok(output);
+        }
+        let mut output = Vec::new();
+        output.extend_from_slice(MAGIC);
+        output.push(MODE_STORED);
+        output.extend_from_slice(&self.fallback.compress(data)?);
+        Ok(output)
+    }
+
+    /// Restores the original JPEG file bytes.
+    fn decompress(&self, data: &[u8]) -> Result<Vec<u8>, CompressionError> {
+        if data.len() <= MAGIC.len() || &data[..MAGIC.len()] != MAGIC {
+            return Err(CompressionError::Decompression("Invalid JPEG recompression header".to_string()));
+        }
+        let body = &data[MAGIC.len() + 1..];
+        match data[MAGIC.len()] {
+            MODE_STORED => self.fallback.decompress(body),
+            MODE_MODELED => {
+                let skeleton_len = body
+                    .get(..4)
+                    .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]) as usize)
+                    .ok_or_else(|| CompressionError::Decompression("Truncated JPEG skeleton".to_string()))?;
+                let skeleton = body
+                    .get(4..4 + skeleton_len)
+                    .ok_or_else(|| CompressionError::Decompression("Truncated JPEG skeleton".to_string()))?;
+                let skeleton = self.fallback.decompress(skeleton)?;
+                restore(&skeleton, None, Some(&body[4 + skeleton_len..]))
+                    .map_err(|e| CompressionError::Decompression(e.to_string()))
+            }
+            mode => Err(CompressionError::Decompression(format!("Unknown JPEG recompression mode {}", mode))),
+        }
+    }
+}
+
+/// Implement `fmt::Display` for `JpegRecompressor` for better readability.
+impl fmt::Display for JpegRecompressor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "JpegRecompressor (Fallback: {})", self.fallback)
+    }
+}
+
+fn error(message: &str) -> CompressionError {
+    CompressionError::Compression(message.to_string())
+}
+
+#[derive(Debug, Clone)]
+struct FrameComponent {
+    id: u8,
+    h: usize,
+    v: usize,
+    /// Width and height in blocks of the component's MCU-padded grid.
+    grid_w: usize,
+    grid_h: usize,
+    /// Width and height in blocks of the component itself, used by non-interleaved scans.
+    blocks_w: usize,
+    blocks_h: usize,
+}
+
+#[derive(Debug, Clone)]
+struct Frame {
+    components: Vec<FrameComponent>,
+    mcus_x: usize,
+    mcus_y: usize,
+}
+
+impl Frame {
+    fn parse(segment: &[u8]) -> Result<Self, CompressionError> {
+        if segment.len() < 6 || segment[0] != 8 {
+            return Err(error("Only 8-bit JPEG frames are supported"));
+        }
+        let height = u16::from_be_bytes([segment[1], segment[2]]) as usize;
+        let width = u16::from_be_bytes([segment[3], segment[4]]) as usize;
+        let count = segment[5] as usize;
+        if width == 0 || height == 0 || count == 0 || count > 4 || segment.len() < 6 + 3 * count {
+            return Err(error("Unsupported JPEG frame header"));
+        }
+        let sampling: Vec<(u8, usize, usize)> = (0..count)
+            .map(|i| {
+                let c = &segment[6 + 3 * i..9 + 3 * i];
+                (c[0], (c[1] >> 4) as usize, (c[1] & 15) as usize)
+            })
+            .collect();
+        if sampling.iter().any(|&(_, h, v)| !(1..=4).contains(&h) || !(1..=4).contains(&v)) {
+            return Err(error("Invalid JPEG sampling factors"));
+        }
+        let hmax = sampling.iter().map(|s| s.1).max().unwrap_or(1);
+        let vmax = sampling.iter().map(|s| s.2).max().unwrap_or(1);
+        let (mcus_x, mcus_y) = (width.div_ceil(8 * hmax), height.div_ceil(8 * vmax));
+        let components = sampling
+            .into_iter()
+            .map(|(id, h, v)| FrameComponent {
+                id,
+                h,
+                v,
+                grid_w: mcus_x * h,
+                grid_h: mcus_y * v,
+                blocks_w: (width * h).div_ceil(hmax).div_ceil(8),
+                blocks_h: (height * v).div_ceil(vmax).div_ceil(8),
+            })
+            .collect();
+        Ok(Frame {
+            components,
+            mcus_x,
+            mcus_y,
+        })
+    }
+}
+
+/// Huffman table definitions currently in effect, as `(bits, values)` per slot.
+#[derive(Default)]
+struct Tables {
+    dc: [Option<([u8; 16], Vec<u8>)>; 4],
+    ac: [Option<([u8; 16], Vec<u8>)>; 4],
+}
+
+impl Tables {
+    fn parse(&mut self, mut segment: &[u8]) -> Result<(), CompressionError> {
+        while !segment.is_empty() {
+            if segment.len() < 17 {
+                return Err(error("Truncated DHT segment"));
+            }
+            let (class, id) = (segment[0] >> 4, (segment[0] & 15) as usize);
+            let mut bits = [0u8; 16];
+            bits.copy_from_slice(&segment[1..17]);
+            let count: usize = bits.iter().map(|&b| b as usize).sum();
+            if class > 1 || id > 3 || segment.len() < 17 + count {
+                return Err(error("Invalid DHT segment"));
+            }
+            let table = Some((bits, segment[17..17 + count].to_vec()));
+            if class == 0 {
+                self.dc[id] = table;
+            } else {
+                self.ac[id] = table;
+            }
+            segment = &segment[17 + count..];
+        }
+        Ok(())
+    }
+}
+
+/// One component of a scan: index into the frame plus its DC and AC table slots.
+#[derive(Debug, Clone, Copy)]
+struct ScanComponent {
+    index: usize,
+    dc: usize,
+    ac: usize,
+}
+
+fn parse_scan(segment: &[u8], frame: &Frame) -> Result<Vec<ScanComponent>, CompressionError> {
+    let count = *segment.first().ok_or_else(|| error("Empty SOS segment"))? as usize;
+    if count == 0 || count > 4 || segment.len() != 4 + 2 * count {
+        return Err(error("Invalid SOS segment"));
+    }
+    if segment[1 + 2 * count..] != [0, 63, 0] {
+        return Err(error("Only sequential baseline scans are supported"));
+    }
+    (0..count)
+        .map(|i| {
+            let (id, tables) = (segment[1 + 2 * i], segment[2 + 2 * i]);
+            let index = frame
+                .components
+                .iter()
+                .position(|c| c.id == id)
+                .ok_or_else(|| error("SOS references an unknown component"))?;
+            Ok(ScanComponent {
+                index,
+                dc: (tables >> 4) as usize & 3,
+                ac: (tables & 15) as usize & 3,
+            })
+        })
+        .collect()
+}
+
+/// Walks the marker structure of a JPEG (or of a skeleton with the scans removed).
+///
+/// `on_scan` is called right after each SOS header with the position where the
+/// entropy-coded data starts and returns how many bytes of scan data to skip.
+fn walk<F>(data: &[u8], mut on_scan: F) -> Result<(), CompressionError>
+where
+    F: FnMut(usize, &Frame, &Tables, &[ScanComponent], usize) -> Result<usize, CompressionError>,
+{
+    if data.len() < 4 || data[..2] != [0xFF, 0xD8] {
+        return Err(error("Missing JPEG SOI marker"));
+    }
+    let mut frame: Option<Frame> = None;
+    let mut tables = Tables::default();
+    let mut restart = 0;
+    let mut pos = 2;
+    loop {
+        if data.len() < pos + 2 || data[pos] != 0xFF {
+            return Err(error("Expected a JPEG marker"));
+        }
+        let marker = data[pos + 1];
+        if marker == 0xD9 {
+            return Ok(());
+        }
+        if marker == 0x01 || marker == 0xFF || (0xD0..=0xD8).contains(&marker) {
+            return Err(error("Unexpected standalone JPEG marker"));
+        }
+        let len = data
+            .get(pos + 2..pos + 4)
+            .map(|b| u16::from_be_bytes([b[0], b[1]]) as usize)
+            .ok_or_else(|| error("Truncated JPEG segment"))?;
+        let segment = data.get(pos + 4..pos + 2 + len).filter(|_| len >= 2).ok_or_else(|| error("Truncated JPEG segment"))?;
+        pos += 2 + len;
+        match marker {
+            0xC0 | 0xC1 if frame.is_some() => return Err(error("Multiple frames are not supported")),
+            0xC0 | 0xC1 => frame = Some(Frame::parse(segment)?),
+            0xC2..=0xCF if marker != 0xC4 && marker != 0xC8 && marker != 0xCC => {
+                return Err(error("Only baseline Huffman JPEGs can be modelled"));
+            }
+            0xCC => return Err(error("Arithmetic-coded JPEGs are not supported")),
+            0xC4 => tables.parse(segment)?,
+            0xDD if segment.len() == 2 => restart = u16::from_be_bytes([segment[0], segment[1]]) as usize,
+            0xDA => {
+                let frame = frame.as_ref().ok_or_else(|| error("SOS before SOF"))?;
+                let scan = parse_scan(segment, frame)?;
+                pos += on_scan(pos, frame, &tables, &scan, restart)?;
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Quantized coefficients of every component, in zigzag order, over the MCU-padded block grid.
+#[derive(Debug, Clone)]
+struct Coefficients {
+    components: Vec<(usize, usize)>,
+    blocks: Vec<Vec<[i16; 64]>>,
+}
+
+impl Coefficients {
+    /// Allocates zeroed blocks for `frame`, whose dimensions come from an untrusted SOF header.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the coefficients, or a `CompressionError` if they would exceed
+    /// [`MAX_DECODED_LEN`](super::utils::MAX_DECODED_LEN) bytes.
+    fn new(frame: &Frame) -> Result<Self, CompressionError> {
+        let blocks = frame.components.iter().map(|c| c.grid_w * c.grid_h).sum();
+        checked_image_len(blocks, 64, std::mem::size_of::<i16>())?;
+        Ok(Coefficients {
+            components: frame.components.iter().map(|c| (c.grid_w, c.grid_h)).collect(),
+            blocks: frame.components.iter().map(|c| vec![[0i16; 64]; c.grid_w * c.grid_h]).collect(),
+        })
+    }
+}
+
+/// Lists the data units of a scan as `(scan component, block index)`, grouped by MCU.
+fn scan_units(frame: &Frame, scan: &[ScanComponent]) -> Vec<Vec<(usize, usize)>> {
+    if let [single] = scan {
+        let c = &frame.components[single.index];
+        return (0..c.blocks_h)
+            .flat_map(|y| (0..c.blocks_w).map(move |x| vec![(0, y * c.grid_w + x)]))
+            .collect();
+    }
+    let mut units = Vec::with_capacity(frame.mcus_x * frame.mcus_y);
+    for my in 0..frame.mcus_y {
+        for mx in 0..frame.mcus_x {
+            let mut mcu = Vec::new();
+            for (s, sc) in scan.iter().enumerate() {
+                let c = &frame.components[sc.index];
+                for by in 0..c.v {
+                    for bx in 0..c.h {
+                        mcu.push((s, (my * c.v + by) * c.grid_w + mx * c.h + bx));
+                    }
+                }
+            }
+            units.push(mcu);
+        }
+    }
+    units
+}
+
+/// Canonical Huffman decoder built from DHT counts and symbols (Annex F.2.2.3).
+struct HuffmanDecoder {
+    max_code: [i32; 18],
+    offset: [i32; 17],
+    values: Vec<u8>,
+}
+
+impl HuffmanDecoder {
+    fn new((bits, values): &([u8; 16], Vec<u8>)) -> Self {
+        let mut max_code = [-1i32; 18];
+        let mut offset = [0i32; 17];
+        let (mut code, mut k) = (0i32, 0i32);
+        for len in 1..=16 {
+            let count = bits[len - 1] as i32;
+            offset[len] = k - code;
+            if count > 0 {
+                code += count;
+                k += count;
+                max_code[len] = code - 1;
+            }
+            code <<= 1;
+        }
+        max_code[17] = i32::MAX;
+        HuffmanDecoder {
+            max_code,
+            offset,
+            values: values.clone(),
+        }
+    }
+
+    fn decode(&self, reader: &mut BitReader<'_>) -> Result<u8, CompressionError> {
+        let mut code = reader.bit()? as i32;
+        for len in 1..=16 {
+            if code <= self.max_code[len] {
+                return self
+                    .values
+                    .get((code + self.offset[len]) as usize)
+                    .copied()
+                    .ok_or_else(|| error("Invalid Huffman code"));
+            }
+            code = (code << 1) | reader.bit()? as i32;
+        }
+        Err(error("Invalid Huffman code"))
+    }
+}
+
+/// MSB-first reader over one unstuffed restart interval.
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    bit: u8,
+}
+
+impl BitReader<'_> {
+    fn bit(&mut self) -> Result<u32, CompressionError> {
+        let byte = *self.data.get(self.pos).ok_or_else(|| error("Scan data ended early"))?;
+        let value = (byte >> (7 - self.bit)) & 1;
+        self.bit += 1;
+        if self.bit == 8 {
+            self.bit = 0;
+            self.pos += 1;
+        }
+        Ok(value as u32)
+    }
+
+    fn bits(&mut self, count: u8) -> Result<u32, CompressionError> {
+        (0..count).try_fold(0, |acc, _| Ok((acc << 1) | self.bit()?))
+    }
+
+    /// Checks that the interval ends here, padded with one bits.
+    fn finish_padded(mut self) -> Result<(), CompressionError> {
+        while self.bit != 0 {
+            if self.bit()? != 1 {
+                return Err(error("Scan padding is not all ones"));
+            }
+        }
+        if self.pos != self.data.len() {
+            return Err(error("Unexpected bytes at the end of a restart interval"));
+        }
+        Ok(())
+    }
+}
+
+/// Undoes the JPEG sign encoding of `bits` extra bits of size `size`.
+fn extend(bits: u32, size: u8) -> i32 {
+    if size == 0 {
+        0
+    } else if bits < 1 << (size - 1) {
+        bits as i32 - (1 << size) + 1
+    } else {
+        bits as i32
+    }
+}
+
+/// Decodes one scan into `coefficients` and returns the length of its entropy-coded data.
+fn decode_scan(
+    data: &[u8],
+    frame: &Frame,
+    tables: &Tables,
+    scan: &[ScanComponent],
+    restart: usize,
+    coefficients: &mut Coefficients,
+) -> Result<usize, CompressionError> {
+    let decoders = scan
+        .iter()
+        .map(|sc| {
+            let dc = tables.dc[sc.dc].as_ref().ok_or_else(|| error("Missing DC table"))?;
+            let ac = tables.ac[sc.ac].as_ref().ok_or_else(|| error("Missing AC table"))?;
+            Ok((HuffmanDecoder::new(dc), HuffmanDecoder::new(ac)))
+        })
+        .collect::<Result<Vec<_>, CompressionError>>()?;
+
+    // Split the entropy-coded data into unstuffed restart intervals.
+    let mut intervals = vec![Vec::new()];
+    let mut pos = 0;
+    loop {
+        match (data.get(pos), data.get(pos + 1)) {
+            (Some(0xFF), Some(0x00)) => {
+                intervals.last_mut().unwrap().push(0xFF);
+                pos += 2;
+            }
+            (Some(0xFF), Some(&marker)) if (0xD0..=0xD7).contains(&marker) => {
+                if marker != 0xD0 + ((intervals.len() - 1) % 8) as u8 {
+                    return Err(error("Out-of-order restart marker"));
+                }
+                intervals.push(Vec::new());
+                pos += 2;
+            }
+            (Some(0xFF), _) | (None, _) => break,
+            (Some(&byte), _) => {
+                intervals.last_mut().unwrap().push(byte);
+                pos += 1;
+            }
+        }
+    }
+
+    let units = scan_units(frame, scan);
+    let per_interval = if restart == 0 { units.len().max(1) } else { restart };
+    if intervals.len() != units.len().div_ceil(per_interval).max(1) {
+        return Err(error("Restart interval count does not match the frame"));
+    }
+    for (chunk, bytes) in units.chunks(per_interval).zip(&intervals) {
+        let mut reader = BitReader { data: bytes, pos: 0, bit: 0 };
+        let mut predictors = vec![0i32; scan.len()];
+        for unit in chunk {
+            for &(s, index) in unit {
+                let (dc_table, ac_table) = &decoders[s];
+                let block = &mut coefficients.blocks[scan[s].index][index];
+                let size = dc_table.decode(&mut reader)?;
+                if size > 11 {
+                    return Err(error("DC difference out of range"));
+                }
+                predictors[s] += extend(reader.bits(size)?, size);
+                block[0] = predictors[s] as i16;
+                let mut k = 1;
+                while k < 64 {
+                    let rs = ac_table.decode(&mut reader)?;
+                    let (run, size) = ((rs >> 4) as usize, rs & 15);
+                    if size == 0 {
+                        if run != 15 {
+                            break;
+                        }
+                        k += 16;
+                        continue;
+                    }
+                    k += run;
+                    if k > 63 || size > 10 {
+                        return Err(error("AC coefficient out of range"));
+                    }
+                    block[k] = extend(reader.bits(size)?, size) as i16;
+                    k += 1;
+                }
+                if k > 64 {
+                    return Err(error("Zero run past the end of a block"));
+                }
+            }
+        }
+        reader.finish_padded()?;
+    }
+    Ok(pos)
+}
+
+/// Regenerates the entropy-coded data of one scan from `coefficients`.
+fn encode_scan(
+    frame: &Frame,
+    tables: &Tables,
+    scan: &[ScanComponent],
+    restart: usize,
+    coefficients: &Coefficients,
+) -> Result<Vec<u8>, CompressionError> {
+    let encoders = scan
+        .iter()
+        .map(|sc| {
+            let (dc_bits, dc_values) = tables.dc[sc.dc].clone().ok_or_else(|| error("Missing DC table"))?;
+            let (ac_bits, ac_values) = tables.ac[sc.ac].clone().ok_or_else(|| error("Missing AC table"))?;
+            Ok((HuffmanTable::new(dc_bits, dc_values), HuffmanTable::new(ac_bits, ac_values)))
+        })
+        .collect::<Result<Vec<_>, CompressionError>>()?;
+
+    let units = scan_units(frame, scan);
+    let per_interval = if restart == 0 { units.len().max(1) } else { restart };
+    let mut out = Vec::new();
+    for (n, chunk) in units.chunks(per_interval).enumerate() {
+        if n > 0 {
+            out.extend_from_slice(&[0xFF, 0xD0 + ((n - 1) % 8) as u8]);
+        }
+        let mut writer = BitWriter::new(out);
+        let mut predictors = vec![0i32; scan.len()];
+        for unit in chunk {
+            for &(s, index) in unit {
+                let (dc_table, ac_table) = &encoders[s];
+                let block = &coefficients.blocks[scan[s].index][index];
+                let (size, bits) = magnitude(block[0] as i32 - predictors[s]);
+                predictors[s] = block[0] as i32;
+                let (code, len) = dc_table.code(size);
+                writer.write(code as u32, len);
+                writer.write(bits, size);
+                let mut run = 0;
+                for &coefficient in &block[1..] {
+                    if coefficient == 0 {
+                        run += 1;
+                        continue;
+                    }
+                    while run >= 16 {
+                        let (code, len) = ac_table.code(0xF0);
+                        writer.write(code as u32, len);
+                        run -= 16;
+                    }
+                    let (size, bits) = magnitude(coefficient as i32);
+                    let (code, len) = ac_table.code((run << 4) | size);
+                    writer.write(code as u32, len);
+                    writer.write(bits, size);
+                    run = 0;
+                }
+                if run > 0 {
+                    let (code, len) = ac_table.code(0x00);
+                    writer.write(code as u32, len);
+                }
+            }
+        }
+        out = writer.finish();
+    }
+    Ok(out)
+}
+
+/// Rebuilds a JPEG from its skeleton, taking coefficients either directly or from a coded stream.
+fn restore(
+    skeleton: &[u8],
+    mut coefficients: Option<Coefficients>,
+    coded: Option<&[u8]>,
+) -> Result<Vec<u8>, CompressionError> {
+    let mut output = Vec::with_capacity(skeleton.len() * 8);
+    let mut copied = 0;
+    walk(skeleton, |pos, frame, tables, scan, restart| {
+        if coefficients.is_none() {
+            let mut decoded = Coefficients::new(frame)?;
+            let mut decoder = RangeDecoder::new(coded.unwrap_or_default());
+            code_coefficients(&mut Coder::Decode(&mut decoder), &mut decoded)?;
+            coefficients = Some(decoded);
+        }
+        output.extend_from_slice(&skeleton[copied..pos]);
+        output.extend_from_slice(&encode_scan(frame, tables, scan, restart, coefficients.as_ref().unwrap())?);
+        copied = pos;
+        Ok(0)
+    })?;
+    output.extend_from_slice(&skeleton[copied..]);
+    Ok(output)
+}
+
+/// Adaptive contexts for one class of components (luma or chroma).
+#[derive(Clone)]
+struct BlockModels {
+    dc: Vec<IntModel>,
+    count: Vec<UIntModel>,
+    nonzero: Vec<BitModel>,
+    magnitude: Vec<UIntModel>,
+    sign: Vec<BitModel>,
+}
+
+impl Default for BlockModels {
+    fn default() -> Self {
+        BlockModels {
+            dc: vec![IntModel::default(); NEIGHBOR_CONTEXTS],
+            count: vec![UIntModel::default(); COUNT_CONTEXTS],
+            nonzero: vec![BitModel::default(); 64 * NEIGHBOR_CONTEXTS * REMAINING_CONTEXTS],
+            magnitude: vec![UIntModel::default(); MAGNITUDE_BANDS * NEIGHBOR_CONTEXTS],
+            sign: vec![BitModel::default(); 64],
+        }
+    }
+}
+
+fn neighbor_bucket(activity: u32) -> usize {
+    match activity {
+        0 => 0,
+        1 => 1,
+        2 => 2,
+        3..=4 => 3,
+        5..=8 => 4,
+        _ => NEIGHBOR_CONTEXTS - 1,
+    }
+}
+
+fn count_bucket(count: u32) -> usize {
+    match count {
+        0..=4 => count as usize,
+        5..=6 => 5,
+        7..=9 => 6,
+        10..=13 => 7,
+        14..=18 => 8,
+        19..=26 => 9,
+        27..=40 => 10,
+        _ => COUNT_CONTEXTS - 1,
+    }
+}
+
+fn magnitude_band(k: usize) -> usize {
+    match k {
+        1..=2 => 0,
+        3..=5 => 1,
+        6..=9 => 2,
+        10..=14 => 3,
+        15..=20 => 4,
+        21..=27 => 5,
+        28..=40 => 6,
+        _ => MAGNITUDE_BANDS - 1,
+    }
+}
+
+/// Codes every block of every component, predicting from the blocks above and to the left.
+///
+/// Each block is coded as its DC residual against a median predictor, the
+/// number of non-zero AC coefficients, and then the AC coefficients in zigzag
+/// order until that count is exhausted. Contexts combine the zigzag position
+/// with the magnitude of the same coefficient in the neighbouring blocks.
+/// Decoded values outside the `i16` coefficient range are rejected as corrupt,
+/// and decoding stops with an error as soon as the coded stream runs out.
+fn code_coefficients(coder: &mut Coder<'_, '_>, coefficients: &mut Coefficients) -> Result<(), CompressionError> {
+    let corrupt = || CompressionError::Decompression("Corrupt JPEG coefficients".to_string());
+    let truncated = || CompressionError::Decompression("Truncated JPEG coefficients".to_string());
+    let mut models = [BlockModels::default(), BlockModels::default()];
+    for (c, &(grid_w, grid_h)) in coefficients.components.clone().iter().enumerate() {
+        let models = &mut models[(c > 0) as usize];
+        let blocks = &mut coefficients.blocks[c];
+        let nonzero_count = |block: &[i16; 64]| block[1..].iter().filter(|&&v| v != 0).count() as u32;
+        for y in 0..grid_h {
+            for x in 0..grid_w {
+                let i = y * grid_w + x;
+                let above = (y > 0).then(|| blocks[i - grid_w]);
+                let left = (x > 0).then(|| blocks[i - 1]);
+                let corner = (x > 0 && y > 0).then(|| blocks[i - grid_w - 1]);
+
+                let (a, l) = (above.map(|b| b[0] as i32), left.map(|b| b[0] as i32));
+                let (prediction, activity) = match (a, l, corner.map(|b| b[0] as i32)) {
+                    (Some(a), Some(l), Some(c)) => {
+                        let median = if c >= a.max(l) {
+                            a.min(l)
+                        } else if c <= a.min(l) {
+                            a.max(l)
+                        } else {
+                            a + l - c
+                        };
+                        (median, (a - c).unsigned_abs() + (l - c).unsigned_abs())
+                    }
+                    (Some(v), _, _) | (_, Some(v), _) => (v, 2),
+                    _ => (0, 8),
+                };
+                let mut residual = blocks[i][0] as i32 - prediction;
+                coder.code_int(&mut models.dc[neighbor_bucket(activity / 4)], &mut residual);
+                blocks[i][0] = prediction
+                    .checked_add(residual)
+                    .and_then(|value| i16::try_from(value).ok())
+                    .ok_or_else(corrupt)?;
+
+                let neighbor_counts = match (above.as_ref().map(nonzero_count), left.as_ref().map(nonzero_count)) {
+                    (Some(a), Some(l)) => (a + l).div_ceil(2),
+                    (Some(v), None) | (None, Some(v)) => v,
+                    (None, None) => 0,
+                };
+                let mut remaining = nonzero_count(&blocks[i]);
+                coder.code_uint(&mut models.count[count_bucket(neighbor_counts)], &mut remaining);
+
+                for k in 1..64 {
+                    if remaining == 0 {
+                        blocks[i][k] = 0;
+                        continue;
+                    }
+                    let activity = match (above.map(|b| b[k].unsigned_abs()), left.map(|b| b[k].unsigned_abs())) {
+                        (Some(a), Some(l)) => a as u32 + l as u32,
+                        (Some(v), None) | (None, Some(v)) => 2 * v as u32,
+                        (None, None) => 0,
+                    };
+                    let n = neighbor_bucket(activity);
+                    let r = (remaining as usize - 1).min(REMAINING_CONTEXTS - 1);
+                    let mut nonzero = blocks[i][k] != 0;
+                    coder.code_bit(&mut models.nonzero[(k * NEIGHBOR_CONTEXTS + n) * REMAINING_CONTEXTS + r], &mut nonzero);
+                    if !nonzero {
+                        blocks[i][k] = 0;
+                        continue;
+                    }
+                    let mut size = (blocks[i][k].unsigned_abs() as u32).saturating_sub(1);
+                    coder.code_uint(&mut models.magnitude[magnitude_band(k) * NEIGHBOR_CONTEXTS + n], &mut size);
+                    let mut negative = blocks[i][k] < 0;
+                    coder.code_bit(&mut models.sign[k], &mut negative);
+                    let value = size.checked_add(1).and_then(|v| i16::try_from(v).ok()).ok_or_else(corrupt)?;
+                    blocks[i][k] = if negative { -value } else { value };
+                    remaining -= 1;
+                }
+                if coder.is_exhausted() {
+                    return Err(truncated());
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compression::jpeg::{ChromaSubsampling, JpegEncoder};
+
+    fn photo(width: usize, height: usize) -> Vec<u8> {
+        (0..width * height)
+            .flat_map(|p| {
+                let (x, y) = ((p % width) as f32, (p / width) as f32);
+                let texture = ((p as u32).wrapping_mul(2_654_435_761) >> 28) as f32;
+                let r = 128.0 + 90.0 * (x / 13.0).sin() * (y / 17.0).cos() + texture;
+                let g = 110.0 + 60.0 * (y / 9.0).sin() + texture;
+                let b = 90.0 + (x + y) / 3.0;
+                [r as u8, g as u8, b as u8]
+            })
+            .collect()
+    }
+
+    /// Inserts a DRI segment after SOI so scans use restart markers.
+    fn with_restart_interval(jpeg: &[u8], interval: u16) -> Vec<u8> {
+        let mut out = jpeg[..2].to_vec();
+        out.extend_from_slice(&[0xFF, 0xDD, 0, 4]);
+        out.extend_from_slice(&interval.to_be_bytes());
+        out.extend_from_slice(&jpeg[2..]);
+        out
+    }
+
+    #[test]
+    fn test_round_trip_is_bit_exact() {
+        let pixels = photo(61, 45);
+        for subsampling in [ChromaSubsampling::Yuv444, ChromaSubsampling::Yuv422, ChromaSubsampling::Yuv420] {
+            let jpeg = JpegEncoder::new(85).unwrap().with_subsampling(subsampling).encode(&pixels, 61, 45, 3).unwrap();
+            let recompressor = JpegRecompressor::new();
+            let packed = recompressor.compress(&jpeg).unwrap();
+            assert!(JpegRecompressor::is_modeled(&packed));
+            assert_eq!(recompressor.decompress(&packed).unwrap(), jpeg);
+        }
+    }
+
+    #[test]
+    fn test_libjpeg_files_are_reproduced() {
+        // Written by libjpeg-turbo 2.1: 4:2:0 with JFIF, Exif, a comment and restart markers every
+        // MCU row; grayscale with optimized Huffman tables and restarts every 5 MCUs; and 4:4:4.
+        let fixtures: [&[u8]; 3] = [
+            include_bytes!("../../tests/fixtures/libjpeg-420-exif-restart.jpg"),
+            include_bytes!("../../tests/fixtures/libjpeg-gray-optimized.jpg"),
+            include_bytes!("../../tests/fixtures/libjpeg-444-photo.jpg"),
+        ];
+        let recompressor = JpegRecompressor::new();
+        for jpeg in fixtures {
+            let packed = recompressor.compress(jpeg).unwrap();
+            assert!(JpegRecompressor::is_modeled(&packed));
+            assert_eq!(recompressor.decompress(&packed).unwrap(), jpeg);
+        }
+        assert!(fixtures[0].windows(2).any(|w| w == [0xFF, 0xE1]));
+        assert!(fixtures[0].windows(2).any(|w| w == [0xFF, 0xD0]));
+    }
+
+    #[test]
+    fn test_grayscale_round_trip() {
+        let pixels: Vec<u8> = photo(30, 20).into_iter().step_by(3).collect();
+        let jpeg = JpegEncoder::new(60).unwrap().encode(&pixels, 30, 20, 1).unwrap();
+        let recompressor = JpegRecompressor::new();
+        let packed = recompressor.compress(&jpeg).unwrap();
+        assert!(JpegRecompressor::is_modeled(&packed));
+        assert_eq!(recompressor.decompress(&packed).unwrap(), jpeg);
+    }
+
+    #[test]
+    fn test_corrupt_coefficients_do_not_reproduce_the_jpeg() {
+        let jpeg = JpegEncoder::new(75).unwrap().encode(&photo(24, 16), 24, 16, 3).unwrap();
+        let packed = JpegRecompressor::new().compress(&jpeg).unwrap();
+        let skeleton_len = u32::from_be_bytes(packed[5..9].try_into().unwrap()) as usize;
+        for fill in [0x00, 0x7F, 0xFF] {
+            let mut corrupt = packed[..9 + skeleton_len].to_vec();
+            corrupt.extend(std::iter::repeat_n(fill, 256));
+            // Garbage must fail or at least not reproduce the original, and must not panic.
+            let restored = JpegRecompressor::new().decompress(&corrupt);
+            assert!(restored.map_or(true, |restored| restored != jpeg), "fill {:#04x}", fill);
+        }
+    }
+
+    #[test]
+    fn test_hostile_frames_and_short_streams_are_errors() {
+        let recompressor = JpegRecompressor::new();
+        let jpeg = JpegEncoder::new(75).unwrap().encode(&photo(64, 48), 64, 48, 3).unwrap();
+        let packed = recompressor.compress(&jpeg).unwrap();
+        assert!(JpegRecompressor::is_modeled(&packed));
+        let skeleton_len = u32::from_be_bytes(packed[5..9].try_into().unwrap()) as usize;
+        let coded = &packed[9 + skeleton_len..];
+
+        // The coded stream cut short runs out before the last block.
+        let mut short = packed[..9 + skeleton_len].to_vec();
+        short.extend_from_slice(&coded[..coded.len() / 2]);
+        assert!(recompressor.decompress(&short).is_err());
+
+        // A SOF claiming 65535x65535 pixels is rejected before its blocks are allocated.
+        let mut skeleton = recompressor.fallback.decompress(&packed[9..9 + skeleton_len]).unwrap();
+        let sof = skeleton.windows(2).position(|w| w == [0xFF, 0xC0]).unwrap();
+        skeleton[sof + 5..sof + 9].copy_from_slice(&[0xFF; 4]);
+        let skeleton = recompressor.fallback.compress(&skeleton).unwrap();
+        let mut hostile = packed[..5].to_vec();
+        hostile.extend_from_slice(&(skeleton.len() as u32).to_be_bytes());
+        hostile.extend_from_slice(&skeleton);
+        hostile.extend_from_slice(&[0x55; 64]);
+        assert!(recompressor.decompress(&hostile).is_err());
+    }
+
+    #[test]
+    fn test_multiple_frames_are_stored() {
+        // A small frame and its scan followed by a larger frame with more components.
+        let gray: Vec<u8> = photo(16, 8).into_iter().step_by(3).collect();
+        let mut jpeg = JpegEncoder::new(75).unwrap().encode(&gray, 16, 8, 1).unwrap();
+        let large = JpegEncoder::new(75).unwrap().encode(&photo(64, 48), 64, 48, 3).unwrap();
+        jpeg.truncate(jpeg.len() - 2);
+        jpeg.extend_from_slice(&large[2..]);
+        assert!(walk(&jpeg, |_, _, _, _, _| Ok(0)).is_err());
+
+        let recompressor = JpegRecompressor::new();
+        let packed = recompressor.compress(&jpeg).unwrap();
+        assert!(!JpegRecompressor::is_modeled(&packed));
+        assert_eq!(recompressor.decompress(&packed).unwrap(), jpeg);
+    }
+
+    #[test]
+    fn test_saves_space_on_photos() {
+        let pixels = photo(256, 192);
+        let jpeg = JpegEncoder::new(90).unwrap().encode(&pixels, 256, 192, 3).unwrap();
+        let packed = JpegRecompressor::new().compress(&jpeg).unwrap();
+        assert!(
+            (packed.len() as f64) < jpeg.len() as f64 * 0.80,
+            "{} -> {} bytes",
+            jpeg.len(),
+            packed.len()
+        );
+
+        let jpeg: &[u8] = include_bytes!("../../tests/fixtures/libjpeg-444-photo.jpg");
+        let packed = JpegRecompressor::new().compress(jpeg).unwrap();
+        assert!(
+            (packed.len() as f64) < jpeg.len() as f64 * 0.80,
+            "libjpeg: {} -> {} bytes",
+            jpeg.len(),
+            packed.len()
+        );
+    }
+
+    #[test]
+    fn test_restart_markers_and_trailing_bytes() {
+        let pixels = photo(40, 40);
+        let jpeg = JpegEncoder::new(75).unwrap().encode(&pixels, 40, 40, 3).unwrap();
+        let mut jpeg = with_restart_interval(&jpeg, 2);
+        // Re-encode the scan so the restart markers are actually present.
+        let recompressor = JpegRecompressor::new();
+        let mut rewritten = None;
+        walk(&jpeg, |pos, frame, tables, scan, restart| {
+            let mut c = Coefficients::new(frame)?;
+            let len = decode_scan(&jpeg[pos..], frame, tables, scan, 0, &mut c)?;
+            let scan_data = encode_scan(frame, tables, scan, restart, &c)?;
+            rewritten = Some((pos, len, scan_data));
+            Ok(len)
+        })
+        .unwrap();
+        let (pos, len, scan_data) = rewritten.unwrap();
+        jpeg.splice(pos..pos + len, scan_data);
+        assert!(jpeg.windows(2).any(|w| w == [0xFF, 0xD3]));
+        assert!(image::load_from_memory(&jpeg).is_ok());
+        jpeg.extend_from_slice(b"trailing garbage");
+
+        let packed = recompressor.compress(&jpeg).unwrap();
+        assert!(JpegRecompressor::is_modeled(&packed));
+        assert_eq!(recompressor.decompress(&packed).unwrap(), jpeg);
+    }
+
+    #[test]
+    fn test_unsupported_input_is_stored() {
+        let recompressor = JpegRecompressor::new();
+        let data = b"definitely not a jpeg file";
+        let packed = recompressor.compress(data).unwrap();
+        assert!(!JpegRecompressor::is_modeled(&packed));
+        assert_eq!(recompressor.decompress(&packed).unwrap(), data.to_vec());
+        assert!(recompressor.decompress(b"JRC").is_err());
+    }
+}
@@ -1,13 +1,18 @@
 // src/compression/lzw.rs
 
+use super::dictionary::Dictionary;
+use super::stream::{compress_to_vec, StreamEncoder, StreamingCompressor};
+use super::utils::MAX_DECODED_LEN;
 use super::{Compressor, CompressionError};
 use std::collections::HashMap;
 use std::fmt;
+use std::io::{self, BufReader, BufWriter, Read, Write};
 
 /// A compressor that uses the LZW algorithm.
 #[derive(Debug, Clone)]
 pub struct LzwCompressor {
     max_table_size: usize,
+    dictionary: Option<Dictionary>,
 }
 
 
@@ -27,102 +32,546 @@ impl LzwCompressor {
     /// let compressor = LzwCompressor::new(4096); // Example max table size
     /// ```
     pub fn new(max_table_size: usize) -> Self {
-        LzwCompressor { max_table_size }
+        LzwCompressor {
+            max_table_size,
+            dictionary: None,
+        }
     }
-}
 
-impl Compressor for LzwCompressor {
-    fn compress(&self, data: &[u8]) -> Result<Vec<u8>, CompressionError> {
-        let mut dictionary: HashMap<Vec<u8>, usize> = HashMap::new();
-        for i in 0..=255 {
-            dictionary.insert(vec![i as u8], i);
-        }
-
-        let mut w: Vec<u8> = Vec::new();
-        let mut result: Vec<u8> = Vec::new();
-        let mut next_code = 256;
-
-        for &k in data {
-            let mut wk = w.clone();
-            wk.push(k);
-            if dictionary.contains_key(&wk) {
-                w = wk;
-            } else {
-                if let Some(&code) = dictionary.get(&w) {
-                    result.extend(&code.to_be_bytes());
-                } else {
-                    return Err(CompressionError::Compression("Failed to retrieve code from dictionary".to_string()));
+    /// Sets a preset dictionary whose strings seed the code table.
+    ///
+    /// Both sides run the table-building pass over the dictionary before the
+    /// data, so strings it contains get codes without first being seen in
+    /// the input. The output then starts with the 4-byte big-endian
+    /// dictionary ID, which decompression checks.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use image_compression::compression::dictionary::Dictionary;
+    /// use image_compression::compression::lzw::LzwCompressor;
+    /// use image_compression::compression::Compressor;
+    ///
+    /// let dictionary = Dictionary::new(b"TOBEORNOTTOBEORTOBEORNOT".to_vec()).unwrap();
+    /// let compressor = LzwCompressor::new(4096).with_dictionary(dictionary);
+    /// let compressed = compressor.compress(b"TOBEORNOT").unwrap();
+    /// assert_eq!(compressor.decompress(&compressed).unwrap(), b"TOBEORNOT");
+    /// ```
+    pub fn with_dictionary(mut self, dictionary: Dictionary) -> Self {
+        self.dictionary = Some(dictionary);
+        self
+    }
+
+    /// Retrieves the preset dictionary, if any.
+    pub fn get_dictionary(&self) -> Option<&Dictionary> {
+        self.dictionary.as_ref()
+    }
+
+    /// Encodes `data` as a GIF-style LZW code stream.
+    ///
+    /// Codes start at `min_code_size + 1` bits and grow up to 12 bits; the
+    /// stream begins with a clear code, emits another one whenever the table
+    /// is full, and ends with the end-of-information code. Codes are packed
+    /// least significant bit first, as GIF requires.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - Symbols to encode, each smaller than `1 << min_code_size`.
+    /// * `min_code_size` - The initial code size (2-8) written in front of GIF image data.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the packed code stream or a `CompressionError`.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use image_compression::compression::lzw::LzwCompressor;
+    ///
+    /// let compressor = LzwCompressor::new(4096);
+    /// let codes = compressor.compress_variable_width(&[0, 1, 1, 0, 1, 1], 2).unwrap();
+    /// assert!(!codes.is_empty());
+    /// ```
+    pub fn compress_variable_width(&self, data: &[u8], min_code_size: u8) -> Result<Vec<u8>, CompressionError> {
+        if !(2..=8).contains(&min_code_size) {
+            return Err(CompressionError::InvalidLevel(format!("LZW minimum code size {}", min_code_size)));
+        }
+        if let Some(&symbol) = data.iter().find(|&&s| (s as u16) >> min_code_size != 0) {
+            return Err(CompressionError::Compression(format!(
+                "Symbol {} does not fit in {} bits",
+                symbol, min_code_size
+            )));
+        }
+        let mut writer = CodeWriter::new(BitOrder::LsbFirst);
+        self.encode_codes(data, min_code_size, 0, &mut writer);
+        Ok(writer.finish())
+    }
+
+    /// Encodes `data` as a TIFF LZW strip (compression 5).
+    ///
+    /// TIFF uses 8-bit symbols, packs codes most significant bit first and
+    /// widens codes one entry earlier than GIF does ("early change").
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use image_compression::compression::lzw::LzwCompressor;
+    ///
+    /// let compressor = LzwCompressor::new(4096);
+    /// let strip = compressor.compress_tiff(b"TOBEORNOTTOBEORTOBEORNOT");
+    /// assert_eq!(compressor.decompress_tiff(&strip, 24).unwrap(), b"TOBEORNOTTOBEORTOBEORNOT");
+    /// ```
+    pub fn compress_tiff(&self, data: &[u8]) -> Vec<u8> {
+        let mut writer = CodeWriter::new(BitOrder::MsbFirst);
+        self.encode_codes(data, 8, 1, &mut writer);
+        writer.finish()
+    }
+
+    /// Decodes a TIFF LZW strip produced by [`LzwCompressor::compress_tiff`] or any other TIFF writer.
+    ///
+    /// Fails rather than decoding more than `max_len` bytes, the size the strip should hold.
+    pub fn decompress_tiff(&self, data: &[u8], max_len: usize) -> Result<Vec<u8>, CompressionError> {
+        decode_codes(data, 8, 1, BitOrder::MsbFirst, max_len)
+    }
+
+    /// Decodes a GIF-style code stream produced by [`LzwCompressor::compress_variable_width`].
+    ///
+    /// Fails rather than decoding more than `max_len` symbols, the pixel count of the frame.
+    pub fn decompress_variable_width(&self, data: &[u8], min_code_size: u8, max_len: usize) -> Result<Vec<u8>, CompressionError> {
+        if !(2..=8).contains(&min_code_size) {
+            return Err(CompressionError::InvalidLevel(format!("LZW minimum code size {}", min_code_size)));
+        }
+        decode_codes(data, min_code_size, 0, BitOrder::LsbFirst, max_len)
+    }
+
+    /// Decodes 16-bit code data, failing rather than decoding more than `max_len` bytes.
+    fn decompress_bounded(&self, data: &[u8], max_len: usize) -> Result<Vec<u8>, CompressionError> {
+        if !data.len().is_multiple_of(2) {
+            return Err(CompressionError::Decompression("Invalid compressed data".to_string()));
+        }
+        let mut output = Vec::new();
+        self.decoder(Box::new(data))?
+            .take(max_len as u64 + 1)
+            .read_to_end(&mut output)
+            .map_err(|e| CompressionError::Decompression(e.to_string()))?;
+        if output.len() > max_len {
+            return Err(CompressionError::Decompression(format!("LZW data decodes past {} bytes", max_len)));
+        }
+        Ok(output)
+    }
+
+    /// Shared clear-code LZW encoder; `early` is 1 when codes widen one entry early.
+    fn encode_codes(&self, data: &[u8], min_code_size: u8, early: u16, writer: &mut CodeWriter) {
+        let clear = 1u16 << min_code_size;
+        let end = clear + 1;
+        let limit = self.max_table_size.clamp(clear as usize + 2, 4096 - early as usize) as u16;
+
+        let mut dictionary: HashMap<(u16, u8), u16> = HashMap::new();
+        let mut width = min_code_size + 1;
+        let mut next_code = clear + 2;
+        writer.write(clear, width);
+
+        let mut symbols = data.iter();
+        if let Some(&first) = symbols.next() {
+            let mut prefix = first as u16;
+            for &k in symbols {
+                if let Some(&code) = dictionary.get(&(prefix, k)) {
+                    prefix = code;
+                    continue;
                 }
-                if next_code < self.max_table_size {
-                    dictionary.insert(wk, next_code);
+                writer.write(prefix, width);
+                // The decoder learns each entry one code later, so widen once it would.
+                if next_code + early >= 1 << width && width < 12 {
+                    width += 1;
+                }
+                if next_code < limit {
+                    dictionary.insert((prefix, k), next_code);
                     next_code += 1;
+                } else {
+                    writer.write(clear, width);
+                    dictionary.clear();
+                    width = min_code_size + 1;
+                    next_code = clear + 2;
                 }
-                w = vec![k];
+                prefix = k as u16;
             }
+            writer.write(prefix, width);
+            if next_code + early >= 1 << width && width < 12 {
+                width += 1;
+            }
+        }
+        writer.write(end, width);
+    }
+}
+
+/// Order in which variable-width codes are packed into bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BitOrder {
+    /// GIF packs codes starting at the least significant bit.
+    LsbFirst,
+    /// TIFF packs codes starting at the most significant bit.
+    MsbFirst,
+}
+
+/// Packs variable-width codes into bytes.
+struct CodeWriter {
+    order: BitOrder,
+    out: Vec<u8>,
+    buffer: u32,
+    bits: u8,
+}
+
+impl CodeWriter {
+    fn new(order: BitOrder) -> Self {
+        CodeWriter {
+            order,
+            out: Vec::new(),
+            buffer: 0,
+            bits: 0,
         }
+    }
 
-        if !w.is_empty() {
-            if let Some(&code) = dictionary.get(&w) {
-                result.extend(&code.to_be_bytes());
-            } else {
-                return Err(CompressionError::Compression("Failed to retrieve final code from dictionary".to_string()));
+    fn write(&mut self, code: u16, width: u8) {
+        match self.order {
+            BitOrder::LsbFirst => {
+                self.buffer |= (code as u32) << self.bits;
+                self.bits += width;
+                while self.bits >= 8 {
+                    self.out.push(self.buffer as u8);
+                    self.buffer >>= 8;
+                    self.bits -= 8;
+                }
+            }
+            BitOrder::MsbFirst => {
+                self.buffer = (self.buffer << width) | code as u32;
+                self.bits += width;
+                while self.bits >= 8 {
+                    self.bits -= 8;
+                    self.out.push((self.buffer >> self.bits) as u8);
+                }
             }
         }
+    }
 
-        Ok(result)
+    fn finish(mut self) -> Vec<u8> {
+        if self.bits > 0 {
+            match self.order {
+                BitOrder::LsbFirst => self.out.push(self.buffer as u8),
+                BitOrder::MsbFirst => self.out.push((self.buffer << (8 - self.bits)) as u8),
+            }
+        }
+        self.out
     }
+}
 
-    fn decompress(&self, data: &[u8]) -> Result<Vec<u8>, CompressionError> {
-        let mut dictionary: Vec<Vec<u8>> = Vec::with_capacity(self.max_table_size);
-        for i in 0..=255 {
-            dictionary.push(vec![i as u8]);
+/// Decodes a clear-code LZW stream written by [`LzwCompressor::encode_codes`] or a compatible encoder,
+/// failing once the output exceeds `max_len` bytes.
+fn decode_codes(
+    data: &[u8],
+    min_code_size: u8,
+    early: u16,
+    order: BitOrder,
+    max_len: usize,
+) -> Result<Vec<u8>, CompressionError> {
+    let invalid = || CompressionError::Decompression("Invalid LZW code".to_string());
+    let clear = 1u16 << min_code_size;
+    let end = clear + 1;
+    // Each entry is (prefix code, last byte, length); single symbols have no prefix.
+    let mut table: Vec<(u16, u8, usize)> = (0..clear).map(|s| (u16::MAX, s as u8, 1)).collect();
+    table.extend([(u16::MAX, 0, 0), (u16::MAX, 0, 0)]);
+    let mut width = min_code_size + 1;
+    let mut previous: Option<u16> = None;
+    let mut output = Vec::new();
+    let (mut buffer, mut bits, mut pos) = (0u32, 0u8, 0usize);
+
+    loop {
+        while bits < width {
+            let Some(&byte) = data.get(pos) else {
+                // Tolerate streams that end without an end-of-information code.
+                return Ok(output);
+            };
+            pos += 1;
+            match order {
+                BitOrder::LsbFirst => buffer |= (byte as u32) << bits,
+                BitOrder::MsbFirst => buffer = (buffer << 8) | byte as u32,
+            }
+            bits += 8;
         }
+        let code = match order {
+            BitOrder::LsbFirst => {
+                let code = (buffer & ((1 << width) - 1)) as u16;
+                buffer >>= width;
+                code
+            }
+            BitOrder::MsbFirst => ((buffer >> (bits - width)) & ((1 << width) - 1)) as u16,
+        };
+        bits -= width;
 
-        let mut result: Vec<u8> = Vec::new();
+        if code == clear {
+            table.truncate(clear as usize + 2);
+            width = min_code_size + 1;
+            previous = None;
+            continue;
+        }
+        if code == end {
+            return Ok(output);
+        }
 
-        let mut iter = data.chunks(2); // Assuming codes are 16-bit
-        let first_code = match iter.next() {
-            Some(chunk) if chunk.len() == 2 => u16::from_be_bytes([chunk[0], chunk[1]]) as usize,
-            _ => return Err(CompressionError::Decompression("Invalid compressed data".to_string())),
+        let start = output.len();
+        let first = match (previous, (code as usize) < table.len()) {
+            (_, true) => {
+                append_entry(&table, code, &mut output);
+                output[start]
+            }
+            (Some(prev), false) if code as usize == table.len() => {
+                append_entry(&table, prev, &mut output);
+                let first = output[start];
+                output.push(first);
+                first
+            }
+            _ => return Err(invalid()),
         };
+        if output.len() > max_len {
+            return Err(CompressionError::Decompression(format!("LZW data decodes past {} bytes", max_len)));
+        }
+        if let Some(prev) = previous {
+            if table.len() < 4096 {
+                let length = table[prev as usize].2 + 1;
+                table.push((prev, first, length));
+            }
+        }
+        if table.len() as u16 + early >= 1 << width && width < 12 {
+            width += 1;
+        }
+        previous = Some(code);
+    }
+}
+
+/// Appends the string of table entry `code` to `output`.
+fn append_entry(table: &[(u16, u8, usize)], mut code: u16, output: &mut Vec<u8>) {
+    let length = table[code as usize].2;
+    let start = output.len();
+    output.resize(start + length, 0);
+    for i in (0..length).rev() {
+        let (prefix, byte, _) = table[code as usize];
+        output[start + i] = byte;
+        code = prefix;
+    }
+}
+
+impl Compressor for LzwCompressor {
+    fn compress(&self, data: &[u8]) -> Result<Vec<u8>, CompressionError> {
+        compress_to_vec(self, data)
+    }
+
+    /// Decompresses data of at most [`MAX_DECODED_LEN`] bytes; stream larger data with [`StreamingCompressor`].
+    fn decompress(&self, data: &[u8]) -> Result<Vec<u8>, CompressionError> {
+        self.decompress_bounded(data, MAX_DECODED_LEN)
+    }
+}
+
+impl StreamingCompressor for LzwCompressor {
+    fn encoder<'a>(&self, output: Box<dyn Write + 'a>) -> Result<Box<dyn StreamEncoder + 'a>, CompressionError> {
+        // Codes are stored as 16-bit values, so the table can never grow past 65536 entries.
+        let max_table_size = self.max_table_size.min(1 << 16);
+        let mut output = BufWriter::new(output);
+        let mut dictionary: HashMap<(u16, u8), u16> = HashMap::new();
+        if let Some(preset) = &self.dictionary {
+            output
+                .write_all(&preset.get_id().to_be_bytes())
+                .map_err(|e| CompressionError::Compression(e.to_string()))?;
+            for (i, entry) in primed_entries(preset, max_table_size).into_iter().enumerate() {
+                dictionary.insert(entry, (256 + i) as u16);
+            }
+        }
+        Ok(Box::new(LzwEncoder {
+            output,
+            next_code: 256 + dictionary.len(),
+            dictionary,
+            w: None,
+            max_table_size,
+        }))
+    }
 
-        let mut w = match dictionary.get(first_code) {
-            Some(bytes) => bytes.clone(),
-            None => return Err(CompressionError::Decompression("Invalid compressed code".to_string())),
+    fn decoder<'a>(&self, input: Box<dyn Read + 'a>) -> Result<Box<dyn Read + 'a>, CompressionError> {
+        let max_table_size = self.max_table_size.min(1 << 16);
+        let mut input = BufReader::new(input);
+        let mut table: Vec<(u16, u8, usize)> = (0..=255).map(|i| (u16::MAX, i as u8, 1)).collect();
+        if let Some(preset) = &self.dictionary {
+            let mut id = [0u8; 4];
+            input
+                .read_exact(&mut id)
+                .map_err(|_| CompressionError::Decompression("Invalid compressed data".to_string()))?;
+            let id = u32::from_be_bytes(id);
+            if id != preset.get_id() {
+                return Err(CompressionError::Decompression(format!(
+                    "Data requires dictionary {:08x}, but dictionary {:08x} was given",
+                    id,
+                    preset.get_id()
+                )));
+            }
+            for (prefix, byte) in primed_entries(preset, max_table_size) {
+                let length = table[prefix as usize].2 + 1;
+                table.push((prefix, byte, length));
+            }
+        }
+        Ok(Box::new(LzwDecoder {
+            input,
+            table,
+            previous: None,
+            pending: Vec::new(),
+            position: 0,
+            max_table_size,
+        }))
+    }
+}
+
+/// Returns the table entries as `(prefix code, byte)`, in code order from 256, that encoding `preset` would add.
+fn primed_entries(preset: &Dictionary, max_table_size: usize) -> Vec<(u16, u8)> {
+    let mut known: HashMap<(u16, u8), u16> = HashMap::new();
+    let mut entries = Vec::new();
+    let mut w: Option<u16> = None;
+    for &k in preset.as_bytes() {
+        let Some(prefix) = w else {
+            w = Some(k as u16);
+            continue;
         };
-        result.extend(&w);
-
-        for chunk in iter {
-            if chunk.len() != 2 {
-                return Err(CompressionError::Decompression("Invalid compressed data".to_string()));
-            }
-            let k = u16::from_be_bytes([chunk[0], chunk[1]]) as usize;
-            let entry = if let Some(bytes) = dictionary.get(k) {
-                bytes.clone()
-            } else if k == dictionary.len() {
-                let mut new_entry = w.clone();
-                new_entry.push(w[0]);
-                new_entry
-            } else {
-                return Err(CompressionError::Decompression("Invalid compressed code".to_string()));
+        if let Some(&code) = known.get(&(prefix, k)) {
+            w = Some(code);
+            continue;
+        }
+        if 256 + entries.len() < max_table_size {
+            known.insert((prefix, k), (256 + entries.len()) as u16);
+            entries.push((prefix, k));
+        }
+        w = Some(k as u16);
+    }
+    entries
+}
+
+/// Streaming encoder emitting 16-bit big-endian codes.
+struct LzwEncoder<'a> {
+    output: BufWriter<Box<dyn Write + 'a>>,
+    /// Maps (prefix code, next byte) to the code of the extended string; single bytes are their own codes.
+    dictionary: HashMap<(u16, u8), u16>,
+    /// Code of the longest match so far, or `None` before the first byte.
+    w: Option<u16>,
+    next_code: usize,
+    max_table_size: usize,
+}
+
+impl Write for LzwEncoder<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for &k in buf {
+            let Some(prefix) = self.w else {
+                self.w = Some(k as u16);
+                continue;
             };
-            result.extend(&entry);
-            if dictionary.len() < self.max_table_size {
-                let mut new_entry = w.clone();
-                new_entry.push(entry[0]);
-                dictionary.push(new_entry);
+            match self.dictionary.get(&(prefix, k)) {
+                Some(&code) => self.w = Some(code),
+                None => {
+                    self.output.write_all(&prefix.to_be_bytes())?;
+                    if self.next_code < self.max_table_size {
+                        self.dictionary.insert((prefix, k), self.next_code as u16);
+                        self.next_code += 1;
+                    }
+                    self.w = Some(k as u16);
+                }
             }
-            w = entry;
         }
+        Ok(buf.len())
+    }
 
-        Ok(result)
+    fn flush(&mut self) -> io::Result<()> {
+        self.output.flush()
+    }
+}
+
+impl StreamEncoder for LzwEncoder<'_> {
+    fn finish(mut self: Box<Self>) -> Result<(), CompressionError> {
+        if let Some(code) = self.w.take() {
+            self.output
+                .write_all(&code.to_be_bytes())
+                .map_err(|e| CompressionError::Compression(e.to_string()))?;
+        }
+        self.output.flush().map_err(|e| CompressionError::Compression(e.to_string()))
+    }
+}
+
+/// Streaming decoder reading 16-bit big-endian codes.
+struct LzwDecoder<'a> {
+    input: BufReader<Box<dyn Read + 'a>>,
+    /// Each entry is (prefix code, last byte, length), as in [`decode_codes`].
+    table: Vec<(u16, u8, usize)>,
+    previous: Option<u16>,
+    /// Decoded bytes of the last code not yet returned.
+    pending: Vec<u8>,
+    position: usize,
+    max_table_size: usize,
+}
+
+impl LzwDecoder<'_> {
+    /// Reads the next code, or `None` at the end of the input.
+    fn next_code(&mut self) -> io::Result<Option<u16>> {
+        let mut code = [0u8; 2];
+        let mut filled = 0;
+        while filled < 2 {
+            match self.input.read(&mut code[filled..]) {
+                Ok(0) if filled == 0 => return Ok(None),
+                Ok(0) => return Err(io::Error::new(io::ErrorKind::InvalidData, "Invalid compressed data")),
+                Ok(n) => filled += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(Some(u16::from_be_bytes(code)))
+    }
+}
+
+impl Read for LzwDecoder<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.position == self.pending.len() {
+            let Some(k) = self.next_code()? else {
+                return Ok(0);
+            };
+            self.pending.clear();
+            self.position = 0;
+            let first = match self.previous {
+                _ if (k as usize) < self.table.len() => {
+                    append_entry(&self.table, k, &mut self.pending);
+                    self.pending[0]
+                }
+                Some(prev) if k as usize == self.table.len() => {
+                    append_entry(&self.table, prev, &mut self.pending);
+                    let first = self.pending[0];
+                    self.pending.push(first);
+                    first
+                }
+                _ => return Err(io::Error::new(io::ErrorKind::InvalidData, "Invalid compressed code")),
+            };
+            if let Some(prev) = self.previous {
+                if self.table.len() < self.max_table_size {
+                    let length = self.table[prev as usize].2 + 1;
+                    self.table.push((prev, first, length));
+                }
+            }
+            self.previous = Some(k);
+        }
+        let n = buf.len().min(self.pending.len() - self.position);
+        buf[..n].copy_from_slice(&self.pending[self.position..self.position + n]);
+        self.position += n;
+        Ok(n)
     }
 }
 
 /// Implement `fmt::Display` for `LzwCompressor` for better readability.
 impl fmt::Display for LzwCompressor {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "LzwCompressor (Max Table Size: {})", self.max_table_size)
+        write!(f, "LzwCompressor (Max Table Size: {})", self.max_table_size)?;
+        if let Some(dictionary) = &self.dictionary {
+            write!(f, " with dictionary {:08x}", dictionary.get_id())?;
+        }
+        Ok(())
     }
 }
 
@@ -160,4 +609,72 @@ mod tests {
             panic!("Expected CompressionError::Decompression");
         }
     }
+
+    #[test]
+    fn test_lzw_compressor_large_input() {
+        let compressor = LzwCompressor::new(4096);
+        let data: Vec<u8> = (0..50_000u32).map(|i| (i * i % 251) as u8).collect();
+        let compressed = compressor.compress(&data).unwrap();
+        assert_eq!(compressor.decompress(&compressed).unwrap(), data);
+
+        // Fills a 65536-entry table, whose highest code is u16::MAX.
+        let wide = LzwCompressor::new(65536);
+        let data: Vec<u8> = (0..600_000u32).map(|i| (i.wrapping_mul(2_654_435_761) >> 29) as u8).collect();
+        assert_eq!(wide.decompress(&wide.compress(&data).unwrap()).unwrap(), data);
+    }
+
+    #[test]
+    fn test_lzw_compressor_dictionary() {
+        let dictionary = Dictionary::new(b"<icon size=\"16\" palette=\"web\">".repeat(4)).unwrap();
+        let compressor = LzwCompressor::new(4096).with_dictionary(dictionary.clone());
+        let data = b"<icon size=\"16\" palette=\"web\"><icon size=\"16\" palette=\"web\">";
+        let compressed = compressor.compress(data).unwrap();
+        assert_eq!(&compressed[..4], &dictionary.get_id().to_be_bytes());
+        assert!(compressed.len() < LzwCompressor::new(4096).compress(data).unwrap().len());
+        assert_eq!(compressor.decompress(&compressed).unwrap(), data.to_vec());
+        assert_eq!(compressor.decompress(&compressor.compress(b"").unwrap()).unwrap(), b"");
+
+        // A table that fills up while priming still round-trips.
+        let tiny = LzwCompressor::new(260).with_dictionary(dictionary);
+        assert_eq!(tiny.decompress(&tiny.compress(data).unwrap()).unwrap(), data.to_vec());
+
+        let other = LzwCompressor::new(4096).with_dictionary(Dictionary::new(b"other".to_vec()).unwrap());
+        assert!(matches!(other.decompress(&compressed), Err(CompressionError::Decompression(_))));
+    }
+
+    #[test]
+    fn test_variable_width_round_trips() {
+        let compressor = LzwCompressor::new(4096);
+        let noisy: Vec<u8> = (0..40_000u32).map(|i| (i.wrapping_mul(2_654_435_761) >> 24) as u8).collect();
+        let smooth: Vec<u8> = (0..40_000u32).map(|i| (i / 300) as u8).collect();
+        for data in [noisy, smooth, Vec::new(), vec![7]] {
+            let gif = compressor.compress_variable_width(&data, 8).unwrap();
+            assert_eq!(compressor.decompress_variable_width(&gif, 8, data.len()).unwrap(), data);
+            let tiff = compressor.compress_tiff(&data);
+            assert_eq!(compressor.decompress_tiff(&tiff, data.len()).unwrap(), data);
+        }
+        let small = LzwCompressor::new(300);
+        let data: Vec<u8> = (0..5000u32).map(|i| (i % 4) as u8).collect();
+        let gif = small.compress_variable_width(&data, 2).unwrap();
+        assert_eq!(small.decompress_variable_width(&gif, 2, data.len()).unwrap(), data);
+        assert!(small.decompress_variable_width(&gif, 2, data.len() - 1).is_err());
+    }
+
+    #[test]
+    fn test_output_is_bounded() {
+        let compressor = LzwCompressor::new(4096);
+        let zeros = vec![0u8; 1 << 20];
+        let compressed = compressor.compress(&zeros).unwrap();
+        assert!(compressed.len() < 8192);
+        assert_eq!(compressor.decompress_bounded(&compressed, zeros.len()).unwrap(), zeros);
+        assert!(compressor.decompress_bounded(&compressed, zeros.len() - 1).is_err());
+    }
+
+    #[test]
+    fn test_variable_width_rejects_wide_symbols() {
+        let compressor = LzwCompressor::new(4096);
+        assert!(compressor.compress_variable_width(&[0, 4], 2).is_err());
+        assert!(compressor.compress_variable_width(&[0], 1).is_err());
+        assert!(compressor.compress_variable_width(&[], 2).is_ok());
+    }
 }
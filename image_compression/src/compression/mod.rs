@@ -45,20 +45,48 @@ pub trait Compressor {
     fn decompress(&self, data: &[u8]) -> Result<Vec<u8>, CompressionError>;
 }
 
+pub mod auto;
+pub mod blocks;
+pub mod ccitt;
 pub mod deflate;
+pub mod dictionary;
+pub mod entropy;
+pub mod gif;
+pub mod jpeg;
+pub mod jpeg_recompress;
 pub mod lzw;
+pub mod parallel;
+pub mod parts;
+pub mod png;
+pub mod png_recompress;
+pub mod progressive;
+pub mod pyramid;
+pub mod spiht;
+pub mod stream;
 pub mod utils;
+pub mod wavelet;
 
 /// Enum representing the supported compression algorithms.
 pub enum CompressionAlgorithmType {
     Deflate(deflate::DeflateCompressor),
     Lzw(lzw::LzwCompressor),
-    // Add other algorithms as needed
+    Wavelet(wavelet::WaveletCompressor),
+    Spiht(spiht::SpihtCompressor),
+    JpegRecompress(jpeg_recompress::JpegRecompressor),
+    PngRecompress(png_recompress::PngRecompressor),
+    Ccitt(ccitt::CcittCompressor),
 }
 
 // src/compression/mod.rs
 
 impl CompressionAlgorithmType {
+    /// Creates the algorithm named `name`, as listed by [`CompressionAlgorithmType::name_of`].
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the algorithm, or `CompressionError::UnknownAlgorithm` if the name
+    /// is unknown or names an image codec configured per image, which
+    /// [`CompressionAlgorithmType::for_block`] creates instead.
     pub fn create(algorithm: &str, level: Option<u32>) -> Result<Self, CompressionError> {
         match algorithm.to_lowercase().as_str() {
             "deflate" => {
@@ -72,9 +100,102 @@ impl CompressionAlgorithmType {
                 let compressor = lzw::LzwCompressor::new(4096); // Provide the required usize argument
                 Ok(CompressionAlgorithmType::Lzw(compressor))
             },
+            "jpeg-recompress" => Ok(CompressionAlgorithmType::JpegRecompress(jpeg_recompress::JpegRecompressor::new())),
+            "png-recompress" => Ok(CompressionAlgorithmType::PngRecompress(png_recompress::PngRecompressor::new())),
+            name @ ("wavelet" | "spiht") => Err(CompressionError::UnknownAlgorithm(format!(
+                "{} needs the image geometry; compress an image with it, or use compress-parallel or pack",
+                name
+            ))),
+            "ccitt" => Err(CompressionError::UnknownAlgorithm(
+                "ccitt needs the image geometry; compress a black and white image with it, or use encode-tiff".to_string(),
+            )),
             other => Err(CompressionError::UnknownAlgorithm(other.to_string())),
         }
     }
+
+    /// Identifier of CCITT fax coding, whose blocks hold packed 1-bit rows rather than 8-bit pixels.
+    pub const CCITT_ID: u8 = 6;
+
+    /// Identifiers of every algorithm.
+    pub const IDS: [u8; 7] = [0, 1, 2, 3, 4, 5, 6];
+
+    /// Identifiers of the algorithms that code raw pixels, in the order block-based selection tries them.
+    pub const PIXEL_IDS: [u8; 5] = [0, 1, 2, 3, 6];
+
+    /// Names of the algorithms, indexed by identifier.
+    const NAMES: [&'static str; 7] = ["deflate", "lzw", "wavelet", "spiht", "jpeg-recompress", "png-recompress", "ccitt"];
+
+    /// Returns the name of the algorithm with identifier `id`, or `None` if the identifier is unknown.
+    pub fn name_of(id: u8) -> Option<&'static str> {
+        Self::NAMES.get(id as usize).copied()
+    }
+
+    /// Returns the identifier of the algorithm named `name`, or `None` if the name is unknown.
+    pub fn id_of(name: &str) -> Option<u8> {
+        Self::NAMES.iter().position(|&n| n == name).map(|id| id as u8)
+    }
+
+    /// Returns the identifier stored in block maps for this algorithm.
+    pub fn id(&self) -> u8 {
+        match self {
+            CompressionAlgorithmType::Deflate(_) => 0,
+            CompressionAlgorithmType::Lzw(_) => 1,
+            CompressionAlgorithmType::Wavelet(_) => 2,
+            CompressionAlgorithmType::Spiht(_) => 3,
+            CompressionAlgorithmType::JpegRecompress(_) => 4,
+            CompressionAlgorithmType::PngRecompress(_) => 5,
+            CompressionAlgorithmType::Ccitt(_) => Self::CCITT_ID,
+        }
+    }
+
+    /// Returns whether the algorithm named `name` codes pixels of a known geometry, so that only
+    /// [`CompressionAlgorithmType::for_block`] can create it.
+    pub fn needs_geometry(name: &str) -> bool {
+        matches!(name.to_lowercase().as_str(), "wavelet" | "spiht" | "ccitt")
+    }
+
+    /// Returns the algorithm's name as accepted by [`CompressionAlgorithmType::create`], or by
+    /// [`CompressionAlgorithmType::for_block`] for image codecs.
+    pub fn name(&self) -> &'static str {
+        Self::NAMES[self.id() as usize]
+    }
+
+    /// Returns the algorithm primed with a preset dictionary.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the algorithm or `CompressionError::UnknownAlgorithm` if it is not an
+    /// LZ codec and so cannot use a dictionary.
+    pub fn with_dictionary(self, dictionary: dictionary::Dictionary) -> Result<Self, CompressionError> {
+        match self {
+            CompressionAlgorithmType::Deflate(c) => Ok(CompressionAlgorithmType::Deflate(c.with_dictionary(dictionary))),
+            CompressionAlgorithmType::Lzw(c) => Ok(CompressionAlgorithmType::Lzw(c.with_dictionary(dictionary))),
+            other => Err(CompressionError::UnknownAlgorithm(format!("{} with a dictionary", other.name()))),
+        }
+    }
+
+    /// Creates the algorithm with identifier `id` configured for a `width` x `height` pixel block.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - One of [`CompressionAlgorithmType::IDS`].
+    /// * `width`, `height`, `channels` - Geometry of the block, used by the image codecs.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the algorithm or a `CompressionError` if the identifier is unknown.
+    pub fn for_block(id: u8, width: u32, height: u32, channels: u8) -> Result<Self, CompressionError> {
+        match id {
+            0 => Ok(CompressionAlgorithmType::Deflate(deflate::DeflateCompressor::with_level_number(9)?)),
+            1 => Ok(CompressionAlgorithmType::Lzw(lzw::LzwCompressor::new(4096))),
+            2 => Ok(CompressionAlgorithmType::Wavelet(wavelet::WaveletCompressor::new(width, height, channels))),
+            3 => Ok(CompressionAlgorithmType::Spiht(spiht::SpihtCompressor::new(width, height, channels))),
+            4 => Ok(CompressionAlgorithmType::JpegRecompress(jpeg_recompress::JpegRecompressor::new())),
+            5 => Ok(CompressionAlgorithmType::PngRecompress(png_recompress::PngRecompressor::new())),
+            Self::CCITT_ID => Ok(CompressionAlgorithmType::Ccitt(ccitt::CcittCompressor::new(ccitt::CcittMode::Group4, width, height))),
+            other => Err(CompressionError::UnknownAlgorithm(format!("algorithm id {}", other))),
+        }
+    }
 }
 
 
@@ -83,7 +204,11 @@ impl Compressor for CompressionAlgorithmType {
         match self {
             CompressionAlgorithmType::Deflate(c) => c.compress(data),
             CompressionAlgorithmType::Lzw(c) => c.compress(data),
-            // Handle other algorithms
+            CompressionAlgorithmType::Wavelet(c) => c.compress(data),
+            CompressionAlgorithmType::Spiht(c) => c.compress(data),
+            CompressionAlgorithmType::JpegRecompress(c) => c.compress(data),
+            CompressionAlgorithmType::PngRecompress(c) => c.compress(data),
+            CompressionAlgorithmType::Ccitt(c) => c.compress(data),
         }
     }
 
@@ -91,7 +216,41 @@ impl Compressor for CompressionAlgorithmType {
         match self {
             CompressionAlgorithmType::Deflate(c) => c.decompress(data),
             CompressionAlgorithmType::Lzw(c) => c.decompress(data),
-            // Handle other algorithms
+            CompressionAlgorithmType::Wavelet(c) => c.decompress(data),
+            CompressionAlgorithmType::Spiht(c) => c.decompress(data),
+            CompressionAlgorithmType::JpegRecompress(c) => c.decompress(data),
+            CompressionAlgorithmType::PngRecompress(c) => c.decompress(data),
+            CompressionAlgorithmType::Ccitt(c) => c.decompress(data),
+        }
+    }
+}
+
+/// Deflate and LZW stream natively; the other algorithms buffer through [`stream::Buffered`].
+impl stream::StreamingCompressor for CompressionAlgorithmType {
+    fn encoder<'a>(
+        &self,
+        output: Box<dyn std::io::Write + 'a>,
+    ) -> Result<Box<dyn stream::StreamEncoder + 'a>, CompressionError> {
+        match self {
+            CompressionAlgorithmType::Deflate(c) => c.encoder(output),
+            CompressionAlgorithmType::Lzw(c) => c.encoder(output),
+            CompressionAlgorithmType::Wavelet(c) => stream::Buffered(c.clone()).encoder(output),
+            CompressionAlgorithmType::Spiht(c) => stream::Buffered(c.clone()).encoder(output),
+            CompressionAlgorithmType::JpegRecompress(c) => stream::Buffered(c.clone()).encoder(output),
+            CompressionAlgorithmType::PngRecompress(c) => stream::Buffered(c.clone()).encoder(output),
+            CompressionAlgorithmType::Ccitt(c) => stream::Buffered(c.clone()).encoder(output),
+        }
+    }
+
+    fn decoder<'a>(&self, input: Box<dyn std::io::Read + 'a>) -> Result<Box<dyn std::io::Read + 'a>, CompressionError> {
+        match self {
+            CompressionAlgorithmType::Deflate(c) => c.decoder(input),
+            CompressionAlgorithmType::Lzw(c) => c.decoder(input),
+            CompressionAlgorithmType::Wavelet(c) => stream::Buffered(c.clone()).decoder(input),
+            CompressionAlgorithmType::Spiht(c) => stream::Buffered(c.clone()).decoder(input),
+            CompressionAlgorithmType::JpegRecompress(c) => stream::Buffered(c.clone()).decoder(input),
+            CompressionAlgorithmType::PngRecompress(c) => stream::Buffered(c.clone()).decoder(input),
+            CompressionAlgorithmType::Ccitt(c) => stream::Buffered(c.clone()).decoder(input),
         }
     }
 }
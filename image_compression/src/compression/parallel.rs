@@ -0,0 +1,657 @@
+// src/compression/parallel.rs
+
+//! Module implementing block-parallel compression on a thread pool.
+//!
+//! The image is split into independent strips of whole rows or rectangular
+//! tiles, and each part is compressed on its own by a part compressor built
+//! for the part's geometry. Parts are compressed and decompressed on a rayon
+//! thread pool. The header records the algorithm that compressed the parts
+//! and an offset index of them, so each one can be located without reading
+//! the others, and
+//! [`ParallelCompressor::decode_region`] decompresses only the tiles a region
+//! touches. Containers written by [`ParallelCompressor::for_algorithm`] can
+//! be decoded with [`ParallelCompressor::from_header`] alone.
+//!
+//! The output does not depend on the thread count, because every part is
+//! compressed alone and the parts are stored in row-major order. Part
+//! compressors whose output depends on timing, such as a time-budgeted
+//! automatic selection, are the exception.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use image_compression::compression::Compressor;
+//! use image_compression::compression::deflate::DeflateCompressor;
+//! use image_compression::compression::parallel::ParallelCompressor;
+//!
+//! let pixels: Vec<u8> = (0..64 * 48 * 3).map(|i| (i % 251) as u8).collect();
+//! let compressor = ParallelCompressor::new(64, 48, 3, DeflateCompressor::new())
+//!     .with_strips(16)
+//!     .unwrap()
+//!     .with_threads(4)
+//!     .unwrap();
+//! let compressed = compressor.compress(&pixels).unwrap();
+//! assert_eq!(compressor.decompress(&compressed).unwrap(), pixels);
+//! ```
+
+use super::parts::PartCodec;
+use super::utils::checked_image_len;
+use super::{CompressionError, Compressor};
+use rayon::prelude::*;
+use std::fmt;
+use std::sync::Arc;
+
+const MAGIC: &[u8; 4] = b"PAR\x02";
+const HEADER_LEN: usize = 26;
+/// Bytes per index entry: a 64-bit payload offset and a 32-bit length.
+const INDEX_ENTRY_LEN: usize = 12;
+const DEFAULT_STRIP_ROWS: u32 = 64;
+
+pub use super::parts::{PartFactory, CUSTOM_ALGORITHM};
+
+/// How the image is split into parts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Partition {
+    /// Full-width strips of this many rows.
+    Strips(u32),
+    /// Tiles of this width and height.
+    Tiles(u32, u32),
+}
+
+impl Partition {
+    /// Returns the width and height of a full part of an image `width` pixels wide.
+    fn part_size(&self, width: u32) -> (u32, u32) {
+        match *self {
+            Partition::Strips(rows) => (width.max(1), rows),
+            Partition::Tiles(tile_width, tile_height) => (tile_width, tile_height),
+        }
+    }
+}
+
+/// A compressor that splits an image into parts and compresses them in parallel.
+#[derive(Clone)]
+pub struct ParallelCompressor {
+    width: u32,
+    height: u32,
+    channels: u8,
+    partition: Partition,
+    threads: usize,
+    /// Pool of `threads` workers shared by clones, or `None` to use rayon's global pool.
+    pool: Option<Arc<rayon::ThreadPool>>,
+    /// Builds the part compressors and holds the algorithm id recorded in the header.
+    codec: PartCodec,
+}
+
+impl ParallelCompressor {
+    /// Creates a new `ParallelCompressor` compressing every part with a clone of `compressor`.
+    ///
+    /// Parts are 64-row strips, compressed on as many threads as there are CPUs.
+    ///
+    /// # Arguments
+    ///
+    /// * `width` - Image width in pixels.
+    /// * `height` - Image height in pixels.
+    /// * `channels` - Number of interleaved 8-bit channels per pixel.
+    /// * `compressor` - The compressor applied to each part.
+    pub fn new<C>(width: u32, height: u32, channels: u8, compressor: C) -> Self
+    where
+        C: Compressor + Clone + Send + Sync + 'static,
+    {
+        Self::with_codec(width, height, channels, PartCodec::cloning(compressor))
+    }
+
+    /// Creates a new `ParallelCompressor` building each part's compressor from its geometry.
+    ///
+    /// Use this for codecs configured with image dimensions, such as the wavelet codecs.
+    ///
+    /// # Arguments
+    ///
+    /// * `width` - Image width in pixels.
+    /// * `height` - Image height in pixels.
+    /// * `channels` - Number of interleaved 8-bit channels per pixel.
+    /// * `factory` - Builds the compressor for a part from its width, height and channel count.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use image_compression::compression::CompressionAlgorithmType;
+    /// use image_compression::compression::parallel::{ParallelCompressor, Partition};
+    ///
+    /// let compressor = ParallelCompressor::with_factory(512, 512, 3, |w, h, c| {
+    ///     Ok(Box::new(CompressionAlgorithmType::for_block(2, w, h, c)?) as _)
+    /// })
+    /// .with_tiles(128, 128)
+    /// .unwrap();
+    /// assert_eq!(compressor.get_partition(), Partition::Tiles(128, 128));
+    /// ```
+    pub fn with_factory<F>(width: u32, height: u32, channels: u8, factory: F) -> Self
+    where
+        F: Fn(u32, u32, u8) -> Result<Box<dyn Compressor + Send + Sync>, CompressionError> + Send + Sync + 'static,
+    {
+        Self::with_codec(width, height, channels, PartCodec::custom(factory))
+    }
+
+    /// Creates a new `ParallelCompressor` compressing every part with the algorithm identified by `id`.
+    ///
+    /// Each part's compressor is built by
+    /// [`CompressionAlgorithmType::for_block`](super::CompressionAlgorithmType::for_block), and `id` is
+    /// recorded in the header so [`ParallelCompressor::from_header`] can decode the output.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use image_compression::compression::Compressor;
+    /// use image_compression::compression::parallel::ParallelCompressor;
+    ///
+    /// let pixels: Vec<u8> = (0..64 * 64).map(|i| (i % 64) as u8).collect();
+    /// let compressed = ParallelCompressor::for_algorithm(64, 64, 1, 2).compress(&pixels).unwrap();
+    /// let decoder = ParallelCompressor::from_header(&compressed).unwrap();
+    /// assert_eq!(decoder.decompress(&compressed).unwrap(), pixels);
+    /// ```
+    pub fn for_algorithm(width: u32, height: u32, channels: u8, id: u8) -> Self {
+        Self::with_codec(width, height, channels, PartCodec::for_algorithm(id))
+    }
+
+    /// Creates a compressor decoding `data` with the algorithm and geometry recorded in its header.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the compressor, or a `CompressionError` if the header is invalid
+    /// or the parts were compressed by a custom compressor.
+    pub fn from_header(data: &[u8]) -> Result<Self, CompressionError> {
+        let header = Header::parse(data)?;
+        let codec = PartCodec::from_header_id(header.algorithm)?;
+        Ok(Self::with_codec(header.width, header.height, header.channels, codec))
+    }
+
+    /// Records `id` as the algorithm a custom factory builds, so [`ParallelCompressor::from_header`]
+    /// can decode the output.
+    pub fn with_algorithm_id(mut self, id: u8) -> Self {
+        self.codec = self.codec.with_algorithm_id(id);
+        self
+    }
+
+    fn with_codec(width: u32, height: u32, channels: u8, codec: PartCodec) -> Self {
+        ParallelCompressor {
+            width,
+            height,
+            channels,
+            partition: Partition::Strips(DEFAULT_STRIP_ROWS),
+            threads: 0,
+            pool: None,
+            codec,
+        }
+    }
+
+    /// Splits the image into full-width strips of `rows` rows.
+    pub fn with_strips(self, rows: u32) -> Result<Self, CompressionError> {
+        self.with_partition(Partition::Strips(rows))
+    }
+
+    /// Splits the image into `width` x `height` tiles.
+    pub fn with_tiles(self, width: u32, height: u32) -> Result<Self, CompressionError> {
+        self.with_partition(Partition::Tiles(width, height))
+    }
+
+    /// Sets how the image is split into parts.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the compressor or `CompressionError::InvalidLevel` if a part dimension is zero.
+    pub fn with_partition(mut self, partition: Partition) -> Result<Self, CompressionError> {
+        let (width, height) = partition.part_size(1);
+        if width == 0 || height == 0 {
+            return Err(CompressionError::InvalidLevel(format!("Part size must be positive: {:?}", partition)));
+        }
+        self.partition = partition;
+        Ok(self)
+    }
+
+    /// Sets the number of worker threads; `0` uses rayon's global pool with one per CPU.
+    ///
+    /// The pool is started here once and reused by every call, including on clones. The thread
+    /// count affects speed only, never the compressed output.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the compressor or `CompressionError::Compression` if the pool could not be started.
+    pub fn with_threads(mut self, threads: usize) -> Result<Self, CompressionError> {
+        self.pool = match threads {
+            0 => None,
+            n => Some(Arc::new(
+                rayon::ThreadPoolBuilder::new()
+                    .num_threads(n)
+                    .build()
+                    .map_err(|e| CompressionError::Compression(format!("Failed to start thread pool: {}", e)))?,
+            )),
+        };
+        self.threads = threads;
+        Ok(self)
+    }
+
+    /// Retrieves how the image is split into parts.
+    pub fn get_partition(&self) -> Partition {
+        self.partition
+    }
+
+    /// Retrieves the configured number of worker threads, `0` meaning one per CPU.
+    pub fn get_threads(&self) -> usize {
+        self.threads
+    }
+
+    /// Returns a copy configured for a `width` x `height` image of the same channel count.
+    pub(crate) fn with_geometry(&self, width: u32, height: u32) -> Self {
+        ParallelCompressor {
+            width,
+            height,
+            ..self.clone()
+        }
+    }
+
+    /// Retrieves the image width, height and channel count.
+    pub fn get_dimensions(&self) -> (u32, u32, u8) {
+        (self.width, self.height, self.channels)
+    }
+
+    /// Reads the image geometry from the header of parallel-compressed data.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the width, height and channel count, or a `CompressionError` if the header is invalid.
+    pub fn read_dimensions(data: &[u8]) -> Result<(u32, u32, u8), CompressionError> {
+        let header = Header::parse(data)?;
+        Ok((header.width, header.height, header.channels))
+    }
+
+    /// Reads the identifier of the part algorithm from the header of parallel-compressed data.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the algorithm id, `None` for a custom part compressor, or a
+    /// `CompressionError` if the header is invalid.
+    pub fn read_algorithm(data: &[u8]) -> Result<Option<u8>, CompressionError> {
+        let header = Header::parse(data)?;
+        Ok(PartCodec::known(header.algorithm))
+    }
+
+    /// Decompresses the `width` x `height` region at `x`, `y` of parallel-compressed data.
+    ///
+    /// Only the parts overlapping the region are read and decompressed, so
+    /// with a tiled layout the cost grows with the region rather than the
+    /// image.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - Data written by [`Compressor::compress`].
+    /// * `x`, `y` - Top-left corner of the region in pixels.
+    /// * `width`, `height` - Size of the region in pixels.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the region's interleaved pixels, or a `CompressionError` if the
+    /// data is invalid or the region is empty or extends past the image.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use image_compression::compression::Compressor;
+    /// use image_compression::compression::lzw::LzwCompressor;
+    /// use image_compression::compression::parallel::ParallelCompressor;
+    ///
+    /// let pixels: Vec<u8> = (0..100 * 100).map(|i| (i % 100) as u8).collect();
+    /// let compressor = ParallelCompressor::new(100, 100, 1, LzwCompressor::new(4096)).with_tiles(32, 32).unwrap();
+    /// let compressed = compressor.compress(&pixels).unwrap();
+    /// let region = compressor.decode_region(&compressed, 40, 10, 3, 2).unwrap();
+    /// assert_eq!(region, vec![40, 41, 42, 40, 41, 42]);
+    /// ```
+    pub fn decode_region(&self, data: &[u8], x: u32, y: u32, width: u32, height: u32) -> Result<Vec<u8>, CompressionError> {
+        let header = Header::parse(data)?;
+        let inside = |start: u32, len: u32, limit: u32| len > 0 && start.checked_add(len).is_some_and(|end| end <= limit);
+        if !inside(x, width, header.width) || !inside(y, height, header.height) {
+            return Err(CompressionError::Decompression(format!(
+                "Region {}x{} at {},{} is outside the {}x{} image",
+                width, height, x, y, header.width, header.height
+            )));
+        }
+        self.decode_parts(data, &header, x, y, width, height)
+    }
+
+    /// Decompresses the parts overlapping a region in parallel and copies the overlap into a region buffer.
+    fn decode_parts(
+        &self,
+        data: &[u8],
+        header: &Header,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+    ) -> Result<Vec<u8>, CompressionError> {
+        let truncated = || CompressionError::Decompression("Truncated parallel-compressed data".to_string());
+        let parts = Self::parts(header.width, header.height, header.part_width, header.part_height);
+        let payload_start = HEADER_LEN + parts.len() * INDEX_ENTRY_LEN;
+        let index = data.get(HEADER_LEN..payload_start).ok_or_else(truncated)?;
+        let touched = parts
+            .iter()
+            .zip(index.chunks(INDEX_ENTRY_LEN))
+            .filter(|&(&(px, py, pw, ph), _)| px < x + width && x < px + pw && py < y + height && y < py + ph)
+            .map(|(&part, entry)| {
+                let offset = u64::from_be_bytes(entry[..8].try_into().unwrap());
+                let len = u32::from_be_bytes(entry[8..].try_into().unwrap()) as u64;
+                let start = (payload_start as u64).checked_add(offset).ok_or_else(truncated)?;
+                let start = usize::try_from(start).map_err(|_| truncated())?;
+                let end = start.checked_add(len as usize).ok_or_else(truncated)?;
+                Ok((part, data.get(start..end).ok_or_else(truncated)?))
+            })
+            .collect::<Result<Vec<_>, CompressionError>>()?;
+
+        let channels = header.channels as usize;
+        let decoded = self.install(|| {
+            touched
+                .par_iter()
+                .map(|&((px, py, pw, ph), payload)| {
+                    let part = self.codec.build(pw, ph, header.channels)?.decompress(payload)?;
+                    if part.len() != pw as usize * ph as usize * channels {
+                        return Err(CompressionError::Decompression(format!("part at {},{} has the wrong size", px, py)));
+                    }
+                    Ok(part)
+                })
+                .collect::<Result<Vec<_>, _>>()
+        })?;
+
+        let mut output = vec![0u8; checked_image_len(width as usize, height as usize, channels)?];
+        for (&((px, py, pw, ph), _), part) in touched.iter().zip(&decoded) {
+            let (left, right) = (px.max(x), (px + pw).min(x + width));
+            let row_len = (right - left) as usize * channels;
+            for row in py.max(y)..(py + ph).min(y + height) {
+                let source = ((row - py) as usize * pw as usize + (left - px) as usize) * channels;
+                let target = ((row - y) as usize * width as usize + (left - x) as usize) * channels;
+                output[target..target + row_len].copy_from_slice(&part[source..source + row_len]);
+            }
+        }
+        Ok(output)
+    }
+
+    /// Returns the parts of an image as `(x, y, width, height)` in row-major order.
+    fn parts(width: u32, height: u32, part_width: u32, part_height: u32) -> Vec<(u32, u32, u32, u32)> {
+        let mut parts = Vec::new();
+        for y in (0..height).step_by(part_height as usize) {
+            for x in (0..width).step_by(part_width as usize) {
+                parts.push((x, y, part_width.min(width - x), part_height.min(height - y)));
+            }
+        }
+        parts
+    }
+
+    /// Runs `op` on the configured pool, so its parallel iterators use that pool's threads.
+    fn install<R: Send>(&self, op: impl FnOnce() -> R + Send) -> R {
+        match &self.pool {
+            Some(pool) => pool.install(op),
+            None => op(),
+        }
+    }
+}
+
+/// The fixed-size header of parallel-compressed data.
+struct Header {
+    width: u32,
+    height: u32,
+    channels: u8,
+    part_width: u32,
+    part_height: u32,
+    count: u32,
+    algorithm: u8,
+}
+
+impl Header {
+    fn write(&self, output: &mut Vec<u8>) {
+        output.extend_from_slice(MAGIC);
+        output.extend_from_slice(&self.width.to_be_bytes());
+        output.extend_from_slice(&self.height.to_be_bytes());
+        output.push(self.channels);
+        output.extend_from_slice(&self.part_width.to_be_bytes());
+        output.extend_from_slice(&self.part_height.to_be_bytes());
+        output.extend_from_slice(&self.count.to_be_bytes());
+        output.push(self.algorithm);
+    }
+
+    fn parse(data: &[u8]) -> Result<Self, CompressionError> {
+        let invalid = || CompressionError::Decompression("Invalid parallel-compressed data".to_string());
+        if data.len() < HEADER_LEN || &data[..4] != MAGIC {
+            return Err(invalid());
+        }
+        let word = |at: usize| u32::from_be_bytes([data[at], data[at + 1], data[at + 2], data[at + 3]]);
+        let header = Header {
+            width: word(4),
+            height: word(8),
+            channels: data[12],
+            part_width: word(13),
+            part_height: word(17),
+            count: word(21),
+            algorithm: data[25],
+        };
+        if header.channels == 0 || header.part_width == 0 || header.part_height == 0 {
+            return Err(invalid());
+        }
+        checked_image_len(header.width as usize, header.height as usize, header.channels as usize)?;
+        // The index must be present for every part before the part list is built
+        let count = (header.width.div_ceil(header.part_width) as u64)
+            .checked_mul(header.height.div_ceil(header.part_height) as u64)
+            .ok_or_else(invalid)?;
+        let index_end = (header.count as u64)
+            .checked_mul(INDEX_ENTRY_LEN as u64)
+            .and_then(|len| len.checked_add(HEADER_LEN as u64));
+        if count != header.count as u64 || index_end.is_none_or(|end| end > data.len() as u64) {
+            return Err(invalid());
+        }
+        Ok(header)
+    }
+}
+
+impl Compressor for ParallelCompressor {
+    fn compress(&self, data: &[u8]) -> Result<Vec<u8>, CompressionError> {
+        let channels = self.channels as usize;
+        if data.len() != self.width as usize * self.height as usize * channels {
+            return Err(CompressionError::Compression(format!(
+                "Expected {}x{}x{} bytes, got {}",
+                self.width,
+                self.height,
+                self.channels,
+                data.len()
+            )));
+        }
+        let (part_width, part_height) = self.partition.part_size(self.width);
+        let parts = Self::parts(self.width, self.height, part_width, part_height);
+
+        let payloads = self.install(|| {
+            parts
+                .par_iter()
+                .map(|&(x, y, w, h)| {
+                    let row_len = w as usize * channels;
+                    let mut part = Vec::with_capacity(row_len * h as usize);
+                    for row in y..y + h {
+                        let start = (row as usize * self.width as usize + x as usize) * channels;
+                        part.extend_from_slice(&data[start..start + row_len]);
+                    }
+                    self.codec.build(w, h, self.channels)?.compress(&part)
+                })
+                .collect::<Result<Vec<_>, _>>()
+        })?;
+
+        let header = Header {
+            width: self.width,
+            height: self.height,
+            channels: self.channels,
+            part_width,
+            part_height,
+            count: parts.len() as u32,
+            algorithm: self.codec.algorithm(),
+        };
+        let index_len = parts.len() * INDEX_ENTRY_LEN;
+        let payload_len: usize = payloads.iter().map(Vec::len).sum();
+        let mut output = Vec::with_capacity(HEADER_LEN + index_len + payload_len);
+        header.write(&mut output);
+        let mut offset = 0u64;
+        for payload in &payloads {
+            let len = u32::try_from(payload.len())
+                .map_err(|_| CompressionError::Compression("Part exceeds 4 GiB".to_string()))?;
+            output.extend_from_slice(&offset.to_be_bytes());
+            output.extend_from_slice(&len.to_be_bytes());
+            offset += len as u64;
+        }
+        for payload in &payloads {
+            output.extend_from_slice(payload);
+        }
+        Ok(output)
+    }
+
+    /// Decompresses data using the geometry and offset index in its header.
+    fn decompress(&self, data: &[u8]) -> Result<Vec<u8>, CompressionError> {
+        let header = Header::parse(data)?;
+        self.decode_parts(data, &header, 0, 0, header.width, header.height)
+    }
+}
+
+impl fmt::Debug for ParallelCompressor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ParallelCompressor")
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .field("channels", &self.channels)
+            .field("partition", &self.partition)
+            .field("threads", &self.threads)
+            .field("algorithm", &self.codec.algorithm())
+            .finish_non_exhaustive()
+    }
+}
+
+impl fmt::Display for ParallelCompressor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let partition = match self.partition {
+            Partition::Strips(rows) => format!("Strips: {} rows", rows),
+            Partition::Tiles(width, height) => format!("Tiles: {}x{}", width, height),
+        };
+        let threads = match self.threads {
+            0 => "all".to_string(),
+            n => n.to_string(),
+        };
+        write!(
+            f,
+            "ParallelCompressor ({}x{}x{}, {}, Threads: {})",
+            self.width, self.height, self.channels, partition, threads
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compression::deflate::DeflateCompressor;
+    use crate::compression::lzw::LzwCompressor;
+    use crate::compression::CompressionAlgorithmType;
+
+    fn sample(width: u32, height: u32, channels: u8) -> Vec<u8> {
+        (0..width * height * channels as u32)
+            .map(|i| ((i % 97) as u8).wrapping_mul((i / 500) as u8 | 1))
+            .collect()
+    }
+
+    #[test]
+    fn test_output_is_independent_of_thread_count() {
+        let data = sample(203, 157, 3);
+        for partition in [Partition::Strips(16), Partition::Tiles(64, 40)] {
+            let compressor = ParallelCompressor::new(203, 157, 3, DeflateCompressor::new())
+                .with_partition(partition)
+                .unwrap();
+            let single = compressor.clone().with_threads(1).unwrap().compress(&data).unwrap();
+            for threads in [2, 3, 8] {
+                let compressor = compressor.clone().with_threads(threads).unwrap();
+                assert_eq!(compressor.compress(&data).unwrap(), single, "{:?} on {} threads", partition, threads);
+                assert_eq!(compressor.decompress(&single).unwrap(), data);
+            }
+        }
+    }
+
+    #[test]
+    fn test_geometry_aware_parts() {
+        let data: Vec<u8> = (0..96 * 80).map(|i| ((i % 96) * 2) as u8).collect();
+        let compressor = ParallelCompressor::with_factory(96, 80, 1, |w, h, c| {
+            Ok(Box::new(CompressionAlgorithmType::for_block(2, w, h, c)?) as _)
+        })
+        .with_tiles(32, 32)
+        .unwrap();
+        let compressed = compressor.compress(&data).unwrap();
+        assert_eq!(ParallelCompressor::read_dimensions(&compressed).unwrap(), (96, 80, 1));
+        assert_eq!(compressor.decompress(&compressed).unwrap(), data);
+        assert_eq!(ParallelCompressor::read_algorithm(&compressed).unwrap(), None);
+        assert!(ParallelCompressor::from_header(&compressed).is_err());
+    }
+
+    #[test]
+    fn test_header_records_the_algorithm() {
+        let data = sample(70, 50, 3);
+        for id in CompressionAlgorithmType::PIXEL_IDS.into_iter().filter(|&id| id != CompressionAlgorithmType::CCITT_ID) {
+            let compressed = ParallelCompressor::for_algorithm(70, 50, 3, id).with_tiles(32, 32).unwrap().compress(&data).unwrap();
+            assert_eq!(ParallelCompressor::read_algorithm(&compressed).unwrap(), Some(id));
+            let decoder = ParallelCompressor::from_header(&compressed).unwrap();
+            assert_eq!(decoder.get_dimensions(), (70, 50, 3));
+            assert_eq!(decoder.decompress(&compressed).unwrap(), data, "algorithm {}", id);
+        }
+    }
+
+    #[test]
+    fn test_decode_region() {
+        let data = sample(150, 90, 3);
+        let compressor = ParallelCompressor::new(150, 90, 3, DeflateCompressor::new()).with_tiles(32, 24).unwrap();
+        let compressed = compressor.compress(&data).unwrap();
+        for (x, y, w, h) in [(0, 0, 150, 90), (31, 23, 2, 2), (100, 50, 50, 40), (7, 80, 1, 1)] {
+            let expected: Vec<u8> = (y..y + h)
+                .flat_map(|row| {
+                    let start = (row * 150 + x) as usize * 3;
+                    data[start..start + w as usize * 3].to_vec()
+                })
+                .collect();
+            assert_eq!(compressor.decode_region(&compressed, x, y, w, h).unwrap(), expected, "{},{} {}x{}", x, y, w, h);
+        }
+        assert!(compressor.decode_region(&compressed, 140, 0, 11, 1).is_err());
+        assert!(compressor.decode_region(&compressed, 0, 0, 0, 5).is_err());
+
+        // Only the tiles a region touches are read, so damage elsewhere goes unnoticed.
+        let mut damaged = compressed.clone();
+        let last = damaged.len() - 1;
+        damaged[last] ^= 0xFF;
+        assert!(compressor.decompress(&damaged).is_err());
+        assert!(compressor.decode_region(&damaged, 0, 0, 32, 24).is_ok());
+    }
+
+    #[test]
+    fn test_invalid_input() {
+        let compressor = ParallelCompressor::new(10, 10, 1, LzwCompressor::new(4096));
+        assert!(compressor.compress(&[0u8; 99]).is_err());
+        assert!(compressor.clone().with_strips(0).is_err());
+        assert!(compressor.clone().with_tiles(8, 0).is_err());
+
+        let compressed = compressor.compress(&[7u8; 100]).unwrap();
+        assert!(compressor.decompress(&compressed[..compressed.len() - 1]).is_err());
+        assert!(compressor.decompress(b"PAR\x02").is_err());
+        let mut corrupt = compressed.clone();
+        corrupt[24] += 1;
+        assert!(compressor.decompress(&corrupt).is_err());
+
+        // Headers describing billions of parts fail before the part list or image is allocated
+        let mut huge = compressed[..HEADER_LEN].to_vec();
+        huge[4..8].copy_from_slice(&u32::MAX.to_be_bytes());
+        huge[8..12].copy_from_slice(&u32::MAX.to_be_bytes());
+        huge[13..17].copy_from_slice(&1u32.to_be_bytes());
+        huge[17..21].copy_from_slice(&1u32.to_be_bytes());
+        for count in [0, 1, u32::MAX] {
+            huge[21..25].copy_from_slice(&count.to_be_bytes());
+            assert!(compressor.decompress(&huge).is_err());
+        }
+        let mut sparse = compressed[..HEADER_LEN].to_vec();
+        sparse[4..8].copy_from_slice(&65_536u32.to_be_bytes());
+        sparse[8..12].copy_from_slice(&1u32.to_be_bytes());
+        sparse[13..17].copy_from_slice(&1u32.to_be_bytes());
+        sparse[21..25].copy_from_slice(&65_536u32.to_be_bytes());
+        assert!(compressor.decompress(&sparse).is_err());
+    }
+}
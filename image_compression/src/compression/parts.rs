@@ -0,0 +1,93 @@
+// src/compression/parts.rs
+
+//! Module building the compressors of the pieces an image container splits an image into.
+//!
+//! [`ParallelCompressor`](super::parallel::ParallelCompressor) compresses
+//! strips or tiles and
+//! [`ProgressiveCompressor`](super::progressive::ProgressiveCompressor)
+//! compresses interlacing passes, each with a compressor built for the
+//! piece's geometry. Both record in their header which algorithm that
+//! compressor runs, so output of a known algorithm can be decoded from the
+//! header alone. This module holds the factory and the recorded id for
+//! both containers.
+
+use super::{CompressionAlgorithmType, CompressionError, Compressor};
+use std::sync::Arc;
+
+/// Algorithm id recorded for parts compressed by a caller-supplied compressor or factory.
+pub const CUSTOM_ALGORITHM: u8 = 0xFF;
+
+/// Builds the compressor for one part from the part's width, height and channel count.
+pub type PartFactory =
+    Arc<dyn Fn(u32, u32, u8) -> Result<Box<dyn Compressor + Send + Sync>, CompressionError> + Send + Sync>;
+
+/// The factory building a container's part compressors and the algorithm id recorded for them.
+#[derive(Clone)]
+pub(crate) struct PartCodec {
+    factory: PartFactory,
+    algorithm: u8,
+}
+
+impl PartCodec {
+    /// Creates a codec compressing every part with a clone of `compressor`, recorded as custom.
+    pub(crate) fn cloning<C>(compressor: C) -> Self
+    where
+        C: Compressor + Clone + Send + Sync + 'static,
+    {
+        Self::custom(move |_, _, _| Ok(Box::new(compressor.clone()) as Box<dyn Compressor + Send + Sync>))
+    }
+
+    /// Creates a codec building each part's compressor with `factory`, recorded as custom.
+    pub(crate) fn custom<F>(factory: F) -> Self
+    where
+        F: Fn(u32, u32, u8) -> Result<Box<dyn Compressor + Send + Sync>, CompressionError> + Send + Sync + 'static,
+    {
+        PartCodec {
+            factory: Arc::new(factory),
+            algorithm: CUSTOM_ALGORITHM,
+        }
+    }
+
+    /// Creates a codec building each part's compressor with [`CompressionAlgorithmType::for_block`].
+    pub(crate) fn for_algorithm(id: u8) -> Self {
+        Self::custom(move |w, h, c| Ok(Box::new(CompressionAlgorithmType::for_block(id, w, h, c)?) as _))
+            .with_algorithm_id(id)
+    }
+
+    /// Creates the codec for an algorithm id read from a container header.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the codec, or `CompressionError::UnknownAlgorithm` if the parts
+    /// were compressed by a custom compressor or an algorithm this build does not know.
+    pub(crate) fn from_header_id(id: u8) -> Result<Self, CompressionError> {
+        if id == CUSTOM_ALGORITHM || CompressionAlgorithmType::name_of(id).is_none() {
+            return Err(CompressionError::UnknownAlgorithm(format!("algorithm id {}", id)));
+        }
+        Ok(Self::for_algorithm(id))
+    }
+
+    /// Records `id` as the algorithm the factory builds.
+    ///
+    /// Containers expose this for factories wrapping a known algorithm, for example with a
+    /// preset dictionary, so decoders can still learn the algorithm from the header.
+    pub(crate) fn with_algorithm_id(mut self, id: u8) -> Self {
+        self.algorithm = id;
+        self
+    }
+
+    /// Builds the compressor for a `width` x `height` part with `channels` channels.
+    pub(crate) fn build(&self, width: u32, height: u32, channels: u8) -> Result<Box<dyn Compressor + Send + Sync>, CompressionError> {
+        (self.factory)(width, height, channels)
+    }
+
+    /// Retrieves the algorithm id written to the header.
+    pub(crate) fn algorithm(&self) -> u8 {
+        self.algorithm
+    }
+
+    /// Converts an algorithm id read from a header to `None` for a custom part compressor.
+    pub(crate) fn known(id: u8) -> Option<u8> {
+        Some(id).filter(|&id| id != CUSTOM_ALGORITHM)
+    }
+}
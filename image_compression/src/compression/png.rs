@@ -0,0 +1,652 @@
+// src/compression/png.rs
+
+//! Module implementing a PNG optimizer that re-encodes images as smaller, standard PNG files.
+//!
+//! The optimizer looks for the cheapest lossless representation of the
+//! pixels: 16-bit samples that are really 8-bit, opaque alpha channels,
+//! grayscale images stored as RGB, images with few enough colors for a
+//! palette, and grayscale images that fit in 1, 2 or 4 bits. Every candidate
+//! is then tried with several scanline filter strategies and Deflate levels
+//! from [`DeflateCompressor`], and the smallest file wins.
+//!
+//! The winning file is decoded again and compared with the input, so an
+//! optimized PNG is only returned once pixel equality has been proven.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use image::{DynamicImage, RgbImage};
+//! use image_compression::compression::png::PngOptimizer;
+//!
+//! let image = DynamicImage::ImageRgb8(RgbImage::from_fn(16, 16, |x, _| {
+//!     if x < 8 { image::Rgb([255, 0, 0]) } else { image::Rgb([0, 0, 255]) }
+//! }));
+//! let optimized = PngOptimizer::new().optimize(&image).unwrap();
+//! let decoded = image::load_from_memory(&optimized.data).unwrap();
+//! assert_eq!(decoded.to_rgb8(), image.to_rgb8());
+//! ```
+
+use super::deflate::DeflateCompressor;
+use super::utils::{adler32, calculate_entropy};
+use super::{CompressionError, Compressor};
+use flate2::Crc;
+use image::DynamicImage;
+use std::collections::HashMap;
+use std::fmt;
+
+/// The eight-byte signature every PNG file starts with.
+pub(crate) const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+
+/// PNG color types as stored in the IHDR chunk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PngColorType {
+    Grayscale,
+    Rgb,
+    Indexed,
+    GrayscaleAlpha,
+    Rgba,
+}
+
+impl PngColorType {
+    /// Returns the IHDR color type code.
+    pub fn code(self) -> u8 {
+        match self {
+            PngColorType::Grayscale => 0,
+            PngColorType::Rgb => 2,
+            PngColorType::Indexed => 3,
+            PngColorType::GrayscaleAlpha => 4,
+            PngColorType::Rgba => 6,
+        }
+    }
+
+    /// Number of samples stored per pixel.
+    pub fn channels(self) -> usize {
+        match self {
+            PngColorType::Grayscale | PngColorType::Indexed => 1,
+            PngColorType::GrayscaleAlpha => 2,
+            PngColorType::Rgb => 3,
+            PngColorType::Rgba => 4,
+        }
+    }
+}
+
+/// The five PNG scanline filter types.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PngFilter {
+    None,
+    Sub,
+    Up,
+    Average,
+    Paeth,
+}
+
+impl PngFilter {
+    /// Every filter type, in code order.
+    pub const ALL: [PngFilter; 5] = [PngFilter::None, PngFilter::Sub, PngFilter::Up, PngFilter::Average, PngFilter::Paeth];
+
+    /// Returns the filter type byte written before each scanline.
+    pub fn code(self) -> u8 {
+        match self {
+            PngFilter::None => 0,
+            PngFilter::Sub => 1,
+            PngFilter::Up => 2,
+            PngFilter::Average => 3,
+            PngFilter::Paeth => 4,
+        }
+    }
+}
+
+impl fmt::Display for PngFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+/// How the filter type of each scanline is chosen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterStrategy {
+    /// The same PNG filter type on every row.
+    Fixed(PngFilter),
+    /// Per row, the filter with the smallest sum of absolute signed residuals.
+    MinSum,
+    /// Per row, the filter whose residuals have the lowest Shannon entropy.
+    Entropy,
+}
+
+impl FilterStrategy {
+    /// Every strategy the optimizer tries by default.
+    pub const ALL: [FilterStrategy; 7] = [
+        FilterStrategy::Fixed(PngFilter::None),
+        FilterStrategy::Fixed(PngFilter::Sub),
+        FilterStrategy::Fixed(PngFilter::Up),
+        FilterStrategy::Fixed(PngFilter::Average),
+        FilterStrategy::Fixed(PngFilter::Paeth),
+        FilterStrategy::MinSum,
+        FilterStrategy::Entropy,
+    ];
+}
+
+impl fmt::Display for FilterStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FilterStrategy::Fixed(filter) => write!(f, "{}", filter),
+            FilterStrategy::MinSum => write!(f, "MinSum"),
+            FilterStrategy::Entropy => write!(f, "Entropy"),
+        }
+    }
+}
+
+/// The smallest PNG found for an image, together with the settings that produced it.
+#[derive(Debug, Clone)]
+pub struct OptimizedPng {
+    /// The complete PNG file.
+    pub data: Vec<u8>,
+    pub color_type: PngColorType,
+    pub bit_depth: u8,
+    pub filter: FilterStrategy,
+    /// The Deflate level used for the IDAT stream.
+    pub level: u32,
+}
+
+/// A PNG optimizer that searches color reductions, filters and Deflate levels.
+#[derive(Debug, Clone)]
+pub struct PngOptimizer {
+    levels: Vec<u32>,
+}
+
+impl Default for PngOptimizer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PngOptimizer {
+    /// Creates a new `PngOptimizer` that tries Deflate levels 1 through 9.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use image_compression::compression::png::PngOptimizer;
+    ///
+    /// let optimizer = PngOptimizer::new();
+    /// assert_eq!(optimizer.get_levels(), &[1, 2, 3, 4, 5, 6, 7, 8, 9]);
+    /// ```
+    pub fn new() -> Self {
+        PngOptimizer { levels: (1..=9).collect() }
+    }
+
+    /// Creates a new `PngOptimizer` that only tries the given Deflate levels.
+    ///
+    /// # Arguments
+    ///
+    /// * `levels` - Deflate levels (0-9) to try on the best color type and filter.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the `PngOptimizer` or a `CompressionError` if a level is invalid.
+    pub fn with_levels(levels: &[u32]) -> Result<Self, CompressionError> {
+        if levels.is_empty() {
+            return Err(CompressionError::InvalidLevel("no Deflate levels given".to_string()));
+        }
+        for &level in levels {
+            DeflateCompressor::with_level_number(level)?;
+        }
+        Ok(PngOptimizer { levels: levels.to_vec() })
+    }
+
+    /// Retrieves the Deflate levels that are tried.
+    pub fn get_levels(&self) -> &[u32] {
+        &self.levels
+    }
+
+    /// Finds the smallest PNG encoding of `image` and verifies that it decodes to the same pixels.
+    ///
+    /// # Arguments
+    ///
+    /// * `image` - The image to encode. Floating point images are rejected.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the optimized PNG or a `CompressionError`.
+    pub fn optimize(&self, image: &DynamicImage) -> Result<OptimizedPng, CompressionError> {
+        let pixels = Pixels::new(image)?;
+        let filter_level = *self.levels.iter().max().expect("levels are never empty");
+
+        // Pick the color type and filter strategy at the strongest level, then tune the level.
+        let mut best: Option<(usize, Candidate, FilterStrategy, Vec<u8>)> = None;
+        for candidate in pixels.candidates() {
+            let rows = candidate.rows(&pixels);
+            for strategy in FilterStrategy::ALL {
+                let filtered = filter_image(&rows, candidate.filter_bpp(), strategy);
+                let size = deflate(&filtered, filter_level)?.len() + candidate.header_len();
+                if best.as_ref().is_none_or(|b| size < b.0) {
+                    best = Some((size, candidate.clone(), strategy, filtered));
+                }
+            }
+        }
+        let (_, candidate, filter, filtered) = best.expect("at least one candidate is always produced");
+
+        let mut best_stream: Option<(u32, Vec<u8>)> = None;
+        for &level in &self.levels {
+            let stream = deflate(&filtered, level)?;
+            if best_stream.as_ref().is_none_or(|b| stream.len() < b.1.len()) {
+                best_stream = Some((level, stream));
+            }
+        }
+        let (level, stream) = best_stream.expect("levels are never empty");
+
+        let data = candidate.write(&pixels, &zlib_wrap(&stream, &filtered, level));
+        let decoded = image::load_from_memory_with_format(&data, image::ImageFormat::Png)
+            .map_err(|e| CompressionError::Compression(format!("optimized PNG does not decode: {}", e)))?;
+        if decoded.width() != pixels.width as u32
+            || decoded.height() != pixels.height as u32
+            || decoded.to_rgba16().into_raw() != image.to_rgba16().into_raw()
+        {
+            return Err(CompressionError::Compression("optimized PNG changed the pixels".to_string()));
+        }
+
+        Ok(OptimizedPng {
+            data,
+            color_type: candidate.color_type,
+            bit_depth: candidate.bit_depth,
+            filter,
+            level,
+        })
+    }
+}
+
+/// Implement `fmt::Display` for `PngOptimizer` for better readability.
+impl fmt::Display for PngOptimizer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "PngOptimizer (Deflate Levels: {:?})", self.levels)
+    }
+}
+
+/// The input pixels as RGBA samples, keeping track of whether 16 bits are really needed.
+struct Pixels {
+    width: usize,
+    height: usize,
+    /// RGBA samples scaled to 16 bits.
+    samples: Vec<[u16; 4]>,
+    sixteen_bit: bool,
+}
+
+impl Pixels {
+    fn new(image: &DynamicImage) -> Result<Self, CompressionError> {
+        let color = image.color();
+        if matches!(color, image::ColorType::Rgb32F | image::ColorType::Rgba32F) {
+            return Err(CompressionError::Compression("PNG cannot store floating point samples".to_string()));
+        }
+        let samples: Vec<[u16; 4]> = image
+            .to_rgba16()
+            .pixels()
+            .map(|p| p.0)
+            .collect();
+        let sixteen_bit = color.bytes_per_pixel() / color.channel_count() == 2
+            && samples.iter().flatten().any(|&s| s % 257 != 0);
+        Ok(Pixels {
+            width: image.width() as usize,
+            height: image.height() as usize,
+            samples,
+            sixteen_bit,
+        })
+    }
+
+    /// Lists every color type and bit depth that can represent the pixels exactly.
+    fn candidates(&self) -> Vec<Candidate> {
+        let opaque = self.samples.iter().all(|p| p[3] == u16::MAX);
+        let gray = self.samples.iter().all(|p| p[0] == p[1] && p[1] == p[2]);
+        let depth = if self.sixteen_bit { 16 } else { 8 };
+        let candidate = |color_type, bit_depth| Candidate {
+            color_type,
+            bit_depth,
+            palette: Vec::new(),
+        };
+
+        let mut candidates = Vec::new();
+        match (gray, opaque) {
+            (true, true) => candidates.push(candidate(PngColorType::Grayscale, depth)),
+            (true, false) => candidates.push(candidate(PngColorType::GrayscaleAlpha, depth)),
+            (false, true) => candidates.push(candidate(PngColorType::Rgb, depth)),
+            (false, false) => candidates.push(candidate(PngColorType::Rgba, depth)),
+        }
+        if gray && opaque && !self.sixteen_bit {
+            // Use the smallest depth whose scaled sample values cover every gray level present.
+            for low_depth in [1u8, 2, 4] {
+                let scale = 255 / ((1u16 << low_depth) - 1);
+                if self.samples.iter().all(|p| (p[0] >> 8) % scale == 0) {
+                    candidates.push(candidate(PngColorType::Grayscale, low_depth));
+                    break;
+                }
+            }
+        }
+        if !self.sixteen_bit {
+            if let Some(palette) = self.palette() {
+                let bit_depth = match palette.len() {
+                    0..=2 => 1,
+                    3..=4 => 2,
+                    5..=16 => 4,
+                    _ => 8,
+                };
+                candidates.push(Candidate {
+                    color_type: PngColorType::Indexed,
+                    bit_depth,
+                    palette,
+                });
+            }
+        }
+        candidates
+    }
+
+    /// Builds a palette of at most 256 colors, translucent entries first so tRNS stays short.
+    fn palette(&self) -> Option<Vec<[u8; 4]>> {
+        let mut counts: HashMap<[u8; 4], usize> = HashMap::new();
+        for p in &self.samples {
+            *counts.entry(p.map(|s| (s >> 8) as u8)).or_default() += 1;
+            if counts.len() > 256 {
+                return None;
+            }
+        }
+        let mut palette: Vec<([u8; 4], usize)> = counts.into_iter().collect();
+        palette.sort_by_key(|&(color, count)| (color[3] == 255, std::cmp::Reverse(count), color));
+        Some(palette.into_iter().map(|(color, _)| color).collect())
+    }
+}
+
+/// One color type and bit depth the pixels can be stored in.
+#[derive(Debug, Clone)]
+struct Candidate {
+    color_type: PngColorType,
+    bit_depth: u8,
+    palette: Vec<[u8; 4]>,
+}
+
+impl Candidate {
+    /// Bytes per complete pixel, rounded up to one, as used by the PNG filters.
+    fn filter_bpp(&self) -> usize {
+        (self.color_type.channels() * self.bit_depth as usize).div_ceil(8)
+    }
+
+    /// Size of everything in the file except the IDAT payload.
+    fn header_len(&self) -> usize {
+        let palette = if self.palette.is_empty() {
+            0
+        } else {
+            12 + 3 * self.palette.len() + 12 + self.palette.iter().filter(|c| c[3] != 255).count()
+        };
+        PNG_SIGNATURE.len() + 25 + palette + 12 + 6 + 12
+    }
+
+    /// Packs the pixels into unfiltered scanlines.
+    fn rows(&self, pixels: &Pixels) -> Vec<Vec<u8>> {
+        let index: HashMap<[u8; 4], u8> = self
+            .palette
+            .iter()
+            .enumerate()
+            .map(|(i, &color)| (color, i as u8))
+            .collect();
+        let row_len = (pixels.width * self.color_type.channels() * self.bit_depth as usize).div_ceil(8);
+        pixels
+            .samples
+            .chunks(pixels.width.max(1))
+            .take(pixels.height)
+            .map(|row| {
+                let mut samples: Vec<u16> = Vec::with_capacity(row.len() * 4);
+                for p in row {
+                    match self.color_type {
+                        PngColorType::Grayscale => samples.push(p[0]),
+                        PngColorType::GrayscaleAlpha => samples.extend_from_slice(&[p[0], p[3]]),
+                        PngColorType::Rgb => samples.extend_from_slice(&p[..3]),
+                        PngColorType::Rgba => samples.extend_from_slice(p),
+                        PngColorType::Indexed => samples.push(index[&p.map(|s| (s >> 8) as u8)] as u16),
+                    }
+                }
+                let mut out = Vec::with_capacity(row_len);
+                match (self.bit_depth, self.color_type) {
+                    (16, _) => samples.iter().for_each(|s| out.extend_from_slice(&s.to_be_bytes())),
+                    (8, PngColorType::Indexed) => out.extend(samples.iter().map(|&s| s as u8)),
+                    (8, _) => out.extend(samples.iter().map(|&s| (s >> 8) as u8)),
+                    (depth, color_type) => {
+                        let per_byte = 8 / depth as usize;
+                        for chunk in samples.chunks(per_byte) {
+                            let mut byte = 0u8;
+                            for (i, &s) in chunk.iter().enumerate() {
+                                let value = if color_type == PngColorType::Indexed {
+                                    s as u8
+                                } else {
+                                    ((s >> 8) / (255 / ((1u16 << depth) - 1))) as u8
+                                };
+                                byte |= value << (8 - depth as usize * (i + 1));
+                            }
+                            out.push(byte);
+                        }
+                    }
+                }
+                out
+            })
+            .collect()
+    }
+
+    /// Assembles the PNG file around an already compressed zlib stream.
+    fn write(&self, pixels: &Pixels, zlib: &[u8]) -> Vec<u8> {
+        let mut png = PNG_SIGNATURE.to_vec();
+        let mut ihdr = Vec::with_capacity(13);
+        ihdr.extend_from_slice(&(pixels.width as u32).to_be_bytes());
+        ihdr.extend_from_slice(&(pixels.height as u32).to_be_bytes());
+        ihdr.extend_from_slice(&[self.bit_depth, self.color_type.code(), 0, 0, 0]);
+        write_chunk(&mut png, b"IHDR", &ihdr);
+        if !self.palette.is_empty() {
+            let plte: Vec<u8> = self.palette.iter().flat_map(|c| [c[0], c[1], c[2]]).collect();
+            write_chunk(&mut png, b"PLTE", &plte);
+            let trns: Vec<u8> = self.palette.iter().map(|c| c[3]).take_while(|&a| a != 255).collect();
+            if !trns.is_empty() {
+                write_chunk(&mut png, b"tRNS", &trns);
+            }
+        }
+        write_chunk(&mut png, b"IDAT", zlib);
+        write_chunk(&mut png, b"IEND", &[]);
+        png
+    }
+}
+
+/// Appends a chunk with its length and CRC to `png`.
+pub(crate) fn write_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let mut crc = Crc::new();
+    crc.update(kind);
+    crc.update(data);
+    png.extend_from_slice(&crc.sum().to_be_bytes());
+}
+
+/// Wraps a raw Deflate stream of `data` in a zlib header and Adler-32 trailer.
+///
+/// The header's level hint matches what `flate2` writes for `level`, so the
+/// stream is identical to a `flate2` zlib encoder's output.
+pub(crate) fn zlib_wrap(deflate: &[u8], data: &[u8], level: u32) -> Vec<u8> {
+    let mut zlib = Vec::with_capacity(deflate.len() + 6);
+    zlib.extend_from_slice(match level {
+        0..=1 => &[0x78, 0x01],
+        2..=3 => &[0x78, 0x5E],
+        4..=8 => &[0x78, 0x9C],
+        _ => &[0x78, 0xDA],
+    });
+    zlib.extend_from_slice(deflate);
+    zlib.extend_from_slice(&adler32(data).to_be_bytes());
+    zlib
+}
+
+fn deflate(data: &[u8], level: u32) -> Result<Vec<u8>, CompressionError> {
+    DeflateCompressor::with_level_number(level)?.compress(data)
+}
+
+/// The Paeth predictor from the PNG specification.
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let (pa, pb, pc) = ((p - a as i16).abs(), (p - b as i16).abs(), (p - c as i16).abs());
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+/// Applies PNG filter type `filter` to `row`, appending the filter byte and residuals to `out`.
+pub(crate) fn filter_row(filter: u8, row: &[u8], prev: &[u8], bpp: usize, out: &mut Vec<u8>) {
+    out.push(filter);
+    for i in 0..row.len() {
+        let a = if i >= bpp { row[i - bpp] } else { 0 };
+        let b = prev.get(i).copied().unwrap_or(0);
+        let c = if i >= bpp { prev.get(i - bpp).copied().unwrap_or(0) } else { 0 };
+        let predicted = match filter {
+            0 => 0,
+            1 => a,
+            2 => b,
+            3 => ((a as u16 + b as u16) / 2) as u8,
+            _ => paeth(a, b, c),
+        };
+        out.push(row[i].wrapping_sub(predicted));
+    }
+}
+
+/// Reverses PNG filter type `filter` in place, given the already reconstructed previous row.
+pub(crate) fn unfilter_row(filter: u8, row: &mut [u8], prev: &[u8], bpp: usize) {
+    for i in 0..row.len() {
+        let a = if i >= bpp { row[i - bpp] } else { 0 };
+        let b = prev.get(i).copied().unwrap_or(0);
+        let c = if i >= bpp { prev.get(i - bpp).copied().unwrap_or(0) } else { 0 };
+        let predicted = match filter {
+            0 => 0,
+            1 => a,
+            2 => b,
+            3 => ((a as u16 + b as u16) / 2) as u8,
+            _ => paeth(a, b, c),
+        };
+        row[i] = row[i].wrapping_add(predicted);
+    }
+}
+
+/// Filters every row of an image with the given strategy.
+fn filter_image(rows: &[Vec<u8>], bpp: usize, strategy: FilterStrategy) -> Vec<u8> {
+    let row_len = rows.first().map_or(0, Vec::len);
+    let mut out = Vec::with_capacity(rows.len() * (row_len + 1));
+    let empty = vec![0u8; row_len];
+    let mut trial = Vec::with_capacity(row_len + 1);
+    for (y, row) in rows.iter().enumerate() {
+        let prev = if y > 0 { &rows[y - 1] } else { &empty };
+        let filter = choose_filter(row, prev, bpp, strategy, &mut trial);
+        filter_row(filter, row, prev, bpp, &mut out);
+    }
+    out
+}
+
+/// Returns the filter type `strategy` picks for `row`, using `trial` as scratch space.
+pub(crate) fn choose_filter(row: &[u8], prev: &[u8], bpp: usize, strategy: FilterStrategy, trial: &mut Vec<u8>) -> u8 {
+    match strategy {
+        FilterStrategy::Fixed(filter) => filter.code(),
+        FilterStrategy::MinSum | FilterStrategy::Entropy => {
+            let mut best = (f64::MAX, 0);
+            for filter in 0..5 {
+                trial.clear();
+                filter_row(filter, row, prev, bpp, trial);
+                let cost = if strategy == FilterStrategy::MinSum {
+                    trial[1..].iter().map(|&r| (r as i8).unsigned_abs() as f64).sum()
+                } else {
+                    calculate_entropy(&trial[1..])
+                };
+                if cost < best.0 {
+                    best = (cost, filter);
+                }
+            }
+            best.1
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{GrayImage, Rgb, RgbImage, Rgba, RgbaImage};
+
+    fn noise(x: u32, y: u32) -> u32 {
+        (x.wrapping_mul(374_761_393) ^ y.wrapping_mul(668_265_263)).wrapping_mul(2_654_435_761) >> 24
+    }
+
+    fn optimize(image: &DynamicImage) -> OptimizedPng {
+        let optimized = PngOptimizer::new().optimize(image).unwrap();
+        let decoded = image::load_from_memory(&optimized.data).unwrap();
+        assert_eq!(decoded.to_rgba16().into_raw(), image.to_rgba16().into_raw());
+        optimized
+    }
+
+    #[test]
+    fn test_reduces_rgb_gray_to_low_bit_depth() {
+        let image = DynamicImage::ImageRgb8(RgbImage::from_fn(97, 61, |x, y| {
+            let v = if noise(x, y) < 128 { 255 } else { 0 };
+            Rgb([v, v, v])
+        }));
+        let optimized = optimize(&image);
+        assert_eq!(optimized.color_type, PngColorType::Grayscale);
+        assert_eq!(optimized.bit_depth, 1);
+    }
+
+    #[test]
+    fn test_palette_keeps_transparent_pixels_exact() {
+        let colors = [Rgba([10, 20, 30, 0]), Rgba([200, 0, 0, 128]), Rgba([0, 90, 255, 255]), Rgba([1, 2, 3, 255]), Rgba([7, 7, 9, 0])];
+        let image = DynamicImage::ImageRgba8(RgbaImage::from_fn(64, 48, |x, y| colors[(noise(x, y) % 5) as usize]));
+        let optimized = optimize(&image);
+        assert_eq!(optimized.color_type, PngColorType::Indexed);
+        assert_eq!(optimized.bit_depth, 4);
+    }
+
+    #[test]
+    fn test_sixteen_bit_input() {
+        let exact = DynamicImage::ImageRgb16(image::ImageBuffer::from_fn(9, 9, |x, y| {
+            image::Rgb([(x * 20 * 257) as u16, (y * 257) as u16, 0])
+        }));
+        assert_eq!(optimize(&exact).bit_depth, 8);
+
+        let deep = DynamicImage::ImageLuma16(image::ImageBuffer::from_fn(9, 9, |x, y| image::Luma([(x * 1000 + y) as u16])));
+        let optimized = optimize(&deep);
+        assert_eq!((optimized.color_type, optimized.bit_depth), (PngColorType::Grayscale, 16));
+    }
+
+    #[test]
+    fn test_smaller_than_plain_encoder() {
+        let image = DynamicImage::ImageLuma8(GrayImage::from_fn(128, 96, |x, y| {
+            image::Luma([((x * 2 + y) / 4 * 4) as u8])
+        }));
+        let mut plain = Vec::new();
+        image
+            .write_to(&mut std::io::Cursor::new(&mut plain), image::ImageFormat::Png)
+            .unwrap();
+        let optimized = optimize(&image);
+        assert!(optimized.data.len() < plain.len(), "{} >= {}", optimized.data.len(), plain.len());
+    }
+
+    #[test]
+    fn test_fixed_filters_round_trip() {
+        let rows: Vec<Vec<u8>> = (0..4).map(|y| (0..12).map(|x| (noise(x, y) / 2 + x * 9) as u8).collect()).collect();
+        for filter in PngFilter::ALL {
+            let filtered = filter_image(&rows, 3, FilterStrategy::Fixed(filter));
+            assert!(filtered.chunks(13).all(|line| line[0] == filter.code()));
+            let mut png = PNG_SIGNATURE.to_vec();
+            write_chunk(&mut png, b"IHDR", &[0, 0, 0, 4, 0, 0, 0, 4, 8, 2, 0, 0, 0]);
+            write_chunk(&mut png, b"IDAT", &zlib_wrap(&deflate(&filtered, 6).unwrap(), &filtered, 6));
+            write_chunk(&mut png, b"IEND", &[]);
+            let decoded = image::load_from_memory(&png).unwrap().to_rgb8();
+            assert_eq!(decoded.into_raw(), rows.concat());
+        }
+        assert_eq!(FilterStrategy::Fixed(PngFilter::Paeth).to_string(), "Paeth");
+    }
+
+    #[test]
+    fn test_invalid_levels() {
+        assert!(PngOptimizer::with_levels(&[]).is_err());
+        assert!(PngOptimizer::with_levels(&[12]).is_err());
+        assert_eq!(PngOptimizer::with_levels(&[6]).unwrap().get_levels(), &[6]);
+    }
+}
@@ -0,0 +1,985 @@
+// src/compression/png_recompress.rs
+
+//! Module implementing bit-exact recompression of existing PNG files.
+//!
+//! Like precomp, the compressor inflates the zlib stream spread over the IDAT
+//! chunks and searches for the Deflate encoder settings that reproduce it
+//! byte for byte. When they are found, only the settings need to be kept: the
+//! pixels are stored with a stronger codec from this crate (the reversible
+//! [`WaveletCompressor`] for 8-bit images, best-level Deflate otherwise) and
+//! the remaining chunks are kept verbatim as a skeleton.
+//!
+//! Decompression re-filters the pixels with the original per-row filter
+//! types, re-runs the recorded encoder and splits the stream back into the
+//! original IDAT chunks. Streams no known encoder reproduces are kept as they
+//! are, and the result is always checked against the input before it is used.
+//!
+//! The search covers the encoders this crate links: `fdeflate`, as used by
+//! the `png` crate, `miniz_oxide` at every level and strategy, and zlib
+//! itself, as used by libpng and most other tools, at every level, memory
+//! level, window size and strategy the stream's zlib header allows. The
+//! header is stored as is. Streams none of them reproduce fall back to
+//! verbatim storage; see [`PngRecompressor::encoder_of`].
+//!
+//! A rebuilt stream is only as exact as the encoder that rebuilds it, so the
+//! container records a fingerprint of the encoders that packed it and a CRC
+//! of the original file. Restoring with encoders that behave differently, or
+//! getting any byte wrong, is an error rather than a different file.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use image::{DynamicImage, RgbImage};
+//! use image_compression::compression::Compressor;
+//! use image_compression::compression::png_recompress::PngRecompressor;
+//!
+//! let image = DynamicImage::ImageRgb8(RgbImage::from_fn(32, 32, |x, y| image::Rgb([x as u8 * 8, y as u8 * 8, 90])));
+//! let mut png = Vec::new();
+//! image.write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png).unwrap();
+//!
+//! let recompressor = PngRecompressor::new();
+//! let packed = recompressor.compress(&png).unwrap();
+//! assert_eq!(recompressor.decompress(&packed).unwrap(), png);
+//! ```
+
+use super::deflate::DeflateCompressor;
+use super::png::{filter_row, unfilter_row, PNG_SIGNATURE};
+use super::utils::{adler32, MAX_DECODED_LEN};
+use super::wavelet::WaveletCompressor;
+use super::{CompressionError, Compressor};
+use flate2::Crc;
+use libz_sys as zlib;
+use miniz_oxide::deflate::core::{compress_to_output, create_comp_flags_from_zip_params, CompressorOxide, TDEFLFlush, TDEFLStatus};
+use std::alloc::{self, Layout};
+use std::ffi::CStr;
+use std::fmt;
+use std::io::{self, Cursor, Read, Write};
+use std::os::raw::c_int;
+use std::ptr;
+use std::sync::OnceLock;
+
+const MAGIC: &[u8; 4] = b"PRC\x02";
+/// Length of the magic, the mode byte and the CRC of the original file.
+const PREFIX_LEN: usize = 9;
+const MODE_STORED: u8 = 0;
+const MODE_MODELED: u8 = 1;
+
+/// How the pixel data of a modelled PNG is stored.
+const PIXELS_DEFLATE: u8 = 0;
+const PIXELS_WAVELET: u8 = 1;
+
+/// Deflate encoders whose output can be reproduced exactly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ZlibEncoderKind {
+    /// No known encoder matched; the zlib stream is kept as is.
+    Verbatim,
+    /// The `fdeflate` fast encoder used by the `png` crate's default setting.
+    Fdeflate,
+    /// The `fdeflate` stored-only encoder the `png` crate falls back to for incompressible data.
+    FdeflateStored,
+    /// `miniz_oxide`, the backend of `flate2`, at a level (0-10) and strategy (0-4, as in zlib).
+    Miniz { level: u8, strategy: u8 },
+    /// zlib at a level (0-9), memory level (1-9), window size (9-15 bits) and strategy (0-4).
+    Zlib { level: u8, mem_level: u8, window_bits: u8, strategy: u8 },
+}
+
+/// Names of the `miniz_oxide` strategies, by zlib strategy number.
+const STRATEGIES: [&str; 5] = ["default", "filtered", "huffman-only", "rle", "fixed"];
+
+/// First encoder id of the zlib settings, which follow each other in [`ZlibEncoderKind::id`] order.
+const ZLIB_IDS: u16 = 256;
+
+impl ZlibEncoderKind {
+    /// Every reproducible encoder whose output can start with the 2-byte zlib `header`, most common first.
+    ///
+    /// The header pins zlib's window size and narrows its level; the other encoders ignore it.
+    fn candidates(header: [u8; 2]) -> impl Iterator<Item = ZlibEncoderKind> {
+        let common = [(6, 0), (9, 0), (1, 0)].map(|(level, strategy)| ZlibEncoderKind::Miniz { level, strategy });
+        let rest = (0..=10u8)
+            .flat_map(|level| {
+                // Level 0 only writes stored blocks, whatever the strategy.
+                let strategies = if level == 0 { 0..1 } else { 0..STRATEGIES.len() as u8 };
+                strategies.map(move |strategy| ZlibEncoderKind::Miniz { level, strategy })
+            })
+            .filter(move |kind| !common.contains(kind));
+        // zlib writes its window size minus 8 (never below 9) and a level class into the header.
+        let window_bits = (header[0] >> 4).max(1) + 8;
+        let level_class = header[1] >> 6;
+        // libpng's defaults first: memory level 8 and the filtered strategy.
+        let zlib = (0..=9u8)
+            .flat_map(|level| [8, 9, 7, 6, 5, 4, 3, 2, 1].map(|mem_level| (level, mem_level)))
+            .flat_map(|(level, mem_level)| [1, 0, 2, 3, 4].map(|strategy| (level, mem_level, strategy)))
+            .filter(move |&(level, _, strategy)| zlib_level_class(level, strategy) == level_class && header[0] & 0x0f == 8)
+            .map(move |(level, mem_level, strategy)| ZlibEncoderKind::Zlib { level, mem_level, window_bits, strategy });
+        [ZlibEncoderKind::Fdeflate]
+            .into_iter()
+            .chain(common)
+            .chain([ZlibEncoderKind::FdeflateStored])
+            .chain(rest)
+            .chain(zlib)
+    }
+
+    fn id(self) -> u16 {
+        match self {
+            ZlibEncoderKind::Verbatim => 0,
+            ZlibEncoderKind::Fdeflate => 1,
+            ZlibEncoderKind::FdeflateStored => 2,
+            ZlibEncoderKind::Miniz { level, strategy } => 16 + (level * STRATEGIES.len() as u8 + strategy) as u16,
+            ZlibEncoderKind::Zlib { level, mem_level, window_bits, strategy } => {
+                let settings = (level as u16 * 9 + mem_level as u16 - 1) * 7 + window_bits as u16 - 9;
+                ZLIB_IDS + settings * STRATEGIES.len() as u16 + strategy as u16
+            }
+        }
+    }
+
+    fn from_id(id: u16) -> Result<Self, CompressionError> {
+        let strategies = STRATEGIES.len() as u16;
+        match id {
+            0 => Ok(ZlibEncoderKind::Verbatim),
+            1 => Ok(ZlibEncoderKind::Fdeflate),
+            2 => Ok(ZlibEncoderKind::FdeflateStored),
+            16..=70 => Ok(ZlibEncoderKind::Miniz {
+                level: ((id - 16) / strategies) as u8,
+                strategy: ((id - 16) % strategies) as u8,
+            }),
+            _ if (ZLIB_IDS..ZLIB_IDS + 10 * 9 * 7 * strategies).contains(&id) => {
+                let (settings, strategy) = ((id - ZLIB_IDS) / strategies, (id - ZLIB_IDS) % strategies);
+                Ok(ZlibEncoderKind::Zlib {
+                    level: (settings / 63) as u8,
+                    mem_level: (settings / 7 % 9 + 1) as u8,
+                    window_bits: (settings % 7 + 9) as u8,
+                    strategy: strategy as u8,
+                })
+            }
+            _ => Err(CompressionError::Decompression(format!("Unknown zlib encoder {}", id))),
+        }
+    }
+
+    /// Compresses `data`, written in `row_len` sized pieces as PNG encoders feed their scanlines.
+    ///
+    /// The result is the zlib stream without its 2-byte header. When `expected` is given,
+    /// encoding stops as soon as the output departs from it and `None` is returned.
+    fn encode(self, data: &[u8], row_len: usize, expected: Option<&[u8]>) -> Result<Option<Vec<u8>>, CompressionError> {
+        let io = |e: io::Error| CompressionError::Compression(e.to_string());
+        let mut output = Matching { output: Vec::new(), skip: 2, expected, diverged: false };
+        let rows = data.chunks(row_len.max(1));
+        match self {
+            ZlibEncoderKind::Verbatim => {
+                return Err(CompressionError::Compression("verbatim streams cannot be encoded".to_string()))
+            }
+            ZlibEncoderKind::Fdeflate => {
+                let mut compressor = fdeflate::Compressor::new(&mut output).map_err(io)?;
+                for row in rows {
+                    compressor.write_data(row).map_err(io)?;
+                }
+                compressor.finish().map_err(io)?;
+            }
+            ZlibEncoderKind::FdeflateStored => {
+                let result = fdeflate::StoredOnlyCompressor::new(Cursor::new(Vec::new())).and_then(|mut compressor| {
+                    rows.clone().try_for_each(|row| compressor.write_data(row))?;
+                    compressor.finish()
+                });
+                output.write_all(&result.map_err(io)?.into_inner()).map_err(io)?;
+            }
+            ZlibEncoderKind::Miniz { level, strategy } => {
+                output.skip = 0;
+                let flags = create_comp_flags_from_zip_params(level as i32, 0, strategy as i32);
+                let mut compressor = CompressorOxide::new(flags);
+                // Returning false from the callback stops the encoder at the first difference.
+                let (status, _) = compress_to_output(&mut compressor, data, TDEFLFlush::Finish, |chunk| {
+                    output.write_all(chunk).is_ok() && !output.diverged
+                });
+                if !output.diverged && status != TDEFLStatus::Done {
+                    return Err(CompressionError::Compression(format!("miniz_oxide failed: {:?}", status)));
+                }
+                output.write_all(&adler32(data).to_be_bytes()).map_err(io)?;
+            }
+            ZlibEncoderKind::Zlib { level, mem_level, window_bits, strategy } => {
+                let mut deflate = ZlibDeflate::new(level, mem_level, window_bits, strategy)?;
+                deflate.finish(data, |chunk| output.write_all(chunk).is_ok() && !output.diverged)?;
+            }
+        }
+        Ok(output.finish())
+    }
+
+    /// Fingerprints the output of every candidate encoder on a fixed probe input.
+    ///
+    /// Packed files record it, so restoring with encoders that would rebuild different
+    /// streams, such as after a dependency update, is detected up front.
+    fn fingerprint() -> u32 {
+        static FINGERPRINT: OnceLock<u32> = OnceLock::new();
+        *FINGERPRINT.get_or_init(|| {
+            let probe: Vec<u8> = (0..24_000u32)
+                .map(|i| if i % 7 == 0 { (i.wrapping_mul(2_654_435_761) >> 24) as u8 } else { (i / 97) as u8 })
+                .collect();
+            let mut crc = Crc::new();
+            // SAFETY: zlibVersion returns a static NUL-terminated string.
+            crc.update(unsafe { CStr::from_ptr(zlib::zlibVersion()) }.to_bytes());
+            for kind in ZlibEncoderKind::candidates([0x78, 0x9c]) {
+                crc.update(&kind.id().to_be_bytes());
+                crc.update(&kind.encode(&probe, 301, None).ok().flatten().unwrap_or_default());
+            }
+            crc.sum()
+        })
+    }
+}
+
+impl fmt::Display for ZlibEncoderKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ZlibEncoderKind::Verbatim => write!(f, "verbatim"),
+            ZlibEncoderKind::Fdeflate => write!(f, "fdeflate"),
+            ZlibEncoderKind::FdeflateStored => write!(f, "fdeflate stored"),
+            ZlibEncoderKind::Miniz { level, strategy } => {
+                write!(f, "miniz_oxide level {} {}", level, STRATEGIES[*strategy as usize])
+            }
+            ZlibEncoderKind::Zlib { level, mem_level, window_bits, strategy } => write!(
+                f,
+                "zlib level {} memLevel {} windowBits {} {}",
+                level, mem_level, window_bits, STRATEGIES[*strategy as usize]
+            ),
+        }
+    }
+}
+
+/// Returns the level class zlib writes into the header for `level` and `strategy`.
+fn zlib_level_class(level: u8, strategy: u8) -> u8 {
+    match level {
+        _ if strategy >= 2 || level < 2 => 0,
+        2..=5 => 1,
+        6 => 2,
+        _ => 3,
+    }
+}
+
+/// A zlib Deflate stream with settings `flate2` does not expose, such as the memory level.
+struct ZlibDeflate {
+    /// Boxed because zlib keeps a pointer to the stream.
+    stream: Box<zlib::z_stream>,
+}
+
+/// Bytes in front of every zlib allocation, holding its size; enough for any alignment zlib needs.
+const ALLOC_HEADER: usize = 16;
+
+unsafe extern "C" fn zlib_alloc(_: zlib::voidpf, items: zlib::uInt, size: zlib::uInt) -> zlib::voidpf {
+    let len = (items as usize).checked_mul(size as usize).and_then(|len| len.checked_add(ALLOC_HEADER));
+    let Some(layout) = len.and_then(|len| Layout::from_size_align(len, ALLOC_HEADER).ok()) else {
+        return ptr::null_mut();
+    };
+    let memory = alloc::alloc(layout);
+    if memory.is_null() {
+        return ptr::null_mut();
+    }
+    (memory as *mut usize).write(layout.size());
+    memory.add(ALLOC_HEADER) as zlib::voidpf
+}
+
+unsafe extern "C" fn zlib_free(_: zlib::voidpf, address: zlib::voidpf) {
+    if address.is_null() {
+        return;
+    }
+    let memory = (address as *mut u8).sub(ALLOC_HEADER);
+    let len = (memory as *mut usize).read();
+    alloc::dealloc(memory, Layout::from_size_align_unchecked(len, ALLOC_HEADER));
+}
+
+impl ZlibDeflate {
+    fn new(level: u8, mem_level: u8, window_bits: u8, strategy: u8) -> Result<Self, CompressionError> {
+        let mut stream = Box::new(zlib::z_stream {
+            next_in: ptr::null_mut(),
+            avail_in: 0,
+            total_in: 0,
+            next_out: ptr::null_mut(),
+            avail_out: 0,
+            total_out: 0,
+            msg: ptr::null_mut(),
+            state: ptr::null_mut(),
+            zalloc: zlib_alloc,
+            zfree: zlib_free,
+            opaque: ptr::null_mut(),
+            data_type: 0,
+            adler: 0,
+            reserved: 0,
+        });
+        // SAFETY: the stream is fully initialized and boxed, so it stays put until deflateEnd.
+        let status = unsafe {
+            zlib::deflateInit2_(
+                &mut *stream,
+                level as c_int,
+                zlib::Z_DEFLATED,
+                window_bits as c_int,
+                mem_level as c_int,
+                strategy as c_int,
+                zlib::zlibVersion(),
+                std::mem::size_of::<zlib::z_stream>() as c_int,
+            )
+        };
+        if status != zlib::Z_OK {
+            return Err(error(&format!("zlib rejected its settings ({})", status)));
+        }
+        Ok(ZlibDeflate { stream })
+    }
+
+    /// Compresses `data` into a complete zlib stream, handing it to `sink` piece by piece.
+    ///
+    /// Stops early, without error, when `sink` returns false.
+    fn finish(&mut self, data: &[u8], mut sink: impl FnMut(&[u8]) -> bool) -> Result<(), CompressionError> {
+        let mut buffer = [0u8; 16 * 1024];
+        // zlib only reads through next_in.
+        self.stream.next_in = data.as_ptr() as *mut u8;
+        self.stream.avail_in = data.len().try_into().map_err(|_| error("Too much data for zlib"))?;
+        loop {
+            self.stream.next_out = buffer.as_mut_ptr();
+            self.stream.avail_out = buffer.len() as zlib::uInt;
+            // SAFETY: the input and output pointers cover live buffers of the given lengths.
+            let status = unsafe { zlib::deflate(&mut *self.stream, zlib::Z_FINISH) };
+            let written = buffer.len() - self.stream.avail_out as usize;
+            if !sink(&buffer[..written]) || status == zlib::Z_STREAM_END {
+                return Ok(());
+            }
+            if status != zlib::Z_OK {
+                return Err(error(&format!("zlib failed ({})", status)));
+            }
+        }
+    }
+}
+
+impl Drop for ZlibDeflate {
+    fn drop(&mut self) {
+        // SAFETY: the stream was initialized by deflateInit2_.
+        unsafe {
+            zlib::deflateEnd(&mut *self.stream);
+        }
+    }
+}
+
+/// Collects encoder output after skipping a header, comparing it with an expected stream as it arrives.
+struct Matching<'a> {
+    output: Vec<u8>,
+    skip: usize,
+    expected: Option<&'a [u8]>,
+    diverged: bool,
+}
+
+impl Matching<'_> {
+    /// Returns the output, or `None` if it does not equal the expected stream.
+    fn finish(self) -> Option<Vec<u8>> {
+        match self.expected {
+            _ if self.diverged => None,
+            Some(expected) if expected != self.output.as_slice() => None,
+            _ => Some(self.output),
+        }
+    }
+}
+
+impl Write for Matching<'_> {
+    /// Never fails, as `fdeflate` unwraps write errors; once diverged, further output is dropped.
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let skipped = self.skip.min(buf.len());
+        self.skip -= skipped;
+        if self.diverged {
+            return Ok(buf.len());
+        }
+        let start = self.output.len();
+        self.output.extend_from_slice(&buf[skipped..]);
+        if let Some(expected) = self.expected {
+            self.diverged = expected.get(start..self.output.len()) != Some(&self.output[start..]);
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A compressor that packs PNG files and restores them byte for byte.
+#[derive(Debug, Clone)]
+pub struct PngRecompressor {
+    fallback: DeflateCompressor,
+}
+
+impl Default for PngRecompressor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PngRecompressor {
+    /// Creates a new `PngRecompressor`.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use image_compression::compression::png_recompress::PngRecompressor;
+    ///
+    /// let recompressor = PngRecompressor::new();
+    /// ```
+    pub fn new() -> Self {
+        PngRecompressor {
+            fallback: DeflateCompressor::with_level_number(9).expect("level 9 is valid"),
+        }
+    }
+
+    /// Returns the encoder that reproduced the IDAT stream of a packed PNG, if it was modelled.
+    ///
+    /// `Some(ZlibEncoderKind::Verbatim)` means the file was parsed but no known encoder
+    /// reproduced its stream, so the compressed pixels were kept as they were.
+    pub fn encoder_of(data: &[u8]) -> Option<ZlibEncoderKind> {
+        if data.len() < PREFIX_LEN + 4 || &data[..MAGIC.len()] != MAGIC || data[MAGIC.len()] != MODE_MODELED {
+            return None;
+        }
+        let skeleton_len = u32::from_be_bytes(data[PREFIX_LEN..PREFIX_LEN + 4].try_into().ok()?) as usize;
+        let id = data.get(PREFIX_LEN + 4 + skeleton_len..PREFIX_LEN + 6 + skeleton_len)?;
+        ZlibEncoderKind::from_id(u16::from_be_bytes(id.try_into().ok()?)).ok()
+    }
+
+    fn compress_modeled(&self, data: &[u8]) -> Result<Vec<u8>, CompressionError> {
+        let png = Png::parse(data)?;
+        let inflated = inflate(&png.zlib, png.header.filtered_len())?;
+        // Streams with a preset dictionary cannot be rebuilt from the pixels alone.
+        let modelable = png.zlib.len() > 2 && png.zlib[1] & 0x20 == 0;
+        let row_len = png.header.filtered_row_len();
+        let encoder = if modelable {
+            ZlibEncoderKind::candidates([png.zlib[0], png.zlib[1]])
+                .find(|kind| matches!(kind.encode(&inflated, row_len, Some(&png.zlib[2..])), Ok(Some(_))))
+                .unwrap_or(ZlibEncoderKind::Verbatim)
+        } else {
+            ZlibEncoderKind::Verbatim
+        };
+
+        let mut output = Vec::new();
+        output.extend_from_slice(MAGIC);
+        output.push(MODE_MODELED);
+        output.extend_from_slice(&crc32(data).to_be_bytes());
+        let skeleton = self.fallback.compress(&png.skeleton)?;
+        output.extend_from_slice(&(skeleton.len() as u32).to_be_bytes());
+        output.extend_from_slice(&skeleton);
+        output.extend_from_slice(&encoder.id().to_be_bytes());
+        if encoder == ZlibEncoderKind::Verbatim {
+            output.extend_from_slice(&self.fallback.compress(&png.zlib)?);
+        } else {
+            output.extend_from_slice(&ZlibEncoderKind::fingerprint().to_be_bytes());
+            output.extend_from_slice(&png.zlib[..2]);
+            output.extend_from_slice(&self.compress_pixels(&png.header, &inflated)?);
+        }
+
+        if self.decompress(&output)? != data {
+            return Err(CompressionError::Compression("PNG cannot be reproduced exactly".to_string()));
+        }
+        Ok(output)
+    }
+
+    /// Stores the inflated scanlines with whichever codec gives the smaller result.
+    fn compress_pixels(&self, header: &Header, filtered: &[u8]) -> Result<Vec<u8>, CompressionError> {
+        let mut best = vec![PIXELS_DEFLATE];
+        best.extend_from_slice(&self.fallback.compress(filtered)?);
+
+        if let Some((filters, pixels)) = header.unfilter(filtered) {
+            let wavelet = WaveletCompressor::new(header.width, header.height, header.channels() as u8);
+            let filters = self.fallback.compress(&filters)?;
+            let pixels = wavelet.compress(&pixels)?;
+            if 1 + 4 + filters.len() + pixels.len() < best.len() {
+                best = vec![PIXELS_WAVELET];
+                best.extend_from_slice(&(filters.len() as u32).to_be_bytes());
+                best.extend_from_slice(&filters);
+                best.extend_from_slice(&pixels);
+            }
+        }
+        Ok(best)
+    }
+
+    fn decompress_pixels(&self, header: &Header, data: &[u8]) -> Result<Vec<u8>, CompressionError> {
+        let truncated = || CompressionError::Decompression("Truncated PNG pixel data".to_string());
+        match data.first() {
+            Some(&PIXELS_DEFLATE) => inflate_raw(&data[1..], header.filtered_len()),
+            Some(&PIXELS_WAVELET) => {
+                let filters_len = data.get(1..5).ok_or_else(truncated)?;
+                let filters_len = u32::from_be_bytes(filters_len.try_into().unwrap()) as usize;
+                let filters = inflate_raw(data.get(5..5 + filters_len).ok_or_else(truncated)?, header.height as u64)?;
+                let wavelet = WaveletCompressor::new(header.width, header.height, header.channels() as u8);
+                let pixels = wavelet.decompress(&data[5 + filters_len..])?;
+                header.refilter(&filters, &pixels)
+            }
+            _ => Err(CompressionError::Decompression("Unknown PNG pixel codec".to_string())),
+        }
+    }
+}
+
+impl Compressor for PngRecompressor {
+    /// Packs a PNG file, modelling its IDAT stream when it can be reproduced exactly.
+    ///
+    /// Small files whose modelled form would not beat plain Deflate are stored instead.
+    fn compress(&self, data: &[u8]) -> Result<Vec<u8>, CompressionError> {
+        let mut stored = Vec::new();
+        stored.extend_from_slice(MAGIC);
+        stored.push(MODE_STORED);
+        stored.extend_from_slice(&crc32(data).to_be_bytes());
+        stored.extend_from_slice(&self.fallback.compress(data)?);
+        match self.compress_modeled(data) {
+            Ok(output) if output.len() < stored.len() => Ok(output),
+            _ => Ok(stored),
+        }
+    }
+
+    /// Restores the original PNG file bytes.
+    ///
+    /// Fails if the file was packed with Deflate encoders that behave differently from the
+    /// ones in this build, or if the restored bytes do not match the original's CRC.
+    fn decompress(&self, data: &[u8]) -> Result<Vec<u8>, CompressionError> {
+        let truncated = || CompressionError::Decompression("Truncated PNG recompression data".to_string());
+        if data.len() < PREFIX_LEN || &data[..MAGIC.len()] != MAGIC {
+            return Err(CompressionError::Decompression("Invalid PNG recompression header".to_string()));
+        }
+        let expected_crc = u32::from_be_bytes(data[MAGIC.len() + 1..PREFIX_LEN].try_into().unwrap());
+        let body = &data[PREFIX_LEN..];
+        let restored = match data[MAGIC.len()] {
+            MODE_STORED => inflate_raw(body, MAX_DECODED_LEN as u64)?,
+            MODE_MODELED => {
+                let skeleton_len = u32::from_be_bytes(body.get(..4).ok_or_else(truncated)?.try_into().unwrap()) as usize;
+                let skeleton = inflate_raw(body.get(4..4 + skeleton_len).ok_or_else(truncated)?, MAX_DECODED_LEN as u64)?;
+                let id = body.get(4 + skeleton_len..6 + skeleton_len).ok_or_else(truncated)?;
+                let encoder = ZlibEncoderKind::from_id(u16::from_be_bytes(id.try_into().unwrap()))?;
+                let mut payload = &body[6 + skeleton_len..];
+                // IHDR must be the first chunk, so it sits right after the signature.
+                let header = skeleton
+                    .get(12..29)
+                    .filter(|ihdr| ihdr.starts_with(b"IHDR"))
+                    .map(|ihdr| Header::parse(&ihdr[4..]))
+                    .ok_or_else(truncated)?;
+                let zlib = if encoder == ZlibEncoderKind::Verbatim {
+                    inflate_raw(payload, MAX_DECODED_LEN as u64)?
+                } else {
+                    let fingerprint = u32::from_be_bytes(payload.get(..4).ok_or_else(truncated)?.try_into().unwrap());
+                    if fingerprint != ZlibEncoderKind::fingerprint() {
+                        return Err(CompressionError::Decompression(format!(
+                            "PNG was packed with Deflate encoders (tag {:08x}) that differ from this build's ({:08x})",
+                            fingerprint,
+                            ZlibEncoderKind::fingerprint()
+                        )));
+                    }
+                    let mut zlib = payload.get(4..6).ok_or_else(truncated)?.to_vec();
+                    payload = &payload[6..];
+                    let filtered = self.decompress_pixels(&header, payload)?;
+                    zlib.extend(encoder.encode(&filtered, header.filtered_row_len(), None)?.unwrap_or_default());
+                    zlib
+                };
+                Png::restore(&skeleton, &zlib)?
+            }
+            mode => return Err(CompressionError::Decompression(format!("Unknown PNG recompression mode {}", mode))),
+        };
+        if crc32(&restored) != expected_crc {
+            return Err(CompressionError::Decompression("Restored PNG does not match the original's CRC".to_string()));
+        }
+        Ok(restored)
+    }
+}
+
+/// Implement `fmt::Display` for `PngRecompressor` for better readability.
+impl fmt::Display for PngRecompressor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "PngRecompressor (Fallback: {})", self.fallback)
+    }
+}
+
+fn error(message: &str) -> CompressionError {
+    CompressionError::Compression(message.to_string())
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = Crc::new();
+    crc.update(data);
+    crc.sum()
+}
+
+/// Inflates a zlib stream that should hold at most `limit` bytes.
+fn inflate(zlib: &[u8], limit: u64) -> Result<Vec<u8>, CompressionError> {
+    let mut inflated = Vec::new();
+    flate2::read::ZlibDecoder::new(zlib)
+        .take(limit.saturating_add(1))
+        .read_to_end(&mut inflated)
+        .map_err(|e| CompressionError::Compression(e.to_string()))?;
+    if inflated.len() as u64 > limit {
+        return Err(error("IDAT stream inflates past the image size"));
+    }
+    Ok(inflated)
+}
+
+/// Inflates raw Deflate data written by the fallback compressor, which should hold at most `limit` bytes.
+///
+/// `limit` may come from an untrusted IHDR, so it is also capped at [`MAX_DECODED_LEN`].
+fn inflate_raw(data: &[u8], limit: u64) -> Result<Vec<u8>, CompressionError> {
+    let limit = limit.min(MAX_DECODED_LEN as u64);
+    let mut inflated = Vec::new();
+    flate2::read::DeflateDecoder::new(data)
+        .take(limit.saturating_add(1))
+        .read_to_end(&mut inflated)
+        .map_err(|e| CompressionError::Decompression(e.to_string()))?;
+    if inflated.len() as u64 > limit {
+        return Err(CompressionError::Decompression("Packed PNG data inflates past its size".to_string()));
+    }
+    Ok(inflated)
+}
+
+/// The IHDR fields needed to reinterpret the inflated scanlines.
+#[derive(Debug, Clone, Copy)]
+struct Header {
+    width: u32,
+    height: u32,
+    bit_depth: u8,
+    color_type: u8,
+    interlaced: bool,
+}
+
+impl Header {
+    /// Reads the fields from a 13-byte IHDR payload.
+    fn parse(ihdr: &[u8]) -> Self {
+        Header {
+            width: u32::from_be_bytes(ihdr[..4].try_into().unwrap()),
+            height: u32::from_be_bytes(ihdr[4..8].try_into().unwrap()),
+            bit_depth: ihdr[8],
+            color_type: ihdr[9],
+            interlaced: ihdr[12] != 0,
+        }
+    }
+
+    fn channels(&self) -> usize {
+        match self.color_type {
+            2 => 3,
+            4 => 2,
+            6 => 4,
+            _ => 1,
+        }
+    }
+
+    /// Length of one filtered scanline, including its filter type byte.
+    fn filtered_row_len(&self) -> usize {
+        1 + (self.width as usize * self.channels() * self.bit_depth as usize).div_ceil(8)
+    }
+
+    /// Length of all filtered scanlines, over the seven Adam7 passes if interlaced.
+    fn filtered_len(&self) -> u64 {
+        let bits = self.channels() as u64 * self.bit_depth as u64;
+        let rows = |width: u64, height: u64| {
+            if width == 0 {
+                0
+            } else {
+                height.saturating_mul(1 + (width * bits).div_ceil(8))
+            }
+        };
+        let (width, height) = (self.width as u64, self.height as u64);
+        if !self.interlaced {
+            return rows(width, height);
+        }
+        const PASSES: [(u64, u64, u64, u64); 7] =
+            [(0, 0, 8, 8), (4, 0, 8, 8), (0, 4, 4, 8), (2, 0, 4, 4), (0, 2, 2, 4), (1, 0, 2, 2), (0, 1, 1, 2)];
+        PASSES
+            .iter()
+            .map(|&(x, y, dx, dy)| rows(width.saturating_sub(x).div_ceil(dx), height.saturating_sub(y).div_ceil(dy)))
+            .fold(0, u64::saturating_add)
+    }
+
+    /// Splits 8-bit, non-interlaced scanlines into their filter types and the raw pixels.
+    fn unfilter(&self, filtered: &[u8]) -> Option<(Vec<u8>, Vec<u8>)> {
+        let row_len = self.filtered_row_len();
+        if self.bit_depth != 8 || self.interlaced || filtered.len() != row_len * self.height as usize {
+            return None;
+        }
+        let mut filters = Vec::with_capacity(self.height as usize);
+        let mut pixels = Vec::with_capacity(filtered.len());
+        let mut prev = vec![0u8; row_len - 1];
+        for row in filtered.chunks(row_len) {
+            if row[0] > 4 {
+                return None;
+            }
+            let mut current = row[1..].to_vec();
+            unfilter_row(row[0], &mut current, &prev, self.channels());
+            filters.push(row[0]);
+            pixels.extend_from_slice(&current);
+            prev = current;
+        }
+        Some((filters, pixels))
+    }
+
+    /// Inverse of [`Header::unfilter`].
+    fn refilter(&self, filters: &[u8], pixels: &[u8]) -> Result<Vec<u8>, CompressionError> {
+        let row_len = self.filtered_row_len() - 1;
+        if filters.len() != self.height as usize || pixels.len() != row_len * self.height as usize {
+            return Err(CompressionError::Decompression("PNG pixel data does not match IHDR".to_string()));
+        }
+        let mut filtered = Vec::with_capacity(pixels.len() + filters.len());
+        let empty = vec![0u8; row_len];
+        for (y, row) in pixels.chunks(row_len.max(1)).take(filters.len()).enumerate() {
+            let prev = if y > 0 { &pixels[(y - 1) * row_len..y * row_len] } else { &empty };
+            filter_row(filters[y], row, prev, self.channels(), &mut filtered);
+        }
+        Ok(filtered)
+    }
+}
+
+/// A PNG split into its concatenated IDAT stream and everything else.
+struct Png {
+    header: Header,
+    zlib: Vec<u8>,
+    /// The file with the IDAT chunk payloads removed; their lengths and CRCs remain.
+    skeleton: Vec<u8>,
+}
+
+impl Png {
+    fn parse(data: &[u8]) -> Result<Self, CompressionError> {
+        if !data.starts_with(&PNG_SIGNATURE) {
+            return Err(error("Missing PNG signature"));
+        }
+        let mut header = None;
+        let mut zlib = Vec::new();
+        let mut skeleton = PNG_SIGNATURE.to_vec();
+        let mut pos = PNG_SIGNATURE.len();
+        loop {
+            let chunk_header = data.get(pos..pos + 8).ok_or_else(|| error("Truncated PNG chunk"))?;
+            let len = u32::from_be_bytes(chunk_header[..4].try_into().unwrap()) as usize;
+            let kind = &chunk_header[4..8];
+            let payload = data.get(pos + 8..pos + 8 + len).ok_or_else(|| error("Truncated PNG chunk"))?;
+            let crc = data.get(pos + 8 + len..pos + 12 + len).ok_or_else(|| error("Truncated PNG chunk"))?;
+            skeleton.extend_from_slice(chunk_header);
+            match kind {
+                b"IHDR" if len == 13 && pos == PNG_SIGNATURE.len() => {
+                    header = Some(Header::parse(payload));
+                    skeleton.extend_from_slice(payload);
+                }
+                b"IDAT" => zlib.extend_from_slice(payload),
+                _ => skeleton.extend_from_slice(payload),
+            }
+            skeleton.extend_from_slice(crc);
+            pos += 12 + len;
+            if kind == b"IEND" {
+                break;
+            }
+        }
+        skeleton.extend_from_slice(&data[pos..]);
+        Ok(Png {
+            header: header.ok_or_else(|| error("Missing IHDR chunk"))?,
+            zlib,
+            skeleton,
+        })
+    }
+
+    /// Refills the IDAT chunks of `skeleton` from `zlib`.
+    fn restore(skeleton: &[u8], zlib: &[u8]) -> Result<Vec<u8>, CompressionError> {
+        let invalid = || CompressionError::Decompression("Invalid PNG skeleton".to_string());
+        let mut output = Vec::with_capacity(skeleton.len() + zlib.len());
+        output.extend_from_slice(&skeleton[..PNG_SIGNATURE.len()]);
+        let mut pos = PNG_SIGNATURE.len();
+        let mut zlib_pos = 0;
+        loop {
+            let chunk_header = skeleton.get(pos..pos + 8).ok_or_else(invalid)?;
+            let len = u32::from_be_bytes(chunk_header[..4].try_into().unwrap()) as usize;
+            let kind = &chunk_header[4..8];
+            output.extend_from_slice(chunk_header);
+            pos += 8;
+            if kind == b"IDAT" {
+                output.extend_from_slice(zlib.get(zlib_pos..zlib_pos + len).ok_or_else(invalid)?);
+                zlib_pos += len;
+            } else {
+                output.extend_from_slice(skeleton.get(pos..pos + len).ok_or_else(invalid)?);
+                pos += len;
+            }
+            output.extend_from_slice(skeleton.get(pos..pos + 4).ok_or_else(invalid)?);
+            pos += 4;
+            if kind == b"IEND" {
+                break;
+            }
+        }
+        output.extend_from_slice(&skeleton[pos..]);
+        Ok(output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compression::png::PngOptimizer;
+    use image::{DynamicImage, ImageEncoder, RgbImage};
+
+    fn photo(width: u32, height: u32) -> DynamicImage {
+        DynamicImage::ImageRgb8(RgbImage::from_fn(width, height, |x, y| {
+            let noise = (x.wrapping_mul(374_761_393) ^ y.wrapping_mul(668_265_263)).wrapping_mul(2_654_435_761) >> 30;
+            let (fx, fy) = (x as f32, y as f32);
+            image::Rgb([
+                (120.0 + 80.0 * (fx / 11.0).sin() * (fy / 7.0).cos()) as u8 + noise as u8,
+                (100.0 + 60.0 * (fy / 13.0).sin()) as u8 + noise as u8,
+                (fx + fy) as u8 / 2,
+            ])
+        }))
+    }
+
+    fn encode_png(image: &DynamicImage, compression: image::codecs::png::CompressionType) -> Vec<u8> {
+        let mut png = Vec::new();
+        let rgb = image.to_rgb8();
+        image::codecs::png::PngEncoder::new_with_quality(&mut png, compression, image::codecs::png::FilterType::Adaptive)
+            .write_image(&rgb, rgb.width(), rgb.height(), image::ExtendedColorType::Rgb8)
+            .unwrap();
+        png
+    }
+
+    #[test]
+    fn test_round_trip_for_each_png_crate_setting() {
+        use image::codecs::png::CompressionType;
+        let image = photo(67, 41);
+        for compression in [CompressionType::Fast, CompressionType::Default, CompressionType::Best] {
+            let png = encode_png(&image, compression);
+            let recompressor = PngRecompressor::new();
+            let packed = recompressor.compress(&png).unwrap();
+            assert_ne!(PngRecompressor::encoder_of(&packed), Some(ZlibEncoderKind::Verbatim));
+            assert!(PngRecompressor::encoder_of(&packed).is_some());
+            assert_eq!(recompressor.decompress(&packed).unwrap(), png);
+        }
+    }
+
+    #[test]
+    fn test_libpng_streams_are_reproduced() {
+        // Written by libpng 1.6 with its default settings, and at level 9.
+        let fixtures: [&[u8]; 2] = [
+            include_bytes!("../../tests/fixtures/libpng-rgb.png"),
+            include_bytes!("../../tests/fixtures/libpng-gray-9.png"),
+        ];
+        let recompressor = PngRecompressor::new();
+        for png in fixtures {
+            let packed = recompressor.compress_modeled(png).unwrap();
+            assert!(matches!(PngRecompressor::encoder_of(&packed), Some(ZlibEncoderKind::Zlib { .. })));
+            assert_eq!(recompressor.decompress(&packed).unwrap(), png);
+        }
+    }
+
+    #[test]
+    fn test_beats_original_png() {
+        let png = encode_png(&photo(192, 128), image::codecs::png::CompressionType::Best);
+        let packed = PngRecompressor::new().compress(&png).unwrap();
+        assert!(packed.len() < png.len(), "{} >= {}", packed.len(), png.len());
+    }
+
+    #[test]
+    fn test_split_idat_and_extra_chunks() {
+        // The optimizer's output is rechunked into small IDATs with a text chunk in between.
+        let optimized = PngOptimizer::new().optimize(&photo(20, 20)).unwrap().data;
+        let parsed = Png::parse(&optimized).unwrap();
+        let mut png = optimized[..33].to_vec();
+        crate::compression::png::write_chunk(&mut png, b"tEXt", b"Comment\0split");
+        for piece in parsed.zlib.chunks(100) {
+            crate::compression::png::write_chunk(&mut png, b"IDAT", piece);
+        }
+        crate::compression::png::write_chunk(&mut png, b"IEND", &[]);
+        png.extend_from_slice(b"trailer");
+
+        let recompressor = PngRecompressor::new();
+        let packed = recompressor.compress(&png).unwrap();
+        assert!(matches!(PngRecompressor::encoder_of(&packed), Some(ZlibEncoderKind::Miniz { .. })));
+        assert_eq!(recompressor.decompress(&packed).unwrap(), png);
+    }
+
+    #[test]
+    fn test_strategy_search_and_foreign_header() {
+        let png = encode_png(&photo(40, 30), image::codecs::png::CompressionType::Default);
+        let parsed = Png::parse(&png).unwrap();
+        let inflated = inflate(&parsed.zlib, parsed.header.filtered_len()).unwrap();
+        let kind = ZlibEncoderKind::Miniz { level: 5, strategy: 1 };
+        // A zlib header announcing an 8 KiB window, as libpng writes for small images.
+        let mut zlib = vec![0x58, 0];
+        zlib[1] = 31 - ((0x58u16 * 256) % 31) as u8;
+        zlib.extend(kind.encode(&inflated, parsed.header.filtered_row_len(), None).unwrap().unwrap());
+        assert_eq!(inflate(&zlib, inflated.len() as u64).unwrap(), inflated);
+        let mut rebuilt = png[..33].to_vec();
+        crate::compression::png::write_chunk(&mut rebuilt, b"IDAT", &zlib);
+        crate::compression::png::write_chunk(&mut rebuilt, b"IEND", &[]);
+
+        let recompressor = PngRecompressor::new();
+        let packed = recompressor.compress_modeled(&rebuilt).unwrap();
+        assert_eq!(PngRecompressor::encoder_of(&packed), Some(kind));
+        assert_eq!(recompressor.decompress(&packed).unwrap(), rebuilt);
+        for id in 0..=u16::MAX {
+            if let Ok(kind) = ZlibEncoderKind::from_id(id) {
+                assert_eq!(kind.id(), id);
+            }
+        }
+    }
+
+    #[test]
+    fn test_idat_larger_than_the_image_is_rejected() {
+        let mut png = encode_png(&photo(8, 8), image::codecs::png::CompressionType::Default);
+        let header = Png::parse(&png).unwrap().header;
+        assert_eq!(header.filtered_len(), 200);
+        assert_eq!(Header { interlaced: true, ..header }.filtered_len(), 207);
+
+        // A megabyte of zeros behind an 8x8 header.
+        let mut bomb = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::best());
+        bomb.write_all(&vec![0; 1 << 20]).unwrap();
+        let zlib = bomb.finish().unwrap();
+        assert!(inflate(&zlib[..], header.filtered_len()).is_err());
+        png.truncate(33);
+        crate::compression::png::write_chunk(&mut png, b"IDAT", &zlib);
+        crate::compression::png::write_chunk(&mut png, b"IEND", &[]);
+        let recompressor = PngRecompressor::new();
+        assert!(recompressor.compress_modeled(&png).is_err());
+        let packed = recompressor.compress(&png).unwrap();
+        assert_eq!(recompressor.decompress(&packed).unwrap(), png);
+
+        // Packed pixels are inflated no further than the IHDR allows either.
+        let mut pixels = vec![PIXELS_DEFLATE];
+        pixels.extend(recompressor.fallback.compress(&vec![0; 1 << 20]).unwrap());
+        assert!(recompressor.decompress_pixels(&header, &pixels).is_err());
+        pixels.truncate(1);
+        pixels.extend(recompressor.fallback.compress(&[0; 200]).unwrap());
+        assert_eq!(recompressor.decompress_pixels(&header, &pixels).unwrap().len(), 200);
+        let huge = Header { width: u32::MAX, height: u32::MAX, bit_depth: 16, color_type: 6, interlaced: true };
+        assert_eq!(huge.filtered_len(), u64::MAX);
+    }
+
+    #[test]
+    fn test_mismatched_encoders_or_bytes_are_errors() {
+        let png = encode_png(&photo(24, 24), image::codecs::png::CompressionType::Default);
+        let recompressor = PngRecompressor::new();
+        let packed = recompressor.compress_modeled(&png).unwrap();
+        assert_ne!(PngRecompressor::encoder_of(&packed), Some(ZlibEncoderKind::Verbatim));
+
+        let mut wrong_crc = packed.clone();
+        wrong_crc[5] ^= 1;
+        assert!(recompressor.decompress(&wrong_crc).is_err());
+
+        // The encoder fingerprint follows the skeleton and the encoder id.
+        let skeleton_len = u32::from_be_bytes(packed[PREFIX_LEN..PREFIX_LEN + 4].try_into().unwrap()) as usize;
+        let mut other_encoders = packed.clone();
+        other_encoders[PREFIX_LEN + 6 + skeleton_len] ^= 1;
+        let error = recompressor.decompress(&other_encoders).unwrap_err().to_string();
+        assert!(error.contains("differ"), "{}", error);
+    }
+
+    #[test]
+    fn test_unknown_streams_and_non_png_input() {
+        let recompressor = PngRecompressor::new();
+        // Re-deflate the IDAT stream with a stored block layout no candidate produces.
+        let png = encode_png(&photo(16, 16), image::codecs::png::CompressionType::Default);
+        let parsed = Png::parse(&png).unwrap();
+        let inflated = inflate(&parsed.zlib, parsed.header.filtered_len()).unwrap();
+        let mut odd = flate2::Compress::new(flate2::Compression::new(6), true);
+        let mut zlib = Vec::with_capacity(inflated.len() * 2);
+        odd.compress_vec(&inflated[..100], &mut zlib, flate2::FlushCompress::Full).unwrap();
+        odd.compress_vec(&inflated[100..], &mut zlib, flate2::FlushCompress::Finish).unwrap();
+        assert_eq!(inflate(&zlib, inflated.len() as u64).unwrap(), inflated);
+        let mut rebuilt = png[..33].to_vec();
+        crate::compression::png::write_chunk(&mut rebuilt, b"IDAT", &zlib);
+        crate::compression::png::write_chunk(&mut rebuilt, b"IEND", &[]);
+
+        let packed = recompressor.compress(&rebuilt).unwrap();
+        assert!(matches!(PngRecompressor::encoder_of(&packed), None | Some(ZlibEncoderKind::Verbatim)));
+        assert_eq!(recompressor.decompress(&packed).unwrap(), rebuilt);
+
+        // A PNG without IDAT chunks has no zlib header to model.
+        let mut empty = png[..33].to_vec();
+        crate::compression::png::write_chunk(&mut empty, b"IEND", &[]);
+        let packed = recompressor.compress(&empty).unwrap();
+        assert_eq!(recompressor.decompress(&packed).unwrap(), empty);
+
+        let packed = recompressor.compress(b"not a png").unwrap();
+        assert_eq!(PngRecompressor::encoder_of(&packed), None);
+        assert_eq!(recompressor.decompress(&packed).unwrap(), b"not a png".to_vec());
+    }
+}
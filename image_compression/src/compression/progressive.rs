@@ -0,0 +1,528 @@
+// src/compression/progressive.rs
+
+//! Module implementing progressive pass ordering for preview-first decoding.
+//!
+//! Pixels are reordered into interlacing passes, coarsest first, and each
+//! pass is compressed on its own. A truncated prefix of the output thus
+//! holds a number of complete passes, which
+//! [`ProgressiveCompressor::decode_partial`] turns into a full-size image by
+//! repeating every known pixel over the cells not yet transmitted.
+//!
+//! The hierarchical order starts with one pixel of every `2^levels` square
+//! and then halves the spacing horizontally and vertically in alternate
+//! passes. With three levels this is exactly the Adam7 order of PNG.
+//!
+//! The header records the algorithm compressing the passes, so output of
+//! [`ProgressiveCompressor::for_algorithm`] can be decoded with
+//! [`ProgressiveCompressor::from_header`] alone.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use image_compression::compression::Compressor;
+//! use image_compression::compression::deflate::DeflateCompressor;
+//! use image_compression::compression::progressive::ProgressiveCompressor;
+//!
+//! let pixels: Vec<u8> = (0..40 * 30).map(|i| (i % 40 * 6) as u8).collect();
+//! let compressor = ProgressiveCompressor::new(40, 30, 1, DeflateCompressor::new());
+//! let compressed = compressor.compress(&pixels).unwrap();
+//! assert_eq!(compressor.decompress(&compressed).unwrap(), pixels);
+//!
+//! let preview = compressor.decode_partial(&compressed[..compressed.len() / 2]).unwrap();
+//! assert_eq!(preview.pixels.len(), pixels.len());
+//! assert!(preview.passes < preview.total_passes);
+//! ```
+
+use super::parts::{PartCodec, CUSTOM_ALGORITHM};
+use super::utils::checked_image_len;
+use super::{CompressionError, Compressor};
+use rayon::prelude::*;
+use std::fmt;
+
+const MAGIC: &[u8; 4] = b"PRG\x02";
+const HEADER_LEN: usize = 15;
+const MAX_LEVELS: u8 = 15;
+
+/// The order in which pixels are transmitted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PassOrder {
+    /// The seven passes of PNG's Adam7 interlacing, starting with one pixel in 64.
+    Adam7,
+    /// `2 * levels + 1` passes, starting with one pixel in `4^levels`.
+    Hierarchical(u8),
+}
+
+impl PassOrder {
+    fn levels(self) -> u8 {
+        match self {
+            PassOrder::Adam7 => 3,
+            PassOrder::Hierarchical(levels) => levels,
+        }
+    }
+
+    fn from_levels(levels: u8) -> Self {
+        match levels {
+            3 => PassOrder::Adam7,
+            levels => PassOrder::Hierarchical(levels),
+        }
+    }
+
+    /// Returns every pass as `(x0, y0, dx, dy)` with the spacing `(dx, dy)` of the known pixels after it.
+    fn passes(self) -> Vec<(Pass, (u32, u32))> {
+        let size = 1u32 << self.levels();
+        let mut passes = vec![(Pass { x0: 0, y0: 0, dx: size, dy: size }, (size, size))];
+        let mut step = size / 2;
+        while step > 0 {
+            passes.push((Pass { x0: step, y0: 0, dx: 2 * step, dy: 2 * step }, (step, 2 * step)));
+            passes.push((Pass { x0: 0, y0: step, dx: step, dy: 2 * step }, (step, step)));
+            step /= 2;
+        }
+        passes
+    }
+}
+
+/// The pixels at `(x0 + i * dx, y0 + j * dy)`.
+#[derive(Debug, Clone, Copy)]
+struct Pass {
+    x0: u32,
+    y0: u32,
+    dx: u32,
+    dy: u32,
+}
+
+impl Pass {
+    /// Returns the pass's width and height in an image of the given size.
+    fn size(&self, width: u32, height: u32) -> (u32, u32) {
+        let count = |start: u32, step: u32, len: u32| if len > start { (len - start).div_ceil(step) } else { 0 };
+        (count(self.x0, self.dx, width), count(self.y0, self.dy, height))
+    }
+}
+
+/// The best image decodable from a possibly truncated progressive stream.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PartialImage {
+    pub width: u32,
+    pub height: u32,
+    pub channels: u8,
+    /// Full-size interleaved pixels, exact once every pass is present.
+    pub pixels: Vec<u8>,
+    /// Number of complete passes decoded.
+    pub passes: usize,
+    pub total_passes: usize,
+}
+
+impl PartialImage {
+    /// Returns whether every pass was decoded, making the image exact.
+    pub fn is_complete(&self) -> bool {
+        self.passes == self.total_passes
+    }
+}
+
+/// A compressor writing pixels in progressive passes.
+#[derive(Clone)]
+pub struct ProgressiveCompressor {
+    width: u32,
+    height: u32,
+    channels: u8,
+    order: PassOrder,
+    /// Builds the pass compressors and holds the algorithm id recorded in the header.
+    codec: PartCodec,
+}
+
+impl ProgressiveCompressor {
+    /// Creates a new `ProgressiveCompressor` in Adam7 order compressing every pass with a clone of `compressor`.
+    ///
+    /// # Arguments
+    ///
+    /// * `width` - Image width in pixels.
+    /// * `height` - Image height in pixels.
+    /// * `channels` - Number of interleaved 8-bit channels per pixel.
+    /// * `compressor` - The compressor applied to each pass.
+    pub fn new<C>(width: u32, height: u32, channels: u8, compressor: C) -> Self
+    where
+        C: Compressor + Clone + Send + Sync + 'static,
+    {
+        Self::with_codec(width, height, channels, PartCodec::cloning(compressor))
+    }
+
+    /// Creates a new `ProgressiveCompressor` in Adam7 order building each pass's compressor from its geometry.
+    ///
+    /// Every pass is a regular subgrid of the image, so codecs configured
+    /// with image dimensions can compress it as a smaller image.
+    pub fn with_factory<F>(width: u32, height: u32, channels: u8, factory: F) -> Self
+    where
+        F: Fn(u32, u32, u8) -> Result<Box<dyn Compressor + Send + Sync>, CompressionError> + Send + Sync + 'static,
+    {
+        Self::with_codec(width, height, channels, PartCodec::custom(factory))
+    }
+
+    /// Creates a new `ProgressiveCompressor` in Adam7 order compressing every pass with the algorithm identified by `id`.
+    ///
+    /// Each pass's compressor is built by
+    /// [`CompressionAlgorithmType::for_block`](super::CompressionAlgorithmType::for_block), and `id` is
+    /// recorded in the header so [`ProgressiveCompressor::from_header`] can decode the output.
+    pub fn for_algorithm(width: u32, height: u32, channels: u8, id: u8) -> Self {
+        Self::with_codec(width, height, channels, PartCodec::for_algorithm(id))
+    }
+
+    /// Creates a compressor that only decodes, with the pass algorithm recorded in the header of `data`.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the compressor, or a `CompressionError` if the header is invalid
+    /// or the passes were compressed by a custom compressor.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use image_compression::compression::Compressor;
+    /// use image_compression::compression::progressive::ProgressiveCompressor;
+    ///
+    /// let pixels: Vec<u8> = (0..20 * 10).map(|i| i as u8).collect();
+    /// let compressed = ProgressiveCompressor::for_algorithm(20, 10, 1, 1).compress(&pixels).unwrap();
+    /// let decoder = ProgressiveCompressor::from_header(&compressed).unwrap();
+    /// assert_eq!(decoder.decompress(&compressed).unwrap(), pixels);
+    /// ```
+    pub fn from_header(data: &[u8]) -> Result<Self, CompressionError> {
+        let id = Self::read_algorithm(data)?.unwrap_or(CUSTOM_ALGORITHM);
+        Ok(Self::with_codec(0, 0, 1, PartCodec::from_header_id(id)?))
+    }
+
+    /// Reads the identifier of the pass algorithm from the header of progressive data.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the algorithm id, `None` for a custom pass compressor, or a
+    /// `CompressionError` if `data` does not start with a progressive header.
+    pub fn read_algorithm(data: &[u8]) -> Result<Option<u8>, CompressionError> {
+        if data.len() < HEADER_LEN || &data[..4] != MAGIC {
+            return Err(CompressionError::Decompression("Invalid progressive data".to_string()));
+        }
+        Ok(PartCodec::known(data[14]))
+    }
+
+    /// Records `id` as the algorithm a custom factory builds, for
+    /// [`ProgressiveCompressor::read_algorithm`] and [`ProgressiveCompressor::from_header`].
+    pub fn with_algorithm_id(mut self, id: u8) -> Self {
+        self.codec = self.codec.with_algorithm_id(id);
+        self
+    }
+
+    fn with_codec(width: u32, height: u32, channels: u8, codec: PartCodec) -> Self {
+        ProgressiveCompressor {
+            width,
+            height,
+            channels,
+            order: PassOrder::Adam7,
+            codec,
+        }
+    }
+
+    /// Creates a new `ProgressiveCompressor` that only decodes, reading the geometry and pass order from its input.
+    ///
+    /// [`ProgressiveCompressor::decode_partial`] and [`Compressor::decompress`] take everything but
+    /// the part codec from the header, so no image dimensions are needed. Compressing with it
+    /// accepts only an empty image. [`ProgressiveCompressor::from_header`] also takes the codec
+    /// from the header when it is a known algorithm.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use image_compression::compression::Compressor;
+    /// use image_compression::compression::lzw::LzwCompressor;
+    /// use image_compression::compression::progressive::ProgressiveCompressor;
+    ///
+    /// let pixels: Vec<u8> = (0..20 * 10).map(|i| i as u8).collect();
+    /// let compressed = ProgressiveCompressor::new(20, 10, 1, LzwCompressor::new(4096)).compress(&pixels).unwrap();
+    /// let decoder = ProgressiveCompressor::for_decoding(|_, _, _| Ok(Box::new(LzwCompressor::new(4096)) as _));
+    /// assert_eq!(decoder.decode_partial(&compressed).unwrap().pixels, pixels);
+    /// ```
+    pub fn for_decoding<F>(factory: F) -> Self
+    where
+        F: Fn(u32, u32, u8) -> Result<Box<dyn Compressor + Send + Sync>, CompressionError> + Send + Sync + 'static,
+    {
+        Self::with_factory(0, 0, 1, factory)
+    }
+
+    /// Sets the pass order.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the compressor or `CompressionError::InvalidLevel` if a
+    /// hierarchical order has no levels or more than 15.
+    pub fn with_order(mut self, order: PassOrder) -> Result<Self, CompressionError> {
+        if !(1..=MAX_LEVELS).contains(&order.levels()) {
+            return Err(CompressionError::InvalidLevel(format!(
+                "Hierarchical levels must be between 1 and {}, got {}",
+                MAX_LEVELS,
+                order.levels()
+            )));
+        }
+        self.order = PassOrder::from_levels(order.levels());
+        Ok(self)
+    }
+
+    /// Retrieves the pass order.
+    pub fn get_order(&self) -> PassOrder {
+        self.order
+    }
+
+    /// Decodes every complete pass at the start of `data` into a full-size image.
+    ///
+    /// Pixels of passes that are missing or cut off are copied from the
+    /// nearest known pixel above and to the left, so the result looks like a
+    /// lower-resolution version of the image.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - A prefix of data written by [`Compressor::compress`].
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the image, or a `CompressionError` if `data` does not even
+    /// hold the header and first pass, or if a complete pass is corrupt.
+    pub fn decode_partial(&self, data: &[u8]) -> Result<PartialImage, CompressionError> {
+        let invalid = || CompressionError::Decompression("Invalid progressive data".to_string());
+        if data.len() < HEADER_LEN || &data[..4] != MAGIC {
+            return Err(invalid());
+        }
+        let width = u32::from_be_bytes([data[4], data[5], data[6], data[7]]);
+        let height = u32::from_be_bytes([data[8], data[9], data[10], data[11]]);
+        let channels = data[12];
+        if channels == 0 || !(1..=MAX_LEVELS).contains(&data[13]) {
+            return Err(invalid());
+        }
+        let passes = PassOrder::from_levels(data[13]).passes();
+        let size = channels as usize;
+        let image_len = checked_image_len(width as usize, height as usize, size)?;
+
+        // Locate the passes that are fully present.
+        let mut payloads = Vec::new();
+        let mut position = HEADER_LEN;
+        while payloads.len() < passes.len() {
+            let Some(len_bytes) = data.get(position..position + 4) else { break };
+            let len = u32::from_be_bytes([len_bytes[0], len_bytes[1], len_bytes[2], len_bytes[3]]) as usize;
+            let Some(payload) = data.get(position + 4..position + 4 + len) else { break };
+            payloads.push(payload);
+            position += 4 + len;
+        }
+        if payloads.is_empty() {
+            return Err(CompressionError::Decompression("The first progressive pass is incomplete".to_string()));
+        }
+
+        let decoded = payloads
+            .par_iter()
+            .zip(&passes)
+            .map(|(payload, (pass, _))| {
+                let (w, h) = pass.size(width, height);
+                if w == 0 || h == 0 {
+                    return Ok(Vec::new());
+                }
+                let pixels = self.codec.build(w, h, channels)?.decompress(payload)?;
+                if pixels.len() != w as usize * h as usize * size {
+                    return Err(CompressionError::Decompression("A progressive pass has the wrong size".to_string()));
+                }
+                Ok(pixels)
+            })
+            .collect::<Result<Vec<_>, CompressionError>>()?;
+
+        let mut pixels = vec![0u8; image_len];
+        for ((pass, _), pass_pixels) in passes.iter().zip(&decoded) {
+            let (w, _) = pass.size(width, height);
+            for (j, row) in pass_pixels.chunks(w as usize * size).enumerate() {
+                let y = (pass.y0 + j as u32 * pass.dy) as usize;
+                for (i, pixel) in row.chunks(size).enumerate() {
+                    let x = (pass.x0 + i as u32 * pass.dx) as usize;
+                    let start = (y * width as usize + x) * size;
+                    pixels[start..start + size].copy_from_slice(pixel);
+                }
+            }
+        }
+
+        let (spacing_x, spacing_y) = passes[decoded.len() - 1].1;
+        if (spacing_x, spacing_y) != (1, 1) {
+            let row_len = width as usize * size;
+            for y in 0..height as usize {
+                let source_y = y - y % spacing_y as usize;
+                for x in 0..width as usize {
+                    let source_x = x - x % spacing_x as usize;
+                    if (source_x, source_y) != (x, y) {
+                        let source = source_y * row_len + source_x * size;
+                        pixels.copy_within(source..source + size, y * row_len + x * size);
+                    }
+                }
+            }
+        }
+
+        Ok(PartialImage {
+            width,
+            height,
+            channels,
+            pixels,
+            passes: decoded.len(),
+            total_passes: passes.len(),
+        })
+    }
+}
+
+impl Compressor for ProgressiveCompressor {
+    fn compress(&self, data: &[u8]) -> Result<Vec<u8>, CompressionError> {
+        let size = self.channels as usize;
+        let row_len = self.width as usize * size;
+        if data.len() != row_len * self.height as usize {
+            return Err(CompressionError::Compression(format!(
+                "Expected {}x{}x{} bytes, got {}",
+                self.width,
+                self.height,
+                self.channels,
+                data.len()
+            )));
+        }
+        let payloads = self
+            .order
+            .passes()
+            .par_iter()
+            .map(|(pass, _)| {
+                let (w, h) = pass.size(self.width, self.height);
+                if w == 0 || h == 0 {
+                    return Ok(Vec::new());
+                }
+                let mut pixels = Vec::with_capacity(w as usize * h as usize * size);
+                for y in (pass.y0..self.height).step_by(pass.dy as usize) {
+                    for x in (pass.x0..self.width).step_by(pass.dx as usize) {
+                        let start = y as usize * row_len + x as usize * size;
+                        pixels.extend_from_slice(&data[start..start + size]);
+                    }
+                }
+                self.codec.build(w, h, self.channels)?.compress(&pixels)
+            })
+            .collect::<Result<Vec<_>, CompressionError>>()?;
+
+        let mut output = Vec::with_capacity(HEADER_LEN + payloads.iter().map(|p| 4 + p.len()).sum::<usize>());
+        output.extend_from_slice(MAGIC);
+        output.extend_from_slice(&self.width.to_be_bytes());
+        output.extend_from_slice(&self.height.to_be_bytes());
+        output.push(self.channels);
+        output.push(self.order.levels());
+        output.push(self.codec.algorithm());
+        for payload in &payloads {
+            let len = u32::try_from(payload.len())
+                .map_err(|_| CompressionError::Compression("Pass exceeds 4 GiB".to_string()))?;
+            output.extend_from_slice(&len.to_be_bytes());
+            output.extend_from_slice(payload);
+        }
+        Ok(output)
+    }
+
+    /// Decompresses data, failing unless every pass is present.
+    fn decompress(&self, data: &[u8]) -> Result<Vec<u8>, CompressionError> {
+        let image = self.decode_partial(data)?;
+        if !image.is_complete() {
+            return Err(CompressionError::Decompression(format!(
+                "Truncated progressive data: {} of {} passes",
+                image.passes, image.total_passes
+            )));
+        }
+        Ok(image.pixels)
+    }
+}
+
+impl fmt::Debug for ProgressiveCompressor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ProgressiveCompressor")
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .field("channels", &self.channels)
+            .field("order", &self.order)
+            .field("algorithm", &self.codec.algorithm())
+            .finish_non_exhaustive()
+    }
+}
+
+impl fmt::Display for ProgressiveCompressor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let order = match self.order {
+            PassOrder::Adam7 => "Adam7".to_string(),
+            PassOrder::Hierarchical(levels) => format!("Hierarchical, {} levels", levels),
+        };
+        write!(f, "ProgressiveCompressor ({}x{}x{}, Order: {})", self.width, self.height, self.channels, order)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compression::lzw::LzwCompressor;
+
+    #[test]
+    fn test_adam7_passes() {
+        let passes: Vec<_> = PassOrder::Adam7.passes().iter().map(|(p, _)| (p.x0, p.y0, p.dx, p.dy)).collect();
+        assert_eq!(passes, vec![(0, 0, 8, 8), (4, 0, 8, 8), (0, 4, 4, 8), (2, 0, 4, 4), (0, 2, 2, 4), (1, 0, 2, 2), (0, 1, 1, 2)]);
+        assert_eq!(PassOrder::Hierarchical(5).passes().len(), 11);
+        // Small images leave the early passes empty.
+        assert_eq!(passes_sizes(3, 2), vec![(1, 1), (0, 1), (1, 0), (1, 1), (2, 0), (1, 1), (3, 1)]);
+    }
+
+    fn passes_sizes(width: u32, height: u32) -> Vec<(u32, u32)> {
+        PassOrder::Adam7.passes().iter().map(|(p, _)| p.size(width, height)).collect()
+    }
+
+    #[test]
+    fn test_round_trip_and_previews() {
+        let (width, height) = (37, 23);
+        let data: Vec<u8> = (0..width * height * 3).map(|i| (i * 13 % 256) as u8).collect();
+        for order in [PassOrder::Adam7, PassOrder::Hierarchical(1), PassOrder::Hierarchical(6)] {
+            let compressor = ProgressiveCompressor::new(width, height, 3, LzwCompressor::new(4096)).with_order(order).unwrap();
+            let compressed = compressor.compress(&data).unwrap();
+            assert_eq!(compressor.decompress(&compressed).unwrap(), data, "{:?}", order);
+
+            let mut previous = 0;
+            for len in (HEADER_LEN + 4..compressed.len()).step_by(97) {
+                let Ok(preview) = compressor.decode_partial(&compressed[..len]) else { continue };
+                assert!(preview.passes >= previous && preview.passes < preview.total_passes);
+                previous = preview.passes;
+                // The top-left pixel is always the first one sent.
+                assert_eq!(preview.pixels[..3], data[..3]);
+            }
+            assert!(compressor.decompress(&compressed[..compressed.len() - 1]).is_err());
+        }
+    }
+
+    #[test]
+    fn test_preview_repeats_known_pixels() {
+        let data: Vec<u8> = (0..16 * 16).map(|i| i as u8).collect();
+        let compressor = ProgressiveCompressor::new(16, 16, 1, LzwCompressor::new(4096));
+        let compressed = compressor.compress(&data).unwrap();
+        // Header plus the first pass: the four pixels at multiples of eight.
+        let first_len = u32::from_be_bytes(compressed[HEADER_LEN..HEADER_LEN + 4].try_into().unwrap()) as usize;
+        let preview = compressor.decode_partial(&compressed[..HEADER_LEN + 4 + first_len]).unwrap();
+        assert_eq!(preview.passes, 1);
+        for (i, &value) in preview.pixels.iter().enumerate() {
+            let (x, y) = (i % 16, i / 16);
+            assert_eq!(value, data[(y - y % 8) * 16 + x - x % 8]);
+        }
+        assert!(compressor.decode_partial(&compressed[..HEADER_LEN + 2]).is_err());
+        assert!(compressor.clone().with_order(PassOrder::Hierarchical(0)).is_err());
+        assert!(compressor.clone().with_order(PassOrder::Hierarchical(16)).is_err());
+
+        // A header promising a huge image is rejected before anything is allocated
+        let mut huge = compressed[..HEADER_LEN + 4 + first_len].to_vec();
+        huge[4..12].fill(0xFF);
+        assert!(compressor.decode_partial(&huge).is_err());
+    }
+
+    #[test]
+    fn test_header_records_the_algorithm() {
+        let data: Vec<u8> = (0..33 * 21 * 3).map(|i| (i * 7 % 256) as u8).collect();
+        let custom = ProgressiveCompressor::new(33, 21, 3, LzwCompressor::new(4096)).compress(&data).unwrap();
+        assert_eq!(ProgressiveCompressor::read_algorithm(&custom).unwrap(), None);
+        assert!(ProgressiveCompressor::from_header(&custom).is_err());
+
+        for id in [0, 1, 2, 3] {
+            let compressed = ProgressiveCompressor::for_algorithm(33, 21, 3, id).compress(&data).unwrap();
+            assert_eq!(ProgressiveCompressor::read_algorithm(&compressed).unwrap(), Some(id));
+            let decoder = ProgressiveCompressor::from_header(&compressed).unwrap();
+            assert_eq!(decoder.decompress(&compressed).unwrap(), data, "algorithm {}", id);
+        }
+    }
+}
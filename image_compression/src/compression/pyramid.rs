@@ -0,0 +1,422 @@
+// src/compression/pyramid.rs
+
+//! Module implementing multi-resolution pyramid storage.
+//!
+//! A pyramid stores the full image followed by successively downsampled
+//! levels, each half the width and height of the one before (rounded up),
+//! down to a single pixel or a configured number of levels. Levels are made
+//! with a choice of [`DownsampleFilter`]s, and every level is compressed on
+//! its own as a [`ParallelCompressor`] container. An index of the levels in
+//! the header lets a viewer fetch one zoom level, or only a region of it,
+//! without decompressing the others.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use image_compression::compression::Compressor;
+//! use image_compression::compression::deflate::DeflateCompressor;
+//! use image_compression::compression::parallel::ParallelCompressor;
+//! use image_compression::compression::pyramid::{DownsampleFilter, PyramidCompressor};
+//!
+//! let pixels: Vec<u8> = (0..64 * 64).map(|i| (i % 64 * 4) as u8).collect();
+//! let parts = ParallelCompressor::new(64, 64, 1, DeflateCompressor::new()).with_tiles(16, 16).unwrap();
+//! let compressor = PyramidCompressor::new(parts).with_filter(DownsampleFilter::Triangle);
+//! let compressed = compressor.compress(&pixels).unwrap();
+//! assert_eq!(PyramidCompressor::read_levels(&compressed).unwrap()[2], (16, 16));
+//! assert_eq!(compressor.decode_level(&compressed, 0).unwrap(), pixels);
+//! assert_eq!(compressor.decode_region(&compressed, 6, 0, 0, 1, 1).unwrap().len(), 1);
+//! ```
+
+use super::parallel::ParallelCompressor;
+use super::{CompressionError, Compressor};
+use rayon::prelude::*;
+use std::fmt;
+
+const MAGIC: &[u8; 4] = b"PYR\x01";
+const HEADER_LEN: usize = 15;
+/// Bytes per level index entry: a 64-bit offset and a 32-bit length.
+const INDEX_ENTRY_LEN: usize = 12;
+
+/// The filter used to halve each level.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DownsampleFilter {
+    /// Keeps the top-left pixel of every 2x2 block.
+    Nearest,
+    /// Averages every 2x2 block.
+    Box,
+    /// Bilinear tent filter, weighting a 4x4 neighbourhood 1-3-3-1.
+    Triangle,
+    /// Lanczos windowed sinc with three lobes, the sharpest of the filters.
+    Lanczos3,
+}
+
+impl DownsampleFilter {
+    /// Every filter, in identifier order.
+    pub const ALL: [DownsampleFilter; 4] =
+        [DownsampleFilter::Nearest, DownsampleFilter::Box, DownsampleFilter::Triangle, DownsampleFilter::Lanczos3];
+
+    /// Parses a filter name: `nearest`, `box`, `triangle` or `lanczos3`.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use image_compression::compression::pyramid::DownsampleFilter;
+    ///
+    /// assert_eq!(DownsampleFilter::from_name("lanczos3").unwrap(), DownsampleFilter::Lanczos3);
+    /// assert!(DownsampleFilter::from_name("cubic").is_err());
+    /// ```
+    pub fn from_name(name: &str) -> Result<Self, CompressionError> {
+        Self::ALL
+            .into_iter()
+            .find(|filter| filter.name() == name.to_lowercase())
+            .ok_or_else(|| CompressionError::InvalidLevel(format!("downsampling filter {}", name)))
+    }
+
+    /// Returns the filter's name as accepted by [`DownsampleFilter::from_name`].
+    pub fn name(self) -> &'static str {
+        match self {
+            DownsampleFilter::Nearest => "nearest",
+            DownsampleFilter::Box => "box",
+            DownsampleFilter::Triangle => "triangle",
+            DownsampleFilter::Lanczos3 => "lanczos3",
+        }
+    }
+
+    fn id(self) -> u8 {
+        Self::ALL.iter().position(|&filter| filter == self).unwrap() as u8
+    }
+
+    /// Kernel radius in output pixels.
+    fn radius(self) -> f64 {
+        match self {
+            DownsampleFilter::Nearest | DownsampleFilter::Box => 0.5,
+            DownsampleFilter::Triangle => 1.0,
+            DownsampleFilter::Lanczos3 => 3.0,
+        }
+    }
+
+    /// Kernel weight at distance `t` output pixels from the output sample's center.
+    fn weight(self, t: f64) -> f64 {
+        let t = t.abs();
+        match self {
+            DownsampleFilter::Nearest | DownsampleFilter::Box => 1.0,
+            DownsampleFilter::Triangle => (1.0 - t).max(0.0),
+            DownsampleFilter::Lanczos3 if t < 1e-9 => 1.0,
+            DownsampleFilter::Lanczos3 => {
+                let x = std::f64::consts::PI * t;
+                3.0 * x.sin() * (x / 3.0).sin() / (x * x)
+            }
+        }
+    }
+
+    /// Returns the source indices and normalized weights for each output sample of a halved axis of length `len`.
+    fn taps(self, len: u32) -> Vec<Vec<(usize, f64)>> {
+        let radius = self.radius();
+        (0..len.div_ceil(2))
+            .map(|i| {
+                if self == DownsampleFilter::Nearest {
+                    return vec![(2 * i as usize, 1.0)];
+                }
+                // Output sample i is centered on source coordinate 2i + 1.
+                let center = 2.0 * i as f64 + 1.0;
+                let first = (center - 2.0 * radius).floor() as i64;
+                let last = (center + 2.0 * radius).ceil() as i64;
+                let mut taps: Vec<(usize, f64)> = (first..last)
+                    .filter_map(|j| {
+                        let t = (j as f64 + 0.5 - center) / 2.0;
+                        (t.abs() < radius).then(|| (j.clamp(0, len as i64 - 1) as usize, self.weight(t)))
+                    })
+                    .collect();
+                let total: f64 = taps.iter().map(|&(_, w)| w).sum();
+                taps.iter_mut().for_each(|(_, w)| *w /= total);
+                taps
+            })
+            .collect()
+    }
+}
+
+/// Halves an image in each dimension, rounding up.
+///
+/// # Arguments
+///
+/// * `data` - Interleaved 8-bit pixels, `width` x `height` x `channels` bytes.
+/// * `width`, `height`, `channels` - Geometry of `data`.
+/// * `filter` - The downsampling filter.
+///
+/// # Returns
+///
+/// The downsampled pixels, `width.div_ceil(2)` x `height.div_ceil(2)` x `channels` bytes.
+pub fn downsample(data: &[u8], width: u32, height: u32, channels: u8, filter: DownsampleFilter) -> Vec<u8> {
+    let channels = channels as usize;
+    let (out_width, out_height) = (width.div_ceil(2) as usize, height.div_ceil(2) as usize);
+    let (column_taps, row_taps) = (filter.taps(width), filter.taps(height));
+
+    // Horizontal pass in floating point, then vertical pass with rounding.
+    let mut horizontal = vec![0f64; out_width * height as usize * channels];
+    horizontal.par_chunks_mut(out_width * channels).enumerate().for_each(|(y, row)| {
+        let source = &data[y * width as usize * channels..(y + 1) * width as usize * channels];
+        for (x, taps) in column_taps.iter().enumerate() {
+            for c in 0..channels {
+                row[x * channels + c] = taps.iter().map(|&(j, w)| source[j * channels + c] as f64 * w).sum();
+            }
+        }
+    });
+    let mut output = vec![0u8; out_width * out_height * channels];
+    output.par_chunks_mut(out_width * channels).zip(&row_taps).for_each(|(row, taps)| {
+        for (i, value) in row.iter_mut().enumerate() {
+            let sum: f64 = taps.iter().map(|&(j, w)| horizontal[j * out_width * channels + i] * w).sum();
+            *value = sum.round().clamp(0.0, 255.0) as u8;
+        }
+    });
+    output
+}
+
+/// A compressor storing an image together with its downsampled levels.
+#[derive(Debug, Clone)]
+pub struct PyramidCompressor {
+    parts: ParallelCompressor,
+    filter: DownsampleFilter,
+    max_levels: u8,
+}
+
+impl PyramidCompressor {
+    /// Creates a new `PyramidCompressor` with box filtering and levels down to a single pixel.
+    ///
+    /// # Arguments
+    ///
+    /// * `parts` - Compressor for the full-resolution level, whose partition,
+    ///   part codec and thread count are used for every level.
+    pub fn new(parts: ParallelCompressor) -> Self {
+        PyramidCompressor {
+            parts,
+            filter: DownsampleFilter::Box,
+            max_levels: u8::MAX,
+        }
+    }
+
+    /// Sets the downsampling filter.
+    pub fn with_filter(mut self, filter: DownsampleFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Limits the number of stored levels, including the full-resolution one.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the compressor or `CompressionError::InvalidLevel` if `levels` is zero.
+    pub fn with_max_levels(mut self, levels: u8) -> Result<Self, CompressionError> {
+        if levels == 0 {
+            return Err(CompressionError::InvalidLevel("A pyramid needs at least one level".to_string()));
+        }
+        self.max_levels = levels;
+        Ok(self)
+    }
+
+    /// Retrieves the downsampling filter.
+    pub fn get_filter(&self) -> DownsampleFilter {
+        self.filter
+    }
+
+    /// Reads the width and height of every level from the header of pyramid data.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the level dimensions, full resolution first, or a `CompressionError` if the header is invalid.
+    pub fn read_levels(data: &[u8]) -> Result<Vec<(u32, u32)>, CompressionError> {
+        let (width, height, _, _, count) = Self::parse_header(data)?;
+        Ok(Self::level_sizes(width, height, count))
+    }
+
+    /// Decompresses one level of pyramid data.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - Data written by [`Compressor::compress`].
+    /// * `level` - The level, `0` being full resolution.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the level's interleaved pixels or a `CompressionError`.
+    pub fn decode_level(&self, data: &[u8], level: u8) -> Result<Vec<u8>, CompressionError> {
+        self.parts.decompress(Self::level_container(data, level)?)
+    }
+
+    /// Decompresses a region of one level of pyramid data, reading only the parts it touches.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - Data written by [`Compressor::compress`].
+    /// * `level` - The level, `0` being full resolution.
+    /// * `x`, `y`, `width`, `height` - The region, in pixels of that level.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the region's interleaved pixels or a `CompressionError`.
+    pub fn decode_region(
+        &self,
+        data: &[u8],
+        level: u8,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+    ) -> Result<Vec<u8>, CompressionError> {
+        self.parts.decode_region(Self::level_container(data, level)?, x, y, width, height)
+    }
+
+    /// Returns the sizes of `count` levels of a `width` x `height` image.
+    fn level_sizes(width: u32, height: u32, count: u8) -> Vec<(u32, u32)> {
+        std::iter::successors(Some((width, height)), |&(w, h)| Some((w.div_ceil(2), h.div_ceil(2))))
+            .take(count as usize)
+            .collect()
+    }
+
+    fn parse_header(data: &[u8]) -> Result<(u32, u32, u8, DownsampleFilter, u8), CompressionError> {
+        let invalid = || CompressionError::Decompression("Invalid pyramid data".to_string());
+        if data.len() < HEADER_LEN || &data[..4] != MAGIC {
+            return Err(invalid());
+        }
+        let filter = *DownsampleFilter::ALL.get(data[13] as usize).ok_or_else(invalid)?;
+        if data[12] == 0 || data[14] == 0 {
+            return Err(invalid());
+        }
+        Ok((
+            u32::from_be_bytes([data[4], data[5], data[6], data[7]]),
+            u32::from_be_bytes([data[8], data[9], data[10], data[11]]),
+            data[12],
+            filter,
+            data[14],
+        ))
+    }
+
+    /// Returns the [`ParallelCompressor`] container holding one level of pyramid data.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - Data written by [`Compressor::compress`].
+    /// * `level` - The level, `0` being full resolution.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the level's container or a `CompressionError` if the level is not stored.
+    pub fn level_container(data: &[u8], level: u8) -> Result<&[u8], CompressionError> {
+        let (_, _, _, _, count) = Self::parse_header(data)?;
+        if level >= count {
+            return Err(CompressionError::Decompression(format!("Level {} not stored, only {}", level, count)));
+        }
+        let truncated = || CompressionError::Decompression("Truncated pyramid data".to_string());
+        let payload_start = HEADER_LEN + count as usize * INDEX_ENTRY_LEN;
+        let entry_start = HEADER_LEN + level as usize * INDEX_ENTRY_LEN;
+        let entry = data.get(entry_start..entry_start + INDEX_ENTRY_LEN).ok_or_else(truncated)?;
+        let offset = u64::from_be_bytes(entry[..8].try_into().unwrap());
+        let len = u32::from_be_bytes(entry[8..].try_into().unwrap()) as usize;
+        let start = usize::try_from(offset).ok().and_then(|o| o.checked_add(payload_start)).ok_or_else(truncated)?;
+        let end = start.checked_add(len).ok_or_else(truncated)?;
+        data.get(start..end).ok_or_else(truncated)
+    }
+}
+
+impl Compressor for PyramidCompressor {
+    fn compress(&self, data: &[u8]) -> Result<Vec<u8>, CompressionError> {
+        let (width, height, channels) = self.parts.get_dimensions();
+        let mut sizes = Self::level_sizes(width, height, self.max_levels);
+        if let Some(smallest) = sizes.iter().position(|&(w, h)| w <= 1 && h <= 1) {
+            sizes.truncate(smallest + 1);
+        }
+
+        let mut levels = Vec::with_capacity(sizes.len());
+        let mut pixels = data.to_vec();
+        for (i, &(w, h)) in sizes.iter().enumerate() {
+            if i > 0 {
+                let (previous_width, previous_height) = sizes[i - 1];
+                pixels = downsample(&pixels, previous_width, previous_height, channels, self.filter);
+            }
+            levels.push(self.parts.with_geometry(w, h).compress(&pixels)?);
+        }
+
+        let mut output = Vec::new();
+        output.extend_from_slice(MAGIC);
+        output.extend_from_slice(&width.to_be_bytes());
+        output.extend_from_slice(&height.to_be_bytes());
+        output.push(channels);
+        output.push(self.filter.id());
+        output.push(levels.len() as u8);
+        let mut offset = 0u64;
+        for level in &levels {
+            let len = u32::try_from(level.len())
+                .map_err(|_| CompressionError::Compression("Level exceeds 4 GiB".to_string()))?;
+            output.extend_from_slice(&offset.to_be_bytes());
+            output.extend_from_slice(&len.to_be_bytes());
+            offset += len as u64;
+        }
+        for level in &levels {
+            output.extend_from_slice(level);
+        }
+        Ok(output)
+    }
+
+    /// Decompresses the full-resolution level.
+    fn decompress(&self, data: &[u8]) -> Result<Vec<u8>, CompressionError> {
+        self.decode_level(data, 0)
+    }
+}
+
+impl fmt::Display for PyramidCompressor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (width, height, channels) = self.parts.get_dimensions();
+        write!(f, "PyramidCompressor ({}x{}x{}, Filter: {}", width, height, channels, self.filter.name())?;
+        if self.max_levels != u8::MAX {
+            write!(f, ", Max Levels: {}", self.max_levels)?;
+        }
+        write!(f, ")")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compression::lzw::LzwCompressor;
+
+    #[test]
+    fn test_filters() {
+        // A 4x2 gray image made of the 2x2 blocks 0, 100 / 200, 255 and 10, 10 / 10, 10.
+        let data = [0, 100, 10, 10, 200, 255, 10, 10];
+        assert_eq!(downsample(&data, 4, 2, 1, DownsampleFilter::Nearest), vec![0, 10]);
+        assert_eq!(downsample(&data, 4, 2, 1, DownsampleFilter::Box), vec![139, 10]);
+
+        // Flat images stay flat under every filter, including odd sizes and Lanczos overshoot.
+        for filter in DownsampleFilter::ALL {
+            let flat = vec![77u8; 7 * 5 * 2];
+            assert_eq!(downsample(&flat, 7, 5, 2, filter), vec![77u8; 4 * 3 * 2], "{:?}", filter);
+        }
+
+        // Triangle weights a ramp 1-3-3-1, which keeps its midpoints.
+        let ramp: Vec<u8> = (0..8).map(|x| x * 10).collect();
+        assert_eq!(downsample(&ramp, 8, 1, 1, DownsampleFilter::Triangle), vec![6, 25, 45, 64]);
+    }
+
+    #[test]
+    fn test_levels_and_regions() {
+        let (width, height) = (45, 30);
+        let data: Vec<u8> = (0..width * height * 3).map(|i| (i * 7 % 256) as u8).collect();
+        let parts = ParallelCompressor::new(width, height, 3, LzwCompressor::new(4096)).with_tiles(8, 8).unwrap();
+        let compressor = PyramidCompressor::new(parts).with_filter(DownsampleFilter::Lanczos3);
+        let compressed = compressor.compress(&data).unwrap();
+
+        let levels = PyramidCompressor::read_levels(&compressed).unwrap();
+        assert_eq!(levels, vec![(45, 30), (23, 15), (12, 8), (6, 4), (3, 2), (2, 1), (1, 1)]);
+        assert_eq!(compressor.decompress(&compressed).unwrap(), data);
+
+        let level_one = downsample(&data, width, height, 3, DownsampleFilter::Lanczos3);
+        assert_eq!(compressor.decode_level(&compressed, 1).unwrap(), level_one);
+        let region = compressor.decode_region(&compressed, 1, 20, 10, 3, 5).unwrap();
+        let expected: Vec<u8> = (10..15).flat_map(|y| level_one[(y * 23 + 20) * 3..(y * 23 + 23) * 3].to_vec()).collect();
+        assert_eq!(region, expected);
+        assert!(compressor.decode_level(&compressed, 7).is_err());
+
+        let limited = compressor.clone().with_max_levels(2).unwrap().compress(&data).unwrap();
+        assert_eq!(PyramidCompressor::read_levels(&limited).unwrap().len(), 2);
+        assert!(compressor.with_max_levels(0).is_err());
+    }
+}
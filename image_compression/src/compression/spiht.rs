@@ -0,0 +1,642 @@
+// src/compression/spiht.rs
+
+//! Module implementing an embedded lossy-to-lossless wavelet coder.
+//!
+//! The image is decomposed with the reversible 5/3 transform from
+//! [`super::wavelet`] and the coefficients are coded bitplane by bitplane with
+//! the SPIHT set-partitioning algorithm, driven by the adaptive range coder
+//! from [`super::entropy`]. Every prefix of the stream is a valid, coarser
+//! approximation of the image and the complete stream reconstructs it exactly.
+//!
+//! Because the payload is a single embedded stream, reducing a file to a byte
+//! budget is a plain truncation, see [`SpihtCompressor::truncate`] and
+//! [`SpihtCompressor::truncate_file`].
+//!
+//! # Examples
+//!
+//! ```rust
+//! use image_compression::compression::Compressor;
+//! use image_compression::compression::spiht::SpihtCompressor;
+//!
+//! let pixels: Vec<u8> = (0..32 * 32).map(|i| (i % 32 * 4 + i / 32 * 2) as u8).collect();
+//! let compressor = SpihtCompressor::new(32, 32, 1);
+//! let compressed = compressor.compress(&pixels).unwrap();
+//! assert_eq!(compressor.decompress(&compressed).unwrap(), pixels);
+//!
+//! // Any prefix decodes to an approximation of the same size.
+//! let preview = SpihtCompressor::decode_prefix(&compressed, compressed.len() / 2).unwrap();
+//! assert_eq!(preview.len(), pixels.len());
+//! ```
+
+use super::entropy::{BitModel, Coder, RangeDecoder, RangeEncoder};
+use super::utils::{checked_image_len, MAX_DECODED_LEN};
+use super::wavelet::{effective_levels, forward_transform, inverse_transform, level_sizes, DEFAULT_LEVELS, MAX_LEVELS};
+use super::{CompressionError, Compressor};
+use std::fmt;
+use std::fs::OpenOptions;
+use std::io::Read;
+use std::path::Path;
+
+const MAGIC: &[u8; 4] = b"SPH\x01";
+const HEADER_LEN: usize = 15;
+const TOMBSTONE: u32 = u32::MAX;
+/// Most bitplanes a stream may declare; 5/3 coefficients of 8-bit samples need far fewer,
+/// and the bound keeps reconstructed magnitudes within `i32`.
+const MAX_PLANES: u32 = 24;
+/// Bytes of tree structure held per pixel while coding, including the parent list used to build it.
+const TREE_BYTES: usize = 21;
+/// Bytes of coefficient state and list entries held per sample while coding.
+const STATE_BYTES: usize = 19;
+
+/// A compressor producing an embedded SPIHT bitstream over reversible wavelet coefficients.
+#[derive(Debug, Clone)]
+pub struct SpihtCompressor {
+    width: u32,
+    height: u32,
+    channels: u8,
+    levels: u8,
+}
+
+impl SpihtCompressor {
+    /// Creates a new `SpihtCompressor` for images of the given geometry with five decomposition levels.
+    ///
+    /// # Arguments
+    ///
+    /// * `width` - Image width in pixels.
+    /// * `height` - Image height in pixels.
+    /// * `channels` - Number of interleaved 8-bit channels per pixel.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use image_compression::compression::spiht::SpihtCompressor;
+    ///
+    /// let compressor = SpihtCompressor::new(640, 480, 3);
+    /// ```
+    pub fn new(width: u32, height: u32, channels: u8) -> Self {
+        SpihtCompressor {
+            width,
+            height,
+            channels,
+            levels: DEFAULT_LEVELS,
+        }
+    }
+
+    /// Creates a new `SpihtCompressor` with a specified number of decomposition levels.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the `SpihtCompressor` or a `CompressionError` if `levels` exceeds 16.
+    pub fn with_levels(width: u32, height: u32, channels: u8, levels: u8) -> Result<Self, CompressionError> {
+        if levels > MAX_LEVELS {
+            return Err(CompressionError::InvalidLevel(format!("{} wavelet levels", levels)));
+        }
+        Ok(SpihtCompressor {
+            width,
+            height,
+            channels,
+            levels,
+        })
+    }
+
+    /// Retrieves the requested number of decomposition levels.
+    pub fn get_levels(&self) -> u8 {
+        self.levels
+    }
+
+    /// Decodes the image from the first `prefix_len` bytes of a stream.
+    ///
+    /// The prefix must at least contain the header. Shorter prefixes give coarser
+    /// approximations; the full length reconstructs the original samples exactly.
+    pub fn decode_prefix(data: &[u8], prefix_len: usize) -> Result<Vec<u8>, CompressionError> {
+        let header = Header::parse(data)?;
+        let payload = &data[HEADER_LEN..prefix_len.clamp(HEADER_LEN, data.len())];
+        let (width, height, channels) = (header.width, header.height, header.channels);
+        checked_image_len(width, height, channels)?;
+        let sizes = level_sizes(width, height, header.levels);
+        let trees = Trees::build(width, height, channels, &sizes)
+            .ok_or_else(|| CompressionError::Decompression("SPIHT image too large".to_string()))?;
+
+        let mut states: Vec<ChannelState> = (0..channels).map(|_| ChannelState::decoding(&trees)).collect();
+        let mut models: Vec<Models> = vec![Models::default(); channels];
+        let mut decoder = RangeDecoder::new(payload);
+        let mut coder = Coder::Decode(&mut decoder);
+        'planes: for plane in (0..header.planes).rev() {
+            for (state, models) in states.iter_mut().zip(models.iter_mut()) {
+                if state.code_plane(&mut coder, &trees, models, plane).is_none() {
+                    break 'planes;
+                }
+            }
+        }
+
+        let mut output = vec![0u8; width * height * channels];
+        for (c, state) in states.iter().enumerate() {
+            let mut coefficients = state.reconstruct();
+            inverse_transform(&mut coefficients, width, &sizes, 0);
+            for (i, &v) in coefficients.iter().enumerate() {
+                output[i * channels + c] = v.clamp(0, 255) as u8;
+            }
+        }
+        Ok(output)
+    }
+
+    /// Cuts a stream down to at most `target_size` bytes without re-encoding.
+    ///
+    /// # Returns
+    ///
+    /// The truncated stream, or a `CompressionError` if `data` is not a SPIHT
+    /// stream or `target_size` cannot hold its header.
+    pub fn truncate(data: &[u8], target_size: usize) -> Result<Vec<u8>, CompressionError> {
+        Header::parse(data)?;
+        if target_size < HEADER_LEN {
+            return Err(CompressionError::Compression(format!(
+                "Target size {} is smaller than the {}-byte header",
+                target_size, HEADER_LEN
+            )));
+        }
+        Ok(data[..target_size.min(data.len())].to_vec())
+    }
+
+    /// Truncates a SPIHT file in place to at most `target_size` bytes.
+    ///
+    /// # Returns
+    ///
+    /// The new file length, or a `CompressionError` if the file cannot be
+    /// accessed, is not a SPIHT stream or `target_size` cannot hold its header.
+    pub fn truncate_file<P: AsRef<Path>>(path: P, target_size: u64) -> Result<u64, CompressionError> {
+        let io_error = |e: std::io::Error| CompressionError::Compression(e.to_string());
+        let mut file = OpenOptions::new().read(true).write(true).open(path).map_err(io_error)?;
+        let mut header = [0u8; HEADER_LEN];
+        file.read_exact(&mut header).map_err(io_error)?;
+        Header::parse(&header)?;
+        if target_size < HEADER_LEN as u64 {
+            return Err(CompressionError::Compression(format!(
+                "Target size {} is smaller than the {}-byte header",
+                target_size, HEADER_LEN
+            )));
+        }
+        let length = file.metadata().map_err(io_error)?.len().min(target_size);
+        file.set_len(length).map_err(io_error)?;
+        Ok(length)
+    }
+}
+
+impl Compressor for SpihtCompressor {
+    /// Compresses interleaved 8-bit samples of the configured geometry into an embedded stream.
+    fn compress(&self, data: &[u8]) -> Result<Vec<u8>, CompressionError> {
+        let (width, height, channels) = (self.width as usize, self.height as usize, self.channels as usize);
+        let expected = checked_image_len(width, height, channels).ok();
+        if channels == 0 || expected != Some(data.len()) {
+            return Err(CompressionError::Compression(format!(
+                "Expected {}x{}x{} samples, got {} bytes",
+                width, height, channels, data.len()
+            )));
+        }
+        let levels = effective_levels(width, height, self.levels as usize);
+        let sizes = level_sizes(width, height, levels);
+        let trees = Trees::build(width, height, channels, &sizes)
+            .ok_or_else(|| CompressionError::Compression("SPIHT image too large".to_string()))?;
+        let mut states: Vec<ChannelState> = (0..channels)
+            .map(|c| {
+                let mut plane: Vec<i32> = data.iter().skip(c).step_by(channels).map(|&v| v as i32).collect();
+                forward_transform(&mut plane, width, &sizes);
+                ChannelState::encoding(&trees, &plane)
+            })
+            .collect();
+        let max = states.iter().flat_map(|s| s.magnitude.iter()).copied().max().unwrap_or(0);
+        let planes = 32 - max.leading_zeros();
+
+        let mut encoder = RangeEncoder::new();
+        let mut coder = Coder::Encode(&mut encoder);
+        let mut models: Vec<Models> = vec![Models::default(); channels];
+        for plane in (0..planes).rev() {
+            for (state, models) in states.iter_mut().zip(models.iter_mut()) {
+                state.code_plane(&mut coder, &trees, models, plane);
+            }
+        }
+
+        let mut output = Vec::new();
+        output.extend_from_slice(MAGIC);
+        output.extend_from_slice(&self.width.to_be_bytes());
+        output.extend_from_slice(&self.height.to_be_bytes());
+        output.extend_from_slice(&[self.channels, levels as u8, planes as u8]);
+        output.extend_from_slice(&encoder.finish());
+        Ok(output)
+    }
+
+    /// Decompresses a complete stream produced by [`SpihtCompressor::compress`].
+    fn decompress(&self, data: &[u8]) -> Result<Vec<u8>, CompressionError> {
+        SpihtCompressor::decode_prefix(data, data.len())
+    }
+}
+
+/// Implement `fmt::Display` for `SpihtCompressor` for better readability.
+impl fmt::Display for SpihtCompressor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "SpihtCompressor ({}x{}x{}, Levels: {})",
+            self.width, self.height, self.channels, self.levels
+        )
+    }
+}
+
+struct Header {
+    width: usize,
+    height: usize,
+    channels: usize,
+    levels: usize,
+    planes: u32,
+}
+
+impl Header {
+    fn parse(data: &[u8]) -> Result<Self, CompressionError> {
+        if data.len() < HEADER_LEN || &data[..4] != MAGIC {
+            return Err(CompressionError::Decompression("Invalid SPIHT header".to_string()));
+        }
+        let header = Header {
+            width: u32::from_be_bytes([data[4], data[5], data[6], data[7]]) as usize,
+            height: u32::from_be_bytes([data[8], data[9], data[10], data[11]]) as usize,
+            channels: data[12] as usize,
+            levels: data[13] as usize,
+            planes: data[14] as u32,
+        };
+        // Compression never decomposes further than the geometry allows.
+        let max_levels = effective_levels(header.width, header.height, MAX_LEVELS as usize);
+        if header.channels == 0 || header.levels > max_levels || header.planes > MAX_PLANES {
+            return Err(CompressionError::Decompression("Invalid SPIHT header".to_string()));
+        }
+        Ok(header)
+    }
+}
+
+/// Spatial orientation trees over the Mallat layout, stored as adjacency lists.
+///
+/// Each detail coefficient hangs off the co-located coefficient of the same
+/// orientation one level coarser (clamped at band edges, so odd sizes work);
+/// the coarsest detail bands hang off the low-pass band, whose samples are the roots.
+struct Trees {
+    roots: Vec<u32>,
+    child_start: Vec<u32>,
+    children: Vec<u32>,
+    level: Vec<u8>,
+    bottom_up: Vec<u32>,
+}
+
+impl Trees {
+    /// Builds the trees, or returns `None` if the coefficients cannot be indexed with `u32`
+    /// or coding `channels` channels over them would hold more than [`MAX_DECODED_LEN`] bytes.
+    ///
+    /// The check runs before anything is allocated, since a corrupt header can claim any size.
+    fn build(width: usize, height: usize, channels: usize, sizes: &[(usize, usize)]) -> Option<Self> {
+        let pixels = width.checked_mul(height).filter(|&n| n < u32::MAX as usize)?;
+        pixels
+            .checked_mul(TREE_BYTES)?
+            .checked_add(pixels.checked_mul(channels)?.checked_mul(STATE_BYTES)?)
+            .filter(|&bytes| bytes <= MAX_DECODED_LEN)?;
+        let levels = sizes.len() - 1;
+        let band = |k: usize, o: usize| {
+            let ((pw, ph), (lw, lh)) = (sizes[k - 1], sizes[k]);
+            match o {
+                0 => (lw, pw, 0, lh),
+                1 => (0, lw, lh, ph),
+                _ => (lw, pw, lh, ph),
+            }
+        };
+        let (rw, rh) = sizes[levels];
+        let parent_of = |k: usize, o: usize, mut bx: usize, mut by: usize| {
+            for kk in k + 1..=levels {
+                bx /= 2;
+                by /= 2;
+                let (x0, x1, y0, y1) = band(kk, o);
+                if x1 > x0 && y1 > y0 {
+                    return (y0 + by.min(y1 - y0 - 1)) * width + x0 + bx.min(x1 - x0 - 1);
+                }
+            }
+            by.min(rh - 1) * width + bx.min(rw - 1)
+        };
+
+        let mut level = vec![0u8; width * height];
+        let mut parents = Vec::with_capacity(width * height);
+        let mut bottom_up = Vec::with_capacity(width * height);
+        for k in 1..=levels {
+            for o in 0..3 {
+                let (x0, x1, y0, y1) = band(k, o);
+                for y in y0..y1 {
+                    for x in x0..x1 {
+                        let p = y * width + x;
+                        level[p] = k as u8;
+                        parents.push((parent_of(k, o, x - x0, y - y0) as u32, p as u32));
+                        bottom_up.push(p as u32);
+                    }
+                }
+            }
+        }
+        let roots: Vec<u32> = (0..rh).flat_map(|y| (0..rw).map(move |x| (y * width + x) as u32)).collect();
+        bottom_up.extend_from_slice(&roots);
+
+        let mut child_start = vec![0u32; width * height + 1];
+        for &(parent, _) in &parents {
+            child_start[parent as usize + 1] += 1;
+        }
+        for i in 0..width * height {
+            child_start[i + 1] += child_start[i];
+        }
+        let mut fill = child_start.clone();
+        let mut children = vec![0u32; parents.len()];
+        for &(parent, child) in &parents {
+            children[fill[parent as usize] as usize] = child;
+            fill[parent as usize] += 1;
+        }
+        Some(Trees {
+            roots,
+            child_start,
+            children,
+            level,
+            bottom_up,
+        })
+    }
+
+    fn children(&self, p: u32) -> &[u32] {
+        &self.children[self.child_start[p as usize] as usize..self.child_start[p as usize + 1] as usize]
+    }
+
+    fn has_children(&self, p: u32) -> bool {
+        self.child_start[p as usize] != self.child_start[p as usize + 1]
+    }
+}
+
+/// Adaptive contexts for each kind of SPIHT decision, split by decomposition level.
+#[derive(Clone)]
+struct Models {
+    pixel: [BitModel; MAX_LEVELS as usize + 1],
+    descendants: [BitModel; MAX_LEVELS as usize + 1],
+    grandchildren: [BitModel; MAX_LEVELS as usize + 1],
+    sign: BitModel,
+    refine: BitModel,
+}
+
+impl Default for Models {
+    fn default() -> Self {
+        Models {
+            pixel: [BitModel::default(); MAX_LEVELS as usize + 1],
+            descendants: [BitModel::default(); MAX_LEVELS as usize + 1],
+            grandchildren: [BitModel::default(); MAX_LEVELS as usize + 1],
+            sign: BitModel::default(),
+            refine: BitModel::default(),
+        }
+    }
+}
+
+/// SPIHT lists and coefficient magnitudes for one channel.
+///
+/// The encoder fills `magnitude`/`negative` from the transform and the
+/// descendant maxima; the decoder rebuilds them as bits arrive.
+struct ChannelState {
+    encoding: bool,
+    magnitude: Vec<u32>,
+    negative: Vec<bool>,
+    lowest_plane: Vec<u8>,
+    max_descendant: Vec<u32>,
+    max_grandchild: Vec<u32>,
+    lip: Vec<u32>,
+    lis: Vec<(u32, bool)>,
+    lsp: Vec<u32>,
+}
+
+impl ChannelState {
+    fn new(trees: &Trees, magnitude: Vec<u32>, negative: Vec<bool>, encoding: bool) -> Self {
+        let n = magnitude.len();
+        ChannelState {
+            encoding,
+            magnitude,
+            negative,
+            lowest_plane: vec![0; n],
+            max_descendant: Vec::new(),
+            max_grandchild: Vec::new(),
+            lip: trees.roots.clone(),
+            lis: trees.roots.iter().filter(|&&p| trees.has_children(p)).map(|&p| (p, false)).collect(),
+            lsp: Vec::new(),
+        }
+    }
+
+    fn encoding(trees: &Trees, coefficients: &[i32]) -> Self {
+        let magnitude: Vec<u32> = coefficients.iter().map(|c| c.unsigned_abs()).collect();
+        let negative = coefficients.iter().map(|&c| c < 0).collect();
+        let mut max_descendant = vec![0u32; magnitude.len()];
+        let mut max_grandchild = vec![0u32; magnitude.len()];
+        for &p in &trees.bottom_up {
+            for &c in trees.children(p) {
+                let (p, c) = (p as usize, c as usize);
+                max_descendant[p] = max_descendant[p].max(magnitude[c]).max(max_descendant[c]);
+                max_grandchild[p] = max_grandchild[p].max(max_descendant[c]);
+            }
+        }
+        let mut state = ChannelState::new(trees, magnitude, negative, true);
+        state.max_descendant = max_descendant;
+        state.max_grandchild = max_grandchild;
+        state
+    }
+
+    fn decoding(trees: &Trees) -> Self {
+        let n = trees.level.len();
+        ChannelState::new(trees, vec![0; n], vec![false; n], false)
+    }
+
+    /// Codes a newly tested coefficient; returns whether it became significant.
+    fn code_pixel(&mut self, coder: &mut Coder<'_, '_>, models: &mut Models, level: u8, p: u32, plane: u32) -> Option<bool> {
+        let p = p as usize;
+        let threshold = 1u32 << plane;
+        let significant = coder.code_embedded_bit(&mut models.pixel[level as usize], self.encoding && self.magnitude[p] >= threshold)?;
+        if significant {
+            self.negative[p] = coder.code_embedded_bit(&mut models.sign, self.negative[p])?;
+            if !self.encoding {
+                self.magnitude[p] = threshold;
+                self.lowest_plane[p] = plane as u8;
+            }
+            self.lsp.push(p as u32);
+        }
+        Some(significant)
+    }
+
+    /// Runs the sorting and refinement passes for one bitplane.
+    fn code_plane(&mut self, coder: &mut Coder<'_, '_>, trees: &Trees, models: &mut Models, plane: u32) -> Option<()> {
+        let threshold = 1u32 << plane;
+        let refine_count = self.lsp.len();
+
+        let lip = std::mem::take(&mut self.lip);
+        let mut remaining = Vec::with_capacity(lip.len());
+        for &p in &lip {
+            if !self.code_pixel(coder, models, trees.level[p as usize], p, plane)? {
+                remaining.push(p);
+            }
+        }
+        self.lip = remaining;
+
+        let mut i = 0;
+        while i < self.lis.len() {
+            let (p, grandchildren) = self.lis[i];
+            let level = trees.level[p as usize] as usize;
+            if !grandchildren {
+                let value = self.encoding && self.max_descendant[p as usize] >= threshold;
+                if coder.code_embedded_bit(&mut models.descendants[level], value)? {
+                    for &c in trees.children(p) {
+                        if !self.code_pixel(coder, models, trees.level[c as usize], c, plane)? {
+                            self.lip.push(c);
+                        }
+                    }
+                    if trees.children(p).iter().any(|&c| trees.has_children(c)) {
+                        self.lis.push((p, true));
+                    }
+                    self.lis[i].0 = TOMBSTONE;
+                }
+            } else {
+                let value = self.encoding && self.max_grandchild[p as usize] >= threshold;
+                if coder.code_embedded_bit(&mut models.grandchildren[level], value)? {
+                    for &c in trees.children(p) {
+                        if trees.has_children(c) {
+                            self.lis.push((c, false));
+                        }
+                    }
+                    self.lis[i].0 = TOMBSTONE;
+                }
+            }
+            i += 1;
+        }
+        self.lis.retain(|&(p, _)| p != TOMBSTONE);
+
+        for i in 0..refine_count {
+            let p = self.lsp[i] as usize;
+            let bit = coder.code_embedded_bit(&mut models.refine, self.magnitude[p] & threshold != 0)?;
+            if !self.encoding {
+                self.magnitude[p] |= if bit { threshold } else { 0 };
+                self.lowest_plane[p] = plane as u8;
+            }
+        }
+        Some(())
+    }
+
+    /// Returns signed coefficients, placing partially decoded magnitudes mid-interval.
+    fn reconstruct(&self) -> Vec<i32> {
+        self.magnitude
+            .iter()
+            .zip(&self.negative)
+            .zip(&self.lowest_plane)
+            .map(|((&m, &negative), &lowest)| {
+                let m = if m != 0 && lowest > 0 { m + (1 << (lowest - 1)) } else { m } as i32;
+                if negative {
+                    -m
+                } else {
+                    m
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image(width: usize, height: usize, channels: usize) -> Vec<u8> {
+        (0..width * height * channels)
+            .map(|i| {
+                let (p, c) = (i / channels, i % channels);
+                let (x, y) = ((p % width) as f64, (p / width) as f64);
+                (110.0 + 70.0 * (x / 7.0).sin() * (y / 11.0).cos() + (c * 25) as f64) as u8
+            })
+            .collect()
+    }
+
+    fn mean_abs_error(a: &[u8], b: &[u8]) -> f64 {
+        a.iter().zip(b).map(|(&x, &y)| x.abs_diff(y) as f64).sum::<f64>() / a.len() as f64
+    }
+
+    #[test]
+    fn test_spiht_round_trip() {
+        for &(w, h, c) in &[(1, 1, 1), (13, 7, 3), (64, 48, 4), (40, 2, 1)] {
+            let data = image(w, h, c);
+            let compressor = SpihtCompressor::new(w as u32, h as u32, c as u8);
+            let compressed = compressor.compress(&data).unwrap();
+            assert_eq!(compressor.decompress(&compressed).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn test_spiht_rejects_bad_headers() {
+        let compressed = SpihtCompressor::new(4, 4, 1).compress(&[9u8; 16]).unwrap();
+        let mut huge = compressed.clone();
+        huge[4..12].copy_from_slice(&[0xFF; 8]);
+        assert!(SpihtCompressor::decode_prefix(&huge, huge.len()).is_err());
+        let mut planes = compressed;
+        planes[14] = 32;
+        assert!(SpihtCompressor::decode_prefix(&planes, planes.len()).is_err());
+
+        // One flipped width bit claims 8388645x29x3, within the output cap but not the coding state's.
+        let mut wide = SpihtCompressor::new(37, 29, 3).compress(&image(37, 29, 3)).unwrap();
+        wide[5] ^= 0x80;
+        assert_eq!(u32::from_be_bytes(wide[4..8].try_into().unwrap()), 8_388_645);
+        assert!(SpihtCompressor::decode_prefix(&wide, wide.len()).is_err());
+    }
+
+    #[test]
+    fn test_spiht_corrupt_streams() {
+        let compressed = SpihtCompressor::new(4, 4, 1).compress(&[9u8; 16]).unwrap();
+        // A 4x4 image holds at most two levels.
+        let mut deep = compressed.clone();
+        deep[13] = 3;
+        assert!(SpihtCompressor::new(4, 4, 1).decompress(&deep).is_err());
+        assert!(SpihtCompressor::new(u32::MAX, u32::MAX, 255).compress(&[0u8; 16]).is_err());
+
+        // Garbage coded at the largest magnitudes and depth decodes to some image without overflowing.
+        let mut garbage = SpihtCompressor::new(64, 64, 1).compress(&image(64, 64, 1)).unwrap()[..HEADER_LEN].to_vec();
+        garbage[13] = 6;
+        garbage[14] = MAX_PLANES as u8;
+        for fill in [0x00, 0x5A, 0xFF] {
+            let mut stream = garbage.clone();
+            stream.extend(std::iter::repeat_n(fill, 4096));
+            assert_eq!(SpihtCompressor::decode_prefix(&stream, stream.len()).unwrap().len(), 64 * 64);
+        }
+    }
+
+    #[test]
+    fn test_spiht_prefixes_improve_monotonically() {
+        let data = image(64, 64, 3);
+        let compressed = SpihtCompressor::new(64, 64, 3).compress(&data).unwrap();
+        let errors: Vec<f64> = [HEADER_LEN + 64, compressed.len() / 8, compressed.len() / 2, compressed.len()]
+            .iter()
+            .map(|&len| mean_abs_error(&SpihtCompressor::decode_prefix(&compressed, len).unwrap(), &data))
+            .collect();
+        assert!(errors.windows(2).all(|pair| pair[1] <= pair[0]), "{:?}", errors);
+        assert!(errors[1] < 10.0);
+        assert_eq!(errors[3], 0.0);
+    }
+
+    #[test]
+    fn test_spiht_truncate_matches_prefix_decode() {
+        let data = image(32, 32, 1);
+        let compressed = SpihtCompressor::new(32, 32, 1).compress(&data).unwrap();
+        let truncated = SpihtCompressor::truncate(&compressed, 200).unwrap();
+        assert_eq!(truncated.len(), 200);
+        assert_eq!(
+            SpihtCompressor::decode_prefix(&truncated, truncated.len()).unwrap(),
+            SpihtCompressor::decode_prefix(&compressed, 200).unwrap()
+        );
+        assert!(SpihtCompressor::truncate(&compressed, 4).is_err());
+        assert!(SpihtCompressor::truncate(b"not spiht data!!", 10).is_err());
+    }
+
+    #[test]
+    fn test_spiht_truncate_file() {
+        let data = image(32, 32, 3);
+        let compressed = SpihtCompressor::new(32, 32, 3).compress(&data).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("image.sph");
+        std::fs::write(&path, &compressed).unwrap();
+
+        assert_eq!(SpihtCompressor::truncate_file(&path, 300).unwrap(), 300);
+        let truncated = std::fs::read(&path).unwrap();
+        assert_eq!(truncated, compressed[..300]);
+        assert_eq!(SpihtCompressor::decode_prefix(&truncated, 300).unwrap().len(), data.len());
+    }
+}
@@ -0,0 +1,203 @@
+// src/compression/stream.rs
+
+//! Module implementing streaming compression over `Read` and `Write`.
+//!
+//! [`StreamingCompressor`] hands out a `Write`-based encoder that compresses
+//! whatever is written to it, and a `Read`-based decoder that yields the
+//! decompressed bytes of its input. Deflate and LZW stream natively in
+//! bounded memory. Codecs that need their whole input at once, such as the
+//! wavelet and recompression codecs, stream through the [`Buffered`]
+//! adapter over their slice API, and [`compress_to_vec`] and
+//! [`decompress_to_vec`] adapt any streaming compressor back to slices.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use image_compression::compression::deflate::DeflateCompressor;
+//! use image_compression::compression::stream::StreamingCompressor;
+//!
+//! let compressor = DeflateCompressor::new();
+//! let mut compressed = Vec::new();
+//! compressor.compress_stream(&mut &b"streamed data"[..], &mut compressed).unwrap();
+//! let mut restored = Vec::new();
+//! compressor.decompress_stream(&mut &compressed[..], &mut restored).unwrap();
+//! assert_eq!(restored, b"streamed data");
+//! ```
+
+use super::{CompressionError, Compressor};
+use std::io::{self, Cursor, Read, Write};
+
+/// A `Write` sink that compresses the bytes written to it.
+pub trait StreamEncoder: Write {
+    /// Writes any buffered data and trailers to the underlying writer and flushes it.
+    ///
+    /// Dropping an encoder without calling `finish` may leave its output truncated.
+    fn finish(self: Box<Self>) -> Result<(), CompressionError>;
+}
+
+/// A compressor that encodes into a `Write` and decodes from a `Read`.
+pub trait StreamingCompressor {
+    /// Returns an encoder writing compressed data to `output`.
+    ///
+    /// # Arguments
+    ///
+    /// * `output` - Where compressed bytes are written.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the encoder or a `CompressionError` if the compressor cannot stream.
+    fn encoder<'a>(&self, output: Box<dyn Write + 'a>) -> Result<Box<dyn StreamEncoder + 'a>, CompressionError>;
+
+    /// Returns a reader yielding the decompressed contents of `input`.
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - Compressed bytes, as written by an encoder of the same configuration.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the decoder or a `CompressionError` if the input cannot be opened.
+    fn decoder<'a>(&self, input: Box<dyn Read + 'a>) -> Result<Box<dyn Read + 'a>, CompressionError>;
+
+    /// Compresses everything read from `input` into `output`.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the number of uncompressed bytes read or a `CompressionError`.
+    fn compress_stream(&self, input: &mut dyn Read, output: &mut dyn Write) -> Result<u64, CompressionError> {
+        let mut encoder = self.encoder(Box::new(output))?;
+        let read = io::copy(input, &mut encoder).map_err(|e| CompressionError::Compression(e.to_string()))?;
+        encoder.finish()?;
+        Ok(read)
+    }
+
+    /// Decompresses everything read from `input` into `output`.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the number of decompressed bytes written or a `CompressionError`.
+    fn decompress_stream(&self, input: &mut dyn Read, output: &mut dyn Write) -> Result<u64, CompressionError> {
+        let mut decoder = self.decoder(Box::new(input))?;
+        let written = io::copy(&mut decoder, output).map_err(|e| CompressionError::Decompression(e.to_string()))?;
+        output.flush().map_err(|e| CompressionError::Decompression(e.to_string()))?;
+        Ok(written)
+    }
+}
+
+/// Compresses a slice with a streaming compressor.
+pub fn compress_to_vec<C: StreamingCompressor + ?Sized>(compressor: &C, data: &[u8]) -> Result<Vec<u8>, CompressionError> {
+    let mut output = Vec::new();
+    compressor.compress_stream(&mut &data[..], &mut output)?;
+    Ok(output)
+}
+
+/// Decompresses a slice with a streaming compressor.
+pub fn decompress_to_vec<C: StreamingCompressor + ?Sized>(compressor: &C, data: &[u8]) -> Result<Vec<u8>, CompressionError> {
+    let mut output = Vec::new();
+    compressor.decompress_stream(&mut &data[..], &mut output)?;
+    Ok(output)
+}
+
+/// Adapts a slice [`Compressor`] to [`StreamingCompressor`] by buffering whole inputs.
+///
+/// Memory use is that of the slice API: the encoder holds all written data
+/// until `finish`, and the decoder reads its whole input before yielding.
+#[derive(Debug, Clone)]
+pub struct Buffered<C>(pub C);
+
+impl<C: Compressor + Clone + 'static> StreamingCompressor for Buffered<C> {
+    fn encoder<'a>(&self, output: Box<dyn Write + 'a>) -> Result<Box<dyn StreamEncoder + 'a>, CompressionError> {
+        Ok(Box::new(BufferedEncoder {
+            compressor: self.0.clone(),
+            buffer: Vec::new(),
+            output,
+        }))
+    }
+
+    fn decoder<'a>(&self, mut input: Box<dyn Read + 'a>) -> Result<Box<dyn Read + 'a>, CompressionError> {
+        let mut data = Vec::new();
+        input.read_to_end(&mut data).map_err(|e| CompressionError::Decompression(e.to_string()))?;
+        Ok(Box::new(Cursor::new(self.0.decompress(&data)?)))
+    }
+}
+
+struct BufferedEncoder<'a, C> {
+    compressor: C,
+    buffer: Vec<u8>,
+    output: Box<dyn Write + 'a>,
+}
+
+impl<C> Write for BufferedEncoder<'_, C> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<C: Compressor> StreamEncoder for BufferedEncoder<'_, C> {
+    fn finish(mut self: Box<Self>) -> Result<(), CompressionError> {
+        let compressed = self.compressor.compress(&self.buffer)?;
+        self.output
+            .write_all(&compressed)
+            .and_then(|_| self.output.flush())
+            .map_err(|e| CompressionError::Compression(e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compression::wavelet::WaveletCompressor;
+    use crate::compression::CompressionAlgorithmType;
+
+    /// A reader returning at most three bytes per call, to exercise partial reads.
+    struct Trickle<'a>(&'a [u8]);
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = buf.len().min(3).min(self.0.len());
+            buf[..n].copy_from_slice(&self.0[..n]);
+            self.0 = &self.0[n..];
+            Ok(n)
+        }
+    }
+
+    fn sample() -> Vec<u8> {
+        (0..20_000u32).map(|i| ((i * 7) % 251) as u8 ^ (i / 300) as u8).collect()
+    }
+
+    #[test]
+    fn test_streams_match_slice_api() {
+        let data = sample();
+        for algorithm in ["deflate", "lzw"] {
+            let compressor = CompressionAlgorithmType::create(algorithm, None).unwrap();
+            let mut compressed = Vec::new();
+            assert_eq!(compressor.compress_stream(&mut Trickle(&data), &mut compressed).unwrap(), data.len() as u64);
+            assert_eq!(compressed, compressor.compress(&data).unwrap(), "{}", algorithm);
+
+            let mut restored = Vec::new();
+            compressor.decompress_stream(&mut Trickle(&compressed), &mut restored).unwrap();
+            assert_eq!(restored, data, "{}", algorithm);
+        }
+    }
+
+    #[test]
+    fn test_buffered_adapter() {
+        let data: Vec<u8> = (0..32 * 16).map(|i| (i % 32 * 8) as u8).collect();
+        let compressor = Buffered(WaveletCompressor::new(32, 16, 1));
+        let compressed = compress_to_vec(&compressor, &data).unwrap();
+        assert_eq!(compressed, compressor.0.compress(&data).unwrap());
+        assert_eq!(decompress_to_vec(&compressor, &compressed).unwrap(), data);
+    }
+
+    #[test]
+    fn test_corrupt_stream_is_an_error() {
+        let compressor = CompressionAlgorithmType::create("lzw", None).unwrap();
+        assert!(decompress_to_vec(&compressor, &[0, 65, 0]).is_err());
+        assert!(decompress_to_vec(&compressor, &[0, 65, 0x7F, 0xFF]).is_err());
+    }
+}
@@ -1,3 +1,5 @@
+use super::CompressionError;
+
 pub fn calculate_entropy(data: &[u8]) -> f64 {
     let mut freq = [0u32; 256];
     for &byte in data {
@@ -13,3 +15,56 @@ pub fn calculate_entropy(data: &[u8]) -> f64 {
         }
     }).sum()
 }
+
+/// Computes the Adler-32 checksum of `data` (RFC 1950), as ends a zlib stream.
+pub fn adler32(data: &[u8]) -> u32 {
+    const MOD: u32 = 65_521;
+    // 5552 is the most bytes that can be summed before `b` may overflow.
+    let (mut a, mut b) = (1u32, 0u32);
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= MOD;
+        b %= MOD;
+    }
+    (b << 16) | a
+}
+
+/// Largest decoded image, in bytes, that a decoder allocates from a stream header.
+pub const MAX_DECODED_LEN: usize = 1 << 31;
+
+/// Computes the size of a `width` x `height` x `channels` 8-bit image read from untrusted input.
+///
+/// # Returns
+///
+/// A `Result` containing the size in bytes, or `CompressionError::Decompression` if the size
+/// overflows or exceeds [`MAX_DECODED_LEN`].
+pub fn checked_image_len(width: usize, height: usize, channels: usize) -> Result<usize, CompressionError> {
+    width
+        .checked_mul(height)
+        .and_then(|pixels| pixels.checked_mul(channels))
+        .filter(|&len| len <= MAX_DECODED_LEN)
+        .ok_or_else(|| {
+            CompressionError::Decompression(format!("Invalid image size {}x{}x{}", width, height, channels))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_adler32() {
+        assert_eq!(adler32(b""), 1);
+        assert_eq!(adler32(b"Wikipedia"), 0x11E6_0398);
+        let long = vec![0xFFu8; 100_000];
+        let (mut a, mut b) = (1u64, 0u64);
+        for &byte in &long {
+            a = (a + byte as u64) % 65_521;
+            b = (b + a) % 65_521;
+        }
+        assert_eq!(adler32(&long), ((b << 16) | a) as u32);
+    }
+}
@@ -0,0 +1,585 @@
+// src/compression/wavelet.rs
+
+//! Module implementing a reversible LeGall 5/3 wavelet codec.
+//!
+//! Each channel of an 8-bit interleaved image is decomposed with the integer
+//! lifting scheme used by lossless JPEG 2000, so decompression is bit-exact.
+//! The subband coefficients are coded with the adaptive range coder from
+//! [`super::entropy`].
+//!
+//! The stream stores the subbands coarse-to-fine as independently coded,
+//! length-prefixed segments. Reading only the first segments is enough to
+//! reconstruct a reduced-resolution preview, see
+//! [`WaveletCompressor::decode_preview`].
+//!
+//! # Examples
+//!
+//! ```rust
+//! use image_compression::compression::Compressor;
+//! use image_compression::compression::wavelet::WaveletCompressor;
+//!
+//! let pixels: Vec<u8> = (0..16 * 8 * 3).map(|i| (i % 251) as u8).collect();
+//! let compressor = WaveletCompressor::new(16, 8, 3);
+//! let compressed = compressor.compress(&pixels).unwrap();
+//! assert_eq!(compressor.decompress(&compressed).unwrap(), pixels);
+//! ```
+
+use super::entropy::{Coder, IntModel, RangeDecoder, RangeEncoder};
+use super::utils::checked_image_len;
+use super::{CompressionError, Compressor};
+use std::fmt;
+
+const MAGIC: &[u8; 4] = b"W53\x01";
+const HEADER_LEN: usize = 14;
+pub(crate) const DEFAULT_LEVELS: u8 = 5;
+pub(crate) const MAX_LEVELS: u8 = 16;
+const CONTEXTS: usize = 5;
+/// Bound on coefficient magnitudes; the 5/3 transform of 8-bit samples stays far below it,
+/// so larger decoded values can only come from a corrupt stream.
+const MAX_COEFFICIENT: u32 = 1 << 24;
+
+/// A compressor that codes 8-bit images with a reversible 5/3 wavelet transform.
+#[derive(Debug, Clone)]
+pub struct WaveletCompressor {
+    width: u32,
+    height: u32,
+    channels: u8,
+    levels: u8,
+}
+
+/// A reduced-resolution image decoded from the coarse subbands of a wavelet stream.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WaveletPreview {
+    /// Width of the preview in pixels.
+    pub width: u32,
+    /// Height of the preview in pixels.
+    pub height: u32,
+    /// Number of interleaved channels per pixel.
+    pub channels: u8,
+    /// Interleaved 8-bit samples.
+    pub data: Vec<u8>,
+    /// Bytes of the stream read to decode the preview, header included.
+    pub bytes_read: usize,
+}
+
+/// Geometry and decomposition depth parsed from a stream header.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct WaveletHeader {
+    pub(crate) width: usize,
+    pub(crate) height: usize,
+    pub(crate) channels: usize,
+    pub(crate) levels: usize,
+}
+
+impl WaveletCompressor {
+    /// Creates a new `WaveletCompressor` for images of the given geometry with five decomposition levels.
+    ///
+    /// # Arguments
+    ///
+    /// * `width` - Image width in pixels.
+    /// * `height` - Image height in pixels.
+    /// * `channels` - Number of interleaved 8-bit channels per pixel.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use image_compression::compression::wavelet::WaveletCompressor;
+    ///
+    /// let compressor = WaveletCompressor::new(640, 480, 3);
+    /// ```
+    pub fn new(width: u32, height: u32, channels: u8) -> Self {
+        WaveletCompressor {
+            width,
+            height,
+            channels,
+            levels: DEFAULT_LEVELS,
+        }
+    }
+
+    /// Creates a new `WaveletCompressor` with a specified number of decomposition levels.
+    ///
+    /// The effective depth is capped so that the coarsest subband keeps at least one sample.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the `WaveletCompressor` or a `CompressionError` if `levels` exceeds 16.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use image_compression::compression::wavelet::WaveletCompressor;
+    ///
+    /// let compressor = WaveletCompressor::with_levels(640, 480, 3, 3).unwrap();
+    /// assert_eq!(compressor.get_levels(), 3);
+    /// ```
+    pub fn with_levels(width: u32, height: u32, channels: u8, levels: u8) -> Result<Self, CompressionError> {
+        if levels > MAX_LEVELS {
+            return Err(CompressionError::InvalidLevel(format!("{} wavelet levels", levels)));
+        }
+        Ok(WaveletCompressor {
+            width,
+            height,
+            channels,
+            levels,
+        })
+    }
+
+    /// Retrieves the requested number of decomposition levels.
+    pub fn get_levels(&self) -> u8 {
+        self.levels
+    }
+
+    /// Decodes a reduced-resolution preview by reading only the coarse subbands.
+    ///
+    /// A `discard_levels` of `n` skips the `n` finest detail levels and returns an
+    /// image of roughly `1 / 2^n` the original size; `0` yields the full image.
+    /// Only the segments needed for the requested resolution are read, so `data`
+    /// may be a prefix of the complete stream.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use image_compression::compression::Compressor;
+    /// use image_compression::compression::wavelet::WaveletCompressor;
+    ///
+    /// let pixels = vec![128u8; 64 * 64];
+    /// let compressed = WaveletCompressor::new(64, 64, 1).compress(&pixels).unwrap();
+    /// let preview = WaveletCompressor::decode_preview(&compressed, 2).unwrap();
+    /// assert_eq!((preview.width, preview.height), (16, 16));
+    /// ```
+    pub fn decode_preview(data: &[u8], discard_levels: u8) -> Result<WaveletPreview, CompressionError> {
+        let header = parse_header(data)?;
+        let discard = (discard_levels as usize).min(header.levels);
+        let mut segments = Vec::with_capacity(header.levels + 1);
+        let mut pos = HEADER_LEN;
+        for _ in 0..=(header.levels - discard) {
+            let len = read_u32(data, pos)? as usize;
+            let body = data
+                .get(pos + 4..pos + 4 + len)
+                .ok_or_else(|| CompressionError::Decompression("Truncated wavelet segment".to_string()))?;
+            segments.push(body);
+            pos += 4 + len;
+        }
+
+        let planes = decode_planes(&header, &segments)?;
+        let sizes = level_sizes(header.width, header.height, header.levels);
+        let mut output = Vec::with_capacity(sizes[discard].0 * sizes[discard].1 * header.channels);
+        let planes: Vec<Vec<i32>> = planes
+            .into_iter()
+            .map(|mut plane| {
+                inverse_transform(&mut plane, header.width, &sizes, discard);
+                plane
+            })
+            .collect();
+        let (pw, ph) = sizes[discard];
+        for y in 0..ph {
+            for x in 0..pw {
+                for plane in &planes {
+                    output.push(plane[y * header.width + x].clamp(0, 255) as u8);
+                }
+            }
+        }
+        Ok(WaveletPreview {
+            width: pw as u32,
+            height: ph as u32,
+            channels: header.channels as u8,
+            data: output,
+            bytes_read: pos,
+        })
+    }
+}
+
+impl Compressor for WaveletCompressor {
+    /// Compresses interleaved 8-bit samples of the configured geometry.
+    fn compress(&self, data: &[u8]) -> Result<Vec<u8>, CompressionError> {
+        let (width, height, channels) = (self.width as usize, self.height as usize, self.channels as usize);
+        let expected = checked_image_len(width, height, channels).ok();
+        if channels == 0 || expected != Some(data.len()) {
+            return Err(CompressionError::Compression(format!(
+                "Expected {}x{}x{} samples, got {} bytes",
+                width, height, channels, data.len()
+            )));
+        }
+        let levels = effective_levels(width, height, self.levels as usize);
+        let sizes = level_sizes(width, height, levels);
+        let mut planes: Vec<Vec<i32>> = (0..channels)
+            .map(|c| {
+                let mut plane: Vec<i32> = data.iter().skip(c).step_by(channels).map(|&v| v as i32).collect();
+                forward_transform(&mut plane, width, &sizes);
+                plane
+            })
+            .collect();
+
+        let mut output = Vec::new();
+        output.extend_from_slice(MAGIC);
+        output.extend_from_slice(&self.width.to_be_bytes());
+        output.extend_from_slice(&self.height.to_be_bytes());
+        output.push(self.channels);
+        output.push(levels as u8);
+        for segment in 0..=levels {
+            let mut encoder = RangeEncoder::new();
+            code_segment(&mut Coder::Encode(&mut encoder), &mut planes, width, &sizes, segment)?;
+            let bytes = encoder.finish();
+            output.extend_from_slice(&(bytes.len() as u32).to_be_bytes());
+            output.extend_from_slice(&bytes);
+        }
+        Ok(output)
+    }
+
+    /// Decompresses a stream produced by [`WaveletCompressor::compress`].
+    fn decompress(&self, data: &[u8]) -> Result<Vec<u8>, CompressionError> {
+        WaveletCompressor::decode_preview(data, 0).map(|preview| preview.data)
+    }
+}
+
+/// Implement `fmt::Display` for `WaveletCompressor` for better readability.
+impl fmt::Display for WaveletCompressor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "WaveletCompressor ({}x{}x{}, Levels: {})",
+            self.width, self.height, self.channels, self.levels
+        )
+    }
+}
+
+pub(crate) fn parse_header(data: &[u8]) -> Result<WaveletHeader, CompressionError> {
+    if data.len() < HEADER_LEN || &data[..4] != MAGIC {
+        return Err(CompressionError::Decompression("Invalid wavelet header".to_string()));
+    }
+    let header = WaveletHeader {
+        width: read_u32(data, 4)? as usize,
+        height: read_u32(data, 8)? as usize,
+        channels: data[12] as usize,
+        levels: data[13] as usize,
+    };
+    // Compression never decomposes further than the geometry allows.
+    if header.channels == 0 || header.levels > effective_levels(header.width, header.height, MAX_LEVELS as usize) {
+        return Err(CompressionError::Decompression("Invalid wavelet header".to_string()));
+    }
+    Ok(header)
+}
+
+fn read_u32(data: &[u8], pos: usize) -> Result<u32, CompressionError> {
+    data.get(pos..pos + 4)
+        .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+        .ok_or_else(|| CompressionError::Decompression("Truncated wavelet stream".to_string()))
+}
+
+fn decode_planes(header: &WaveletHeader, segments: &[&[u8]]) -> Result<Vec<Vec<i32>>, CompressionError> {
+    checked_image_len(header.width, header.height, header.channels)?;
+    let sizes = level_sizes(header.width, header.height, header.levels);
+    let mut planes = vec![vec![0i32; header.width * header.height]; header.channels];
+    for (segment, body) in segments.iter().enumerate() {
+        let mut decoder = RangeDecoder::new(body);
+        code_segment(&mut Coder::Decode(&mut decoder), &mut planes, header.width, &sizes, segment)?;
+    }
+    Ok(planes)
+}
+
+/// Caps the decomposition depth so that the coarsest band is still non-empty.
+pub(crate) fn effective_levels(width: usize, height: usize, requested: usize) -> usize {
+    let (mut w, mut h, mut levels) = (width, height, 0);
+    while levels < requested && (w > 1 || h > 1) {
+        w = w.div_ceil(2);
+        h = h.div_ceil(2);
+        levels += 1;
+    }
+    levels
+}
+
+/// Returns the low-pass region size at every level, from the full image (index 0) down.
+pub(crate) fn level_sizes(width: usize, height: usize, levels: usize) -> Vec<(usize, usize)> {
+    let mut sizes = vec![(width, height)];
+    for _ in 0..levels {
+        let (w, h) = *sizes.last().unwrap();
+        sizes.push((w.div_ceil(2), h.div_ceil(2)));
+    }
+    sizes
+}
+
+/// One-dimensional forward 5/3 lifting; writes the low band followed by the high band into `out`.
+pub(crate) fn forward_1d(x: &[i32], out: &mut [i32]) {
+    let n = x.len();
+    if n < 2 {
+        out[..n].copy_from_slice(x);
+        return;
+    }
+    let (nl, nh) = (n.div_ceil(2), n / 2);
+    for i in 0..nh {
+        let right = if 2 * i + 2 < n { x[2 * i + 2] } else { x[2 * i] };
+        out[nl + i] = x[2 * i + 1] - ((x[2 * i] + right) >> 1);
+    }
+    for i in 0..nl {
+        let dl = out[nl + if i > 0 { i - 1 } else { 0 }];
+        let dr = out[nl + i.min(nh - 1)];
+        out[i] = x[2 * i] + ((dl + dr + 2) >> 2);
+    }
+}
+
+/// One-dimensional inverse 5/3 lifting, undoing [`forward_1d`] exactly.
+///
+/// The arithmetic wraps, so coefficients from a corrupt stream yield garbage rather than an
+/// overflow; valid streams never come near the `i32` range.
+pub(crate) fn inverse_1d(bands: &[i32], x: &mut [i32]) {
+    let n = bands.len();
+    if n < 2 {
+        x[..n].copy_from_slice(bands);
+        return;
+    }
+    let (nl, nh) = (n.div_ceil(2), n / 2);
+    for i in 0..nl {
+        let dl = bands[nl + if i > 0 { i - 1 } else { 0 }];
+        let dr = bands[nl + i.min(nh - 1)];
+        x[2 * i] = bands[i].wrapping_sub(dl.wrapping_add(dr).wrapping_add(2) >> 2);
+    }
+    for i in 0..nh {
+        let right = if 2 * i + 2 < n { x[2 * i + 2] } else { x[2 * i] };
+        x[2 * i + 1] = bands[nl + i].wrapping_add(x[2 * i].wrapping_add(right) >> 1);
+    }
+}
+
+/// Applies every decomposition level in place using the Mallat layout.
+pub(crate) fn forward_transform(plane: &mut [i32], stride: usize, sizes: &[(usize, usize)]) {
+    for &(w, h) in &sizes[..sizes.len() - 1] {
+        transform_region(plane, stride, w, h, forward_1d);
+    }
+}
+
+/// Undoes decomposition levels in place down to the low-pass band of level `stop`.
+pub(crate) fn inverse_transform(plane: &mut [i32], stride: usize, sizes: &[(usize, usize)], stop: usize) {
+    for &(w, h) in sizes[stop..sizes.len() - 1].iter().rev() {
+        let mut column = vec![0; h];
+        let mut out = vec![0; h];
+        for x in 0..w {
+            for y in 0..h {
+                column[y] = plane[y * stride + x];
+            }
+            inverse_1d(&column, &mut out);
+            for y in 0..h {
+                plane[y * stride + x] = out[y];
+            }
+        }
+        let mut row = vec![0; w];
+        for y in 0..h {
+            inverse_1d(&plane[y * stride..y * stride + w], &mut row);
+            plane[y * stride..y * stride + w].copy_from_slice(&row);
+        }
+    }
+}
+
+fn transform_region(plane: &mut [i32], stride: usize, w: usize, h: usize, f: fn(&[i32], &mut [i32])) {
+    let mut row = vec![0; w];
+    for y in 0..h {
+        f(&plane[y * stride..y * stride + w], &mut row);
+        plane[y * stride..y * stride + w].copy_from_slice(&row);
+    }
+    let mut column = vec![0; h];
+    let mut out = vec![0; h];
+    for x in 0..w {
+        for y in 0..h {
+            column[y] = plane[y * stride + x];
+        }
+        f(&column, &mut out);
+        for y in 0..h {
+            plane[y * stride + x] = out[y];
+        }
+    }
+}
+
+fn bucket(activity: u32) -> usize {
+    match activity {
+        0 => 0,
+        1..=2 => 1,
+        3..=6 => 2,
+        7..=14 => 3,
+        _ => CONTEXTS - 1,
+    }
+}
+
+/// Codes one segment: the coarsest low-pass band (segment 0) or the detail bands of one level.
+///
+/// Segment `s > 0` holds the detail bands of decomposition level `levels + 1 - s`.
+/// Decoded coefficients beyond [`MAX_COEFFICIENT`] are rejected as corrupt.
+fn code_segment(
+    coder: &mut Coder<'_, '_>,
+    planes: &mut [Vec<i32>],
+    stride: usize,
+    sizes: &[(usize, usize)],
+    segment: usize,
+) -> Result<(), CompressionError> {
+    let corrupt = || CompressionError::Decompression("Corrupt wavelet coefficients".to_string());
+    let levels = sizes.len() - 1;
+    if segment == 0 {
+        let (w, h) = sizes[levels];
+        let mut models: Vec<IntModel> = vec![IntModel::default(); CONTEXTS];
+        for plane in planes.iter_mut() {
+            for y in 0..h {
+                let at = |plane: &[i32], x: usize, y: usize| plane[y * stride + x];
+                for x in 0..w {
+                    let a = if x > 0 {
+                        at(plane, x - 1, y)
+                    } else if y > 0 {
+                        at(plane, x, y - 1)
+                    } else {
+                        0
+                    };
+                    let b = if y > 0 { at(plane, x, y - 1) } else { a };
+                    let c = if x > 0 && y > 0 { at(plane, x - 1, y - 1) } else { b };
+                    let prediction = if c >= a.max(b) {
+                        a.min(b)
+                    } else if c <= a.min(b) {
+                        a.max(b)
+                    } else {
+                        a + b - c
+                    };
+                    let ctx = bucket((a - c).unsigned_abs() + (b - c).unsigned_abs());
+                    let mut residual = plane[y * stride + x] - prediction;
+                    coder.code_int(&mut models[ctx], &mut residual);
+                    plane[y * stride + x] = prediction
+                        .checked_add(residual)
+                        .filter(|value| value.unsigned_abs() <= MAX_COEFFICIENT)
+                        .ok_or_else(corrupt)?;
+                }
+            }
+        }
+        return Ok(());
+    }
+
+    let level = levels + 1 - segment;
+    let (pw, ph) = sizes[level - 1];
+    let (lw, lh) = sizes[level];
+    let bands = [(lw, pw, 0, lh), (0, lw, lh, ph), (lw, pw, lh, ph)];
+    let mut models: Vec<Vec<IntModel>> = vec![vec![IntModel::default(); CONTEXTS]; bands.len()];
+    for plane in planes.iter_mut() {
+        for (band, &(x0, x1, y0, y1)) in bands.iter().enumerate() {
+            for y in y0..y1 {
+                for x in x0..x1 {
+                    let left = if x > x0 { plane[y * stride + x - 1].unsigned_abs() } else { 0 };
+                    let top = if y > y0 { plane[(y - 1) * stride + x].unsigned_abs() } else { 0 };
+                    let ctx = bucket(left.saturating_add(top));
+                    coder.code_int(&mut models[band][ctx], &mut plane[y * stride + x]);
+                    if plane[y * stride + x].unsigned_abs() > MAX_COEFFICIENT {
+                        return Err(corrupt());
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gradient(width: usize, height: usize, channels: usize) -> Vec<u8> {
+        (0..width * height * channels)
+            .map(|i| {
+                let (p, c) = (i / channels, i % channels);
+                let mut h = (i as u32).wrapping_mul(0x9E37_79B9);
+                h ^= h >> 15;
+                h = h.wrapping_mul(0x2C1B_3C6D);
+                h ^= h >> 12;
+                let (x, y) = ((p % width) as f64, (p / width) as f64);
+                let wave = 60.0 * (x / 9.0).sin() * (y / 13.0).cos();
+                (100.0 + wave + (c * 20) as f64) as u8 + (h >> 30) as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_lifting_is_reversible() {
+        for n in 1..12 {
+            let x: Vec<i32> = (0..n).map(|i| (i * 37 % 11) as i32 - 5).collect();
+            let mut bands = vec![0; n];
+            let mut back = vec![0; n];
+            forward_1d(&x, &mut bands);
+            inverse_1d(&bands, &mut back);
+            assert_eq!(x, back);
+        }
+    }
+
+    #[test]
+    fn test_wavelet_round_trip_odd_sizes() {
+        for &(w, h, c) in &[(1, 1, 1), (7, 5, 3), (33, 17, 4), (64, 1, 2)] {
+            let data = gradient(w, h, c);
+            let compressor = WaveletCompressor::new(w as u32, h as u32, c as u8);
+            let compressed = compressor.compress(&data).unwrap();
+            assert_eq!(compressor.decompress(&compressed).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn test_wavelet_beats_deflate_on_smooth_images() {
+        let data = gradient(128, 128, 3);
+        let compressed = WaveletCompressor::new(128, 128, 3).compress(&data).unwrap();
+        let deflated = super::super::deflate::DeflateCompressor::with_level_number(9)
+            .unwrap()
+            .compress(&data)
+            .unwrap();
+        assert!(compressed.len() < deflated.len());
+        assert!(compressed.len() < data.len() / 2);
+    }
+
+    #[test]
+    fn test_wavelet_preview_reads_only_coarse_segments() {
+        let data = gradient(40, 24, 3);
+        let compressed = WaveletCompressor::with_levels(40, 24, 3, 3).unwrap().compress(&data).unwrap();
+        let full = WaveletCompressor::decode_preview(&compressed, 0).unwrap();
+        let preview = WaveletCompressor::decode_preview(&compressed, 2).unwrap();
+        assert_eq!((preview.width, preview.height), (10, 6));
+
+        // The coarse segments come first, so a prefix is enough for the preview.
+        let prefix_len = compressed.len() / 2;
+        let from_prefix = WaveletCompressor::decode_preview(&compressed[..prefix_len], 2);
+        assert_eq!(from_prefix.unwrap(), preview);
+        assert!(preview.bytes_read <= prefix_len);
+        assert_eq!(full.bytes_read, compressed.len());
+        assert_eq!(WaveletCompressor::decode_preview(&compressed[..preview.bytes_read], 2).unwrap(), preview);
+        assert!(WaveletCompressor::decode_preview(&compressed[..prefix_len], 0).is_err());
+
+        // Low-pass samples track the full image at the corresponding positions.
+        for y in 0..6 {
+            for x in 0..10 {
+                let coarse = preview.data[(y * 10 + x) * 3];
+                let fine = full.data[(y * 4 * 40 + x * 4) * 3];
+                assert!(coarse.abs_diff(fine) <= 24, "{} vs {} at {},{}", coarse, fine, x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn test_wavelet_rejects_bad_input() {
+        let compressor = WaveletCompressor::new(4, 4, 1);
+        assert!(compressor.compress(&[0u8; 15]).is_err());
+        assert!(compressor.decompress(b"not a wavelet stream").is_err());
+        assert!(WaveletCompressor::with_levels(4, 4, 1, 17).is_err());
+
+        // A 4x4 image holds at most two levels.
+        let mut deep = compressor.compress(&[7u8; 16]).unwrap();
+        deep[13] = 3;
+        assert!(compressor.decompress(&deep).is_err());
+
+        // A huge declared size is rejected before allocating, and garbage
+        // segments decode to an image or an error, never a panic.
+        assert!(WaveletCompressor::new(u32::MAX, u32::MAX, 255).compress(&[0u8; 16]).is_err());
+        let mut huge = compressor.compress(&[7u8; 16]).unwrap();
+        huge[4..12].copy_from_slice(&[0xFF; 8]);
+        assert!(compressor.decompress(&huge).is_err());
+        let header = compressor.compress(&[7u8; 16]).unwrap()[..HEADER_LEN].to_vec();
+        for fill in [0x00, 0x5A, 0xA5, 0xFF] {
+            let mut garbage = header.clone();
+            for _ in 0..=header[13] {
+                garbage.extend_from_slice(&64u32.to_be_bytes());
+                garbage.extend(std::iter::repeat_n(fill, 64));
+            }
+            let _ = compressor.decompress(&garbage);
+        }
+
+        // Extreme coefficients wrap in the inverse lifting instead of overflowing.
+        let bands = [i32::MAX, i32::MIN, i32::MAX, i32::MIN, i32::MAX];
+        let mut x = [0; 5];
+        inverse_1d(&bands, &mut x);
+    }
+}
@@ -3,15 +3,18 @@
 use serde::Deserialize;
 use std::path::Path;
 use config::{Config as ConfigLoader, ConfigError, File};
-use crate::compression::CompressionError;
-use std::convert::TryInto;
+use crate::compression::auto::{AutoSelector, DEFAULT_TIME_BUDGET};
+use crate::compression::{CompressionAlgorithmType, CompressionError};
+use std::time::Duration;
 use log::{info, error};
 
 #[derive(Debug, Deserialize)]
 pub struct AppConfig {
+    /// An algorithm name accepted by `CompressionAlgorithmType::create`, or `"auto"`.
     pub compression_algorithm: String,
     pub compression_level: Option<u32>,
-    // Add other configuration fields as needed
+    /// Time budget in milliseconds for trial compression in `"auto"` mode.
+    pub time_budget_ms: Option<u64>,
 }
 
 impl AppConfig {
@@ -25,8 +28,6 @@ impl AppConfig {
     ///
     /// A `Result` containing the `AppConfig` or a `ConfigError`.
     pub fn load_from_file<P: AsRef<Path>>(path: P) -> Result<Self, ConfigError> {
-        let mut settings = ConfigLoader::default();
-
         // Convert the path to a string, handling potential errors
         let path_str = path.as_ref()
             .to_str()
@@ -34,14 +35,17 @@ impl AppConfig {
 
         info!("Loading configuration from {}", path_str);
 
-        // Merge the configuration file into the settings
-        settings.merge(File::with_path(path_str)).map_err(|e| {
+        // Build the settings from the configuration file
+        let settings = ConfigLoader::builder()
+            .add_source(File::from(path.as_ref()))
+            .build()
+            .map_err(|e| {
             error!("Failed to merge config file '{}': {}", path_str, e);
             e
         })?;
 
         // Attempt to deserialize the settings into `AppConfig`
-        settings.try_into::<AppConfig>().map_err(|e| {
+        settings.try_deserialize::<AppConfig>().map_err(|e| {
             error!("Failed to deserialize config into AppConfig: {}", e);
             e
         })
@@ -63,6 +67,17 @@ impl AppConfig {
         Ok(crate::compression::deflate::DeflateCompressor::with_level(compression))
     }
 
+    /// Returns whether the algorithm, level and preprocessing are chosen automatically.
+    pub fn is_auto(&self) -> bool {
+        self.compression_algorithm.eq_ignore_ascii_case("auto")
+    }
+
+    /// Creates the `AutoSelector` used in `"auto"` mode, honouring `time_budget_ms`.
+    pub fn create_auto_selector(&self) -> AutoSelector {
+        let budget = self.time_budget_ms.map_or(DEFAULT_TIME_BUDGET, Duration::from_millis);
+        AutoSelector::new().with_time_budget(budget)
+    }
+
     /// Validates the configuration fields.
     ///
     /// # Returns
@@ -75,7 +90,18 @@ impl AppConfig {
                 self.compression_level.unwrap_or(6)
             )));
         }
-        // Add more validation as needed
+        // The recompressors pack original files, not the decoded pixels this setting applies to
+        let name = self.compression_algorithm.to_lowercase();
+        if name == "jpeg-recompress" || name == "png-recompress" {
+            return Err(ConfigError::Message(format!(
+                "{} only packs original files; use the recompress-jpeg or recompress-png command",
+                name
+            )));
+        }
+        // Image codecs are created per image, so only their name can be checked here
+        if !self.is_auto() && !CompressionAlgorithmType::needs_geometry(&name) {
+            CompressionAlgorithmType::create(&name, None).map_err(|e| ConfigError::Message(e.to_string()))?;
+        }
         Ok(())
     }
 }
@@ -111,4 +137,43 @@ mod tests {
         // Clean up
         dir.close().unwrap();
     }
+
+    #[test]
+    fn test_auto_mode() {
+        let dir = tempdir().unwrap();
+        let file_path = dir.path().join("auto_config.toml");
+        let mut file = File::create(&file_path).unwrap();
+        writeln!(file, "compression_algorithm = 'auto'\ntime_budget_ms = 50").unwrap();
+
+        let config = AppConfig::load_from_file(&file_path).unwrap();
+        assert!(config.is_auto());
+        assert!(config.validate().is_ok());
+        assert_eq!(config.create_auto_selector().get_time_budget(), Duration::from_millis(50));
+
+        let unknown = AppConfig {
+            compression_algorithm: "zstd".to_string(),
+            compression_level: None,
+            time_budget_ms: None,
+        };
+        assert!(unknown.validate().is_err());
+    }
+
+    #[test]
+    fn test_algorithm_names() {
+        for name in ["wavelet", "spiht", "ccitt"] {
+            let config = AppConfig {
+                compression_algorithm: name.to_string(),
+                compression_level: None,
+                time_budget_ms: None,
+            };
+            assert!(config.validate().is_ok(), "{}", name);
+            assert!(CompressionAlgorithmType::create(name, None).is_err());
+        }
+        let recompress = AppConfig {
+            compression_algorithm: "PNG-Recompress".to_string(),
+            compression_level: None,
+            time_budget_ms: None,
+        };
+        assert!(recompress.validate().unwrap_err().to_string().contains("recompress-png command"));
+    }
 }
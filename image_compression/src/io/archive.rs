@@ -0,0 +1,901 @@
+// src/io/archive.rs
+
+//! Module implementing a multi-image archive with a central index.
+//!
+//! An archive holds many named images, each compressed on its own. The file
+//! starts with a fixed header pointing at the index, which lists every
+//! entry's name, geometry, algorithm, position and pixel checksum. Opening
+//! an [`Archive`] reads only the header and the index, and extracting an
+//! entry then reads only that entry's bytes.
+//!
+//! Layout, all integers big-endian:
+//!
+//! * Header (544 bytes): two 32-byte slots, at offsets 0 and 512, each
+//!   holding `IMA\x02`, a generation counter (`u64`), index offset
+//!   (`u64`), index length (`u32`), CRC-32 of the index (`u32`) and CRC-32
+//!   of the slot's first 28 bytes (`u32`). The valid slot with the highest
+//!   generation points at the current index.
+//! * The compressed entries, back to back.
+//! * The index: entry count (`u32`), then per entry the name length
+//!   (`u16`) and UTF-8 name, width and height (`u32`), channels and
+//!   [`CompressionAlgorithmType::id`] (`u8`), data offset and length
+//!   (`u64`), and CRC-32 of the uncompressed pixels (`u32`).
+//!
+//! An existing archive is updated in place with an [`ArchiveEditor`]. New
+//! and replacement entries are appended after the old index, followed by a
+//! new index, and only once both are synced to disk is the older header
+//! slot rewritten to point at the new index with the next generation. An
+//! interrupted update, even one that tears that slot, therefore leaves the
+//! other slot pointing at the previous, intact index. The data of replaced and
+//! removed entries stays behind as dead space until [`compact`] rewrites the
+//! archive.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use image_compression::compression::CompressionAlgorithmType;
+//! use image_compression::io::archive::{Archive, ArchiveWriter};
+//! use std::io::Cursor;
+//!
+//! let deflate = CompressionAlgorithmType::create("deflate", Some(9)).unwrap();
+//! let mut writer = ArchiveWriter::new(Cursor::new(Vec::new())).unwrap();
+//! writer.add("dot.png", 2, 2, 1, &deflate, &[0, 255, 255, 0]).unwrap();
+//! writer.add("bar.png", 3, 1, 3, &deflate, &[9; 9]).unwrap();
+//! let file = writer.finish().unwrap();
+//!
+//! let mut archive = Archive::open(file).unwrap();
+//! assert_eq!(archive.entries().len(), 2);
+//! assert_eq!(archive.extract("dot.png").unwrap(), [0, 255, 255, 0]);
+//! ```
+
+use crate::compression::{CompressionAlgorithmType, CompressionError, Compressor};
+use flate2::Crc;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Cursor, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+const MAGIC: &[u8; 4] = b"IMA\x02";
+const SLOT_LEN: usize = 32;
+/// Position of the second header slot, in the sector after the first.
+const SECOND_SLOT: u64 = 512;
+const HEADER_LEN: usize = SECOND_SLOT as usize + SLOT_LEN;
+
+/// One image in an archive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArchiveEntry {
+    pub name: String,
+    pub width: u32,
+    pub height: u32,
+    pub channels: u8,
+    /// [`CompressionAlgorithmType::id`] of the compressor.
+    pub algorithm: u8,
+    /// Position of the compressed data from the start of the archive.
+    pub offset: u64,
+    /// Length of the compressed data.
+    pub length: u64,
+    /// CRC-32 of the uncompressed pixels.
+    pub crc: u32,
+}
+
+impl ArchiveEntry {
+    /// Returns the uncompressed size in bytes.
+    pub fn raw_size(&self) -> u64 {
+        self.width as u64 * self.height as u64 * self.channels as u64
+    }
+
+    /// Returns the name of the entry's algorithm.
+    pub fn algorithm_name(&self) -> &'static str {
+        CompressionAlgorithmType::name_of(self.algorithm).unwrap_or("unknown")
+    }
+
+    fn write(&self, output: &mut Vec<u8>) {
+        output.extend_from_slice(&(self.name.len() as u16).to_be_bytes());
+        output.extend_from_slice(self.name.as_bytes());
+        output.extend_from_slice(&self.width.to_be_bytes());
+        output.extend_from_slice(&self.height.to_be_bytes());
+        output.push(self.channels);
+        output.push(self.algorithm);
+        output.extend_from_slice(&self.offset.to_be_bytes());
+        output.extend_from_slice(&self.length.to_be_bytes());
+        output.extend_from_slice(&self.crc.to_be_bytes());
+    }
+
+    /// Parses one entry from the front of `data`, advancing it.
+    fn parse(data: &mut &[u8]) -> Option<Self> {
+        fn take<'a>(data: &mut &'a [u8], len: usize) -> Option<&'a [u8]> {
+            let (head, tail) = (data.get(..len)?, data.get(len..)?);
+            *data = tail;
+            Some(head)
+        }
+        let name_len = u16::from_be_bytes(take(data, 2)?.try_into().ok()?) as usize;
+        let name = String::from_utf8(take(data, name_len)?.to_vec()).ok()?;
+        let fields = take(data, 30)?;
+        Some(ArchiveEntry {
+            name,
+            width: u32::from_be_bytes(fields[0..4].try_into().ok()?),
+            height: u32::from_be_bytes(fields[4..8].try_into().ok()?),
+            channels: fields[8],
+            algorithm: fields[9],
+            offset: u64::from_be_bytes(fields[10..18].try_into().ok()?),
+            length: u64::from_be_bytes(fields[18..26].try_into().ok()?),
+            crc: u32::from_be_bytes(fields[26..30].try_into().ok()?),
+        })
+    }
+}
+
+impl fmt::Display for ArchiveEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:<32} {:>6}x{:<6} {}ch {:<8} {:>10} -> {:>10} bytes ({:.2}:1)",
+            self.name,
+            self.width,
+            self.height,
+            self.channels,
+            self.algorithm_name(),
+            self.raw_size(),
+            self.length,
+            self.raw_size() as f64 / self.length.max(1) as f64
+        )
+    }
+}
+
+fn write_error(e: io::Error) -> CompressionError {
+    CompressionError::Compression(e.to_string())
+}
+
+fn read_error(e: io::Error) -> CompressionError {
+    CompressionError::Decompression(e.to_string())
+}
+
+fn checksum(data: &[u8]) -> u32 {
+    let mut crc = Crc::new();
+    crc.update(data);
+    crc.sum()
+}
+
+/// Serializes the index of `entries`.
+fn encode_index(entries: &[ArchiveEntry]) -> Vec<u8> {
+    let mut index = (entries.len() as u32).to_be_bytes().to_vec();
+    for entry in entries {
+        entry.write(&mut index);
+    }
+    index
+}
+
+/// One of the two header slots, pointing at a version of the index.
+#[derive(Debug, Clone, Copy)]
+struct Slot {
+    generation: u64,
+    offset: u64,
+    length: u32,
+    crc: u32,
+}
+
+impl Slot {
+    /// Returns the slot of `generation` pointing at `index`, written at `offset`.
+    fn new(generation: u64, offset: u64, index: &[u8]) -> Self {
+        Slot {
+            generation,
+            offset,
+            length: index.len() as u32,
+            crc: checksum(index),
+        }
+    }
+
+    /// Returns where the slot of `generation` is stored; consecutive generations alternate.
+    fn position(generation: u64) -> u64 {
+        if generation % 2 == 1 {
+            0
+        } else {
+            SECOND_SLOT
+        }
+    }
+
+    fn encode(&self) -> [u8; SLOT_LEN] {
+        let mut slot = [0u8; SLOT_LEN];
+        slot[..4].copy_from_slice(MAGIC);
+        slot[4..12].copy_from_slice(&self.generation.to_be_bytes());
+        slot[12..20].copy_from_slice(&self.offset.to_be_bytes());
+        slot[20..24].copy_from_slice(&self.length.to_be_bytes());
+        slot[24..28].copy_from_slice(&self.crc.to_be_bytes());
+        let crc = checksum(&slot[..28]);
+        slot[28..].copy_from_slice(&crc.to_be_bytes());
+        slot
+    }
+
+    /// Parses a slot, returning `None` if it was never written or is damaged.
+    fn decode(slot: &[u8; SLOT_LEN]) -> Option<Self> {
+        if &slot[..4] != MAGIC || checksum(&slot[..28]).to_be_bytes() != slot[28..] {
+            return None;
+        }
+        Some(Slot {
+            generation: u64::from_be_bytes(slot[4..12].try_into().ok()?),
+            offset: u64::from_be_bytes(slot[12..20].try_into().ok()?),
+            length: u32::from_be_bytes(slot[20..24].try_into().ok()?),
+            crc: u32::from_be_bytes(slot[24..28].try_into().ok()?),
+        })
+    }
+
+    /// Writes the slot to its position in `output`.
+    fn write<W: Write + Seek>(&self, output: &mut W) -> io::Result<()> {
+        output.seek(SeekFrom::Start(Slot::position(self.generation)))?;
+        output.write_all(&self.encode())
+    }
+}
+
+/// Storage that can be forced to durably hold what was written to it.
+pub trait Durable: Read + Write + Seek {
+    /// Blocks until written data has reached stable storage.
+    fn sync(&mut self) -> io::Result<()>;
+}
+
+impl Durable for File {
+    fn sync(&mut self) -> io::Result<()> {
+        self.sync_data()
+    }
+}
+
+/// In-memory archives have nothing to sync.
+impl Durable for Cursor<Vec<u8>> {
+    fn sync(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Reads the header and index of an archive, following the newest valid header slot.
+///
+/// # Returns
+///
+/// A `Result` containing the entries and that slot.
+fn read_index<R: Read + Seek>(input: &mut R) -> Result<(Vec<ArchiveEntry>, Slot), CompressionError> {
+    let invalid = |reason: &str| CompressionError::Decompression(format!("Invalid archive: {}", reason));
+    let file_len = input.seek(SeekFrom::End(0)).map_err(read_error)?;
+    if file_len < HEADER_LEN as u64 {
+        return Err(invalid("truncated header"));
+    }
+    let mut slots = [[0u8; SLOT_LEN]; 2];
+    for (slot, position) in slots.iter_mut().zip([0, SECOND_SLOT]) {
+        input.seek(SeekFrom::Start(position)).map_err(read_error)?;
+        input.read_exact(slot).map_err(read_error)?;
+    }
+    let slot = slots
+        .iter()
+        .filter_map(Slot::decode)
+        .max_by_key(|slot| slot.generation)
+        .ok_or_else(|| invalid("no valid header slot"))?;
+    if slot.offset.checked_add(slot.length as u64).is_none_or(|end| end > file_len) || slot.length < 4 {
+        return Err(invalid("index out of bounds"));
+    }
+
+    let mut index = vec![0u8; slot.length as usize];
+    input.seek(SeekFrom::Start(slot.offset)).map_err(read_error)?;
+    input.read_exact(&mut index).map_err(read_error)?;
+    if checksum(&index) != slot.crc {
+        return Err(invalid("index checksum mismatch"));
+    }
+    let count = u32::from_be_bytes(index[..4].try_into().unwrap());
+    let mut rest = &index[4..];
+    let entries = (0..count)
+        .map(|_| {
+            let entry = ArchiveEntry::parse(&mut rest).ok_or_else(|| invalid("truncated index"))?;
+            let end = entry.offset.checked_add(entry.length);
+            if entry.offset < HEADER_LEN as u64 || end.is_none_or(|end| end > file_len) {
+                return Err(invalid(&format!("entry {} lies outside the file", entry.name)));
+            }
+            Ok(entry)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok((entries, slot))
+}
+
+/// Writes a new archive, entry by entry.
+pub struct ArchiveWriter<W: Write + Seek> {
+    output: W,
+    entries: Vec<ArchiveEntry>,
+    position: u64,
+}
+
+impl<W: Write + Seek> ArchiveWriter<W> {
+    /// Creates a new `ArchiveWriter`, reserving room for the header at the start of `output`.
+    pub fn new(mut output: W) -> Result<Self, CompressionError> {
+        output.write_all(&[0u8; HEADER_LEN]).map_err(write_error)?;
+        Ok(ArchiveWriter {
+            output,
+            entries: Vec::new(),
+            position: HEADER_LEN as u64,
+        })
+    }
+
+    /// Compresses an image and appends it to the archive.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Unique entry name, at most 65535 bytes of UTF-8.
+    /// * `width`, `height`, `channels` - Image geometry.
+    /// * `algorithm` - Deflate, LZW, wavelet or SPIHT; the last two must be configured for this geometry.
+    /// * `pixels` - Interleaved 8-bit pixels.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the new entry or a `CompressionError` if the name is taken,
+    /// the algorithm cannot store pixels, or writing fails.
+    pub fn add(
+        &mut self,
+        name: &str,
+        width: u32,
+        height: u32,
+        channels: u8,
+        algorithm: &CompressionAlgorithmType,
+        pixels: &[u8],
+    ) -> Result<&ArchiveEntry, CompressionError> {
+        if self.entries.iter().any(|entry| entry.name == name) {
+            return Err(CompressionError::Compression(format!("Duplicate archive entry {}", name)));
+        }
+        let entry = write_entry(&mut self.output, self.position, name, width, height, channels, algorithm, pixels)?;
+        self.position += entry.length;
+        self.entries.push(entry);
+        Ok(self.entries.last().unwrap())
+    }
+
+    /// Retrieves the entries added so far.
+    pub fn entries(&self) -> &[ArchiveEntry] {
+        &self.entries
+    }
+
+    /// Appends an already compressed entry, as read from another archive.
+    fn add_compressed(&mut self, entry: &ArchiveEntry, data: &[u8]) -> Result<(), CompressionError> {
+        self.output.write_all(data).map_err(write_error)?;
+        self.entries.push(ArchiveEntry {
+            offset: self.position,
+            ..entry.clone()
+        });
+        self.position += entry.length;
+        Ok(())
+    }
+
+    /// Writes the index and the header, and returns the output.
+    pub fn finish(mut self) -> Result<W, CompressionError> {
+        let index = encode_index(&self.entries);
+        self.output.write_all(&index).map_err(write_error)?;
+        Slot::new(1, self.position, &index).write(&mut self.output).map_err(write_error)?;
+        self.output.flush().map_err(write_error)?;
+        Ok(self.output)
+    }
+}
+
+/// Returns whether algorithm `id` can store an entry's 8-bit samples: every codec in
+/// [`CompressionAlgorithmType::PIXEL_IDS`] but CCITT, which codes packed 1-bit rows.
+fn stores_samples(id: u8) -> bool {
+    CompressionAlgorithmType::PIXEL_IDS.contains(&id) && Some(id) != CompressionAlgorithmType::id_of("ccitt")
+}
+
+/// Compresses one image and writes its data at `position`, which `output` must be at.
+#[allow(clippy::too_many_arguments)]
+fn write_entry<W: Write>(
+    output: &mut W,
+    position: u64,
+    name: &str,
+    width: u32,
+    height: u32,
+    channels: u8,
+    algorithm: &CompressionAlgorithmType,
+    pixels: &[u8],
+) -> Result<ArchiveEntry, CompressionError> {
+    if name.len() > u16::MAX as usize {
+        return Err(CompressionError::Compression(format!("Archive entry name too long: {}", name)));
+    }
+    if !stores_samples(algorithm.id()) {
+        return Err(CompressionError::UnknownAlgorithm(format!("{} cannot store raw pixels", algorithm.name())));
+    }
+    if pixels.len() as u64 != width as u64 * height as u64 * channels as u64 || channels == 0 {
+        return Err(CompressionError::Compression(format!("{} is not {}x{}x{} pixels", name, width, height, channels)));
+    }
+    let data = algorithm.compress(pixels)?;
+    output.write_all(&data).map_err(write_error)?;
+    Ok(ArchiveEntry {
+        name: name.to_string(),
+        width,
+        height,
+        channels,
+        algorithm: algorithm.id(),
+        offset: position,
+        length: data.len() as u64,
+        crc: checksum(pixels),
+    })
+}
+
+/// An archive opened for reading.
+pub struct Archive<R: Read + Seek> {
+    input: R,
+    entries: Vec<ArchiveEntry>,
+}
+
+impl<R: Read + Seek> Archive<R> {
+    /// Opens an archive, reading only its header and index.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the archive or a `CompressionError` if the header or index is invalid.
+    pub fn open(mut input: R) -> Result<Self, CompressionError> {
+        let (entries, _) = read_index(&mut input)?;
+        Ok(Archive { input, entries })
+    }
+
+    /// Retrieves every entry, in the order they were added.
+    pub fn entries(&self) -> &[ArchiveEntry] {
+        &self.entries
+    }
+
+    /// Returns the entry called `name`, if any.
+    pub fn entry(&self, name: &str) -> Option<&ArchiveEntry> {
+        self.entries.iter().find(|entry| entry.name == name)
+    }
+
+    /// Reads the compressed data of `entry`.
+    pub fn read_compressed(&mut self, entry: &ArchiveEntry) -> Result<Vec<u8>, CompressionError> {
+        let mut data = vec![0u8; entry.length as usize];
+        self.input.seek(SeekFrom::Start(entry.offset)).map_err(read_error)?;
+        self.input.read_exact(&mut data).map_err(read_error)?;
+        Ok(data)
+    }
+
+    /// Reads and decompresses the entry called `name`, checking its CRC.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the entry's interleaved pixels, or a `CompressionError` if there is
+    /// no such entry or its data is corrupt.
+    pub fn extract(&mut self, name: &str) -> Result<Vec<u8>, CompressionError> {
+        let entry = self
+            .entry(name)
+            .cloned()
+            .ok_or_else(|| CompressionError::Decompression(format!("No archive entry named {}", name)))?;
+        let data = self.read_compressed(&entry)?;
+        let algorithm = CompressionAlgorithmType::for_block(entry.algorithm, entry.width, entry.height, entry.channels)?;
+        let pixels = algorithm.decompress(&data)?;
+        if pixels.len() as u64 != entry.raw_size() || checksum(&pixels) != entry.crc {
+            return Err(CompressionError::Decompression(format!("Archive entry {} is corrupt", name)));
+        }
+        Ok(pixels)
+    }
+
+    /// Returns the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.input
+    }
+}
+
+/// Updates an existing archive in place.
+///
+/// Changes are invisible to readers until [`ArchiveEditor::commit`], and
+/// dropping the editor without committing leaves the archive as it was,
+/// apart from unreferenced bytes at its end.
+pub struct ArchiveEditor<F: Durable> {
+    file: F,
+    entries: Vec<ArchiveEntry>,
+    /// Where the next entry's data goes: the end of the file.
+    end: u64,
+    /// The header slot of the current index.
+    slot: Slot,
+}
+
+impl<F: Durable> ArchiveEditor<F> {
+    /// Opens an archive for updating, reading its header and index.
+    pub fn open(mut file: F) -> Result<Self, CompressionError> {
+        let (entries, slot) = read_index(&mut file)?;
+        let end = file.seek(SeekFrom::End(0)).map_err(read_error)?;
+        Ok(ArchiveEditor { file, entries, end, slot })
+    }
+
+    /// Retrieves the entries as they will be after committing.
+    pub fn entries(&self) -> &[ArchiveEntry] {
+        &self.entries
+    }
+
+    /// Returns the bytes of the file not used by the header, live entries or current index.
+    pub fn dead_space(&self) -> u64 {
+        let live: u64 = self.entries.iter().map(|entry| entry.length).sum();
+        self.end
+            .saturating_sub(live)
+            .saturating_sub(HEADER_LEN as u64)
+            .saturating_sub(self.slot.length as u64)
+    }
+
+    /// Compresses an image and appends it as a new entry.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the new entry, or a `CompressionError` if the name is taken
+    /// or the image cannot be stored; see [`ArchiveWriter::add`].
+    pub fn add(
+        &mut self,
+        name: &str,
+        width: u32,
+        height: u32,
+        channels: u8,
+        algorithm: &CompressionAlgorithmType,
+        pixels: &[u8],
+    ) -> Result<&ArchiveEntry, CompressionError> {
+        if self.entries.iter().any(|entry| entry.name == name) {
+            return Err(CompressionError::Compression(format!("Duplicate archive entry {}", name)));
+        }
+        let entry = self.append(name, width, height, channels, algorithm, pixels)?;
+        self.entries.push(entry);
+        Ok(self.entries.last().unwrap())
+    }
+
+    /// Compresses an image and stores it under `name`, replacing any entry of that name in place in the index.
+    ///
+    /// The replaced entry's data becomes dead space.
+    pub fn replace(
+        &mut self,
+        name: &str,
+        width: u32,
+        height: u32,
+        channels: u8,
+        algorithm: &CompressionAlgorithmType,
+        pixels: &[u8],
+    ) -> Result<&ArchiveEntry, CompressionError> {
+        let entry = self.append(name, width, height, channels, algorithm, pixels)?;
+        let position = match self.entries.iter().position(|old| old.name == name) {
+            Some(position) => {
+                self.entries[position] = entry;
+                position
+            }
+            None => {
+                self.entries.push(entry);
+                self.entries.len() - 1
+            }
+        };
+        Ok(&self.entries[position])
+    }
+
+    /// Removes the entry called `name`; its data becomes dead space.
+    pub fn remove(&mut self, name: &str) -> Result<ArchiveEntry, CompressionError> {
+        let position = self
+            .entries
+            .iter()
+            .position(|entry| entry.name == name)
+            .ok_or_else(|| CompressionError::Compression(format!("No archive entry named {}", name)))?;
+        Ok(self.entries.remove(position))
+    }
+
+    /// Writes the new index and then switches the header to it.
+    ///
+    /// The entries and index are synced before the header slot not in use is
+    /// rewritten with the next generation, and that slot is synced before
+    /// returning. The slot in use is never touched, and a torn write fails the
+    /// new slot's checksum, so a reader sees either the old index or the new one.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the file or a `CompressionError` if writing fails.
+    pub fn commit(mut self) -> Result<F, CompressionError> {
+        let index = encode_index(&self.entries);
+        self.file.seek(SeekFrom::Start(self.end)).map_err(write_error)?;
+        self.file.write_all(&index).map_err(write_error)?;
+        self.file.flush().and_then(|_| self.file.sync()).map_err(write_error)?;
+
+        let slot = Slot::new(self.slot.generation + 1, self.end, &index);
+        slot.write(&mut self.file).map_err(write_error)?;
+        self.file.flush().and_then(|_| self.file.sync()).map_err(write_error)?;
+        Ok(self.file)
+    }
+
+    /// Compresses an image and writes its data at the end of the file.
+    fn append(
+        &mut self,
+        name: &str,
+        width: u32,
+        height: u32,
+        channels: u8,
+        algorithm: &CompressionAlgorithmType,
+        pixels: &[u8],
+    ) -> Result<ArchiveEntry, CompressionError> {
+        self.file.seek(SeekFrom::Start(self.end)).map_err(write_error)?;
+        let entry = write_entry(&mut self.file, self.end, name, width, height, channels, algorithm, pixels)?;
+        self.end += entry.length;
+        Ok(entry)
+    }
+}
+
+/// Writes a new archive at `path`, adding its entries through `fill`.
+///
+/// The archive is written to a temporary file in the same directory,
+/// synced, and renamed over any existing file, after which the directory is
+/// synced too. An interruption, or an error from `fill`, therefore leaves
+/// the previous file at `path` untouched.
+///
+/// # Returns
+///
+/// A `Result` containing what `fill` returned, or a `CompressionError`.
+pub fn create<T>(
+    path: &Path,
+    fill: impl FnOnce(&mut ArchiveWriter<BufWriter<&File>>) -> Result<T, CompressionError>,
+) -> Result<T, CompressionError> {
+    let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let temp = tempfile::NamedTempFile::new_in(dir).map_err(write_error)?;
+
+    let mut writer = ArchiveWriter::new(BufWriter::new(temp.as_file()))?;
+    let filled = fill(&mut writer)?;
+    let output = writer.finish()?.into_inner().map_err(|e| write_error(e.into_error()))?;
+    output.sync_all().map_err(write_error)?;
+    temp.persist(path).map_err(|e| write_error(e.error))?;
+    // The rename itself is only durable once the directory is synced.
+    #[cfg(unix)]
+    File::open(dir).and_then(|dir| dir.sync_all()).map_err(write_error)?;
+    Ok(filled)
+}
+
+/// Rewrites the archive at `path` without dead space.
+///
+/// The compacted archive replaces the original through [`create`], so an
+/// interruption leaves the original archive untouched.
+///
+/// # Returns
+///
+/// A `Result` containing the archive size before and after compacting, or a `CompressionError`.
+pub fn compact(path: &Path) -> Result<(u64, u64), CompressionError> {
+    let mut archive = Archive::open(BufReader::new(File::open(path).map_err(read_error)?))?;
+    let before = archive.input.seek(SeekFrom::End(0)).map_err(read_error)?;
+    create(path, |writer| {
+        for entry in archive.entries.clone() {
+            let data = archive.read_compressed(&entry)?;
+            writer.add_compressed(&entry, &data)?;
+        }
+        Ok(())
+    })?;
+    let after = std::fs::metadata(path).map_err(read_error)?.len();
+    Ok((before, after))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn sprite(seed: u8) -> Vec<u8> {
+        (0..16 * 12 * 4).map(|i| (i as u8).wrapping_mul(seed)).collect()
+    }
+
+    fn archive() -> Vec<u8> {
+        let lzw = CompressionAlgorithmType::create("lzw", None).unwrap();
+        let mut writer = ArchiveWriter::new(Cursor::new(Vec::new())).unwrap();
+        for i in 1..=5u8 {
+            writer.add(&format!("sprites/{}.png", i), 16, 12, 4, &lzw, &sprite(i)).unwrap();
+        }
+        let spiht = CompressionAlgorithmType::for_block(3, 16, 16, 1).unwrap();
+        writer.add("gray.png", 16, 16, 1, &spiht, &[128; 256]).unwrap();
+        writer.finish().unwrap().into_inner()
+    }
+
+    /// A reader that records which byte ranges were read.
+    struct Tracking<'a> {
+        inner: Cursor<&'a [u8]>,
+        reads: Vec<(u64, usize)>,
+    }
+
+    impl Read for Tracking<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let position = self.inner.position();
+            let read = self.inner.read(buf)?;
+            self.reads.push((position, read));
+            Ok(read)
+        }
+    }
+
+    impl Seek for Tracking<'_> {
+        fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+            self.inner.seek(pos)
+        }
+    }
+
+    #[test]
+    fn test_random_access() {
+        let data = archive();
+        let mut archive = Archive::open(Tracking { inner: Cursor::new(&data), reads: Vec::new() }).unwrap();
+        let names: Vec<_> = archive.entries().iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, ["sprites/1.png", "sprites/2.png", "sprites/3.png", "sprites/4.png", "sprites/5.png", "gray.png"]);
+        assert_eq!(archive.entry("gray.png").unwrap().algorithm_name(), "spiht");
+
+        let entry = archive.entry("sprites/4.png").unwrap().clone();
+        archive.input.reads.clear();
+        assert_eq!(archive.extract("sprites/4.png").unwrap(), sprite(4));
+        // Only the entry's own bytes were read.
+        let read: usize = archive.input.reads.iter().map(|&(_, len)| len).sum();
+        assert_eq!(read as u64, entry.length);
+        assert!(archive.input.reads.iter().all(|&(at, _)| at >= entry.offset && at < entry.offset + entry.length));
+        assert_eq!(archive.extract("gray.png").unwrap(), vec![128; 256]);
+        assert!(archive.extract("missing.png").is_err());
+    }
+
+    #[test]
+    fn test_corruption_is_detected() {
+        let data = archive();
+        let mut index_damaged = data.clone();
+        let last = index_damaged.len() - 1;
+        index_damaged[last] ^= 1;
+        assert!(Archive::open(Cursor::new(index_damaged)).is_err());
+
+        let mut entry_damaged = data.clone();
+        let entry = Archive::open(Cursor::new(&data)).unwrap().entries()[0].clone();
+        entry_damaged[entry.offset as usize + 3] ^= 0x40;
+        let mut archive = Archive::open(Cursor::new(entry_damaged)).unwrap();
+        assert!(archive.extract("sprites/1.png").is_err());
+        assert_eq!(archive.extract("sprites/2.png").unwrap(), sprite(2));
+    }
+
+    #[test]
+    fn test_entries_outside_the_file_are_rejected() {
+        let data = archive();
+        let entries = Archive::open(Cursor::new(&data)).unwrap().entries().to_vec();
+        let hostile = |entry: ArchiveEntry| {
+            let index = encode_index(&[entries[0].clone(), entry]);
+            let mut archive = data.clone();
+            Slot::new(2, data.len() as u64, &index).write(&mut Cursor::new(&mut archive)).unwrap();
+            archive.extend_from_slice(&index);
+            archive
+        };
+        for (offset, length) in [(entries[1].offset, u64::MAX / 2), (u64::MAX - 4, 8), (4, entries[1].length)] {
+            let archive = hostile(ArchiveEntry { offset, length, ..entries[1].clone() });
+            assert!(Archive::open(Cursor::new(archive)).is_err());
+        }
+        // The same index with an entry in bounds extracts fine.
+        let mut archive = Archive::open(Cursor::new(hostile(entries[1].clone()))).unwrap();
+        assert_eq!(archive.extract("sprites/2.png").unwrap(), sprite(2));
+    }
+
+    #[test]
+    fn test_update_in_place() {
+        let original = archive();
+        let old_entries = Archive::open(Cursor::new(&original)).unwrap().entries().to_vec();
+        let deflate = CompressionAlgorithmType::create("deflate", None).unwrap();
+        let mut editor = ArchiveEditor::open(Cursor::new(original)).unwrap();
+        assert_eq!(editor.dead_space(), 0);
+        editor.add("new.png", 16, 12, 4, &deflate, &sprite(9)).unwrap();
+        assert!(editor.add("new.png", 16, 12, 4, &deflate, &sprite(9)).is_err());
+        editor.replace("sprites/2.png", 16, 12, 4, &deflate, &sprite(7)).unwrap();
+        editor.remove("gray.png").unwrap();
+        assert!(editor.remove("gray.png").is_err());
+        let data = editor.commit().unwrap().into_inner();
+
+        let mut archive = Archive::open(Cursor::new(&data)).unwrap();
+        let names: Vec<_> = archive.entries().iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, ["sprites/1.png", "sprites/2.png", "sprites/3.png", "sprites/4.png", "sprites/5.png", "new.png"]);
+        assert_eq!(archive.extract("sprites/2.png").unwrap(), sprite(7));
+        assert_eq!(archive.extract("new.png").unwrap(), sprite(9));
+        assert_eq!(archive.extract("sprites/5.png").unwrap(), sprite(5));
+
+        // The old index and the data of the replaced and removed entries are dead.
+        let editor = ArchiveEditor::open(Cursor::new(data)).unwrap();
+        let old_index = encode_index(&old_entries).len() as u64;
+        assert_eq!(editor.dead_space(), old_index + old_entries[1].length + old_entries[5].length);
+    }
+
+    #[test]
+    fn test_interrupted_update_keeps_previous_index() {
+        let original = archive();
+        let lzw = CompressionAlgorithmType::create("lzw", None).unwrap();
+        let mut editor = ArchiveEditor::open(Cursor::new(original.clone())).unwrap();
+        editor.replace("sprites/1.png", 16, 12, 4, &lzw, &sprite(3)).unwrap();
+        editor.add("extra.png", 1, 1, 1, &lzw, &[5]).unwrap();
+        let committed = editor.commit().unwrap().into_inner();
+
+        // Every state before the header is rewritten reads as the original archive.
+        for len in original.len()..committed.len() {
+            let mut partial = original.clone();
+            partial.extend_from_slice(&committed[len.min(original.len())..len]);
+            let mut archive = Archive::open(Cursor::new(partial)).unwrap();
+            assert_eq!(archive.entries().len(), 6);
+            assert_eq!(archive.extract("sprites/1.png").unwrap(), sprite(1));
+        }
+        let mut archive = Archive::open(Cursor::new(committed)).unwrap();
+        assert_eq!(archive.extract("sprites/1.png").unwrap(), sprite(3));
+    }
+
+    #[test]
+    fn test_torn_header_keeps_previous_index() {
+        let lzw = CompressionAlgorithmType::create("lzw", None).unwrap();
+        let mut editor = ArchiveEditor::open(Cursor::new(archive())).unwrap();
+        editor.replace("sprites/1.png", 16, 12, 4, &lzw, &sprite(3)).unwrap();
+        let second = editor.commit().unwrap().into_inner();
+        let mut editor = ArchiveEditor::open(Cursor::new(second.clone())).unwrap();
+        editor.remove("sprites/2.png").unwrap();
+        let third = editor.commit().unwrap().into_inner();
+        assert_eq!(Archive::open(Cursor::new(&third)).unwrap().entries().len(), 5);
+
+        // The third generation overwrote the first slot; tear that write at every byte it changed.
+        let changed = (0..SLOT_LEN).rev().find(|&i| second[i] != third[i]).unwrap();
+        for len in 0..=changed {
+            let mut torn = third.clone();
+            torn[len..SLOT_LEN].copy_from_slice(&second[len..SLOT_LEN]);
+            let mut archive = Archive::open(Cursor::new(torn)).unwrap();
+            assert_eq!(archive.entries().len(), 6);
+            assert_eq!(archive.extract("sprites/1.png").unwrap(), sprite(3));
+        }
+        let mut both_torn = third.clone();
+        both_torn[4] ^= 1;
+        both_torn[SECOND_SLOT as usize + 4] ^= 1;
+        assert!(Archive::open(Cursor::new(both_torn)).is_err());
+
+        // Entries sharing more data than the file holds leave no dead space rather than underflowing.
+        let whole = ArchiveEntry {
+            offset: HEADER_LEN as u64,
+            length: (third.len() - HEADER_LEN) as u64,
+            ..Archive::open(Cursor::new(&third)).unwrap().entries()[0].clone()
+        };
+        let index = encode_index(&[whole.clone(), ArchiveEntry { name: "again".to_string(), ..whole }]);
+        let mut hostile = third.clone();
+        Slot::new(4, third.len() as u64, &index).write(&mut Cursor::new(&mut hostile)).unwrap();
+        hostile.extend_from_slice(&index);
+        assert_eq!(ArchiveEditor::open(Cursor::new(hostile)).unwrap().dead_space(), 0);
+    }
+
+    #[test]
+    fn test_compact() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("sprites.ima");
+        std::fs::write(&path, archive()).unwrap();
+        let deflate = CompressionAlgorithmType::create("deflate", None).unwrap();
+        let mut editor = ArchiveEditor::open(File::options().read(true).write(true).open(&path).unwrap()).unwrap();
+        editor.replace("sprites/3.png", 16, 12, 4, &deflate, &sprite(11)).unwrap();
+        editor.remove("sprites/4.png").unwrap();
+        let dead = editor.dead_space();
+        editor.commit().unwrap();
+
+        let (before, after) = compact(&path).unwrap();
+        assert!(dead > 0 && before - after >= dead);
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), after);
+        let mut editor = ArchiveEditor::open(File::options().read(true).write(true).open(&path).unwrap()).unwrap();
+        assert_eq!(editor.dead_space(), 0);
+        assert_eq!(editor.entries().len(), 5);
+        editor.remove("gray.png").unwrap();
+        let mut archive = Archive::open(BufReader::new(File::open(&path).unwrap())).unwrap();
+        assert_eq!(archive.extract("sprites/3.png").unwrap(), sprite(11));
+        assert_eq!(archive.extract("gray.png").unwrap(), vec![128; 256]);
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_create_replaces_only_complete_archives() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("sprites.ima");
+        std::fs::write(&path, archive()).unwrap();
+        let deflate = CompressionAlgorithmType::create("deflate", None).unwrap();
+        let failed = create(&path, |writer| {
+            writer.add("one.png", 1, 1, 1, &deflate, &[1])?;
+            writer.add("two.png", 2, 2, 1, &deflate, &[2])?;
+            Ok(())
+        });
+        assert!(failed.is_err());
+        assert_eq!(std::fs::read(&path).unwrap(), archive());
+
+        let count = create(&path, |writer| {
+            writer.add("one.png", 1, 1, 1, &deflate, &[1])?;
+            Ok(writer.entries().len())
+        })
+        .unwrap();
+        assert_eq!(count, 1);
+        let mut archive = Archive::open(File::open(&path).unwrap()).unwrap();
+        assert_eq!(archive.extract("one.png").unwrap(), [1]);
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_invalid_entries() {
+        let deflate = CompressionAlgorithmType::create("deflate", None).unwrap();
+        let mut writer = ArchiveWriter::new(Cursor::new(Vec::new())).unwrap();
+        writer.add("a", 1, 1, 1, &deflate, &[1]).unwrap();
+        assert!(writer.add("a", 1, 1, 1, &deflate, &[2]).is_err());
+        assert!(writer.add("b", 2, 1, 1, &deflate, &[2]).is_err());
+        let png = CompressionAlgorithmType::create("png-recompress", None).unwrap();
+        assert!(writer.add("c", 1, 1, 1, &png, &[3]).is_err());
+        let ccitt = CompressionAlgorithmType::for_block(6, 8, 1, 1).unwrap();
+        assert!(writer.add("d", 8, 1, 1, &ccitt, &[0; 8]).is_err());
+        assert_eq!(writer.entries().len(), 1);
+        let empty = ArchiveWriter::new(Cursor::new(Vec::new())).unwrap().finish().unwrap();
+        assert!(Archive::open(empty).unwrap().entries().is_empty());
+    }
+}
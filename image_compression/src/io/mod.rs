@@ -0,0 +1,7 @@
+// src/io/mod.rs
+
+pub mod archive;
+pub mod reader;
+pub mod rows;
+pub mod tiff;
+pub mod writer;
@@ -0,0 +1,755 @@
+// src/io/rows.rs
+
+//! Module implementing row-by-row image encoding and decoding.
+//!
+//! [`RowWriter`]s accept scanlines one at a time and [`RowReader`]s yield
+//! them one at a time, so images far larger than memory can be converted
+//! with [`copy_rows`] while only a few rows are held at once. Two formats
+//! are supported, both with 8-bit samples and 1 to 4 channels:
+//!
+//! * The row container: a 15-byte header (`ROW\x01`, big-endian width and
+//!   height, channels, algorithm id and level) followed by PNG-filtered
+//!   rows compressed with streaming Deflate or LZW.
+//! * Non-interlaced 8-bit PNG. Palette images are expanded to RGB or RGBA
+//!   when read.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use image_compression::io::rows::{copy_rows, ContainerRowReader, ContainerRowWriter, PngRowWriter, RowWriter};
+//!
+//! let mut container = Vec::new();
+//! let mut writer = ContainerRowWriter::new(&mut container, 4, 2, 1, 0, None).unwrap();
+//! writer.write_row(&[0, 64, 128, 255]).unwrap();
+//! writer.write_row(&[255, 128, 64, 0]).unwrap();
+//! writer.finish().unwrap();
+//! drop(writer);
+//!
+//! let mut reader = ContainerRowReader::new(&container[..]).unwrap();
+//! let mut png = Vec::new();
+//! let mut writer = PngRowWriter::new(&mut png, 4, 2, 1, 6).unwrap();
+//! assert_eq!(copy_rows(&mut reader, &mut writer).unwrap(), 2);
+//! drop(writer);
+//! assert_eq!(image::load_from_memory(&png).unwrap().to_luma8().into_raw(), [0, 64, 128, 255, 255, 128, 64, 0]);
+//! ```
+
+use crate::compression::deflate::DeflateCompressor;
+use crate::compression::lzw::LzwCompressor;
+use crate::compression::png::{choose_filter, filter_row, unfilter_row, write_chunk, FilterStrategy, PNG_SIGNATURE};
+use crate::compression::stream::{StreamEncoder, StreamingCompressor};
+use crate::compression::{CompressionAlgorithmType, CompressionError};
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::{Compression, Crc};
+use std::io::{self, BufReader, Read, Write};
+
+const MAGIC: &[u8; 4] = b"ROW\x01";
+const HEADER_LEN: usize = 15;
+const NO_LEVEL: u8 = 0xFF;
+/// Largest IDAT chunk [`PngRowWriter`] emits.
+const IDAT_SIZE: usize = 1 << 16;
+/// Longest row, in bytes, that readers and writers buffer; a few rows are held at once.
+const MAX_ROW_LEN: usize = 1 << 26;
+/// Longest IHDR, PLTE or tRNS chunk [`PngRowReader`] accepts; a full palette takes 768 bytes.
+const MAX_HEADER_CHUNK_LEN: usize = 1024;
+
+/// A source of image rows.
+pub trait RowReader {
+    /// Returns `(width, height, channels)` of the image; samples are 8-bit.
+    fn geometry(&self) -> (u32, u32, u8);
+
+    /// Reads the next row into `row`, which must hold `width * channels` bytes.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing `false` once every row has been read, or a `CompressionError`.
+    fn read_row(&mut self, row: &mut [u8]) -> Result<bool, CompressionError>;
+}
+
+/// A sink of image rows.
+pub trait RowWriter {
+    /// Writes the next row of `width * channels` bytes.
+    fn write_row(&mut self, row: &[u8]) -> Result<(), CompressionError>;
+
+    /// Completes the image; fails unless exactly `height` rows were written.
+    fn finish(&mut self) -> Result<(), CompressionError>;
+}
+
+/// Copies every row from `reader` to `writer` and finishes the writer.
+///
+/// # Returns
+///
+/// A `Result` containing the number of rows copied or a `CompressionError`.
+pub fn copy_rows(reader: &mut dyn RowReader, writer: &mut dyn RowWriter) -> Result<u32, CompressionError> {
+    let (width, _, channels) = reader.geometry();
+    let mut row = vec![0u8; width as usize * channels as usize];
+    let mut rows = 0;
+    while reader.read_row(&mut row)? {
+        writer.write_row(&row)?;
+        rows += 1;
+    }
+    writer.finish()?;
+    Ok(rows)
+}
+
+fn check_geometry(width: u32, height: u32, channels: u8) -> Result<(), CompressionError> {
+    if width == 0
+        || height == 0
+        || width > i32::MAX as u32
+        || height > i32::MAX as u32
+        || !(1..=4).contains(&channels)
+        || width as usize * channels as usize > MAX_ROW_LEN
+    {
+        return Err(CompressionError::Compression(format!(
+            "Unsupported image geometry {}x{} with {} channels",
+            width, height, channels
+        )));
+    }
+    Ok(())
+}
+
+fn write_error(e: io::Error) -> CompressionError {
+    CompressionError::Compression(e.to_string())
+}
+
+fn read_error(e: io::Error) -> CompressionError {
+    CompressionError::Decompression(e.to_string())
+}
+
+/// Filters rows with the PNG filters, keeping only the previous row.
+struct RowFilter {
+    bpp: usize,
+    prev: Vec<u8>,
+    filtered: Vec<u8>,
+    trial: Vec<u8>,
+}
+
+impl RowFilter {
+    fn new(row_len: usize, bpp: usize) -> Self {
+        RowFilter {
+            bpp,
+            prev: vec![0; row_len],
+            filtered: Vec::with_capacity(row_len + 1),
+            trial: Vec::with_capacity(row_len + 1),
+        }
+    }
+
+    /// Returns the filter type byte followed by the residuals of `row`.
+    fn filter(&mut self, row: &[u8]) -> &[u8] {
+        let filter = choose_filter(row, &self.prev, self.bpp, FilterStrategy::MinSum, &mut self.trial);
+        self.filtered.clear();
+        filter_row(filter, row, &self.prev, self.bpp, &mut self.filtered);
+        self.prev.copy_from_slice(row);
+        &self.filtered
+    }
+}
+
+/// Reverses PNG filtering, keeping only the previous row.
+struct RowUnfilter {
+    bpp: usize,
+    prev: Vec<u8>,
+    raw: Vec<u8>,
+}
+
+impl RowUnfilter {
+    fn new(row_len: usize, bpp: usize) -> Self {
+        RowUnfilter {
+            bpp,
+            prev: vec![0; row_len],
+            raw: vec![0; row_len + 1],
+        }
+    }
+
+    /// Reads one filtered row from `input` and returns the reconstructed samples.
+    fn read(&mut self, input: &mut dyn Read) -> Result<&[u8], CompressionError> {
+        input.read_exact(&mut self.raw).map_err(read_error)?;
+        let filter = self.raw[0];
+        if filter > 4 {
+            return Err(CompressionError::Decompression(format!("Invalid row filter type {}", filter)));
+        }
+        unfilter_row(filter, &mut self.raw[1..], &self.prev, self.bpp);
+        self.prev.copy_from_slice(&self.raw[1..]);
+        Ok(&self.prev)
+    }
+}
+
+/// Writes the row container, compressing rows as they arrive.
+pub struct ContainerRowWriter<'a> {
+    encoder: Option<Box<dyn StreamEncoder + 'a>>,
+    filter: RowFilter,
+    height: u32,
+    rows: u32,
+}
+
+impl<'a> ContainerRowWriter<'a> {
+    /// Creates a new `ContainerRowWriter` and writes the header.
+    ///
+    /// # Arguments
+    ///
+    /// * `output` - Where the container is written.
+    /// * `width`, `height`, `channels` - Image geometry; 1 to 4 channels of 8-bit samples.
+    /// * `algorithm` - [`CompressionAlgorithmType::id`] of Deflate (0) or LZW (1).
+    /// * `level` - Deflate level, 6 if `None`; ignored by LZW.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the writer or a `CompressionError` if the arguments are invalid.
+    pub fn new<W: Write + 'a>(
+        mut output: W,
+        width: u32,
+        height: u32,
+        channels: u8,
+        algorithm: u8,
+        level: Option<u32>,
+    ) -> Result<Self, CompressionError> {
+        check_geometry(width, height, channels)?;
+        let compressor = match algorithm {
+            0 => CompressionAlgorithmType::Deflate(DeflateCompressor::with_level_number(level.unwrap_or(6))?),
+            1 => CompressionAlgorithmType::Lzw(LzwCompressor::new(4096)),
+            other => {
+                return Err(CompressionError::UnknownAlgorithm(format!("algorithm id {} does not stream rows", other)));
+            }
+        };
+        let mut header = Vec::with_capacity(HEADER_LEN);
+        header.extend_from_slice(MAGIC);
+        header.extend_from_slice(&width.to_be_bytes());
+        header.extend_from_slice(&height.to_be_bytes());
+        header.push(channels);
+        header.push(algorithm);
+        header.push(if algorithm == 0 { level.unwrap_or(6) as u8 } else { NO_LEVEL });
+        output.write_all(&header).map_err(write_error)?;
+        let encoder = compressor.encoder(Box::new(output))?;
+        let row_len = width as usize * channels as usize;
+        Ok(ContainerRowWriter {
+            encoder: Some(encoder),
+            filter: RowFilter::new(row_len, channels as usize),
+            height,
+            rows: 0,
+        })
+    }
+}
+
+impl RowWriter for ContainerRowWriter<'_> {
+    fn write_row(&mut self, row: &[u8]) -> Result<(), CompressionError> {
+        let encoder = self.encoder.as_mut().ok_or_else(|| CompressionError::Compression("Image already finished".to_string()))?;
+        if row.len() != self.filter.prev.len() || self.rows == self.height {
+            return Err(CompressionError::Compression(format!("Unexpected row {} of {} bytes", self.rows, row.len())));
+        }
+        encoder.write_all(self.filter.filter(row)).map_err(write_error)?;
+        self.rows += 1;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), CompressionError> {
+        if self.rows != self.height {
+            return Err(CompressionError::Compression(format!("Wrote {} of {} rows", self.rows, self.height)));
+        }
+        match self.encoder.take() {
+            Some(encoder) => encoder.finish(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Reads the row container, decompressing rows on demand.
+pub struct ContainerRowReader<'a> {
+    decoder: Box<dyn Read + 'a>,
+    unfilter: RowUnfilter,
+    geometry: (u32, u32, u8),
+    rows: u32,
+}
+
+impl<'a> ContainerRowReader<'a> {
+    /// Creates a new `ContainerRowReader` after reading and validating the header.
+    pub fn new<R: Read + 'a>(mut input: R) -> Result<Self, CompressionError> {
+        let mut header = [0u8; HEADER_LEN];
+        input.read_exact(&mut header).map_err(read_error)?;
+        if &header[..4] != MAGIC {
+            return Err(CompressionError::Decompression("Not a row container".to_string()));
+        }
+        let width = u32::from_be_bytes([header[4], header[5], header[6], header[7]]);
+        let height = u32::from_be_bytes([header[8], header[9], header[10], header[11]]);
+        let channels = header[12];
+        check_geometry(width, height, channels).map_err(|e| CompressionError::Decompression(e.to_string()))?;
+        let decoder = match header[13] {
+            0 | 1 => CompressionAlgorithmType::for_block(header[13], width, height, channels)?.decoder(Box::new(input))?,
+            other => return Err(CompressionError::UnknownAlgorithm(format!("algorithm id {}", other))),
+        };
+        Ok(ContainerRowReader {
+            decoder,
+            unfilter: RowUnfilter::new(width as usize * channels as usize, channels as usize),
+            geometry: (width, height, channels),
+            rows: 0,
+        })
+    }
+}
+
+impl RowReader for ContainerRowReader<'_> {
+    fn geometry(&self) -> (u32, u32, u8) {
+        self.geometry
+    }
+
+    fn read_row(&mut self, row: &mut [u8]) -> Result<bool, CompressionError> {
+        if self.rows == self.geometry.1 {
+            return Ok(false);
+        }
+        row.copy_from_slice(self.unfilter.read(&mut self.decoder)?);
+        self.rows += 1;
+        Ok(true)
+    }
+}
+
+/// Splits a zlib stream into IDAT chunks of at most [`IDAT_SIZE`] bytes.
+struct IdatWriter<'a> {
+    output: Box<dyn Write + 'a>,
+    buffer: Vec<u8>,
+}
+
+impl IdatWriter<'_> {
+    fn write_chunk(&mut self, kind: &[u8; 4], len: usize) -> io::Result<()> {
+        let mut chunk = Vec::with_capacity(len + 12);
+        write_chunk(&mut chunk, kind, &self.buffer[..len]);
+        self.buffer.drain(..len);
+        self.output.write_all(&chunk)
+    }
+}
+
+impl Write for IdatWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        while self.buffer.len() >= IDAT_SIZE {
+            self.write_chunk(b"IDAT", IDAT_SIZE)?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.output.flush()
+    }
+}
+
+/// Writes a non-interlaced 8-bit PNG, compressing rows as they arrive.
+pub struct PngRowWriter<'a> {
+    encoder: Option<ZlibEncoder<IdatWriter<'a>>>,
+    filter: RowFilter,
+    height: u32,
+    rows: u32,
+}
+
+impl<'a> PngRowWriter<'a> {
+    /// Creates a new `PngRowWriter` and writes the signature and IHDR chunk.
+    ///
+    /// # Arguments
+    ///
+    /// * `output` - Where the PNG file is written.
+    /// * `width`, `height` - Image dimensions.
+    /// * `channels` - 1 (gray), 2 (gray and alpha), 3 (RGB) or 4 (RGBA).
+    /// * `level` - Deflate level from 0 to 9.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the writer or a `CompressionError` if the arguments are invalid.
+    pub fn new<W: Write + 'a>(mut output: W, width: u32, height: u32, channels: u8, level: u32) -> Result<Self, CompressionError> {
+        check_geometry(width, height, channels)?;
+        if level > 9 {
+            return Err(CompressionError::InvalidLevel(level.to_string()));
+        }
+        let color_type = [0, 4, 2, 6][channels as usize - 1];
+        let mut header = PNG_SIGNATURE.to_vec();
+        let mut ihdr = Vec::with_capacity(13);
+        ihdr.extend_from_slice(&width.to_be_bytes());
+        ihdr.extend_from_slice(&height.to_be_bytes());
+        ihdr.extend_from_slice(&[8, color_type, 0, 0, 0]);
+        write_chunk(&mut header, b"IHDR", &ihdr);
+        output.write_all(&header).map_err(write_error)?;
+        let idat = IdatWriter {
+            output: Box::new(output),
+            buffer: Vec::with_capacity(IDAT_SIZE),
+        };
+        Ok(PngRowWriter {
+            encoder: Some(ZlibEncoder::new(idat, Compression::new(level))),
+            filter: RowFilter::new(width as usize * channels as usize, channels as usize),
+            height,
+            rows: 0,
+        })
+    }
+}
+
+impl RowWriter for PngRowWriter<'_> {
+    fn write_row(&mut self, row: &[u8]) -> Result<(), CompressionError> {
+        let encoder = self.encoder.as_mut().ok_or_else(|| CompressionError::Compression("Image already finished".to_string()))?;
+        if row.len() != self.filter.prev.len() || self.rows == self.height {
+            return Err(CompressionError::Compression(format!("Unexpected row {} of {} bytes", self.rows, row.len())));
+        }
+        encoder.write_all(self.filter.filter(row)).map_err(write_error)?;
+        self.rows += 1;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), CompressionError> {
+        if self.rows != self.height {
+            return Err(CompressionError::Compression(format!("Wrote {} of {} rows", self.rows, self.height)));
+        }
+        let Some(encoder) = self.encoder.take() else {
+            return Ok(());
+        };
+        let mut idat = encoder.finish().map_err(write_error)?;
+        if !idat.buffer.is_empty() {
+            idat.write_chunk(b"IDAT", idat.buffer.len()).map_err(write_error)?;
+        }
+        idat.write_chunk(b"IEND", 0).and_then(|_| idat.flush()).map_err(write_error)
+    }
+}
+
+/// Reads the concatenated data of consecutive IDAT chunks, checking their CRCs.
+struct IdatReader<'a> {
+    input: BufReader<Box<dyn Read + 'a>>,
+    remaining: usize,
+    crc: Crc,
+    done: bool,
+}
+
+impl IdatReader<'_> {
+    /// Reads a chunk header, returning its length and type.
+    fn chunk_header(&mut self) -> io::Result<(usize, [u8; 4])> {
+        let mut header = [0u8; 8];
+        self.input.read_exact(&mut header)?;
+        let len = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+        Ok((len, [header[4], header[5], header[6], header[7]]))
+    }
+
+    /// Checks the CRC that follows a chunk's data.
+    fn check_crc(&mut self) -> io::Result<()> {
+        let mut crc = [0u8; 4];
+        self.input.read_exact(&mut crc)?;
+        if u32::from_be_bytes(crc) != self.crc.sum() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "PNG chunk CRC mismatch"));
+        }
+        Ok(())
+    }
+
+    /// Reads a whole chunk whose header has been read, checking its CRC.
+    ///
+    /// Only the small header chunks are read this way, so longer chunks are rejected rather than
+    /// allocated.
+    fn chunk_data(&mut self, len: usize, kind: &[u8; 4]) -> io::Result<Vec<u8>> {
+        if len > MAX_HEADER_CHUNK_LEN {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "PNG header chunk is too long"));
+        }
+        let mut data = vec![0u8; len];
+        self.input.read_exact(&mut data)?;
+        self.crc.reset();
+        self.crc.update(kind);
+        self.crc.update(&data);
+        self.check_crc()?;
+        Ok(data)
+    }
+
+    /// Skips a chunk whose header has been read, checking its CRC without holding its data.
+    fn skip_chunk(&mut self, len: usize, kind: &[u8; 4]) -> io::Result<()> {
+        self.crc.reset();
+        self.crc.update(kind);
+        let mut buffer = [0u8; 4096];
+        let mut remaining = len;
+        while remaining > 0 {
+            let n = remaining.min(buffer.len());
+            self.input.read_exact(&mut buffer[..n])?;
+            self.crc.update(&buffer[..n]);
+            remaining -= n;
+        }
+        self.check_crc()
+    }
+}
+
+impl Read for IdatReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.remaining == 0 {
+            if self.done {
+                return Ok(0);
+            }
+            self.check_crc()?;
+            let (len, kind) = self.chunk_header()?;
+            if &kind != b"IDAT" {
+                self.done = true;
+                return Ok(0);
+            }
+            self.crc.reset();
+            self.crc.update(&kind);
+            self.remaining = len;
+        }
+        let len = buf.len().min(self.remaining);
+        let n = self.input.read(&mut buf[..len])?;
+        if n == 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Truncated IDAT chunk"));
+        }
+        self.crc.update(&buf[..n]);
+        self.remaining -= n;
+        Ok(n)
+    }
+}
+
+/// Reads a non-interlaced 8-bit PNG, decompressing rows on demand.
+pub struct PngRowReader<'a> {
+    decoder: ZlibDecoder<IdatReader<'a>>,
+    unfilter: RowUnfilter,
+    /// RGBA entries of an indexed image, expanded when rows are read.
+    palette: Option<Vec<[u8; 4]>>,
+    geometry: (u32, u32, u8),
+    rows: u32,
+}
+
+impl<'a> PngRowReader<'a> {
+    /// Creates a new `PngRowReader`, reading every chunk up to the first IDAT.
+    pub fn new<R: Read + 'a>(input: R) -> Result<Self, CompressionError> {
+        let mut reader = IdatReader {
+            input: BufReader::new(Box::new(input) as Box<dyn Read + 'a>),
+            remaining: 0,
+            crc: Crc::new(),
+            done: false,
+        };
+        let mut signature = [0u8; 8];
+        reader.input.read_exact(&mut signature).map_err(read_error)?;
+        if signature != PNG_SIGNATURE {
+            return Err(CompressionError::Decompression("Not a PNG file".to_string()));
+        }
+        let mut ihdr = None;
+        let mut palette: Option<Vec<[u8; 4]>> = None;
+        loop {
+            let (len, kind) = reader.chunk_header().map_err(read_error)?;
+            if &kind == b"IDAT" {
+                reader.crc.reset();
+                reader.crc.update(&kind);
+                reader.remaining = len;
+                break;
+            }
+            if !matches!(&kind, b"IHDR" | b"PLTE" | b"tRNS" | b"IEND") {
+                reader.skip_chunk(len, &kind).map_err(read_error)?;
+                continue;
+            }
+            let data = reader.chunk_data(len, &kind).map_err(read_error)?;
+            match &kind {
+                b"IHDR" if data.len() == 13 => ihdr = Some(data),
+                b"PLTE" => palette = Some(data.chunks_exact(3).map(|c| [c[0], c[1], c[2], 255]).collect()),
+                b"tRNS" => {
+                    if let Some(palette) = palette.as_mut() {
+                        for (entry, &alpha) in palette.iter_mut().zip(&data) {
+                            entry[3] = alpha;
+                        }
+                    }
+                }
+                b"IEND" => return Err(CompressionError::Decompression("PNG file has no image data".to_string())),
+                _ => {}
+            }
+        }
+        let ihdr = ihdr.ok_or_else(|| CompressionError::Decompression("Missing IHDR chunk".to_string()))?;
+        let width = u32::from_be_bytes([ihdr[0], ihdr[1], ihdr[2], ihdr[3]]);
+        let height = u32::from_be_bytes([ihdr[4], ihdr[5], ihdr[6], ihdr[7]]);
+        let (bit_depth, color_type, interlace) = (ihdr[8], ihdr[9], ihdr[12]);
+        let stored_channels = match color_type {
+            0 | 3 => 1,
+            4 => 2,
+            2 => 3,
+            6 => 4,
+            other => return Err(CompressionError::Decompression(format!("Invalid PNG color type {}", other))),
+        };
+        if bit_depth != 8 || interlace != 0 {
+            return Err(CompressionError::Decompression(format!(
+                "Only non-interlaced 8-bit PNG files can be read row by row (bit depth {}, interlace {})",
+                bit_depth, interlace
+            )));
+        }
+        let palette = if color_type == 3 {
+            Some(palette.ok_or_else(|| CompressionError::Decompression("Missing PLTE chunk".to_string()))?)
+        } else {
+            None
+        };
+        let channels = match &palette {
+            Some(entries) if entries.iter().any(|e| e[3] != 255) => 4,
+            Some(_) => 3,
+            None => stored_channels,
+        };
+        check_geometry(width, height, channels).map_err(|e| CompressionError::Decompression(e.to_string()))?;
+        let row_len = width as usize * stored_channels as usize;
+        Ok(PngRowReader {
+            decoder: ZlibDecoder::new(reader),
+            unfilter: RowUnfilter::new(row_len, stored_channels as usize),
+            palette,
+            geometry: (width, height, channels),
+            rows: 0,
+        })
+    }
+}
+
+impl RowReader for PngRowReader<'_> {
+    fn geometry(&self) -> (u32, u32, u8) {
+        self.geometry
+    }
+
+    fn read_row(&mut self, row: &mut [u8]) -> Result<bool, CompressionError> {
+        if self.rows == self.geometry.1 {
+            return Ok(false);
+        }
+        let samples = self.unfilter.read(&mut self.decoder)?;
+        match &self.palette {
+            Some(palette) => {
+                let channels = self.geometry.2 as usize;
+                for (pixel, &index) in row.chunks_exact_mut(channels).zip(samples) {
+                    let entry = palette
+                        .get(index as usize)
+                        .ok_or_else(|| CompressionError::Decompression(format!("Palette index {} out of range", index)))?;
+                    pixel.copy_from_slice(&entry[..channels]);
+                }
+            }
+            None => row.copy_from_slice(samples),
+        }
+        self.rows += 1;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{DynamicImage, ImageFormat, RgbImage, RgbaImage};
+    use std::io::Cursor;
+
+    fn gradient(width: u32, height: u32) -> RgbImage {
+        RgbImage::from_fn(width, height, |x, y| image::Rgb([(x * 3) as u8, (y * 5) as u8, ((x ^ y) * 7) as u8]))
+    }
+
+    #[test]
+    fn test_container_round_trip() {
+        let image = gradient(37, 23);
+        for algorithm in [0, 1] {
+            let mut container = Vec::new();
+            let mut writer = ContainerRowWriter::new(&mut container, 37, 23, 3, algorithm, Some(9)).unwrap();
+            for row in image.rows() {
+                let bytes: Vec<u8> = row.flat_map(|p| p.0).collect();
+                writer.write_row(&bytes).unwrap();
+            }
+            writer.finish().unwrap();
+            drop(writer);
+            assert!(container.len() < image.len() / 2);
+
+            let mut reader = ContainerRowReader::new(&container[..]).unwrap();
+            assert_eq!(reader.geometry(), (37, 23, 3));
+            let mut row = vec![0u8; 37 * 3];
+            let mut restored = Vec::new();
+            while reader.read_row(&mut row).unwrap() {
+                restored.extend_from_slice(&row);
+            }
+            assert_eq!(restored, image.as_raw().as_slice());
+        }
+    }
+
+    #[test]
+    fn test_png_round_trip_through_image_crate() {
+        let image = DynamicImage::ImageRgba8(RgbaImage::from_fn(70, 40, |x, y| image::Rgba([x as u8, y as u8, 9, (x * y) as u8])));
+        let mut source = Vec::new();
+        image.write_to(&mut Cursor::new(&mut source), ImageFormat::Png).unwrap();
+
+        let mut reader = PngRowReader::new(&source[..]).unwrap();
+        let mut png = Vec::new();
+        let mut writer = PngRowWriter::new(&mut png, 70, 40, 4, 6).unwrap();
+        assert_eq!(copy_rows(&mut reader, &mut writer).unwrap(), 40);
+        drop(writer);
+        assert_eq!(image::load_from_memory(&png).unwrap(), image);
+    }
+
+    #[test]
+    fn test_png_multiple_idat_chunks() {
+        // A noisy image gives a zlib stream longer than one IDAT chunk.
+        let noise = |x: u32, y: u32| (x.wrapping_mul(374_761_393) ^ y.wrapping_mul(668_265_263)).wrapping_mul(2_654_435_761) >> 24;
+        let image = RgbImage::from_fn(300, 300, |x, y| image::Rgb([noise(x, y) as u8, noise(y, x) as u8, x as u8]));
+        let mut png = Vec::new();
+        let mut writer = PngRowWriter::new(&mut png, 300, 300, 3, 1).unwrap();
+        for row in image.chunks(300 * 3) {
+            writer.write_row(row).unwrap();
+        }
+        writer.finish().unwrap();
+        drop(writer);
+        assert!(png.windows(4).filter(|w| w == b"IDAT").count() > 1);
+        let mut reader = PngRowReader::new(&png[..]).unwrap();
+        let mut row = vec![0u8; 900];
+        for expected in image.chunks(900) {
+            assert!(reader.read_row(&mut row).unwrap());
+            assert_eq!(row, expected);
+        }
+        assert!(!reader.read_row(&mut row).unwrap());
+    }
+
+    #[test]
+    fn test_png_palette_is_expanded() {
+        let mut png = PNG_SIGNATURE.to_vec();
+        write_chunk(&mut png, b"IHDR", &[0, 0, 0, 3, 0, 0, 0, 1, 8, 3, 0, 0, 0]);
+        write_chunk(&mut png, b"PLTE", &[10, 20, 30, 40, 50, 60]);
+        write_chunk(&mut png, b"tRNS", &[128]);
+        let mut zlib = ZlibEncoder::new(Vec::new(), Compression::default());
+        zlib.write_all(&[0, 1, 0, 1]).unwrap();
+        write_chunk(&mut png, b"IDAT", &zlib.finish().unwrap());
+        write_chunk(&mut png, b"IEND", &[]);
+
+        let mut reader = PngRowReader::new(&png[..]).unwrap();
+        assert_eq!(reader.geometry(), (3, 1, 4));
+        let mut row = [0u8; 12];
+        assert!(reader.read_row(&mut row).unwrap());
+        assert_eq!(row, [40, 50, 60, 255, 10, 20, 30, 128, 40, 50, 60, 255]);
+
+        let corrupt: Vec<u8> = png.iter().enumerate().map(|(i, &b)| if i == 40 { b ^ 1 } else { b }).collect();
+        assert!(PngRowReader::new(&corrupt[..]).is_err());
+    }
+
+    #[test]
+    fn test_png_long_chunks_are_not_buffered() {
+        let mut header = PNG_SIGNATURE.to_vec();
+        write_chunk(&mut header, b"IHDR", &[0, 0, 0, 1, 0, 0, 0, 1, 8, 0, 0, 0, 0]);
+        let mut zlib = ZlibEncoder::new(Vec::new(), Compression::default());
+        zlib.write_all(&[0, 7]).unwrap();
+        let idat = zlib.finish().unwrap();
+
+        // Ancillary chunks of any length are skipped
+        let mut png = header.clone();
+        write_chunk(&mut png, b"tEXt", &vec![b'x'; 100_000]);
+        write_chunk(&mut png, b"IDAT", &idat);
+        write_chunk(&mut png, b"IEND", &[]);
+        let mut row = [0u8; 1];
+        assert!(PngRowReader::new(&png[..]).unwrap().read_row(&mut row).unwrap());
+        assert_eq!(row, [7]);
+
+        // Huge declared lengths fail without allocating them
+        for kind in [b"PLTE", b"zTXt"] {
+            let mut png = header.clone();
+            png.extend_from_slice(&u32::MAX.to_be_bytes());
+            png.extend_from_slice(kind);
+            png.extend_from_slice(&[0; 64]);
+            assert!(PngRowReader::new(&png[..]).is_err());
+        }
+    }
+
+    #[test]
+    fn test_rejects_wrong_row_counts() {
+        let mut png = Vec::new();
+        let mut writer = PngRowWriter::new(&mut png, 2, 2, 1, 6).unwrap();
+        writer.write_row(&[1, 2]).unwrap();
+        assert!(writer.write_row(&[1, 2, 3]).is_err());
+        assert!(writer.finish().is_err());
+        assert!(ContainerRowWriter::new(Vec::new(), 2, 2, 1, 3, None).is_err());
+        assert!(ContainerRowReader::new(&b"ROW\x02"[..]).is_err());
+    }
+
+    #[test]
+    fn test_rows_too_long_to_buffer_are_rejected() {
+        // 15 bytes asking for rows of 8 GiB.
+        let mut container = MAGIC.to_vec();
+        container.extend_from_slice(&(i32::MAX as u32).to_be_bytes());
+        container.extend_from_slice(&1u32.to_be_bytes());
+        container.extend_from_slice(&[4, 0, NO_LEVEL]);
+        assert!(ContainerRowReader::new(&container[..]).is_err());
+
+        let mut png = PNG_SIGNATURE.to_vec();
+        write_chunk(&mut png, b"IHDR", &[0x7F, 0xFF, 0xFF, 0xFF, 0, 0, 0, 1, 8, 6, 0, 0, 0]);
+        assert!(PngRowReader::new(&png[..]).is_err());
+        assert!(ContainerRowWriter::new(Vec::new(), 1 << 25, 1, 4, 0, None).is_err());
+        assert!(ContainerRowWriter::new(Vec::new(), 1 << 24, 1, 4, 0, None).is_ok());
+    }
+}
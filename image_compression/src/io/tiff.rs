@@ -0,0 +1,1033 @@
+// src/io/tiff.rs
+
+//! Module implementing a baseline TIFF writer and a reader for the files it produces.
+//!
+//! Images are stored chunky (interleaved) in strips or tiles, each chunk
+//! compressed independently with the crate's own codecs: LZW in TIFF bit
+//! order, PackBits or Deflate (zlib). The horizontal differencing predictor
+//! can be enabled for LZW and Deflate. Gray and RGB images with or without
+//! alpha are supported at 8 and 16 bits per sample, and common descriptive
+//! tags (description, software, artist, copyright, date and resolution) are
+//! written when set.
+//!
+//! Bilevel images are stored at 1 bit per sample and can additionally use
+//! CCITT Group 3 or Group 4 fax compression; other images are binarized
+//! first when one of those is selected.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use image::{DynamicImage, RgbImage};
+//! use image_compression::io::tiff::{read_tiff, TiffCompression, TiffWriter};
+//!
+//! let image = DynamicImage::ImageRgb8(RgbImage::from_fn(40, 30, |x, y| image::Rgb([x as u8, y as u8, 7])));
+//! let writer = TiffWriter::new().with_compression(TiffCompression::Lzw).with_predictor(true);
+//! let bytes = writer.encode(&image).unwrap();
+//! assert_eq!(read_tiff(&bytes).unwrap().image, image);
+//! ```
+
+use crate::compression::ccitt::{Binarization, BilevelImage, CcittCompressor, CcittMode};
+use crate::compression::deflate::DeflateCompressor;
+use crate::compression::lzw::LzwCompressor;
+use crate::compression::utils::checked_image_len;
+use crate::compression::{CompressionError, Compressor};
+use image::{DynamicImage, ImageBuffer};
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+
+const TAG_IMAGE_WIDTH: u16 = 256;
+const TAG_IMAGE_LENGTH: u16 = 257;
+const TAG_BITS_PER_SAMPLE: u16 = 258;
+const TAG_COMPRESSION: u16 = 259;
+const TAG_PHOTOMETRIC: u16 = 262;
+const TAG_IMAGE_DESCRIPTION: u16 = 270;
+const TAG_STRIP_OFFSETS: u16 = 273;
+const TAG_SAMPLES_PER_PIXEL: u16 = 277;
+const TAG_ROWS_PER_STRIP: u16 = 278;
+const TAG_STRIP_BYTE_COUNTS: u16 = 279;
+const TAG_X_RESOLUTION: u16 = 282;
+const TAG_Y_RESOLUTION: u16 = 283;
+const TAG_PLANAR_CONFIGURATION: u16 = 284;
+const TAG_RESOLUTION_UNIT: u16 = 296;
+const TAG_T4_OPTIONS: u16 = 292;
+const TAG_T6_OPTIONS: u16 = 293;
+const TAG_SOFTWARE: u16 = 305;
+const TAG_DATE_TIME: u16 = 306;
+const TAG_ARTIST: u16 = 315;
+const TAG_PREDICTOR: u16 = 317;
+const TAG_TILE_WIDTH: u16 = 322;
+const TAG_TILE_LENGTH: u16 = 323;
+const TAG_TILE_OFFSETS: u16 = 324;
+const TAG_TILE_BYTE_COUNTS: u16 = 325;
+const TAG_EXTRA_SAMPLES: u16 = 338;
+const TAG_COPYRIGHT: u16 = 33432;
+
+const TYPE_ASCII: u16 = 2;
+const TYPE_SHORT: u16 = 3;
+const TYPE_LONG: u16 = 4;
+const TYPE_RATIONAL: u16 = 5;
+
+/// Compression schemes the writer supports, with their TIFF tag values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TiffCompression {
+    None,
+    Lzw,
+    PackBits,
+    Deflate,
+    CcittGroup3,
+    CcittGroup4,
+}
+
+impl TiffCompression {
+    /// Returns the value of the Compression tag.
+    pub fn code(self) -> u16 {
+        match self {
+            TiffCompression::None => 1,
+            TiffCompression::Lzw => 5,
+            TiffCompression::PackBits => 32773,
+            TiffCompression::Deflate => 8,
+            TiffCompression::CcittGroup3 => 3,
+            TiffCompression::CcittGroup4 => 4,
+        }
+    }
+
+    /// Returns the fax coding scheme of the CCITT compressions.
+    pub fn ccitt_mode(self) -> Option<CcittMode> {
+        match self {
+            TiffCompression::CcittGroup3 => Some(CcittMode::Group3),
+            TiffCompression::CcittGroup4 => Some(CcittMode::Group4),
+            _ => None,
+        }
+    }
+
+    /// Parses a compression name such as `lzw`, `packbits`, `deflate`, `ccitt-g4` or `none`.
+    pub fn from_name(name: &str) -> Result<Self, CompressionError> {
+        match name.to_lowercase().as_str() {
+            "none" => Ok(TiffCompression::None),
+            "lzw" => Ok(TiffCompression::Lzw),
+            "packbits" => Ok(TiffCompression::PackBits),
+            "deflate" | "zip" => Ok(TiffCompression::Deflate),
+            "ccitt-g3" | "g3" => Ok(TiffCompression::CcittGroup3),
+            "ccitt-g4" | "g4" => Ok(TiffCompression::CcittGroup4),
+            other => Err(CompressionError::UnknownAlgorithm(format!("TIFF compression {}", other))),
+        }
+    }
+
+    fn from_code(code: u16) -> Result<Self, CompressionError> {
+        match code {
+            1 => Ok(TiffCompression::None),
+            5 => Ok(TiffCompression::Lzw),
+            32773 => Ok(TiffCompression::PackBits),
+            8 | 32946 => Ok(TiffCompression::Deflate),
+            3 => Ok(TiffCompression::CcittGroup3),
+            4 => Ok(TiffCompression::CcittGroup4),
+            other => Err(decode_error(&format!("unsupported TIFF compression {}", other))),
+        }
+    }
+}
+
+/// How the image is divided into independently compressed chunks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TiffLayout {
+    /// Horizontal strips of the given number of rows.
+    Strips { rows_per_strip: u32 },
+    /// Rectangular tiles; both dimensions must be multiples of 16.
+    Tiles { width: u32, height: u32 },
+}
+
+/// Descriptive TIFF tags.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TiffMetadata {
+    pub description: Option<String>,
+    pub software: Option<String>,
+    pub artist: Option<String>,
+    pub copyright: Option<String>,
+    /// Date and time in the TIFF `YYYY:MM:DD HH:MM:SS` format.
+    pub date_time: Option<String>,
+    /// Pixels per inch as a `(numerator, denominator)` rational, used for both axes.
+    pub dpi: Option<(u32, u32)>,
+}
+
+/// An image read back from a TIFF file, with its metadata.
+#[derive(Debug, Clone)]
+pub struct TiffImage {
+    pub image: DynamicImage,
+    pub compression: TiffCompression,
+    pub predictor: bool,
+    pub metadata: TiffMetadata,
+}
+
+/// A TIFF writer with configurable compression, chunk layout, predictor and metadata.
+#[derive(Debug, Clone)]
+pub struct TiffWriter {
+    compression: TiffCompression,
+    layout: TiffLayout,
+    predictor: bool,
+    binarization: Binarization,
+    metadata: TiffMetadata,
+}
+
+impl Default for TiffWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TiffWriter {
+    /// Creates a new `TiffWriter` using LZW, 16-row strips, no predictor and Otsu binarization.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use image_compression::io::tiff::{TiffCompression, TiffWriter};
+    ///
+    /// let writer = TiffWriter::new();
+    /// assert_eq!(writer.get_compression(), TiffCompression::Lzw);
+    /// ```
+    pub fn new() -> Self {
+        TiffWriter {
+            compression: TiffCompression::Lzw,
+            layout: TiffLayout::Strips { rows_per_strip: 16 },
+            predictor: false,
+            binarization: Binarization::Otsu,
+            metadata: TiffMetadata::default(),
+        }
+    }
+
+    /// Returns the writer using a different compression scheme.
+    pub fn with_compression(mut self, compression: TiffCompression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Returns the writer storing strips of `rows_per_strip` rows.
+    pub fn with_strips(mut self, rows_per_strip: u32) -> Result<Self, CompressionError> {
+        if rows_per_strip == 0 {
+            return Err(CompressionError::InvalidLevel("rows per strip must be positive".to_string()));
+        }
+        self.layout = TiffLayout::Strips { rows_per_strip };
+        Ok(self)
+    }
+
+    /// Returns the writer storing `width` x `height` tiles.
+    ///
+    /// # Arguments
+    ///
+    /// * `width`, `height` - Tile dimensions, which TIFF requires to be non-zero multiples of 16.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the `TiffWriter` or a `CompressionError` if the dimensions are invalid.
+    pub fn with_tiles(mut self, width: u32, height: u32) -> Result<Self, CompressionError> {
+        if width == 0 || height == 0 || !width.is_multiple_of(16) || !height.is_multiple_of(16) {
+            return Err(CompressionError::InvalidLevel(format!("tile size {}x{}", width, height)));
+        }
+        self.layout = TiffLayout::Tiles { width, height };
+        Ok(self)
+    }
+
+    /// Returns the writer with the horizontal differencing predictor enabled or disabled.
+    pub fn with_predictor(mut self, predictor: bool) -> Self {
+        self.predictor = predictor;
+        self
+    }
+
+    /// Returns the writer converting images to bilevel with `binarization` for CCITT compression.
+    pub fn with_binarization(mut self, binarization: Binarization) -> Self {
+        self.binarization = binarization;
+        self
+    }
+
+    /// Returns the writer adding the given descriptive tags.
+    pub fn with_metadata(mut self, metadata: TiffMetadata) -> Self {
+        self.metadata = metadata;
+        self
+    }
+
+    /// Retrieves the compression scheme.
+    pub fn get_compression(&self) -> TiffCompression {
+        self.compression
+    }
+
+    /// Retrieves the chunk layout.
+    pub fn get_layout(&self) -> TiffLayout {
+        self.layout
+    }
+
+    /// Encodes `image` as a little-endian TIFF file.
+    ///
+    /// Floating point images are stored as 16-bit samples. With CCITT
+    /// compression the image is binarized and stored at 1 bit per pixel.
+    pub fn encode(&self, image: &DynamicImage) -> Result<Vec<u8>, CompressionError> {
+        if self.compression.ccitt_mode().is_some() {
+            return self.encode_bilevel(&BilevelImage::from_image(image, self.binarization));
+        }
+        self.encode_raster(&Raster::new(image))
+    }
+
+    /// Encodes a bilevel image at 1 bit per pixel with `WhiteIsZero` photometric interpretation.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use image_compression::compression::ccitt::BilevelImage;
+    /// use image_compression::io::tiff::{read_tiff, TiffCompression, TiffWriter};
+    ///
+    /// let mut page = BilevelImage::new(200, 100);
+    /// (20..180).for_each(|x| page.set(x, 50, true));
+    /// let bytes = TiffWriter::new().with_compression(TiffCompression::CcittGroup4).encode_bilevel(&page).unwrap();
+    /// assert_eq!(read_tiff(&bytes).unwrap().image.to_luma8(), page.to_luma8());
+    /// ```
+    pub fn encode_bilevel(&self, image: &BilevelImage) -> Result<Vec<u8>, CompressionError> {
+        self.encode_raster(&Raster::bilevel(image))
+    }
+
+    fn encode_raster(&self, raster: &Raster) -> Result<Vec<u8>, CompressionError> {
+        let predictor = self.predictor
+            && raster.bits >= 8
+            && matches!(self.compression, TiffCompression::Lzw | TiffCompression::Deflate);
+        let (chunk_w, chunk_h) = match self.layout {
+            TiffLayout::Strips { rows_per_strip } => (raster.width, rows_per_strip.min(raster.height.max(1))),
+            TiffLayout::Tiles { width, height } => (width, height),
+        };
+        let tiled = matches!(self.layout, TiffLayout::Tiles { .. });
+
+        // Image data goes right after the 8-byte header; the IFD follows it.
+        let mut file = b"II\x2A\x00\0\0\0\0".to_vec();
+        let mut offsets = Vec::new();
+        let mut counts = Vec::new();
+        for cy in (0..raster.height).step_by(chunk_h as usize) {
+            for cx in (0..raster.width).step_by(chunk_w as usize) {
+                let mut chunk = raster.chunk(cx, cy, chunk_w, chunk_h, tiled);
+                if predictor {
+                    raster.difference(&mut chunk, chunk_w);
+                }
+                let rows = (chunk.len() / raster.row_bytes(chunk_w).max(1)) as u32;
+                let encoded = compress_chunk(self.compression, &chunk, chunk_w, rows, raster.row_bytes(chunk_w))?;
+                offsets.push(file_offset(file.len())?);
+                counts.push(file_offset(encoded.len())?);
+                file.extend_from_slice(&encoded);
+                if file.len() % 2 == 1 {
+                    file.push(0);
+                }
+            }
+        }
+
+        let mut entries = vec![
+            Entry::longs(TAG_IMAGE_WIDTH, &[raster.width]),
+            Entry::longs(TAG_IMAGE_LENGTH, &[raster.height]),
+            Entry::shorts(TAG_BITS_PER_SAMPLE, &vec![raster.bits; raster.samples as usize]),
+            Entry::shorts(TAG_COMPRESSION, &[self.compression.code()]),
+            Entry::shorts(TAG_PHOTOMETRIC, &[raster.photometric()]),
+            Entry::shorts(TAG_SAMPLES_PER_PIXEL, &[raster.samples]),
+            Entry::shorts(TAG_PLANAR_CONFIGURATION, &[1]),
+        ];
+        if tiled {
+            entries.push(Entry::longs(TAG_TILE_WIDTH, &[chunk_w]));
+            entries.push(Entry::longs(TAG_TILE_LENGTH, &[chunk_h]));
+            entries.push(Entry::longs(TAG_TILE_OFFSETS, &offsets));
+            entries.push(Entry::longs(TAG_TILE_BYTE_COUNTS, &counts));
+        } else {
+            entries.push(Entry::longs(TAG_STRIP_OFFSETS, &offsets));
+            entries.push(Entry::longs(TAG_ROWS_PER_STRIP, &[chunk_h]));
+            entries.push(Entry::longs(TAG_STRIP_BYTE_COUNTS, &counts));
+        }
+        if predictor {
+            entries.push(Entry::shorts(TAG_PREDICTOR, &[2]));
+        }
+        if let Some(mode) = self.compression.ccitt_mode() {
+            let tag = if mode == CcittMode::Group4 { TAG_T6_OPTIONS } else { TAG_T4_OPTIONS };
+            entries.push(Entry::longs(tag, &[mode.tiff_options()]));
+        }
+        if raster.alpha {
+            entries.push(Entry::shorts(TAG_EXTRA_SAMPLES, &[2]));
+        }
+        let metadata = &self.metadata;
+        for (tag, value) in [
+            (TAG_IMAGE_DESCRIPTION, &metadata.description),
+            (TAG_SOFTWARE, &metadata.software),
+            (TAG_DATE_TIME, &metadata.date_time),
+            (TAG_ARTIST, &metadata.artist),
+            (TAG_COPYRIGHT, &metadata.copyright),
+        ] {
+            if let Some(text) = value {
+                entries.push(Entry::ascii(tag, text));
+            }
+        }
+        if let Some(dpi) = metadata.dpi {
+            entries.push(Entry::rational(TAG_X_RESOLUTION, dpi));
+            entries.push(Entry::rational(TAG_Y_RESOLUTION, dpi));
+            entries.push(Entry::shorts(TAG_RESOLUTION_UNIT, &[2]));
+        }
+        entries.sort_by_key(|e| e.tag);
+
+        let ifd_offset = file_offset(file.len())?;
+        file[4..8].copy_from_slice(&ifd_offset.to_le_bytes());
+        let overflow_start = file.len() + 2 + 12 * entries.len() + 4;
+        let mut overflow_offset = file_offset(overflow_start)?;
+        let mut overflow = Vec::new();
+        file.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+        for entry in &entries {
+            file.extend_from_slice(&entry.tag.to_le_bytes());
+            file.extend_from_slice(&entry.kind.to_le_bytes());
+            file.extend_from_slice(&entry.count.to_le_bytes());
+            if entry.data.len() <= 4 {
+                let mut inline = [0u8; 4];
+                inline[..entry.data.len()].copy_from_slice(&entry.data);
+                file.extend_from_slice(&inline);
+            } else {
+                file.extend_from_slice(&overflow_offset.to_le_bytes());
+                overflow.extend_from_slice(&entry.data);
+                if entry.data.len() % 2 == 1 {
+                    overflow.push(0);
+                }
+                overflow_offset = file_offset(overflow_start + overflow.len())?;
+            }
+        }
+        file.extend_from_slice(&0u32.to_le_bytes());
+        file.extend_from_slice(&overflow);
+        Ok(file)
+    }
+
+    /// Encodes `image` and writes it to `path`.
+    pub fn write<P: AsRef<Path>>(&self, path: P, image: &DynamicImage) -> Result<(), CompressionError> {
+        let bytes = self.encode(image)?;
+        fs::write(path, bytes).map_err(|e| CompressionError::Compression(e.to_string()))
+    }
+}
+
+/// A directory entry with its value already serialized in little-endian order.
+struct Entry {
+    tag: u16,
+    kind: u16,
+    count: u32,
+    data: Vec<u8>,
+}
+
+impl Entry {
+    fn shorts(tag: u16, values: &[u16]) -> Self {
+        Entry {
+            tag,
+            kind: TYPE_SHORT,
+            count: values.len() as u32,
+            data: values.iter().flat_map(|v| v.to_le_bytes()).collect(),
+        }
+    }
+
+    fn longs(tag: u16, values: &[u32]) -> Self {
+        Entry {
+            tag,
+            kind: TYPE_LONG,
+            count: values.len() as u32,
+            data: values.iter().flat_map(|v| v.to_le_bytes()).collect(),
+        }
+    }
+
+    fn ascii(tag: u16, text: &str) -> Self {
+        let mut data = text.replace('\0', " ").into_bytes();
+        data.push(0);
+        Entry {
+            tag,
+            kind: TYPE_ASCII,
+            count: data.len() as u32,
+            data,
+        }
+    }
+
+    fn rational(tag: u16, (numerator, denominator): (u32, u32)) -> Self {
+        let mut data = numerator.to_le_bytes().to_vec();
+        data.extend_from_slice(&denominator.max(1).to_le_bytes());
+        Entry {
+            tag,
+            kind: TYPE_RATIONAL,
+            count: 1,
+            data,
+        }
+    }
+}
+
+/// Interleaved samples of an image in TIFF's little-endian sample layout.
+struct Raster {
+    width: u32,
+    height: u32,
+    samples: u16,
+    bits: u16,
+    alpha: bool,
+    bytes: Vec<u8>,
+}
+
+impl Raster {
+    fn new(image: &DynamicImage) -> Self {
+        use image::ColorType::*;
+        let color = image.color();
+        let (samples, alpha) = (color.channel_count() as u16, color.has_alpha());
+        let (bits, bytes) = match color {
+            L8 => (8, image.to_luma8().into_raw()),
+            La8 => (8, image.to_luma_alpha8().into_raw()),
+            Rgb8 => (8, image.to_rgb8().into_raw()),
+            Rgba8 => (8, image.to_rgba8().into_raw()),
+            L16 => (16, le_bytes(image.to_luma16().into_raw())),
+            La16 => (16, le_bytes(image.to_luma_alpha16().into_raw())),
+            Rgb16 | Rgb32F => (16, le_bytes(image.to_rgb16().into_raw())),
+            _ => (16, le_bytes(image.to_rgba16().into_raw())),
+        };
+        Raster {
+            width: image.width(),
+            height: image.height(),
+            samples,
+            bits,
+            alpha,
+            bytes,
+        }
+    }
+
+    fn bilevel(image: &BilevelImage) -> Self {
+        Raster {
+            width: image.width(),
+            height: image.height(),
+            samples: 1,
+            bits: 1,
+            alpha: false,
+            bytes: image.packed().to_vec(),
+        }
+    }
+
+    /// Returns the PhotometricInterpretation: WhiteIsZero for bilevel, else BlackIsZero or RGB.
+    fn photometric(&self) -> u16 {
+        match (self.bits, self.samples) {
+            (1, _) => 0,
+            (_, 1 | 2) => 1,
+            _ => 2,
+        }
+    }
+
+    /// Returns the bytes per row of `width` pixels; rows narrower than a byte are padded.
+    fn row_bytes(&self, width: u32) -> usize {
+        (width as usize * self.samples as usize * self.bits as usize).div_ceil(8)
+    }
+
+    /// Copies a chunk; tiles are padded to their full size, strips are cut at the image edge.
+    ///
+    /// `x` is a multiple of 16 for tiles, so chunks always start on a byte boundary.
+    fn chunk(&self, x: u32, y: u32, width: u32, height: u32, pad: bool) -> Vec<u8> {
+        let rows = if pad { height } else { height.min(self.height - y) };
+        let mut chunk = vec![0u8; self.row_bytes(width) * rows as usize];
+        let copy_width = self.row_bytes(width.min(self.width - x));
+        for row in 0..rows.min(self.height - y) as usize {
+            let src = (y as usize + row) * self.row_bytes(self.width) + self.row_bytes(x);
+            let dst = row * self.row_bytes(width);
+            chunk[dst..dst + copy_width].copy_from_slice(&self.bytes[src..src + copy_width]);
+        }
+        chunk
+    }
+
+    /// Applies the horizontal differencing predictor to every row of a chunk.
+    fn difference(&self, chunk: &mut [u8], width: u32) {
+        apply_predictor(chunk, self.row_bytes(width), self.samples as usize, self.bits, false);
+    }
+}
+
+fn le_bytes(samples: Vec<u16>) -> Vec<u8> {
+    samples.iter().flat_map(|s| s.to_le_bytes()).collect()
+}
+
+/// Applies (`undo == false`) or reverses predictor 2 on each row, per sample and in little-endian order.
+fn apply_predictor(chunk: &mut [u8], row_bytes: usize, samples: usize, bits: u16, undo: bool) {
+    for row in chunk.chunks_mut(row_bytes.max(1)) {
+        if bits == 16 {
+            let mut values: Vec<u16> = row.chunks_exact(2).map(|b| u16::from_le_bytes([b[0], b[1]])).collect();
+            difference_row(&mut values, samples, undo, u16::wrapping_sub, u16::wrapping_add);
+            for (bytes, value) in row.chunks_exact_mut(2).zip(values) {
+                bytes.copy_from_slice(&value.to_le_bytes());
+            }
+        } else {
+            difference_row(row, samples, undo, u8::wrapping_sub, u8::wrapping_add);
+        }
+    }
+}
+
+fn difference_row<T: Copy>(row: &mut [T], samples: usize, undo: bool, sub: fn(T, T) -> T, add: fn(T, T) -> T) {
+    if undo {
+        for i in samples..row.len() {
+            row[i] = add(row[i], row[i - samples]);
+        }
+    } else {
+        for i in (samples..row.len()).rev() {
+            row[i] = sub(row[i], row[i - samples]);
+        }
+    }
+}
+
+fn compress_chunk(
+    compression: TiffCompression,
+    chunk: &[u8],
+    width: u32,
+    rows: u32,
+    row_bytes: usize,
+) -> Result<Vec<u8>, CompressionError> {
+    if let Some(mode) = compression.ccitt_mode() {
+        return CcittCompressor::new(mode, width, rows).compress(chunk);
+    }
+    match compression {
+        TiffCompression::None => Ok(chunk.to_vec()),
+        TiffCompression::Lzw => Ok(LzwCompressor::new(4096).compress_tiff(chunk)),
+        TiffCompression::PackBits => Ok(chunk.chunks(row_bytes.max(1)).flat_map(pack_bits).collect()),
+        TiffCompression::Deflate => {
+            let deflate = DeflateCompressor::with_level_number(6)?.compress(chunk)?;
+            Ok(crate::compression::png::zlib_wrap(&deflate, chunk, 6))
+        }
+        TiffCompression::CcittGroup3 | TiffCompression::CcittGroup4 => unreachable!("handled above"),
+    }
+}
+
+fn decompress_chunk(
+    compression: TiffCompression,
+    fax_mode: CcittMode,
+    data: &[u8],
+    (width, rows): (u32, u32),
+    expected: usize,
+) -> Result<Vec<u8>, CompressionError> {
+    let mut chunk = match compression {
+        TiffCompression::CcittGroup3 | TiffCompression::CcittGroup4 => {
+            CcittCompressor::new(fax_mode, width, rows).decompress(data)?
+        }
+        TiffCompression::None => data.to_vec(),
+        TiffCompression::Lzw => LzwCompressor::new(4096).decompress_tiff(data, expected)?,
+        TiffCompression::PackBits => unpack_bits(data, expected),
+        TiffCompression::Deflate => {
+            let mut out = Vec::with_capacity(expected);
+            flate2::read::ZlibDecoder::new(data)
+                .take(expected as u64 + 1)
+                .read_to_end(&mut out)
+                .map_err(|e| CompressionError::Decompression(e.to_string()))?;
+            if out.len() > expected {
+                return Err(CompressionError::Decompression("TIFF chunk inflates past its size".to_string()));
+            }
+            out
+        }
+    };
+    chunk.resize(expected, 0);
+    Ok(chunk)
+}
+
+/// PackBits-encodes one row: literal runs of up to 128 bytes and repeats of 2-128 bytes.
+fn pack_bits(row: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(row.len() + row.len() / 128 + 1);
+    let mut i = 0;
+    while i < row.len() {
+        let run = row[i..].iter().take(128).take_while(|&&b| b == row[i]).count();
+        if run >= 2 {
+            out.push((257 - run) as u8);
+            out.push(row[i]);
+            i += run;
+            continue;
+        }
+        let start = i;
+        while i < row.len() && i - start < 128 && !(i + 1 < row.len() && row[i] == row[i + 1]) {
+            i += 1;
+        }
+        out.push((i - start - 1) as u8);
+        out.extend_from_slice(&row[start..i]);
+    }
+    out
+}
+
+fn unpack_bits(data: &[u8], expected: usize) -> Vec<u8> {
+    let mut out = Vec::with_capacity(expected);
+    let mut i = 0;
+    while i < data.len() && out.len() < expected {
+        let n = data[i] as i8;
+        i += 1;
+        if n >= 0 {
+            let end = (i + n as usize + 1).min(data.len());
+            out.extend_from_slice(&data[i..end]);
+            i = end;
+        } else if n != -128 {
+            if let Some(&byte) = data.get(i) {
+                out.extend(std::iter::repeat_n(byte, (1 - n as isize) as usize));
+            }
+            i += 1;
+        }
+    }
+    out
+}
+
+/// Converts a position in the file being written to a 32-bit TIFF offset.
+fn file_offset(len: usize) -> Result<u32, CompressionError> {
+    u32::try_from(len).map_err(|_| CompressionError::Compression("TIFF exceeds 4 GiB".to_string()))
+}
+
+fn decode_error(message: &str) -> CompressionError {
+    CompressionError::Decompression(message.to_string())
+}
+
+/// Byte-order aware accessors for a TIFF file.
+struct TiffData<'a> {
+    data: &'a [u8],
+    little_endian: bool,
+}
+
+impl TiffData<'_> {
+    fn u16_at(&self, pos: usize) -> Result<u16, CompressionError> {
+        let b = self.data.get(pos..pos + 2).ok_or_else(|| decode_error("truncated TIFF"))?;
+        Ok(if self.little_endian { u16::from_le_bytes([b[0], b[1]]) } else { u16::from_be_bytes([b[0], b[1]]) })
+    }
+
+    fn u32_at(&self, pos: usize) -> Result<u32, CompressionError> {
+        let b = self.data.get(pos..pos + 4).ok_or_else(|| decode_error("truncated TIFF"))?;
+        let b = [b[0], b[1], b[2], b[3]];
+        Ok(if self.little_endian { u32::from_le_bytes(b) } else { u32::from_be_bytes(b) })
+    }
+
+    fn u32_at_slice(&self, raw: &[u8], pos: usize) -> u32 {
+        let b = [raw[pos], raw[pos + 1], raw[pos + 2], raw[pos + 3]];
+        if self.little_endian { u32::from_le_bytes(b) } else { u32::from_be_bytes(b) }
+    }
+
+    /// Reads the values of the IFD entry at `pos` as integers, or its raw bytes for ASCII tags.
+    fn entry(&self, pos: usize) -> Result<(u16, Vec<u32>, Vec<u8>), CompressionError> {
+        let tag = self.u16_at(pos)?;
+        let kind = self.u16_at(pos + 2)?;
+        let count = self.u32_at(pos + 4)? as usize;
+        let size: usize = match kind {
+            1 | 2 | 6 | 7 => 1,
+            3 | 8 => 2,
+            4 | 9 => 4,
+            5 | 10 => 8,
+            _ => return Ok((tag, Vec::new(), Vec::new())),
+        };
+        let len = size.checked_mul(count).ok_or_else(|| decode_error("TIFF tag count is too large"))?;
+        let start = if len <= 4 { pos + 8 } else { self.u32_at(pos + 8)? as usize };
+        let raw = start
+            .checked_add(len)
+            .and_then(|end| self.data.get(start..end))
+            .ok_or_else(|| decode_error("TIFF tag points outside the file"))?
+            .to_vec();
+        let values = (0..count)
+            .map(|i| match size {
+                1 => Ok(raw[i] as u32),
+                2 => self.u16_at(start + 2 * i).map(u32::from),
+                4 => self.u32_at(start + 4 * i),
+                _ => Ok(self.u32_at(start + 8 * i)? / self.u32_at(start + 8 * i + 4)?.max(1)),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok((tag, values, raw))
+    }
+}
+
+/// Reads the first image of a TIFF file in a layout [`TiffWriter`] produces.
+///
+/// Baseline chunky gray and RGB files with 8 or 16 bits per sample and
+/// bilevel files are supported, in either byte order, with any of the
+/// writer's compressions. Bilevel images are returned as 8-bit grayscale.
+pub fn read_tiff(data: &[u8]) -> Result<TiffImage, CompressionError> {
+    let little_endian = match data.get(..4) {
+        Some(b"II\x2A\x00") => true,
+        Some(b"MM\x00\x2A") => false,
+        _ => return Err(decode_error("not a TIFF file")),
+    };
+    let tiff = TiffData { data, little_endian };
+    let ifd = tiff.u32_at(4)? as usize;
+    let entry_count = tiff.u16_at(ifd)? as usize;
+
+    let mut tags = std::collections::HashMap::new();
+    let mut metadata = TiffMetadata::default();
+    let mut resolution = None;
+    for i in 0..entry_count {
+        let (tag, values, raw) = tiff.entry(ifd + 2 + 12 * i)?;
+        let text = || Some(String::from_utf8_lossy(raw.split(|&b| b == 0).next().unwrap_or(&[])).into_owned());
+        match tag {
+            TAG_IMAGE_DESCRIPTION => metadata.description = text(),
+            TAG_SOFTWARE => metadata.software = text(),
+            TAG_DATE_TIME => metadata.date_time = text(),
+            TAG_ARTIST => metadata.artist = text(),
+            TAG_COPYRIGHT => metadata.copyright = text(),
+            TAG_X_RESOLUTION if raw.len() == 8 => {
+                resolution = Some((tiff.u32_at_slice(&raw, 0), tiff.u32_at_slice(&raw, 4)));
+            }
+            _ => {}
+        }
+        tags.insert(tag, values);
+    }
+    metadata.dpi = resolution;
+
+    let first = |tag: u16, default: Option<u32>| -> Result<u32, CompressionError> {
+        tags.get(&tag)
+            .and_then(|v| v.first().copied())
+            .or(default)
+            .ok_or_else(|| decode_error(&format!("missing TIFF tag {}", tag)))
+    };
+    let width = first(TAG_IMAGE_WIDTH, None)?;
+    let height = first(TAG_IMAGE_LENGTH, None)?;
+    if width == 0 || height == 0 {
+        return Err(decode_error("TIFF image has zero width or height"));
+    }
+    let samples = first(TAG_SAMPLES_PER_PIXEL, Some(1))? as usize;
+    let bits = first(TAG_BITS_PER_SAMPLE, Some(1))? as u16;
+    let compression = TiffCompression::from_code(first(TAG_COMPRESSION, Some(1))? as u16)?;
+    let predictor = first(TAG_PREDICTOR, Some(1))? == 2;
+    let supported = match bits {
+        1 => samples == 1,
+        8 | 16 => (1..=4).contains(&samples),
+        _ => false,
+    };
+    if first(TAG_PLANAR_CONFIGURATION, Some(1))? != 1 || !supported {
+        return Err(decode_error("unsupported TIFF sample layout"));
+    }
+    let fax_mode = match compression {
+        TiffCompression::CcittGroup3 if first(TAG_T4_OPTIONS, Some(0))? & 1 == 1 => CcittMode::Group3TwoDimensional,
+        TiffCompression::CcittGroup3 => CcittMode::Group3,
+        _ => CcittMode::Group4,
+    };
+    let row_bytes_of = |pixels: u32| (pixels as usize * samples * bits as usize).div_ceil(8);
+    let tiled = tags.contains_key(&TAG_TILE_OFFSETS);
+    let (chunk_w, chunk_h, offsets, counts) = if tiled {
+        (
+            first(TAG_TILE_WIDTH, None)?,
+            first(TAG_TILE_LENGTH, None)?,
+            &tags[&TAG_TILE_OFFSETS],
+            tags.get(&TAG_TILE_BYTE_COUNTS).ok_or_else(|| decode_error("missing tile byte counts"))?,
+        )
+    } else {
+        (
+            width,
+            first(TAG_ROWS_PER_STRIP, Some(height))?.min(height).max(1),
+            tags.get(&TAG_STRIP_OFFSETS).ok_or_else(|| decode_error("missing strip offsets"))?,
+            tags.get(&TAG_STRIP_BYTE_COUNTS).ok_or_else(|| decode_error("missing strip byte counts"))?,
+        )
+    };
+
+    if chunk_w == 0 || chunk_h == 0 {
+        return Err(decode_error("TIFF tiles have zero width or height"));
+    }
+    let chunk_len = checked_image_len(row_bytes_of(chunk_w), chunk_h as usize, 1)?;
+    let mut bytes = vec![0u8; checked_image_len(row_bytes_of(width), height as usize, 1)?];
+    let chunks_x = width.div_ceil(chunk_w);
+    for (index, (&offset, &count)) in offsets.iter().zip(counts).enumerate() {
+        let (cx, cy) = ((index as u32 % chunks_x) * chunk_w, (index as u32 / chunks_x) * chunk_h);
+        if cy >= height {
+            break;
+        }
+        let rows = if tiled { chunk_h } else { chunk_h.min(height - cy) };
+        let row_bytes = row_bytes_of(chunk_w);
+        let raw = (offset as usize)
+            .checked_add(count as usize)
+            .and_then(|end| data.get(offset as usize..end))
+            .ok_or_else(|| decode_error("TIFF chunk points outside the file"))?;
+        let expected = if tiled { chunk_len } else { row_bytes * rows as usize };
+        let mut chunk = decompress_chunk(compression, fax_mode, raw, (chunk_w, rows), expected)?;
+        if !little_endian && bits == 16 {
+            chunk.chunks_exact_mut(2).for_each(|b| b.swap(0, 1));
+        }
+        if predictor {
+            apply_predictor(&mut chunk, row_bytes, samples, bits, true);
+        }
+        let copy = row_bytes_of((width - cx).min(chunk_w));
+        for row in 0..rows.min(height - cy) as usize {
+            let dst = (cy as usize + row) * row_bytes_of(width) + row_bytes_of(cx);
+            bytes[dst..dst + copy].copy_from_slice(&chunk[row * row_bytes..row * row_bytes + copy]);
+        }
+    }
+
+    if bits == 1 && first(TAG_PHOTOMETRIC, Some(0))? == 1 {
+        bytes.iter_mut().for_each(|b| *b = !*b);
+    }
+    let image = build_image(width, height, samples, bits, bytes).ok_or_else(|| decode_error("TIFF pixel data is incomplete"))?;
+    Ok(TiffImage {
+        image,
+        compression,
+        predictor,
+        metadata,
+    })
+}
+
+fn build_image(width: u32, height: u32, samples: usize, bits: u16, bytes: Vec<u8>) -> Option<DynamicImage> {
+    if bits == 1 {
+        let bilevel = BilevelImage::from_packed(width, height, bytes).ok()?;
+        return Some(DynamicImage::ImageLuma8(bilevel.to_luma8()));
+    }
+    if bits == 8 {
+        return match samples {
+            1 => ImageBuffer::from_raw(width, height, bytes).map(DynamicImage::ImageLuma8),
+            2 => ImageBuffer::from_raw(width, height, bytes).map(DynamicImage::ImageLumaA8),
+            3 => ImageBuffer::from_raw(width, height, bytes).map(DynamicImage::ImageRgb8),
+            _ => ImageBuffer::from_raw(width, height, bytes).map(DynamicImage::ImageRgba8),
+        };
+    }
+    let words: Vec<u16> = bytes.chunks_exact(2).map(|b| u16::from_le_bytes([b[0], b[1]])).collect();
+    match samples {
+        1 => ImageBuffer::from_raw(width, height, words).map(DynamicImage::ImageLuma16),
+        2 => ImageBuffer::from_raw(width, height, words).map(DynamicImage::ImageLumaA16),
+        3 => ImageBuffer::from_raw(width, height, words).map(DynamicImage::ImageRgb16),
+        _ => ImageBuffer::from_raw(width, height, words).map(DynamicImage::ImageRgba16),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Luma, LumaA, Rgb, Rgba};
+    use std::io::Write;
+
+    fn test_images() -> Vec<DynamicImage> {
+        let noise = |x: u32, y: u32| (x.wrapping_mul(374_761_393) ^ y.wrapping_mul(668_265_263)).wrapping_mul(2_654_435_761) >> 28;
+        vec![
+            DynamicImage::ImageLuma8(ImageBuffer::from_fn(45, 37, |x, y| Luma([(x * 5 + y + noise(x, y)) as u8]))),
+            DynamicImage::ImageLumaA8(ImageBuffer::from_fn(17, 20, |x, y| LumaA([x as u8 * 9, y as u8 * 12]))),
+            DynamicImage::ImageRgb8(ImageBuffer::from_fn(50, 33, |x, y| Rgb([x as u8 * 5, y as u8 * 7, noise(x, y) as u8]))),
+            DynamicImage::ImageRgba8(ImageBuffer::from_fn(33, 50, |x, y| Rgba([x as u8, y as u8, 200, (x * y) as u8]))),
+            DynamicImage::ImageLuma16(ImageBuffer::from_fn(19, 23, |x, y| Luma([(x * 3000 + y * 7) as u16]))),
+            DynamicImage::ImageRgb16(ImageBuffer::from_fn(21, 18, |x, y| Rgb([(x * 3000) as u16, (y * 3001) as u16, 65535]))),
+            DynamicImage::ImageRgba16(ImageBuffer::from_fn(18, 21, |x, y| Rgba([(x * 999) as u16, 5, (y * 3001) as u16, 40000]))),
+        ]
+    }
+
+    fn writers() -> Vec<TiffWriter> {
+        let mut writers = Vec::new();
+        for compression in [TiffCompression::None, TiffCompression::Lzw, TiffCompression::PackBits, TiffCompression::Deflate] {
+            for predictor in [false, true] {
+                writers.push(TiffWriter::new().with_compression(compression).with_predictor(predictor));
+                writers.push(
+                    TiffWriter::new()
+                        .with_compression(compression)
+                        .with_predictor(predictor)
+                        .with_tiles(16, 32)
+                        .unwrap(),
+                );
+            }
+        }
+        writers.push(TiffWriter::new().with_strips(7).unwrap());
+        writers
+    }
+
+    #[test]
+    fn test_round_trip_with_own_reader_and_image_crate() {
+        for image in test_images() {
+            for writer in writers() {
+                let bytes = writer.encode(&image).unwrap();
+                let read = read_tiff(&bytes).unwrap();
+                assert_eq!(read.image, image, "{:?} {:?}", image.color(), writer);
+
+                // The image crate's TIFF decoder does not support gray with alpha.
+                if image.color() == image::ColorType::La8 {
+                    continue;
+                }
+                let decoded = image::load_from_memory_with_format(&bytes, image::ImageFormat::Tiff).unwrap();
+                assert_eq!(decoded.to_rgba16(), image.to_rgba16(), "{:?} {:?}", image.color(), writer);
+            }
+        }
+    }
+
+    #[test]
+    fn test_metadata_round_trip() {
+        let metadata = TiffMetadata {
+            description: Some("scan of page 3".to_string()),
+            software: Some("image_compression".to_string()),
+            artist: Some("A. Photographer".to_string()),
+            copyright: Some("(c) 2024".to_string()),
+            date_time: Some("2024:05:01 12:00:00".to_string()),
+            dpi: Some((300, 1)),
+        };
+        let image = test_images().remove(0);
+        let bytes = TiffWriter::new().with_metadata(metadata.clone()).encode(&image).unwrap();
+        let read = read_tiff(&bytes).unwrap();
+        assert_eq!(read.metadata, metadata);
+        assert_eq!(read.compression, TiffCompression::Lzw);
+    }
+
+    #[test]
+    fn test_compression_shrinks_smooth_images() {
+        let image = DynamicImage::ImageRgb8(ImageBuffer::from_fn(128, 128, |x, y| Rgb([x as u8, y as u8, (x + y) as u8])));
+        let raw = TiffWriter::new().with_compression(TiffCompression::None).encode(&image).unwrap().len();
+        for compression in [TiffCompression::Lzw, TiffCompression::Deflate] {
+            let size = TiffWriter::new().with_compression(compression).with_predictor(true).encode(&image).unwrap().len();
+            assert!(size * 4 < raw, "{:?}: {} vs {}", compression, size, raw);
+        }
+    }
+
+    #[test]
+    fn test_bilevel_round_trip() {
+        let mut page = BilevelImage::new(75, 41);
+        for y in 0..41 {
+            for x in 0..75 {
+                page.set(x, y, (x / 5 + y / 4) % 3 == 0 || x == y);
+            }
+        }
+        for mut writer in writers() {
+            for compression in [writer.get_compression(), TiffCompression::CcittGroup3, TiffCompression::CcittGroup4] {
+                writer = writer.with_compression(compression);
+                let read = read_tiff(&writer.encode_bilevel(&page).unwrap()).unwrap();
+                assert_eq!(read.image, DynamicImage::ImageLuma8(page.to_luma8()), "{:?}", writer);
+            }
+        }
+
+        // Grayscale input is binarized for fax compression.
+        let luma = page.to_luma8();
+        let gray = DynamicImage::ImageLuma8(ImageBuffer::from_fn(75, 41, |x, y| Luma([luma.get_pixel(x, y)[0] / 2 + 60])));
+        let writer = TiffWriter::new().with_compression(TiffCompression::CcittGroup4);
+        assert_eq!(read_tiff(&writer.encode(&gray).unwrap()).unwrap().image.to_luma8(), page.to_luma8());
+    }
+
+    #[test]
+    fn test_pack_bits() {
+        for row in [vec![], vec![1], vec![5; 300], (0..=255).collect(), vec![1, 1, 2, 3, 3, 3, 4]] {
+            assert_eq!(unpack_bits(&pack_bits(&row), row.len()), row);
+        }
+        assert!(TiffWriter::new().with_tiles(10, 16).is_err());
+        assert!(TiffWriter::new().with_strips(0).is_err());
+        assert!(read_tiff(b"not a tiff").is_err());
+    }
+
+    #[test]
+    fn test_hostile_headers_are_errors() {
+        let tiff = |entries: &[(u16, u16, u32, u32)]| {
+            let mut data = b"II\x2A\x00\x08\x00\x00\x00".to_vec();
+            data.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+            for &(tag, kind, count, value) in entries {
+                data.extend_from_slice(&tag.to_le_bytes());
+                data.extend_from_slice(&kind.to_le_bytes());
+                data.extend_from_slice(&count.to_le_bytes());
+                data.extend_from_slice(&value.to_le_bytes());
+            }
+            data.extend_from_slice(&[0; 64]);
+            data
+        };
+        let strips = [(TAG_STRIP_OFFSETS, TYPE_LONG, 1, 8), (TAG_STRIP_BYTE_COUNTS, TYPE_LONG, 1, 4)];
+        for (width, height) in [(0, 5), (5, 0), (u32::MAX, u32::MAX)] {
+            let mut entries = vec![(TAG_IMAGE_WIDTH, TYPE_LONG, 1, width), (TAG_IMAGE_LENGTH, TYPE_LONG, 1, height)];
+            entries.extend(strips);
+            assert!(read_tiff(&tiff(&entries)).is_err());
+        }
+        let tiles = [
+            (TAG_IMAGE_WIDTH, TYPE_LONG, 1, 5),
+            (TAG_IMAGE_LENGTH, TYPE_LONG, 1, 5),
+            (TAG_TILE_WIDTH, TYPE_LONG, 1, 0),
+            (TAG_TILE_LENGTH, TYPE_LONG, 1, 16),
+            (TAG_TILE_OFFSETS, TYPE_LONG, 1, 8),
+            (TAG_TILE_BYTE_COUNTS, TYPE_LONG, 1, 4),
+        ];
+        assert!(read_tiff(&tiff(&tiles)).is_err());
+        assert!(read_tiff(&tiff(&[(TAG_X_RESOLUTION, TYPE_RATIONAL, u32::MAX, u32::MAX)])).is_err());
+    }
+
+    #[test]
+    fn test_chunks_decoding_past_their_size_are_errors() {
+        let zeros = vec![0u8; 1 << 20];
+        let mut deflate = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::best());
+        deflate.write_all(&zeros).unwrap();
+        let deflate = deflate.finish().unwrap();
+        let lzw = LzwCompressor::new(4096).compress_tiff(&zeros);
+        for (compression, data) in [(TiffCompression::Deflate, &deflate), (TiffCompression::Lzw, &lzw)] {
+            let chunk = |expected| decompress_chunk(compression, CcittMode::Group4, data, (1024, 1024), expected);
+            assert!(chunk(16 * 16).is_err());
+            assert!(chunk(zeros.len() - 1).is_err());
+            assert_eq!(chunk(zeros.len()).unwrap(), zeros);
+        }
+    }
+    #[test]
+    fn test_file_offsets_past_4_gib_are_errors() {
+        assert_eq!(file_offset(u32::MAX as usize).unwrap(), u32::MAX);
+        assert!(file_offset(u32::MAX as usize + 1).is_err());
+    }
+}
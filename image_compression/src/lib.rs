@@ -1,10 +1,14 @@
 // src/lib.rs
 
+pub mod analysis;
+pub mod bench;
 pub mod compression;
 pub mod io;
 pub mod config;
 
 // Re-exporting for easier access
+pub use analysis::*;
+pub use bench::*;
 pub use compression::*;
 pub use io::*;
 pub use config::*;
@@ -1,44 +1,1183 @@
 // src/main.rs
 
-use clap::{Arg, Command};
+use clap::{value_parser, Arg, ArgAction, ArgMatches, Command};
+use image::{DynamicImage, ExtendedColorType};
+use image_compression::analysis::build_report;
+use image_compression::bench::baseline::{compare, Baseline};
+use image_compression::bench::report::{rate_distortion, spiht_rate_distortion, wavelet_preview_distortion, HtmlReport, SPIHT_PERCENTAGES};
+use image_compression::bench::{collect_images, BenchAlgorithm, BenchReport, Benchmark};
+use image_compression::compression::blocks::BlockCompressor;
+use image_compression::compression::ccitt::{Binarization, BilevelImage, CcittCompressor, CcittMode};
 use image_compression::compression::deflate::DeflateCompressor;
+use image_compression::compression::dictionary::{train, Dictionary};
+use image_compression::compression::gif::GifEncoder;
+use image_compression::compression::jpeg::{ChromaSubsampling, JpegEncoder};
+use image_compression::compression::jpeg_recompress::JpegRecompressor;
+use image_compression::compression::parallel::{ParallelCompressor, Partition};
+use image_compression::compression::png::PngOptimizer;
+use image_compression::compression::png_recompress::{PngRecompressor, ZlibEncoderKind};
+use image_compression::compression::progressive::{PassOrder, ProgressiveCompressor};
+use image_compression::compression::pyramid::{DownsampleFilter, PyramidCompressor};
+use image_compression::compression::auto::{AutoCompressor, AutoSelector};
+use image_compression::compression::stream::StreamingCompressor;
+use image_compression::compression::{CompressionAlgorithmType, CompressionError, Compressor};
+use image_compression::config::AppConfig;
+use image_compression::io::archive::{self, compact, Archive, ArchiveEditor};
 use image_compression::io::reader::read_image;
+use image_compression::io::rows::{copy_rows, ContainerRowReader, ContainerRowWriter, PngRowReader, PngRowWriter, RowReader, RowWriter};
+use image_compression::io::tiff::{TiffCompression, TiffWriter};
 use image_compression::io::writer::write_image;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::fmt;
+use std::path::Path;
+use std::time::Duration;
+use thiserror::Error;
 
-fn main() {
-    let matches = Command::new("Image Compression Tool")
+/// Prints log records to stderr; installed with `--verbose`.
+struct StderrLogger;
+
+impl log::Log for StderrLogger {
+    fn enabled(&self, _metadata: &log::Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &log::Record) {
+        eprintln!("[{}] {}", record.level(), record.args());
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: StderrLogger = StderrLogger;
+
+/// Header of files written with `--tagged`: magic, algorithm id, pixel format, width and height.
+const IMAGE_MAGIC: &[u8; 4] = b"IMG\x01";
+const IMAGE_HEADER_LEN: usize = 14;
+/// Algorithm id recorded for `auto`, whose output carries its own header.
+const AUTO_ID: u8 = 0xFF;
+const FORMAT_RGB8: u8 = 0;
+/// Packed 1-bit rows, see [`BilevelImage::from_packed`].
+const FORMAT_BILEVEL: u8 = 1;
+
+/// An error ending the program, printed to stderr.
+#[derive(Debug, Error)]
+enum CliError {
+    /// The command line asks for something that cannot be done; exits with status 2 like clap.
+    #[error("{0}")]
+    Usage(String),
+    /// Reading, coding or writing failed; exits with status 1.
+    #[error("{0}")]
+    Failed(String),
+}
+
+impl CliError {
+    /// Wraps an error caused by the options given.
+    fn usage(error: impl fmt::Display) -> Self {
+        CliError::Usage(error.to_string())
+    }
+
+    fn exit_code(&self) -> i32 {
+        match self {
+            CliError::Usage(_) => 2,
+            CliError::Failed(_) => 1,
+        }
+    }
+}
+
+impl From<CompressionError> for CliError {
+    fn from(error: CompressionError) -> Self {
+        CliError::Failed(error.to_string())
+    }
+}
+
+/// Turns any error into a [`CliError::Failed`] saying what was being done.
+trait Context<T> {
+    fn context(self, what: &str) -> Result<T, CliError>;
+}
+
+impl<T, E: fmt::Display> Context<T> for Result<T, E> {
+    fn context(self, what: &str) -> Result<T, CliError> {
+        self.map_err(|e| CliError::Failed(format!("{}: {}", what, e)))
+    }
+}
+
+/// Builds the header written before the compressed pixels.
+fn image_header(id: u8, format: u8, width: u32, height: u32) -> Vec<u8> {
+    let mut header = Vec::with_capacity(IMAGE_HEADER_LEN);
+    header.extend_from_slice(IMAGE_MAGIC);
+    header.extend_from_slice(&[id, format]);
+    header.extend_from_slice(&width.to_le_bytes());
+    header.extend_from_slice(&height.to_le_bytes());
+    header
+}
+
+/// Splits a file written with `--tagged` into its algorithm id, pixel format, geometry and payload.
+fn parse_image_header(data: &[u8]) -> Result<(u8, u8, u32, u32, &[u8]), CompressionError> {
+    if data.len() < IMAGE_HEADER_LEN || &data[..4] != IMAGE_MAGIC || data[5] > FORMAT_BILEVEL {
+        return Err(CompressionError::Decompression("Not a file compressed with --tagged".to_string()));
+    }
+    let width = u32::from_le_bytes(data[6..10].try_into().unwrap());
+    let height = u32::from_le_bytes(data[10..14].try_into().unwrap());
+    Ok((data[4], data[5], width, height, &data[IMAGE_HEADER_LEN..]))
+}
+
+/// Builds a subcommand that maps one input file to one output file.
+fn file_command(name: &'static str, about: &'static str) -> Command {
+    Command::new(name)
+        .about(about)
+        .arg(Arg::new("input")
+            .short('i')
+            .long("input")
+            .required(true)
+            .help("Input file"))
+        .arg(Arg::new("output")
+            .short('o')
+            .long("output")
+            .required(true)
+            .help("Output file"))
+}
+
+/// Builds a subcommand that streams one file through an algorithm.
+fn stream_command(name: &'static str, about: &'static str) -> Command {
+    file_command(name, about)
+        .arg(Arg::new("algorithm")
+            .short('a')
+            .long("algorithm")
+            .default_value("deflate")
+            .help("Algorithm; deflate and lzw stream, others buffer the whole file"))
+        .arg(Arg::new("level")
+            .short('l')
+            .long("level")
+            .value_parser(value_parser!(u32).range(0..=9))
+            .help("Compression level for algorithms that take one"))
+        .arg(dictionary_arg())
+}
+
+/// Builds the option naming a preset dictionary written by train-dict.
+fn dictionary_arg() -> Arg {
+    Arg::new("dict")
+        .long("dict")
+        .help("Preset dictionary written by train-dict, for deflate and lzw; decompression needs the same one")
+}
+
+/// Loads the dictionary named by the `dict` option, if given.
+fn load_dictionary(sub: &ArgMatches) -> Result<Option<Dictionary>, CliError> {
+    sub.get_one::<String>("dict")
+        .map(|path| Dictionary::load(Path::new(path)).context("Failed to read dictionary"))
+        .transpose()
+}
+
+/// Builds a subcommand that compresses or restores an image in independently compressed parts.
+fn part_command(name: &'static str, about: &'static str) -> Command {
+    file_command(name, about)
+        .arg(Arg::new("algorithm")
+            .short('a')
+            .long("algorithm")
+            .default_value("deflate")
+            .value_parser(["deflate", "lzw", "wavelet", "spiht"])
+            .help("Algorithm applied to each part"))
+        .arg(Arg::new("level")
+            .short('l')
+            .long("level")
+            .value_parser(value_parser!(u32).range(0..=9))
+            .help("Deflate compression level"))
+        .arg(dictionary_arg())
+}
+
+/// Builds the option setting the number of worker threads.
+fn threads_arg() -> Arg {
+    Arg::new("threads")
+        .short('t')
+        .long("threads")
+        .default_value("0")
+        .value_parser(value_parser!(usize))
+        .help("Worker threads, 0 for one per CPU; the output does not depend on it")
+}
+
+/// Creates the algorithm chosen by the `algorithm` and `level` options, configured for a `width` x `height` image.
+fn part_algorithm(sub: &ArgMatches, width: u32, height: u32, channels: u8) -> Result<CompressionAlgorithmType, CompressionError> {
+    match sub.get_one::<String>("algorithm").unwrap().as_str() {
+        "wavelet" => CompressionAlgorithmType::for_block(2, width, height, channels),
+        "spiht" => CompressionAlgorithmType::for_block(3, width, height, channels),
+        name => CompressionAlgorithmType::create(name, sub.get_one::<u32>("level").copied()),
+    }
+}
+
+/// Returns the identifier of the algorithm chosen by the `algorithm` option of a part subcommand.
+fn part_algorithm_id(sub: &ArgMatches) -> u8 {
+    CompressionAlgorithmType::id_of(sub.get_one::<String>("algorithm").unwrap()).unwrap()
+}
+
+/// The part compressor returned by the factories of part subcommands.
+type PartResult = Result<Box<dyn Compressor + Send + Sync>, CompressionError>;
+
+/// Returns a factory building the part compressor chosen by the options of a part subcommand.
+fn part_factory(sub: &ArgMatches) -> Result<impl Fn(u32, u32, u8) -> PartResult + Send + Sync + 'static, CliError> {
+    let sub = sub.clone();
+    let dictionary = load_dictionary(&sub)?;
+    Ok(move |w, h, c| with_dictionary(part_algorithm(&sub, w, h, c)?, &dictionary))
+}
+
+/// Returns a factory building the part compressor with identifier `id` recorded in a container header.
+fn decoding_factory(
+    sub: &ArgMatches,
+    id: u8,
+) -> Result<impl Fn(u32, u32, u8) -> PartResult + Send + Sync + 'static, CliError> {
+    let dictionary = load_dictionary(sub)?;
+    Ok(move |w, h, c| with_dictionary(CompressionAlgorithmType::for_block(id, w, h, c)?, &dictionary))
+}
+
+/// Boxes a part compressor, primed with the `--dict` dictionary if one was given.
+fn with_dictionary(algorithm: CompressionAlgorithmType, dictionary: &Option<Dictionary>) -> PartResult {
+    Ok(Box::new(match dictionary {
+        Some(dictionary) => algorithm.with_dictionary(dictionary.clone())?,
+        None => algorithm,
+    }))
+}
+
+/// Creates a parallel compressor for the algorithm and thread options of compress-parallel.
+fn parallel_compressor(sub: &ArgMatches, width: u32, height: u32, channels: u8) -> Result<ParallelCompressor, CliError> {
+    ParallelCompressor::with_factory(width, height, channels, part_factory(sub)?)
+        .with_algorithm_id(part_algorithm_id(sub))
+        .with_threads(*sub.get_one::<usize>("threads").unwrap())
+        .context("Failed to start thread pool")
+}
+
+/// Parses a region given as `X,Y,W,H`.
+fn parse_region(value: &str) -> Result<[u32; 4], String> {
+    let numbers = value
+        .split(',')
+        .map(|n| n.trim().parse::<u32>().map_err(|e| format!("{}: {}", n, e)))
+        .collect::<Result<Vec<_>, _>>()?;
+    numbers.try_into().map_err(|_| "expected four numbers X,Y,W,H".to_string())
+}
+
+/// Creates the algorithm named `name` for the pixels of a `width` x `height` image stored in `format`.
+///
+/// Image codecs are configured for the image; the others take the compression level. The
+/// recompressors are rejected, since they only save space on the original file.
+fn pixel_algorithm(
+    name: &str,
+    level: Option<u32>,
+    width: u32,
+    height: u32,
+    format: u8,
+) -> Result<CompressionAlgorithmType, CompressionError> {
+    let name = name.to_lowercase();
+    match (name.as_str(), format) {
+        ("deflate", _) if level.is_none() => Ok(CompressionAlgorithmType::Deflate(DeflateCompressor::new())),
+        ("wavelet" | "spiht", FORMAT_RGB8) | ("ccitt", FORMAT_BILEVEL) => {
+            let channels = if format == FORMAT_BILEVEL { 1 } else { 3 };
+            CompressionAlgorithmType::for_block(CompressionAlgorithmType::id_of(&name).unwrap(), width, height, channels)
+        }
+        ("wavelet" | "spiht", _) => Err(CompressionError::UnknownAlgorithm(format!(
+            "{} for black and white images; omit -a to fax-code them",
+            name
+        ))),
+        ("ccitt", _) => Err(CompressionError::UnknownAlgorithm("ccitt for images that are not black and white".to_string())),
+        // These pack the original file, so decoded pixels would only be stored with Deflate
+        ("jpeg-recompress", _) => Err(CompressionError::UnknownAlgorithm(
+            "jpeg-recompress for decoded pixels; use recompress-jpeg to pack a JPEG file".to_string(),
+        )),
+        ("png-recompress", _) => Err(CompressionError::UnknownAlgorithm(
+            "png-recompress for decoded pixels; use recompress-png to pack a PNG file".to_string(),
+        )),
+        (name, _) => CompressionAlgorithmType::create(name, level),
+    }
+}
+
+/// Returns the pixels of an image as interleaved 8-bit gray, RGB or RGBA, and the channel count.
+fn interleaved(image: &image::DynamicImage) -> (Vec<u8>, u8) {
+    if image.color().has_alpha() {
+        (image.to_rgba8().into_raw(), 4)
+    } else if image.color().has_color() {
+        (image.to_rgb8().into_raw(), 3)
+    } else {
+        (image.to_luma8().into_raw(), 1)
+    }
+}
+
+/// Returns the 8-bit color type with `channels` interleaved channels.
+fn color_type(channels: u8) -> ExtendedColorType {
+    match channels {
+        1 => ExtendedColorType::L8,
+        2 => ExtendedColorType::La8,
+        3 => ExtendedColorType::Rgb8,
+        _ => ExtendedColorType::Rgba8,
+    }
+}
+
+/// Builds the command line: the top-level options compress an image, the subcommands do the rest.
+fn cli() -> Command {
+    Command::new("Image Compression Tool")
         .version("0.1.0")
         .author("Your Name <you@example.com>")
-        .about("Compresses images losslessly")
+        .about("Compresses images losslessly, or as baseline JPEG with --quality")
         .arg(Arg::new("input")
             .short('i')
             .long("input")
-            .takes_value(true)
             .required(true)
             .help("Input image file"))
         .arg(Arg::new("output")
             .short('o')
             .long("output")
-            .takes_value(true)
             .required(true)
             .help("Output compressed file"))
-        .get_matches();
+        .arg(Arg::new("quality")
+            .short('q')
+            .long("quality")
+            .value_parser(value_parser!(u8).range(1..=100))
+            .help("Encode a baseline JPEG with this quality (1-100) instead of lossless Deflate"))
+        .arg(Arg::new("subsampling")
+            .long("subsampling")
+            .default_value("420")
+            .requires("quality")
+            .help("JPEG chroma subsampling: 444, 422 or 420"))
+        .arg(Arg::new("algorithm")
+            .short('a')
+            .long("algorithm")
+            .conflicts_with("quality")
+            .help("Lossless algorithm (deflate, lzw, ...) or \"auto\" to choose by trial compression [default: deflate]"))
+        .arg(Arg::new("level")
+            .short('l')
+            .long("level")
+            .value_parser(value_parser!(u32).range(0..=9))
+            .help("Compression level for algorithms that take one"))
+        .arg(Arg::new("time-budget")
+            .long("time-budget")
+            .value_parser(value_parser!(u64))
+            .help("Milliseconds \"auto\" may spend on trial compression [default: 200]"))
+        .arg(Arg::new("tagged")
+            .long("tagged")
+            .action(ArgAction::SetTrue)
+            .conflicts_with("quality")
+            .help("Write a header with the algorithm and image size so decompress can restore the image; black and white images are then stored at 1 bit per pixel and fax coded unless -a is given"))
+        .arg(Arg::new("config")
+            .short('c')
+            .long("config")
+            .help("Configuration file providing compression_algorithm, compression_level and time_budget_ms"))
+        .arg(Arg::new("verbose")
+            .short('v')
+            .long("verbose")
+            .global(true)
+            .action(ArgAction::SetTrue)
+            .help("Log progress and decisions to stderr"))
+        .args_conflicts_with_subcommands(true)
+        .subcommand_negates_reqs(true)
+        .subcommand(file_command("decompress", "Restores an image compressed with --tagged"))
+        .subcommand(file_command("recompress-jpeg", "Losslessly shrinks an existing JPEG file"))
+        .subcommand(file_command("restore-jpeg", "Restores the original JPEG from a recompressed file"))
+        .subcommand(file_command("recompress-png", "Packs a PNG file so the identical file can be restored"))
+        .subcommand(file_command("restore-png", "Restores the original PNG from a recompressed file"))
+        .subcommand(file_command("encode-gif", "Encodes an image as a GIF, quantizing it to a palette")
+            .arg(Arg::new("colors")
+                .long("colors")
+                .default_value("256")
+                .value_parser(value_parser!(u16).range(2..=256))
+                .help("Maximum palette size"))
+            .arg(Arg::new("interlace")
+                .long("interlace")
+                .action(ArgAction::SetTrue)
+                .help("Write an interlaced GIF")))
+        .subcommand(file_command("encode-tiff", "Encodes an image as a TIFF file")
+            .arg(Arg::new("compression")
+                .long("compression")
+                .default_value("lzw")
+                .value_parser(["none", "lzw", "packbits", "deflate", "ccitt-g3", "ccitt-g4"])
+                .help("TIFF compression scheme"))
+            .arg(Arg::new("threshold")
+                .long("threshold")
+                .value_parser(value_parser!(u8))
+                .help("Gray level below which pixels become black for CCITT compression (default: Otsu)"))
+            .arg(Arg::new("tile")
+                .long("tile")
+                .value_parser(value_parser!(u32))
+                .help("Store square tiles of this size (a multiple of 16) instead of strips"))
+            .arg(Arg::new("predictor")
+                .long("predictor")
+                .action(ArgAction::SetTrue)
+                .help("Apply the horizontal differencing predictor")))
+        .subcommand(file_command("compress-blocks", "Compresses an image choosing the best algorithm per block")
+            .arg(Arg::new("block-size")
+                .long("block-size")
+                .default_value("64")
+                .value_parser(value_parser!(u16).range(1..))
+                .help("Block edge length in pixels")))
+        .subcommand(file_command("decompress-blocks", "Restores an image compressed with compress-blocks"))
+        .subcommand(part_command("compress-parallel", "Compresses an image as independent strips or tiles on all cores")
+            .arg(threads_arg())
+            .arg(Arg::new("strip-rows")
+                .long("strip-rows")
+                .default_value("64")
+                .value_parser(value_parser!(u32).range(1..))
+                .help("Rows per strip"))
+            .arg(Arg::new("tile")
+                .long("tile")
+                .value_parser(value_parser!(u32).range(1..))
+                .conflicts_with("strip-rows")
+                .help("Use square tiles of this size instead of strips"))
+            .arg(Arg::new("pyramid")
+                .long("pyramid")
+                .action(ArgAction::SetTrue)
+                .help("Also store downsampled levels, each half the size of the one before"))
+            .arg(Arg::new("filter")
+                .long("filter")
+                .default_value("box")
+                .value_parser(["nearest", "box", "triangle", "lanczos3"])
+                .requires("pyramid")
+                .help("Downsampling filter for pyramid levels"))
+            .arg(Arg::new("levels")
+                .long("levels")
+                .default_value("255")
+                .value_parser(value_parser!(u8).range(1..))
+                .requires("pyramid")
+                .help("Maximum number of pyramid levels, including full resolution")))
+        .subcommand(file_command("decompress-parallel", "Restores an image written by compress-parallel")
+            .arg(dictionary_arg())
+            .arg(threads_arg())
+            .arg(Arg::new("crop")
+                .long("crop")
+                .value_name("X,Y,W,H")
+                .value_parser(parse_region)
+                .help("Decode only this region, reading just the tiles it touches"))
+            .arg(Arg::new("zoom")
+                .long("zoom")
+                .default_value("0")
+                .value_parser(value_parser!(u8))
+                .help("Pyramid level to decode, 0 being full resolution; --crop is in that level's pixels")))
+        .subcommand(part_command("compress-progressive", "Compresses an image in interlaced passes so a truncated file still decodes")
+            .arg(Arg::new("levels")
+                .long("levels")
+                .value_parser(value_parser!(u8).range(1..=15))
+                .help("Use a hierarchical order starting with one pixel in 4^levels instead of Adam7")))
+        .subcommand(file_command("decompress-progressive", "Restores the best image available from a possibly truncated compress-progressive file")
+            .arg(dictionary_arg())
+            .arg(Arg::new("bytes")
+                .long("bytes")
+                .value_parser(value_parser!(usize))
+                .help("Only use the first this many bytes, to preview a partial download")))
+        .subcommand(file_command("optimize-png", "Re-encodes an image as the smallest lossless PNG found"))
+        .subcommand(file_command("convert", "Converts between PNG and the row container one scanline at a time")
+            .arg(Arg::new("algorithm")
+                .short('a')
+                .long("algorithm")
+                .default_value("deflate")
+                .value_parser(["deflate", "lzw"])
+                .help("Compression of row container output"))
+            .arg(Arg::new("level")
+                .short('l')
+                .long("level")
+                .default_value("6")
+                .value_parser(value_parser!(u32).range(0..=9))
+                .help("Deflate level for PNG and row container output")))
+        .subcommand(stream_command("compress-file", "Compresses any file, streaming Deflate and LZW in bounded memory"))
+        .subcommand(stream_command("decompress-file", "Restores a file written by compress-file with the same algorithm"))
+        .subcommand(Command::new("pack")
+            .about("Packs images into one archive with a central index")
+            .arg(Arg::new("output")
+                .short('o')
+                .long("output")
+                .required(true)
+                .help("Archive file to create"))
+            .arg(Arg::new("algorithm")
+                .short('a')
+                .long("algorithm")
+                .default_value("deflate")
+                .value_parser(["deflate", "lzw", "wavelet", "spiht"])
+                .help("Algorithm applied to each image"))
+            .arg(Arg::new("level")
+                .short('l')
+                .long("level")
+                .value_parser(value_parser!(u32).range(0..=9))
+                .help("Deflate compression level"))
+            .arg(Arg::new("update")
+                .long("update")
+                .action(ArgAction::SetTrue)
+                .help("Add to an existing archive, replacing entries of the same name"))
+            .arg(Arg::new("inputs")
+                .required(true)
+                .num_args(1..)
+                .help("Image files, or directories searched recursively; entries are named by path relative to the directory")))
+        .subcommand(Command::new("train-dict")
+            .about("Trains a preset dictionary on the pixels of a corpus of small, similar images")
+            .arg(Arg::new("dir")
+                .short('d')
+                .long("dir")
+                .required(true)
+                .help("Directory searched recursively for images"))
+            .arg(Arg::new("output")
+                .short('o')
+                .long("output")
+                .required(true)
+                .help("Dictionary file to write"))
+            .arg(Arg::new("size")
+                .long("size")
+                .default_value("32768")
+                .value_parser(value_parser!(usize))
+                .help("Maximum dictionary size in bytes; Deflate uses at most the last 32768")))
+        .subcommand(Command::new("compact")
+            .about("Rewrites an archive without the space left by replaced entries")
+            .arg(Arg::new("input")
+                .short('i')
+                .long("input")
+                .required(true)
+                .help("Archive file")))
+        .subcommand(Command::new("list")
+            .about("Lists the entries of an archive")
+            .arg(Arg::new("input")
+                .short('i')
+                .long("input")
+                .required(true)
+                .help("Archive file")))
+        .subcommand(Command::new("extract")
+            .about("Extracts one image from an archive, reading only that entry")
+            .arg(Arg::new("input")
+                .short('i')
+                .long("input")
+                .required(true)
+                .help("Archive file"))
+            .arg(Arg::new("name")
+                .required(true)
+                .help("Entry name, as shown by list"))
+            .arg(Arg::new("output")
+                .short('o')
+                .long("output")
+                .help("Output image [default: the entry's file name]")))
+        .subcommand(Command::new("analyze")
+            .about("Reports image statistics, estimated sizes per algorithm and a recommended configuration")
+            .arg(Arg::new("input")
+                .short('i')
+                .long("input")
+                .required(true)
+                .help("Input image"))
+            .arg(Arg::new("json")
+                .long("json")
+                .action(ArgAction::SetTrue)
+                .help("Print the report as JSON"))
+            .arg(Arg::new("time-budget")
+                .long("time-budget")
+                .default_value("1000")
+                .value_parser(value_parser!(u64))
+                .help("Milliseconds to spend on trial compression")))
+        .subcommand(Command::new("bench")
+            .about("Benchmarks compressors over every image in a directory")
+            .arg(Arg::new("dir")
+                .short('d')
+                .long("dir")
+                .required(true)
+                .help("Directory searched recursively for images"))
+            .arg(Arg::new("algorithms")
+                .short('a')
+                .long("algorithms")
+                .value_delimiter(',')
+                .help("Comma-separated algorithms such as deflate:9,lzw:4096,spiht [default: deflate:1,6,9, lzw:4096,65536, wavelet, spiht, ccitt; add auto explicitly]"))
+            .arg(Arg::new("warmup")
+                .long("warmup")
+                .default_value("1")
+                .value_parser(value_parser!(usize))
+                .help("Unmeasured runs before timing"))
+            .arg(Arg::new("repetitions")
+                .short('r')
+                .long("repetitions")
+                .default_value("5")
+                .value_parser(value_parser!(usize))
+                .help("Timed runs per image and algorithm"))
+            .arg(Arg::new("format")
+                .short('f')
+                .long("format")
+                .default_value("table")
+                .value_parser(["table", "csv", "json"])
+                .help("Output format"))
+            .arg(Arg::new("output")
+                .short('o')
+                .long("output")
+                .help("Write the report to this file instead of stdout"))
+            .arg(Arg::new("baseline-dir")
+                .long("baseline-dir")
+                .default_value("bench-baselines")
+                .help("Directory holding named baselines"))
+            .arg(Arg::new("save-baseline")
+                .long("save-baseline")
+                .help("Save this run as the named baseline"))
+            .arg(Arg::new("compare")
+                .long("compare")
+                .help("Compare this run with the named baseline and exit with status 1 on regressions"))
+            .arg(Arg::new("speed-tolerance")
+                .long("speed-tolerance")
+                .default_value("5")
+                .value_parser(value_parser!(f64))
+                .help("Percent throughput drop ignored even when statistically significant")))
+        .subcommand(Command::new("report")
+            .about("Renders benchmark results as a self-contained HTML file")
+            .arg(Arg::new("results")
+                .short('i')
+                .long("results")
+                .required(true)
+                .help("JSON written by bench --format json, or a saved baseline"))
+            .arg(Arg::new("dir")
+                .short('d')
+                .long("dir")
+                .help("Corpus directory; adds thumbnails and JPEG, SPIHT and wavelet preview rate-distortion curves"))
+            .arg(Arg::new("qualities")
+                .long("qualities")
+                .value_delimiter(',')
+                .default_value("10,25,50,75,90,95")
+                .value_parser(value_parser!(u8).range(1..=100))
+                .help("JPEG qualities sampled for rate-distortion curves"))
+            .arg(Arg::new("title")
+                .long("title")
+                .default_value("Compression benchmark")
+                .help("Page title"))
+            .arg(Arg::new("output")
+                .short('o')
+                .long("output")
+                .required(true)
+                .help("Output HTML file")))
+}
+
+fn main() {
+    let matches = cli().get_matches();
+    if matches.get_flag("verbose") && log::set_logger(&LOGGER).is_ok() {
+        log::set_max_level(log::LevelFilter::Debug);
+    }
+
+    let result = match matches.subcommand() {
+        Some(("bench", sub)) => bench(sub),
+        Some(("pack", sub)) => pack(sub),
+        Some(("train-dict", sub)) => train_dict(sub),
+        Some(("compact", sub)) => compact_archive(sub),
+        Some(("list", sub)) => list(sub),
+        Some(("extract", sub)) => extract(sub),
+        Some(("report", sub)) => report(sub),
+        Some(("convert", sub)) => convert(sub),
+        Some((name @ ("compress-file" | "decompress-file"), sub)) => stream_file(name == "compress-file", sub),
+        Some(("analyze", sub)) => analyze(sub),
+        Some(("decompress", sub)) => decompress(sub),
+        Some(("decompress-blocks", sub)) => decompress_blocks(sub),
+        Some(("decompress-parallel", sub)) => decompress_parallel(sub),
+        Some(("decompress-progressive", sub)) => decompress_progressive(sub),
+        Some((name, sub)) => transform_file(name, sub),
+        None => compress(&matches),
+    };
+    if let Err(e) = result {
+        eprintln!("error: {}", e);
+        std::process::exit(e.exit_code());
+    }
+}
+
+fn bench(sub: &ArgMatches) -> Result<(), CliError> {
+    let mut benchmark = Benchmark::new()
+        .with_warmup(*sub.get_one::<usize>("warmup").unwrap())
+        .with_repetitions(*sub.get_one::<usize>("repetitions").unwrap())
+        .map_err(CliError::usage)?;
+    if let Some(specs) = sub.get_many::<String>("algorithms") {
+        let algorithms = specs.map(|spec| BenchAlgorithm::parse(spec)).collect::<Result<Vec<_>, _>>().map_err(CliError::usage)?;
+        benchmark = benchmark.with_algorithms(algorithms).map_err(CliError::usage)?;
+    }
+    let dir = Path::new(sub.get_one::<String>("dir").unwrap());
+    let files = collect_images(dir).context("Failed to read directory")?;
+    let report = benchmark.run(&files, dir).context("Benchmark failed")?;
+    let rendered = match sub.get_one::<String>("format").unwrap().as_str() {
+        "csv" => report.to_csv(),
+        "json" => serde_json::to_string_pretty(&report).context("Failed to serialize report")?,
+        _ => report.to_string(),
+    };
+    match sub.get_one::<String>("output") {
+        Some(path) => fs::write(path, rendered).context("Failed to write report")?,
+        None => println!("{}", rendered.trim_end()),
+    }
+
+    let baseline_dir = Path::new(sub.get_one::<String>("baseline-dir").unwrap());
+    let comparison = match sub.get_one::<String>("compare") {
+        Some(name) => {
+            let baseline = Baseline::load(baseline_dir, name).context("Failed to load baseline")?;
+            Some(compare(&baseline, &report, sub.get_one::<f64>("speed-tolerance").unwrap() / 100.0))
+        }
+        None => None,
+    };
+    if let Some(name) = sub.get_one::<String>("save-baseline") {
+        let path = Baseline::new(name, report).save(baseline_dir).context("Failed to save baseline")?;
+        eprintln!("Saved baseline to {}", path.display());
+    }
+    if let Some(comparison) = comparison {
+        eprintln!("{}", comparison);
+        if comparison.has_regressions() {
+            return Err(CliError::Failed("the benchmark regressed against the baseline".to_string()));
+        }
+    }
+    Ok(())
+}
+
+fn pack(sub: &ArgMatches) -> Result<(), CliError> {
+    let archive_path = sub.get_one::<String>("output").unwrap();
+    let mut images = Vec::new();
+    for input in sub.get_many::<String>("inputs").unwrap() {
+        let path = Path::new(input);
+        let (files, root) = if path.is_dir() {
+            (collect_images(path).context("Failed to read directory")?, path)
+        } else {
+            (vec![path.to_path_buf()], path.parent().unwrap_or(Path::new("")))
+        };
+        for file in files {
+            let name = file.strip_prefix(root).unwrap_or(&file).to_string_lossy().replace('\\', "/");
+            images.push((name, file));
+        }
+    }
+    // Runs inside archive::create, so failures are reported as a CompressionError
+    let encode = |file: &Path| {
+        let image = read_image(file)
+            .map_err(|e| CompressionError::Compression(format!("Failed to read {}: {}", file.display(), e)))?;
+        let (pixels, channels) = interleaved(&image);
+        let algorithm = part_algorithm(sub, image.width(), image.height(), channels)?;
+        Ok::<_, CompressionError>((image.width(), image.height(), channels, algorithm, pixels))
+    };
+
+    if sub.get_flag("update") {
+        let file = File::options().read(true).write(true).open(archive_path).context("Failed to open archive")?;
+        let mut editor = ArchiveEditor::open(file).context("Failed to read archive")?;
+        for (name, file) in &images {
+            let (width, height, channels, algorithm, pixels) = encode(file)?;
+            let entry = editor.replace(name, width, height, channels, &algorithm, &pixels).context("Failed to add image")?;
+            println!("{}", entry);
+        }
+        let file = editor.commit().context("Failed to update archive")?;
+        let editor = ArchiveEditor::open(file).context("Failed to read archive")?;
+        println!("Archive holds {} images, {} bytes reclaimable with compact", editor.entries().len(), editor.dead_space());
+    } else {
+        let count = archive::create(Path::new(archive_path), |writer| {
+            for (name, file) in &images {
+                let (width, height, channels, algorithm, pixels) = encode(file)?;
+                println!("{}", writer.add(name, width, height, channels, &algorithm, &pixels)?);
+            }
+            Ok(writer.entries().len())
+        })
+        .context("Failed to write archive")?;
+        println!("Packed {} images", count);
+    }
+    Ok(())
+}
+
+fn train_dict(sub: &ArgMatches) -> Result<(), CliError> {
+    let files = collect_images(Path::new(sub.get_one::<String>("dir").unwrap())).context("Failed to read directory")?;
+    let samples = files
+        .iter()
+        .map(|file| Ok(interleaved(&read_image(file).context("Failed to read image")?).0))
+        .collect::<Result<Vec<Vec<u8>>, CliError>>()?;
+    let dictionary = train(&samples, *sub.get_one::<usize>("size").unwrap()).context("Training failed")?;
+    dictionary.save(Path::new(sub.get_one::<String>("output").unwrap())).context("Failed to write dictionary")?;
+    println!("{} trained on {} images", dictionary, samples.len());
+    Ok(())
+}
+
+fn compact_archive(sub: &ArgMatches) -> Result<(), CliError> {
+    let (before, after) = compact(Path::new(sub.get_one::<String>("input").unwrap())).context("Failed to compact archive")?;
+    println!("{} -> {} bytes", before, after);
+    Ok(())
+}
+
+/// Opens the archive named by the `input` option.
+fn open_archive(sub: &ArgMatches) -> Result<Archive<BufReader<File>>, CliError> {
+    let file = BufReader::new(File::open(sub.get_one::<String>("input").unwrap()).context("Failed to open archive")?);
+    Archive::open(file).context("Failed to read archive")
+}
+
+fn list(sub: &ArgMatches) -> Result<(), CliError> {
+    for entry in open_archive(sub)?.entries() {
+        println!("{}", entry);
+    }
+    Ok(())
+}
+
+fn extract(sub: &ArgMatches) -> Result<(), CliError> {
+    let mut archive = open_archive(sub)?;
+    let name = sub.get_one::<String>("name").unwrap();
+    let pixels = archive.extract(name).context("Failed to extract entry")?;
+    let entry = archive.entry(name).unwrap();
+    let output_path = sub.get_one::<String>("output").cloned().unwrap_or_else(|| {
+        Path::new(name).file_name().map_or(name.clone(), |file_name| file_name.to_string_lossy().into_owned())
+    });
+    image::save_buffer(&output_path, &pixels, entry.width, entry.height, color_type(entry.channels))
+        .context("Failed to write output image")?;
+    println!("{} -> {}", entry, output_path);
+    Ok(())
+}
+
+fn report(sub: &ArgMatches) -> Result<(), CliError> {
+    let json = fs::read(sub.get_one::<String>("results").unwrap()).context("Failed to read results")?;
+    let results = serde_json::from_slice::<BenchReport>(&json)
+        .or_else(|_| serde_json::from_slice::<Baseline>(&json).map(|baseline| baseline.report))
+        .context("Invalid benchmark results")?;
+    let mut names: Vec<String> = Vec::new();
+    for result in &results.results {
+        if !names.contains(&result.file) {
+            names.push(result.file.clone());
+        }
+    }
+    let mut report = HtmlReport::new(results).with_title(sub.get_one::<String>("title").unwrap());
+    if let Some(dir) = sub.get_one::<String>("dir") {
+        let qualities: Vec<u8> = sub.get_many::<u8>("qualities").unwrap().copied().collect();
+        for name in names {
+            let image = match read_image(Path::new(dir).join(&name)) {
+                Ok(image) => image,
+                Err(e) => {
+                    eprintln!("Skipping {}: {}", name, e);
+                    continue;
+                }
+            };
+            report.add_image(&name, &image).context("Failed to create thumbnail")?;
+            let modes: &[ChromaSubsampling] = if image.color().has_color() {
+                &[ChromaSubsampling::Yuv420, ChromaSubsampling::Yuv444]
+            } else {
+                &[ChromaSubsampling::Yuv444]
+            };
+            for &subsampling in modes {
+                report.add_curve(rate_distortion(&name, &image, subsampling, &qualities).context("JPEG encoding failed")?);
+            }
+            report.add_curve(spiht_rate_distortion(&name, &image, &SPIHT_PERCENTAGES).context("SPIHT encoding failed")?);
+            report.add_curve(wavelet_preview_distortion(&name, &image).context("Wavelet encoding failed")?);
+        }
+    }
+    fs::write(sub.get_one::<String>("output").unwrap(), report.render()).context("Failed to write report")
+}
+
+fn convert(sub: &ArgMatches) -> Result<(), CliError> {
+    // Only a few rows are in memory at a time, whatever the image size
+    let mut input = BufReader::new(File::open(sub.get_one::<String>("input").unwrap()).context("Failed to open input file")?);
+    let is_container = input.fill_buf().context("Failed to read input file")?.starts_with(b"ROW");
+    let mut reader: Box<dyn RowReader> = if is_container {
+        Box::new(ContainerRowReader::new(input).context("Invalid row container")?)
+    } else {
+        Box::new(PngRowReader::new(input).context("Invalid PNG file")?)
+    };
+    let (width, height, channels) = reader.geometry();
+    let output_path = sub.get_one::<String>("output").unwrap();
+    let output = BufWriter::new(File::create(output_path).context("Failed to create output file")?);
+    let level = *sub.get_one::<u32>("level").unwrap();
+    let mut writer: Box<dyn RowWriter> = if output_path.to_lowercase().ends_with(".png") {
+        Box::new(PngRowWriter::new(output, width, height, channels, level).context("Invalid PNG geometry")?)
+    } else {
+        let algorithm = if sub.get_one::<String>("algorithm").unwrap() == "lzw" { 1 } else { 0 };
+        Box::new(ContainerRowWriter::new(output, width, height, channels, algorithm, Some(level)).context("Invalid geometry")?)
+    };
+    let rows = copy_rows(reader.as_mut(), writer.as_mut()).context("Conversion failed")?;
+    println!("Converted {} rows of {}x{} with {} channels", rows, width, height, channels);
+    Ok(())
+}
+
+/// Runs compress-file, or decompress-file when `compress` is false.
+fn stream_file(compress: bool, sub: &ArgMatches) -> Result<(), CliError> {
+    let algorithm = sub.get_one::<String>("algorithm").unwrap();
+    let mut compressor =
+        CompressionAlgorithmType::create(algorithm, sub.get_one::<u32>("level").copied()).map_err(CliError::usage)?;
+    if let Some(dictionary) = load_dictionary(sub)? {
+        compressor = compressor.with_dictionary(dictionary).map_err(CliError::usage)?;
+    }
+    let mut input = BufReader::new(File::open(sub.get_one::<String>("input").unwrap()).context("Failed to open input file")?);
+    let mut output = BufWriter::new(File::create(sub.get_one::<String>("output").unwrap()).context("Failed to create output file")?);
+    if compress {
+        let read = compressor.compress_stream(&mut input, &mut output).context("Compression failed")?;
+        println!("Compressed {} bytes", read);
+    } else {
+        let written = compressor.decompress_stream(&mut input, &mut output).context("Decompression failed")?;
+        println!("Restored {} bytes", written);
+    }
+    Ok(())
+}
+
+fn analyze(sub: &ArgMatches) -> Result<(), CliError> {
+    let image = read_image(sub.get_one::<String>("input").unwrap()).context("Failed to read image")?;
+    let selector = AutoSelector::new().with_time_budget(Duration::from_millis(*sub.get_one::<u64>("time-budget").unwrap()));
+    let report = build_report(&image, &selector).context("Analysis failed")?;
+    if sub.get_flag("json") {
+        println!("{}", serde_json::to_string_pretty(&report).context("Failed to serialize report")?);
+    } else {
+        println!("{}", report);
+    }
+    Ok(())
+}
+
+/// Reads the file named by the `input` option.
+fn read_input(sub: &ArgMatches) -> Result<Vec<u8>, CliError> {
+    fs::read(sub.get_one::<String>("input").unwrap()).context("Failed to read input file")
+}
+
+fn decompress(sub: &ArgMatches) -> Result<(), CliError> {
+    let input = read_input(sub)?;
+    let output_path = sub.get_one::<String>("output").unwrap();
+    let (id, format, width, height, payload) = parse_image_header(&input).context("Invalid compressed image file")?;
+    let pixels = if id == AUTO_ID {
+        AutoCompressor::new().decompress(payload)
+    } else {
+        let channels = if format == FORMAT_BILEVEL { 1 } else { 3 };
+        CompressionAlgorithmType::for_block(id, width, height, channels).and_then(|c| c.decompress(payload))
+    }
+    .context("Decompression failed")?;
+    if format == FORMAT_BILEVEL {
+        let bilevel = BilevelImage::from_packed(width, height, pixels).context("Decompressed pixels do not match the image size")?;
+        bilevel.to_luma8().save(output_path).context("Failed to write output image")?;
+    } else {
+        let rgb = image::RgbImage::from_raw(width, height, pixels)
+            .ok_or_else(|| CliError::Failed("Decompressed pixels do not match the image size".to_string()))?;
+        rgb.save(output_path).context("Failed to write output image")?;
+    }
+    println!("{} bytes -> {}x{} image", input.len(), width, height);
+    Ok(())
+}
+
+fn decompress_blocks(sub: &ArgMatches) -> Result<(), CliError> {
+    let input = read_input(sub)?;
+    let compressor = BlockCompressor::from_header(&input).context("Invalid block-compressed file")?;
+    let pixels = compressor.decompress(&input).context("Block decompression failed")?;
+    let (width, height, channels) = compressor.get_dimensions();
+    image::save_buffer(sub.get_one::<String>("output").unwrap(), &pixels, width, height, color_type(channels))
+        .context("Failed to write output image")?;
+    println!("{} -> {} bytes of pixels", input.len(), pixels.len());
+    Ok(())
+}
+
+fn decompress_parallel(sub: &ArgMatches) -> Result<(), CliError> {
+    let data = read_input(sub)?;
+    let level = *sub.get_one::<u8>("zoom").unwrap();
+    let input = if data.starts_with(b"PYR") {
+        PyramidCompressor::level_container(&data, level).context("Invalid pyramid level")?
+    } else if level == 0 {
+        &data[..]
+    } else {
+        return Err(CliError::Usage("--zoom needs a file written with --pyramid".to_string()));
+    };
+    let (width, height, channels) = ParallelCompressor::read_dimensions(input).context("Invalid parallel-compressed file")?;
+    let id = ParallelCompressor::read_algorithm(input)
+        .context("Invalid parallel-compressed file")?
+        .ok_or_else(|| CliError::Failed("the parts were written by a custom compressor".to_string()))?;
+    let compressor = ParallelCompressor::with_factory(width, height, channels, decoding_factory(sub, id)?)
+        .with_threads(*sub.get_one::<usize>("threads").unwrap())
+        .context("Failed to start thread pool")?;
+    let (pixels, width, height) = match sub.get_one::<[u32; 4]>("crop") {
+        Some(&crop) => {
+            let pixels = compressor
+                .decode_region(input, crop[0], crop[1], crop[2], crop[3])
+                .context("Region decompression failed")?;
+            (pixels, crop[2], crop[3])
+        }
+        None => (compressor.decompress(input).context("Parallel decompression failed")?, width, height),
+    };
+    image::save_buffer(sub.get_one::<String>("output").unwrap(), &pixels, width, height, color_type(channels))
+        .context("Failed to write output image")?;
+    println!("{} -> {} bytes of pixels", input.len(), pixels.len());
+    Ok(())
+}
+
+fn decompress_progressive(sub: &ArgMatches) -> Result<(), CliError> {
+    let input = read_input(sub)?;
+    let available = sub.get_one::<usize>("bytes").map_or(input.len(), |&bytes| bytes.min(input.len()));
+    let id = ProgressiveCompressor::read_algorithm(&input)
+        .context("Invalid progressive file")?
+        .ok_or_else(|| CliError::Failed("the passes were written by a custom compressor".to_string()))?;
+    let image = ProgressiveCompressor::for_decoding(decoding_factory(sub, id)?)
+        .decode_partial(&input[..available])
+        .context("Progressive decompression failed")?;
+    image::save_buffer(sub.get_one::<String>("output").unwrap(), &image.pixels, image.width, image.height, color_type(image.channels))
+        .context("Failed to write output image")?;
+    println!("{} of {} passes from {} bytes", image.passes, image.total_passes, available);
+    Ok(())
+}
+
+/// Runs a subcommand that turns the input file into the output file, reporting both sizes.
+fn transform_file(name: &str, sub: &ArgMatches) -> Result<(), CliError> {
+    let input = read_input(sub)?;
+    let read = || read_image(sub.get_one::<String>("input").unwrap()).context("Failed to read image");
+    let output = match name {
+        "recompress-jpeg" => JpegRecompressor::new().compress(&input).context("JPEG recompression failed")?,
+        "restore-jpeg" => JpegRecompressor::new().decompress(&input).context("JPEG restoration failed")?,
+        "recompress-png" => recompress_png(&input)?,
+        "restore-png" => PngRecompressor::new().decompress(&input).context("PNG restoration failed")?,
+        "encode-gif" => encode_gif(sub, &read()?)?,
+        "encode-tiff" => encode_tiff(sub, &read()?)?,
+        "compress-blocks" => compress_blocks(sub, &read()?)?,
+        "compress-parallel" => compress_parallel(sub, &read()?)?,
+        "compress-progressive" => compress_progressive(sub, &read()?)?,
+        _ => optimize_png(&input, &read()?)?,
+    };
+    fs::write(sub.get_one::<String>("output").unwrap(), &output).context("Failed to write output file")?;
+    println!("{} -> {} bytes", input.len(), output.len());
+    Ok(())
+}
 
-    let input_path = matches.value_of("input").unwrap();
-    let output_path = matches.value_of("output").unwrap();
+fn recompress_png(input: &[u8]) -> Result<Vec<u8>, CliError> {
+    let packed = PngRecompressor::new().compress(input).context("PNG recompression failed")?;
+    match PngRecompressor::encoder_of(&packed) {
+        Some(ZlibEncoderKind::Verbatim) => println!("No known Deflate encoder reproduces the IDAT stream; it is kept compressed as is"),
+        Some(encoder) => println!("IDAT stream reproduced with {}", encoder),
+        None => println!("Stored with Deflate; modelling the PNG did not save space"),
+    }
+    Ok(packed)
+}
+
+fn encode_gif(sub: &ArgMatches, image: &DynamicImage) -> Result<Vec<u8>, CliError> {
+    GifEncoder::new()
+        .with_max_colors(*sub.get_one::<u16>("colors").unwrap() as usize)
+        .map_err(CliError::usage)?
+        .with_interlacing(sub.get_flag("interlace"))
+        .encode_image(image)
+        .context("GIF encoding failed")
+}
 
-    // Read the image
-    let image = read_image(input_path).expect("Failed to read image");
+fn encode_tiff(sub: &ArgMatches, image: &DynamicImage) -> Result<Vec<u8>, CliError> {
+    let compression = TiffCompression::from_name(sub.get_one::<String>("compression").unwrap()).unwrap();
+    let binarization = sub.get_one::<u8>("threshold").map_or(Binarization::Otsu, |&t| Binarization::Threshold(t));
+    let mut writer = TiffWriter::new()
+        .with_compression(compression)
+        .with_predictor(sub.get_flag("predictor"))
+        .with_binarization(binarization);
+    if let Some(&tile) = sub.get_one::<u32>("tile") {
+        writer = writer.with_tiles(tile, tile).map_err(CliError::usage)?;
+    }
+    writer.encode(image).context("TIFF encoding failed")
+}
 
-    // Convert image to raw bytes (assuming RGB)
-    let image_bytes = image.to_rgb8().to_vec();
+fn compress_blocks(sub: &ArgMatches, image: &DynamicImage) -> Result<Vec<u8>, CliError> {
+    let (pixels, channels) = interleaved(image);
+    let block_size = *sub.get_one::<u16>("block-size").unwrap();
+    let compressor =
+        BlockCompressor::with_block_size(image.width(), image.height(), channels, block_size).map_err(CliError::usage)?;
+    let (compressed, map) = compressor.compress_with_map(&pixels).context("Block compression failed")?;
+    println!("{}", map);
+    Ok(compressed)
+}
 
-    // Compress the image
-    let compressor = DeflateCompressor::new();
-    let compressed_data = compressor.compress(&image_bytes).expect("Compression failed");
+fn compress_parallel(sub: &ArgMatches, image: &DynamicImage) -> Result<Vec<u8>, CliError> {
+    let (pixels, channels) = interleaved(image);
+    let partition = match sub.get_one::<u32>("tile") {
+        Some(&tile) => Partition::Tiles(tile, tile),
+        None => Partition::Strips(*sub.get_one::<u32>("strip-rows").unwrap()),
+    };
+    let compressor = parallel_compressor(sub, image.width(), image.height(), channels)?
+        .with_partition(partition)
+        .map_err(CliError::usage)?;
+    if !sub.get_flag("pyramid") {
+        println!("{}", compressor);
+        return compressor.compress(&pixels).context("Parallel compression failed");
+    }
+    let filter = DownsampleFilter::from_name(sub.get_one::<String>("filter").unwrap()).unwrap();
+    let pyramid = PyramidCompressor::new(compressor)
+        .with_filter(filter)
+        .with_max_levels(*sub.get_one::<u8>("levels").unwrap())
+        .map_err(CliError::usage)?;
+    println!("{}", pyramid);
+    let compressed = pyramid.compress(&pixels).context("Pyramid compression failed")?;
+    let levels = PyramidCompressor::read_levels(&compressed).unwrap();
+    println!("{} levels, smallest {}x{}", levels.len(), levels[levels.len() - 1].0, levels[levels.len() - 1].1);
+    Ok(compressed)
+}
 
-    // Write the compressed data
-    write_image(output_path, &compressed_data).expect("Failed to write compressed image");
+fn compress_progressive(sub: &ArgMatches, image: &DynamicImage) -> Result<Vec<u8>, CliError> {
+    let (pixels, channels) = interleaved(image);
+    let order = sub.get_one::<u8>("levels").map_or(PassOrder::Adam7, |&levels| PassOrder::Hierarchical(levels));
+    let compressor = ProgressiveCompressor::with_factory(image.width(), image.height(), channels, part_factory(sub)?)
+        .with_algorithm_id(part_algorithm_id(sub))
+        .with_order(order)
+        .map_err(CliError::usage)?;
+    println!("{}", compressor);
+    compressor.compress(&pixels).context("Progressive compression failed")
+}
 
+fn optimize_png(input: &[u8], image: &DynamicImage) -> Result<Vec<u8>, CliError> {
+    let optimized = PngOptimizer::new().optimize(image).context("PNG optimization failed")?;
+    println!(
+        "{:?} {}-bit, filter {}, Deflate level {}",
+        optimized.color_type, optimized.bit_depth, optimized.filter, optimized.level
+    );
+    // An input PNG that is already smaller is kept as is.
+    if input.starts_with(b"\x89PNG") && input.len() <= optimized.data.len() {
+        Ok(input.to_vec())
+    } else {
+        Ok(optimized.data)
+    }
+}
+
+/// Compresses the image given to the top-level `-i` and `-o` options.
+fn compress(matches: &ArgMatches) -> Result<(), CliError> {
+    let image = read_image(matches.get_one::<String>("input").unwrap()).context("Failed to read image")?;
+    match matches.get_one::<u8>("quality") {
+        Some(&quality) => compress_jpeg(matches, &image, quality)?,
+        None => compress_lossless(matches, &image)?,
+    }
     println!("Image compressed successfully!");
+    Ok(())
+}
+
+/// Encodes a standard JPEG file, keeping grayscale inputs single-channel.
+fn compress_jpeg(matches: &ArgMatches, image: &DynamicImage, quality: u8) -> Result<(), CliError> {
+    let subsampling = ChromaSubsampling::from_name(matches.get_one::<String>("subsampling").unwrap()).map_err(CliError::usage)?;
+    let encoder = JpegEncoder::new(quality).map_err(CliError::usage)?.with_subsampling(subsampling);
+    let (bytes, channels) = if image.color().has_color() {
+        (image.to_rgb8().into_raw(), 3)
+    } else {
+        (image.to_luma8().into_raw(), 1)
+    };
+    let jpeg = encoder.encode(&bytes, image.width(), image.height(), channels).context("JPEG encoding failed")?;
+    write_image(matches.get_one::<String>("output").unwrap(), &jpeg).context("Failed to write compressed image")
+}
+
+/// Compresses the pixels losslessly with the chosen algorithm, writing the `--tagged` header if asked.
+fn compress_lossless(matches: &ArgMatches, image: &DynamicImage) -> Result<(), CliError> {
+    let output_path = matches.get_one::<String>("output").unwrap();
+    // Settings from the configuration file, overridden by the command line
+    let config = match matches.get_one::<String>("config") {
+        Some(path) => {
+            let config = AppConfig::load_from_file(path).context("Failed to load configuration")?;
+            config.validate().map_err(CliError::usage)?;
+            Some(config)
+        }
+        None => None,
+    };
+    let chosen = matches
+        .get_one::<String>("algorithm")
+        .cloned()
+        .or_else(|| config.as_ref().map(|c| c.compression_algorithm.clone()));
+    let level = matches.get_one::<u32>("level").copied().or_else(|| config.as_ref().and_then(|c| c.compression_level));
+    let (width, height) = (image.width(), image.height());
+    let tagged = matches.get_flag("tagged");
+
+    // Tagged black and white documents are stored at 1 bit per pixel, and fax coded unless an algorithm was chosen
+    let bilevel = if tagged { BilevelImage::from_exact(image) } else { None };
+    let (image_bytes, format) = match &bilevel {
+        Some(bilevel) => (bilevel.packed().to_vec(), FORMAT_BILEVEL),
+        None => (image.to_rgb8().into_raw(), FORMAT_RGB8),
+    };
+    let algorithm = chosen.clone().unwrap_or_else(|| "deflate".to_string());
+
+    if bilevel.is_some() && chosen.is_none() {
+        let compressor = CompressionAlgorithmType::Ccitt(CcittCompressor::new(CcittMode::Group4, width, height));
+        let mut output = image_header(compressor.id(), format, width, height);
+        output.extend_from_slice(&compressor.compress(&image_bytes).context("Compression failed")?);
+        write_image(output_path, &output).context("Failed to write compressed image")?;
+        println!("Bilevel image compressed with CCITT Group 4");
+    } else if algorithm.eq_ignore_ascii_case("auto") {
+        let mut selector = config.as_ref().map_or_else(AutoSelector::new, |c| c.create_auto_selector());
+        if let Some(&budget) = matches.get_one::<u64>("time-budget") {
+            selector = selector.with_time_budget(Duration::from_millis(budget));
+        }
+        let mut compressor = AutoCompressor::with_selector(selector);
+        if format == FORMAT_RGB8 {
+            compressor = compressor.with_geometry(width, height, 3);
+        }
+        let (compressed, decision) = compressor.compress_with_decision(&image_bytes).context("Compression failed")?;
+        println!("Auto selected {}", decision);
+        let mut output = if tagged { image_header(AUTO_ID, format, width, height) } else { Vec::new() };
+        output.extend_from_slice(&compressed);
+        write_image(output_path, &output).context("Failed to write compressed image")?;
+    } else {
+        let compressor = pixel_algorithm(&algorithm, level, width, height, format).map_err(CliError::usage)?;
+        // Stream straight to the file rather than holding the compressed copy in memory
+        let mut output = BufWriter::new(File::create(output_path).context("Failed to create output file")?);
+        if tagged {
+            output
+                .write_all(&image_header(compressor.id(), format, width, height))
+                .context("Failed to write compressed image")?;
+        }
+        compressor.compress_stream(&mut &image_bytes[..], &mut output).context("Compression failed")?;
+    }
+    Ok(())
 }